@@ -0,0 +1,194 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use meesign_crypto::proto::{ProtocolGroupInit, ProtocolInit, ProtocolMessage, ProtocolType};
+use meesign_crypto::protocol::{elgamal, index_base, keygen_context, threshold_context, Protocol};
+use prost::bytes::Bytes;
+use prost::Message;
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+
+const PARTIES: [u32; 4] = [2, 5, 10, 20];
+
+/// Protocols with the number of rounds of their keygen and threshold sessions
+const PROTOCOLS: [(ProtocolType, usize, usize); 3] = [
+    (ProtocolType::Gg18, 6, 10),
+    (ProtocolType::Elgamal, 4, 2),
+    (ProtocolType::Frost, 3, 3),
+];
+
+struct Session {
+    ctxs: Vec<Box<dyn Protocol>>,
+    outputs: Vec<Vec<u8>>,
+    /// Wall-clock time of each round, with the parties advanced in parallel
+    rounds: Vec<Duration>,
+}
+
+/// Run a session in-process on a thread pool, relaying the messages
+/// between the parties
+fn run(
+    mut ctxs: Vec<Box<dyn Protocol>>,
+    mut inputs: Vec<Vec<u8>>,
+    protocol_type: ProtocolType,
+    rounds: usize,
+) -> Session {
+    let mut durations = Vec::new();
+    let mut outputs = Vec::new();
+    for round in 0..rounds {
+        let start = Instant::now();
+        outputs = ctxs
+            .par_iter_mut()
+            .zip(&inputs)
+            .map(|(ctx, input)| ctx.advance(input).unwrap())
+            .collect();
+        durations.push(start.elapsed());
+
+        if round + 1 == rounds {
+            break;
+        }
+        let msgs: Vec<Vec<Bytes>> = outputs
+            .iter()
+            .map(|output| ProtocolMessage::decode(&output[..]).unwrap().message)
+            .collect();
+        inputs = (0..msgs.len())
+            .map(|recipient| {
+                ProtocolMessage {
+                    protocol_type: protocol_type as i32,
+                    message: msgs
+                        .iter()
+                        .enumerate()
+                        .filter(|(sender, _)| *sender != recipient)
+                        .map(|(sender, msg)| {
+                            msg[if recipient < sender {
+                                recipient
+                            } else {
+                                recipient - 1
+                            }]
+                            .clone()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }
+                .encode_to_vec()
+            })
+            .collect();
+    }
+    Session {
+        ctxs,
+        outputs,
+        rounds: durations,
+    }
+}
+
+fn keygen(protocol_type: ProtocolType, rounds: usize, parties: u32) -> Session {
+    let threshold = parties / 2 + 1;
+    let ctxs = (0..parties)
+        .map(|_| keygen_context(protocol_type).unwrap())
+        .collect();
+    let inputs = (0..parties)
+        .map(|index| {
+            ProtocolGroupInit {
+                protocol_type: protocol_type as i32,
+                index: index + index_base(protocol_type),
+                parties,
+                threshold,
+                weights: Vec::new(),
+                encrypt: false,
+                metadata: None,
+                version: 0,
+            }
+            .encode_to_vec()
+        })
+        .collect();
+    run(ctxs, inputs, protocol_type, rounds)
+}
+
+fn threshold(protocol_type: ProtocolType, rounds: usize, groups: &[Vec<u8>], pk: &[u8]) -> Session {
+    let base = index_base(protocol_type);
+    let signers = groups.len() as u32 / 2 + 1;
+    let data = match protocol_type {
+        ProtocolType::Elgamal => elgamal::encrypt(b"hello", pk).unwrap(),
+        _ => vec![0; 32],
+    };
+    let ctxs = groups[..signers as usize]
+        .iter()
+        .map(|group| threshold_context(protocol_type, group).unwrap())
+        .collect();
+    let inputs = (0..signers)
+        .map(|index| {
+            ProtocolInit {
+                protocol_type: protocol_type as i32,
+                indices: (base..signers + base).collect(),
+                index: index + base,
+                data: data.clone(),
+                ..Default::default()
+            }
+            .encode_to_vec()
+        })
+        .collect();
+    run(ctxs, inputs, protocol_type, rounds)
+}
+
+/// Measure only the given round of the sessions produced by `session`
+fn round_time(iters: u64, round: usize, session: impl Fn() -> Session) -> Duration {
+    (0..iters).map(|_| session().rounds[round]).sum()
+}
+
+fn bench_keygen(c: &mut Criterion) {
+    for (protocol_type, rounds, _) in PROTOCOLS {
+        let mut group = c.benchmark_group(format!("{:?}/keygen", protocol_type));
+        group.sample_size(10);
+        for parties in PARTIES {
+            group.bench_with_input(BenchmarkId::new("session", parties), &parties, |b, &n| {
+                b.iter(|| keygen(protocol_type, rounds, n))
+            });
+            for round in 0..rounds {
+                let id = BenchmarkId::new(format!("round{}", round + 1), parties);
+                group.bench_with_input(id, &parties, |b, &n| {
+                    b.iter_custom(|iters| {
+                        round_time(iters, round, || keygen(protocol_type, rounds, n))
+                    })
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+fn bench_threshold(c: &mut Criterion) {
+    for (protocol_type, keygen_rounds, rounds) in PROTOCOLS {
+        let mut group = c.benchmark_group(format!("{:?}/threshold", protocol_type));
+        group.sample_size(10);
+        for parties in PARTIES {
+            let session = keygen(protocol_type, keygen_rounds, parties);
+            let pk = ProtocolMessage::decode(&session.outputs[0][..])
+                .unwrap()
+                .message
+                .remove(0)
+                .to_vec();
+            let groups: Vec<Vec<u8>> = session
+                .ctxs
+                .into_iter()
+                .map(|ctx| ctx.finish().unwrap())
+                .collect();
+
+            group.bench_with_input(
+                BenchmarkId::new("session", parties),
+                &groups,
+                |b, groups| b.iter(|| threshold(protocol_type, rounds, groups, &pk)),
+            );
+            for round in 0..rounds {
+                let id = BenchmarkId::new(format!("round{}", round + 1), parties);
+                group.bench_with_input(id, &groups, |b, groups| {
+                    b.iter_custom(|iters| {
+                        round_time(iters, round, || {
+                            threshold(protocol_type, rounds, groups, &pk)
+                        })
+                    })
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_keygen, bench_threshold);
+criterion_main!(benches);
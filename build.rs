@@ -12,6 +12,9 @@ fn main() {
         .expect("Unable to generate bindings")
         .write_to_file("include/bindings.h");
 
-    prost_build::compile_protos(&["proto/meesign.proto"], &["proto/"])
+    // relayed messages are sliced out of the packed buffer without copying
+    prost_build::Config::new()
+        .bytes([".meesign.ProtocolMessage.message"])
+        .compile_protos(&["proto/meesign.proto"], &["proto/"])
         .expect("Unable to compile protos:");
 }
@@ -0,0 +1,175 @@
+use meesign_crypto::proto::{ProtocolGroupInit, ProtocolInit, ProtocolMessage, ProtocolType};
+use meesign_crypto::protocol::{
+    elgamal, hpke, index_base, keygen_context, ppss, threshold_context, Protocol,
+};
+use prost::Message;
+use std::sync::OnceLock;
+
+pub const PROTOCOL_TYPES: [ProtocolType; 12] = [
+    ProtocolType::Gg18,
+    ProtocolType::Elgamal,
+    ProtocolType::Frost,
+    ProtocolType::Ppss,
+    ProtocolType::ElgamalReencrypt,
+    ProtocolType::FrostP256,
+    ProtocolType::Ecdh,
+    ProtocolType::Hpke,
+    ProtocolType::Roast,
+    ProtocolType::Musig2,
+    ProtocolType::FrostEnroll,
+    ProtocolType::FrostRemove,
+];
+
+/// The generator of P-256, a valid counterparty key for ECDH and HPKE
+const P256_GENERATOR: [u8; 33] = [
+    0x03, 0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40,
+    0xf2, 0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2,
+    0x96,
+];
+
+pub fn protocol_type(byte: u8) -> ProtocolType {
+    PROTOCOL_TYPES[byte as usize % PROTOCOL_TYPES.len()]
+}
+
+/// Relay the messages of an honest two-party session for `rounds` rounds,
+/// then feed `payload` to the first party in place of its next input
+pub fn run(
+    ctxs: &mut [Box<dyn Protocol>; 2],
+    mut inputs: [Vec<u8>; 2],
+    rounds: usize,
+    payload: &[u8],
+) {
+    for _ in 0..rounds {
+        let mut outputs = Vec::new();
+        for (ctx, input) in ctxs.iter_mut().zip(&inputs) {
+            match ctx.advance(input) {
+                Ok(output) => outputs.push(output),
+                Err(_) => return,
+            }
+        }
+        inputs = [outputs.remove(1), outputs.remove(0)];
+    }
+    let _ = ctxs[0].advance(payload);
+}
+
+pub fn keygen_inputs(protocol_type: ProtocolType) -> [Vec<u8>; 2] {
+    let init = |index| {
+        ProtocolGroupInit {
+            protocol_type: protocol_type as i32,
+            index: index + index_base(protocol_type),
+            parties: 2,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        }
+        .encode_to_vec()
+    };
+    [init(0), init(1)]
+}
+
+/// Protocol whose key generation creates the groups of `protocol_type`
+fn keygen_type(protocol_type: ProtocolType) -> ProtocolType {
+    match protocol_type {
+        // enrollment joins an existing group, which a FROST key generation creates
+        ProtocolType::FrostEnroll => ProtocolType::Frost,
+        // the other key generations create the groups under their own types
+        other => other,
+    }
+}
+
+pub fn threshold_inputs(protocol_type: ProtocolType, data: &[u8]) -> [Vec<u8>; 2] {
+    let base = index_base(protocol_type);
+    let init = |index| {
+        ProtocolInit {
+            protocol_type: protocol_type as i32,
+            indices: vec![base, base + 1],
+            index: index + base,
+            data: data.to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    };
+    [init(0), init(1)]
+}
+
+pub struct Group {
+    pub contexts: [Vec<u8>; 2],
+    /// Honest input data of a threshold session
+    pub data: Vec<u8>,
+}
+
+/// Run an honest key generation, which is cached for each protocol
+/// as it can take a while
+pub fn group(protocol_type: ProtocolType) -> &'static Group {
+    static GROUPS: [OnceLock<Group>; 12] = [
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+        OnceLock::new(),
+    ];
+    GROUPS[protocol_type as usize].get_or_init(|| {
+        let mut ctxs = [
+            keygen_context(keygen_type(protocol_type)).unwrap(),
+            keygen_context(keygen_type(protocol_type)).unwrap(),
+        ];
+        let mut inputs = keygen_inputs(keygen_type(protocol_type));
+        let mut last = Vec::new();
+        loop {
+            let outputs: Vec<_> = ctxs
+                .iter_mut()
+                .zip(&inputs)
+                .map(|(ctx, input)| ctx.advance(input))
+                .collect();
+            if outputs.iter().any(|output| output.is_err()) {
+                break;
+            }
+            let mut outputs: Vec<_> = outputs.into_iter().map(|o| o.unwrap()).collect();
+            last = outputs[0].clone();
+            inputs = [outputs.remove(1), outputs.remove(0)];
+        }
+        let [first, second] = ctxs;
+        let contexts = [first.finish().unwrap(), second.finish().unwrap()];
+
+        let pk = ProtocolMessage::decode(&last[..])
+            .unwrap()
+            .message
+            .remove(0)
+            .to_vec();
+        let data = match protocol_type {
+            ProtocolType::Gg18 => vec![0; 32],
+            ProtocolType::Frost
+            | ProtocolType::FrostP256
+            | ProtocolType::Roast
+            | ProtocolType::Musig2 => b"hello".to_vec(),
+            ProtocolType::Elgamal => elgamal::encrypt(b"hello", &pk).unwrap(),
+            ProtocolType::Ppss => ppss::blind(b"hello").1,
+            ProtocolType::Ecdh => P256_GENERATOR.to_vec(),
+            ProtocolType::FrostEnroll => 3u16.to_be_bytes().to_vec(),
+            ProtocolType::FrostRemove => 3u16.to_be_bytes().to_vec(),
+            ProtocolType::Hpke => hpke::encrypt(b"hello", &P256_GENERATOR).unwrap(),
+            ProtocolType::ElgamalReencrypt => {
+                let ct = elgamal::encrypt(b"hello", &pk).unwrap();
+                serde_json::to_vec(&(ct, pk)).unwrap()
+            }
+        };
+        Group { contexts, data }
+    })
+}
+
+pub fn threshold_contexts(protocol_type: ProtocolType) -> [Box<dyn Protocol>; 2] {
+    let group = group(protocol_type);
+    [
+        threshold_context(protocol_type, &group.contexts[0]).unwrap(),
+        threshold_context(protocol_type, &group.contexts[1]).unwrap(),
+    ]
+}
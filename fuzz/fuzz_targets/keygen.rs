@@ -0,0 +1,25 @@
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use meesign_crypto::protocol::keygen_context;
+
+// The first byte selects the protocol, the second one the round
+// in which the payload replaces the input of the first party
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let protocol_type = common::protocol_type(data[0]);
+    let mut ctxs = [
+        keygen_context(protocol_type).unwrap(),
+        keygen_context(protocol_type).unwrap(),
+    ];
+    common::run(
+        &mut ctxs,
+        common::keygen_inputs(protocol_type),
+        data[1] as usize,
+        &data[2..],
+    );
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+
+// The first byte selects the protocol, the second one the round
+// in which the payload replaces the input of the first party
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let protocol_type = common::protocol_type(data[0]);
+    let mut ctxs = common::threshold_contexts(protocol_type);
+    common::run(
+        &mut ctxs,
+        common::threshold_inputs(protocol_type, &common::group(protocol_type).data),
+        data[1] as usize,
+        &data[2..],
+    );
+});
@@ -0,0 +1,208 @@
+//! Command-line tool running the protocols of the library locally,
+//! for debugging and as an example of the use of its API
+
+use meesign_crypto::proto::{
+    GroupResult, ProtocolGroupInit, ProtocolInit, ProtocolMessage, ProtocolType, SignatureResult,
+};
+use meesign_crypto::protocol::{self, coordinator, Protocol, ProtocolInfo, Result};
+use prost::{bytes::Bytes, Message};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::process::exit;
+
+const USAGE: &str = "usage:
+    meesign-crypto-cli protocols
+    meesign-crypto-cli sign <protocol> <threshold> <parties> <message>
+    meesign-crypto-cli inspect <context file>
+    meesign-crypto-cli verify <protocol> <public key> <message> <signature>
+    meesign-crypto-cli ssh-key <public key> [comment]
+
+Public keys and signatures are given in hex, messages as text.";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return Err("malformed hex".into());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&text[i..i + 2], 16)?))
+        .collect()
+}
+
+fn find_protocol(name: &str) -> Result<&'static ProtocolInfo> {
+    protocol::protocols()
+        .find(|info| info.name == name)
+        .ok_or_else(|| format!("unknown protocol {}", name).into())
+}
+
+/// Relay the outputs of the parties to each other until the session
+/// finishes, returning the results of the parties
+fn run_session(
+    protocol_type: ProtocolType,
+    mut ctxs: Vec<Box<dyn Protocol>>,
+    inits: Vec<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    let unpack = |output: Vec<u8>| -> Result<Vec<Bytes>> {
+        Ok(ProtocolMessage::decode(&output[..])?.message)
+    };
+    let mut outputs = ctxs
+        .iter_mut()
+        .zip(inits)
+        .map(|(ctx, init)| unpack(ctx.advance(&init)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut round = 1;
+    while ctxs[0].expected_messages().is_some() {
+        let inputs: Vec<Vec<u8>> = (0..ctxs.len())
+            .map(|idx| {
+                let message = outputs
+                    .iter()
+                    .enumerate()
+                    .filter(|(sender, _)| *sender != idx)
+                    .map(|(sender, msgs)| msgs[if sender < idx { idx - 1 } else { idx }].clone())
+                    .collect();
+                (ProtocolMessage {
+                    protocol_type: protocol_type as i32,
+                    message,
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        outputs = ctxs
+            .iter_mut()
+            .zip(inputs)
+            .enumerate()
+            .map(|(idx, (ctx, input))| {
+                let output = ctx
+                    .advance(&input)
+                    .map_err(|e| format!("party {} in round {}: {}", idx, round, e))?;
+                unpack(output)
+            })
+            .collect::<Result<_>>()?;
+        round += 1;
+    }
+    ctxs.into_iter().map(|ctx| ctx.finish()).collect()
+}
+
+/// Generate a group of all the parties, returning their group results
+fn keygen(info: &ProtocolInfo, threshold: u32, parties: u32) -> Result<Vec<Vec<u8>>> {
+    let ctxs = (0..parties)
+        .map(|_| protocol::keygen_context(info.protocol_type))
+        .collect::<Result<_>>()?;
+    let inits = (0..parties)
+        .map(|idx| {
+            (ProtocolGroupInit {
+                protocol_type: info.protocol_type as i32,
+                index: info.index_base + idx,
+                parties,
+                threshold,
+                weights: Vec::new(),
+                encrypt: false,
+                metadata: None,
+                version: 0,
+            })
+            .encode_to_vec()
+        })
+        .collect();
+    run_session(info.protocol_type, ctxs, inits)
+}
+
+fn sign(info: &ProtocolInfo, threshold: u32, parties: u32, message: &[u8]) -> Result<()> {
+    let groups = keygen(info, threshold, parties)?;
+    let group = GroupResult::decode(&groups[0][..])?;
+    println!("public key: {}", hex(&group.public_key));
+
+    let signers = if info.all_sign { parties } else { threshold };
+    let indices: Vec<u32> = (0..signers).map(|idx| info.index_base + idx).collect();
+    let data = if info.prehashed {
+        Sha256::digest(message).to_vec()
+    } else {
+        message.to_vec()
+    };
+    let ctxs = groups[..signers as usize]
+        .iter()
+        .map(|group| protocol::threshold_context(info.protocol_type, group))
+        .collect::<Result<_>>()?;
+    let inits = indices
+        .iter()
+        .map(|index| {
+            (ProtocolInit {
+                protocol_type: info.protocol_type as i32,
+                indices: indices.clone(),
+                index: *index,
+                data: data.clone(),
+                ..Default::default()
+            })
+            .encode_to_vec()
+        })
+        .collect();
+    let results = run_session(info.protocol_type, ctxs, inits)?;
+
+    let result = SignatureResult::decode(&results[0][..])?;
+    println!("signature: {}", hex(&result.signature));
+    coordinator::verify(
+        info.protocol_type,
+        &group.public_key,
+        message,
+        &result.signature,
+    )?;
+    println!("signature valid");
+    Ok(())
+}
+
+fn inspect(path: &str) -> Result<()> {
+    let ctx = protocol::try_deserialize(&std::fs::read(path)?)?;
+    println!("{}", serde_json::to_string_pretty(&ctx.debug_state())?);
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["protocols"] => {
+            let protocols = protocol::supported_protocols()?;
+            let protocols: serde_json::Value = serde_json::from_slice(&protocols)?;
+            println!("{}", serde_json::to_string_pretty(&protocols)?);
+        }
+        ["sign", name, threshold, parties, message] => {
+            sign(
+                find_protocol(name)?,
+                threshold.parse()?,
+                parties.parse()?,
+                message.as_bytes(),
+            )?;
+        }
+        ["inspect", path] => inspect(path)?,
+        ["verify", name, pk, message, signature] => {
+            let info = find_protocol(name)?;
+            coordinator::verify(
+                info.protocol_type,
+                &unhex(pk)?,
+                message.as_bytes(),
+                &unhex(signature)?,
+            )?;
+            println!("signature valid");
+        }
+        #[cfg(feature = "gg18")]
+        ["ssh-key", pk] => println!("{}", protocol::ssh::public_key(&unhex(pk)?, "")?),
+        #[cfg(feature = "gg18")]
+        ["ssh-key", pk, comment] => {
+            println!("{}", protocol::ssh::public_key(&unhex(pk)?, comment)?)
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(error) = run(&args) {
+        eprintln!("{}", error);
+        exit(1);
+    }
+}
@@ -5,13 +5,78 @@ use std::ffi::CString;
 use std::os::raw::c_char;
 
 use crate::auth;
-use crate::protocol::{self, elgamal, frost, gg18, KeygenProtocol, ThresholdProtocol};
+use crate::proto::ProtocolType;
+use crate::protocol::{
+    self, backup, eip712, fingerprint, storage, weighted, KeygenProtocol, ThresholdProtocol,
+};
+#[cfg(feature = "frost")]
+use crate::protocol::{age, enroll, hpke, nested};
+#[cfg(feature = "gg18")]
+use crate::protocol::{cms, migration, pdf, ssh, webauthn};
+#[cfg(feature = "elgamal")]
+use crate::protocol::{elgamal, stream};
 
 #[repr(C)]
 pub enum ProtocolId {
-    Gg18,
-    Elgamal,
-    Frost,
+    #[cfg(feature = "gg18")]
+    Gg18 = 0,
+    #[cfg(feature = "elgamal")]
+    Elgamal = 1,
+    #[cfg(feature = "frost")]
+    Frost = 2,
+    #[cfg(feature = "elgamal")]
+    Ppss = 3,
+    #[cfg(feature = "elgamal")]
+    ElgamalReencrypt = 4,
+    #[cfg(feature = "frost")]
+    FrostP256 = 5,
+    #[cfg(feature = "frost")]
+    Ecdh = 6,
+    #[cfg(feature = "frost")]
+    Hpke = 7,
+    #[cfg(feature = "frost")]
+    Roast = 8,
+    #[cfg(feature = "musig2")]
+    Musig2 = 9,
+    #[cfg(feature = "frost")]
+    FrostEnroll = 10,
+    #[cfg(feature = "frost")]
+    FrostRemove = 11,
+    #[cfg(feature = "mldsa")]
+    MlDsa = 12,
+}
+
+impl From<ProtocolId> for ProtocolType {
+    fn from(proto_id: ProtocolId) -> Self {
+        match proto_id {
+            #[cfg(feature = "gg18")]
+            ProtocolId::Gg18 => ProtocolType::Gg18,
+            #[cfg(feature = "elgamal")]
+            ProtocolId::Elgamal => ProtocolType::Elgamal,
+            #[cfg(feature = "frost")]
+            ProtocolId::Frost => ProtocolType::Frost,
+            #[cfg(feature = "elgamal")]
+            ProtocolId::Ppss => ProtocolType::Ppss,
+            #[cfg(feature = "elgamal")]
+            ProtocolId::ElgamalReencrypt => ProtocolType::ElgamalReencrypt,
+            #[cfg(feature = "frost")]
+            ProtocolId::FrostP256 => ProtocolType::FrostP256,
+            #[cfg(feature = "frost")]
+            ProtocolId::Ecdh => ProtocolType::Ecdh,
+            #[cfg(feature = "frost")]
+            ProtocolId::Hpke => ProtocolType::Hpke,
+            #[cfg(feature = "frost")]
+            ProtocolId::Roast => ProtocolType::Roast,
+            #[cfg(feature = "musig2")]
+            ProtocolId::Musig2 => ProtocolType::Musig2,
+            #[cfg(feature = "frost")]
+            ProtocolId::FrostEnroll => ProtocolType::FrostEnroll,
+            #[cfg(feature = "frost")]
+            ProtocolId::FrostRemove => ProtocolType::FrostRemove,
+            #[cfg(feature = "mldsa")]
+            ProtocolId::MlDsa => ProtocolType::MlDsa,
+        }
+    }
 }
 
 #[repr(C)]
@@ -77,21 +142,163 @@ impl ProtocolResult {
 #[allow(unused_variables)]
 pub unsafe extern "C" fn protocol_result_free(res: ProtocolResult) {}
 
+fn keygen(protocol_type: ProtocolType) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::keygen_context(protocol_type)?;
+    Ok(serde_json::to_vec(&ctx)?)
+}
+
 #[no_mangle]
-pub unsafe extern "C" fn protocol_keygen(proto_id: ProtocolId) -> ProtocolResult {
-    let ctx: Box<dyn protocol::Protocol> = match proto_id {
-        ProtocolId::Gg18 => Box::new(gg18::KeygenContext::new()),
-        ProtocolId::Elgamal => Box::new(elgamal::KeygenContext::new()),
-        ProtocolId::Frost => Box::new(frost::KeygenContext::new()),
-    };
+pub unsafe extern "C" fn protocol_keygen(
+    proto_id: ProtocolId,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    match keygen(proto_id.into()) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+/// Returns the capabilities of a protocol as a JSON object
+#[no_mangle]
+pub unsafe extern "C" fn protocol_capabilities(
+    proto_id: ProtocolId,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    match protocol::capabilities(proto_id.into()) {
+        Ok(capabilities) => capabilities.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Returns the capabilities of all the protocols compiled in as a JSON array
+#[no_mangle]
+pub unsafe extern "C" fn protocol_list(error_out: *mut *mut c_char) -> Buffer {
+    match protocol::supported_protocols() {
+        Ok(protocols) => protocols.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_set_size_limits(message: usize, context: usize) {
+    protocol::set_size_limits(message, context);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_set_compression(enabled: bool) {
+    protocol::set_compression(enabled);
+}
+
+/// Returns the registry of the signing sessions whose nonces were used,
+/// to be persisted along with the contexts
+#[cfg(any(feature = "frost", feature = "musig2"))]
+#[no_mangle]
+pub unsafe extern "C" fn protocol_export_consumed(error_out: *mut *mut c_char) -> Buffer {
+    match protocol::export_consumed() {
+        Ok(registry) => registry.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Restores the registry returned by `protocol_export_consumed`,
+/// returning whether it succeeded
+#[cfg(any(feature = "frost", feature = "musig2"))]
+#[no_mangle]
+pub unsafe extern "C" fn protocol_import_consumed(
+    data_ptr: *const u8,
+    data_len: usize,
+    error_out: *mut *mut c_char,
+) -> bool {
+    let data = unsafe { slice::from_raw_parts(data_ptr, data_len) };
+
+    match protocol::import_consumed(data) {
+        Ok(()) => true,
+        Err(error) => {
+            set_error(error_out, &*error);
+            false
+        }
+    }
+}
+
+/// Returns whether a packed message is well-formed, setting the error if not
+#[no_mangle]
+pub unsafe extern "C" fn protocol_validate_envelope(
+    data_ptr: *const u8,
+    data_len: usize,
+    error_out: *mut *mut c_char,
+) -> bool {
+    let data = unsafe { slice::from_raw_parts(data_ptr, data_len) };
+
+    match protocol::validate_envelope(data) {
+        Ok(()) => true,
+        Err(error) => {
+            set_error(error_out, &*error);
+            false
+        }
+    }
+}
+
+/// Callback approving a signing session, given the protocol type, the data
+/// to be signed and the indices of the signers
+pub type PolicyCallback = extern "C" fn(i32, *const u8, usize, *const u32, usize) -> bool;
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_set_policy(callback: Option<PolicyCallback>) {
+    protocol::set_policy(callback.map(|callback| -> protocol::Policy {
+        Box::new(move |request| {
+            callback(
+                request.protocol_type as i32,
+                request.message.as_ptr(),
+                request.message.len(),
+                request.signers.as_ptr(),
+                request.signers.len(),
+            )
+        })
+    }));
+}
+
+/// Callback receiving the telemetry events of the library as JSON
+#[cfg(feature = "tracing")]
+pub type TelemetryCallback = extern "C" fn(*const c_char);
+
+#[cfg(feature = "tracing")]
+#[no_mangle]
+pub unsafe extern "C" fn protocol_set_telemetry(
+    callback: TelemetryCallback,
+    error_out: *mut *mut c_char,
+) {
+    let hook: protocol::TelemetryHook = Box::new(move |event| {
+        let event = CString::new(serde_json::to_string(event).unwrap()).unwrap();
+        callback(event.as_ptr());
+    });
+    if let Err(error) = protocol::set_telemetry_hook(hook) {
+        set_error(error_out, &*error);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_keygen_weighted() -> ProtocolResult {
+    let ctx = protocol::resumable(Box::new(weighted::KeygenContext::new()));
     let ctx_ser = serde_json::to_vec(&ctx).unwrap();
     ProtocolResult::new(ctx_ser, vec![])
 }
 
 fn advance(ctx1_ser: &[u8], data_in: &[u8]) -> protocol::Result<(Vec<u8>, Vec<u8>)> {
-    let mut ctx1: Box<dyn protocol::Protocol> = serde_json::from_slice(ctx1_ser).unwrap();
+    let mut ctx1 = protocol::try_deserialize(ctx1_ser)?;
     let data_out = ctx1.advance(data_in)?;
-    let ctx2_ser = serde_json::to_vec(&ctx1).unwrap();
+    let ctx2_ser = serde_json::to_vec(&ctx1)?;
     Ok((ctx2_ser, data_out))
 }
 
@@ -115,8 +322,43 @@ pub unsafe extern "C" fn protocol_advance(
     }
 }
 
+fn advance_checkpoint(
+    snapshot: &[u8],
+    key: &[u8],
+    data_in: &[u8],
+) -> protocol::Result<(Vec<u8>, Vec<u8>)> {
+    let mut ctx = protocol::restore(snapshot, key)?;
+    let data_out = ctx.advance(data_in)?;
+    Ok((protocol::checkpoint(&*ctx, key)?, data_out))
+}
+
+/// Advances a context kept as a snapshot authenticated under `key`,
+/// see `protocol_checkpoint`, returning its next snapshot
+#[no_mangle]
+pub unsafe extern "C" fn protocol_advance_checkpoint(
+    snapshot_ptr: *const u8,
+    snapshot_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    data_ptr: *const u8,
+    data_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let snapshot = unsafe { slice::from_raw_parts(snapshot_ptr, snapshot_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let data_in = unsafe { slice::from_raw_parts(data_ptr, data_len) };
+
+    match advance_checkpoint(snapshot, key, data_in) {
+        Ok((snapshot, data_out)) => ProtocolResult::new(snapshot, data_out),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
 fn finish(ctx_ser: &[u8]) -> protocol::Result<(Vec<u8>, Vec<u8>)> {
-    let ctx: Box<dyn protocol::Protocol> = serde_json::from_slice(ctx_ser).unwrap();
+    let ctx = protocol::try_deserialize(ctx_ser)?;
     let data_out = ctx.finish()?;
     Ok((vec![], data_out))
 }
@@ -138,88 +380,1267 @@ pub unsafe extern "C" fn protocol_finish(
     }
 }
 
+fn checkpoint(ctx_ser: &[u8], key: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    protocol::checkpoint(&*ctx, key)
+}
+
 #[no_mangle]
-pub unsafe extern "C" fn protocol_init(
-    proto_id: ProtocolId,
-    group_ptr: *const u8,
-    group_len: usize,
+pub unsafe extern "C" fn protocol_checkpoint(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match checkpoint(ctx_ser, key) {
+        Ok(snapshot) => snapshot.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+fn restore(snapshot: &[u8], key: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::restore(snapshot, key)?;
+    Ok(serde_json::to_vec(&ctx)?)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_restore(
+    snapshot_ptr: *const u8,
+    snapshot_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let snapshot = unsafe { slice::from_raw_parts(snapshot_ptr, snapshot_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match restore(snapshot, key) {
+        Ok(ctx_ser) => ctx_ser.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+fn record_transcript(ctx_ser: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(serde_json::to_vec(&protocol::with_transcript(ctx))?)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_record_transcript(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
 ) -> ProtocolResult {
-    let group_ser = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
 
-    let ctx: Box<dyn protocol::Protocol> = match proto_id {
-        ProtocolId::Gg18 => Box::new(gg18::SignContext::new(group_ser)),
-        ProtocolId::Elgamal => Box::new(elgamal::DecryptContext::new(group_ser)),
-        ProtocolId::Frost => Box::new(frost::SignContext::new(group_ser)),
-    };
-    let ctx_ser = serde_json::to_vec(&ctx).unwrap();
+    match record_transcript(ctx_ser) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
 
-    ProtocolResult::new(ctx_ser, vec![])
+fn last_output(ctx_ser: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(ctx.last_output().ok_or("no output to send again")?)
 }
 
-#[repr(C)]
-pub struct AuthKey {
-    key: Buffer,
-    csr: Buffer,
+/// Returns the last output of the context again, for retransmission
+#[no_mangle]
+pub unsafe extern "C" fn protocol_last_output(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+
+    match last_output(ctx_ser) {
+        Ok(output) => output.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
 }
 
-impl AuthKey {
-    pub fn new(key: Vec<u8>, csr: Vec<u8>) -> Self {
-        Self {
-            key: key.into(),
-            csr: csr.into(),
+fn debug_state(ctx_ser: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(serde_json::to_vec(&ctx.debug_state())?)
+}
+
+/// Returns the state of the context as JSON, for diagnostics
+#[no_mangle]
+pub unsafe extern "C" fn protocol_debug_state(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+
+    match debug_state(ctx_ser) {
+        Ok(state) => state.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
         }
     }
 }
 
+fn transcript(ctx_ser: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(ctx.transcript().ok_or("transcript not recorded")?)
+}
+
+/// Returns the transcript recorded by the context; since finishing does not
+/// advance the context, the transcript of a finished session is read from
+/// the context passed to `protocol_finish`
 #[no_mangle]
-#[allow(unused_variables)]
-pub unsafe extern "C" fn auth_key_free(key: AuthKey) {}
+pub unsafe extern "C" fn protocol_transcript(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+
+    match transcript(ctx_ser) {
+        Ok(transcript) => transcript.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+fn make_progressive(ctx_ser: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(serde_json::to_vec(&protocol::progressive(ctx))?)
+}
 
 #[no_mangle]
-pub unsafe extern "C" fn auth_keygen(name: *const c_char, error_out: *mut *mut c_char) -> AuthKey {
-    let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
-    match auth::gen_key_with_csr(name) {
-        Ok((key, csr)) => AuthKey::new(key, csr),
+pub unsafe extern "C" fn protocol_progressive(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+
+    match make_progressive(ctx_ser) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
         Err(error) => {
-            set_error(error_out, &error);
-            AuthKey::new(vec![], vec![])
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
         }
     }
 }
 
+fn authenticate(ctx_ser: &[u8], key: &[u8], devices: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(serde_json::to_vec(&protocol::authenticated(
+        ctx, key, devices,
+    )?)?)
+}
+
+/// Signs the messages of the context by the DER encoded key of the device
+/// and verifies those of the others against the certificates in `devices`,
+/// an encoded `GroupDevices`
 #[no_mangle]
-pub unsafe extern "C" fn auth_cert_key_to_pkcs12(
+pub unsafe extern "C" fn protocol_authenticate(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
     key_ptr: *const u8,
     key_len: usize,
-    cert_ptr: *const u8,
-    cert_len: usize,
+    devices_ptr: *const u8,
+    devices_len: usize,
     error_out: *mut *mut c_char,
-) -> Buffer {
-    let key_der = unsafe { slice::from_raw_parts(key_ptr, key_len) };
-    let cert_der = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+) -> ProtocolResult {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let devices = unsafe { slice::from_raw_parts(devices_ptr, devices_len) };
 
-    match auth::cert_key_to_pkcs12(key_der, cert_der) {
-        Ok(pkcs12) => pkcs12.into(),
+    match authenticate(ctx_ser, key, devices) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
         Err(error) => {
-            set_error(error_out, &error);
-            vec![].into()
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
         }
     }
 }
 
+fn feed(ctx_ser: &[u8], sender: usize, msg: &[u8]) -> protocol::Result<Vec<u8>> {
+    let mut ctx = protocol::try_deserialize(ctx_ser)?;
+    ctx.feed(sender, msg)?;
+    Ok(serde_json::to_vec(&ctx)?)
+}
+
+/// Buffers the message of the `sender`-th of the other parties, given in
+/// an envelope of its own, returning the updated context
 #[no_mangle]
-pub unsafe extern "C" fn encrypt(
+pub unsafe extern "C" fn protocol_feed(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    sender: usize,
     msg_ptr: *const u8,
     msg_len: usize,
-    key_ptr: *const u8,
-    key_len: usize,
     error_out: *mut *mut c_char,
-) -> Buffer {
+) -> ProtocolResult {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
     let msg = unsafe { slice::from_raw_parts(msg_ptr, msg_len) };
-    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
 
-    match elgamal::encrypt(msg, key) {
-        Ok(ciphertext) => ciphertext.into(),
+    match feed(ctx_ser, sender, msg) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+/// Returns whether the buffered messages can be delivered by advancing
+/// the context with an empty input
+#[no_mangle]
+pub unsafe extern "C" fn protocol_ready(ctx_ptr: *const u8, ctx_len: usize) -> bool {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+    protocol::try_deserialize(ctx_ser).map_or(false, |ctx| ctx.ready())
+}
+
+fn expected_messages(ctx_ser: &[u8]) -> protocol::Result<Option<usize>> {
+    let ctx = protocol::try_deserialize(ctx_ser)?;
+    Ok(ctx.expected_messages())
+}
+
+/// Returns -1 if the context expects no messages from the other parties
+#[no_mangle]
+pub unsafe extern "C" fn protocol_expected_messages(
+    ctx_ptr: *const u8,
+    ctx_len: usize,
+    error_out: *mut *mut c_char,
+) -> isize {
+    let ctx_ser = unsafe { slice::from_raw_parts(ctx_ptr, ctx_len) };
+
+    match expected_messages(ctx_ser) {
+        Ok(Some(count)) => count as isize,
+        Ok(None) => -1,
+        Err(error) => {
+            set_error(error_out, &*error);
+            -1
+        }
+    }
+}
+
+fn init(protocol_type: ProtocolType, group: &[u8]) -> protocol::Result<(Vec<u8>, Vec<u8>)> {
+    let ctx = protocol::threshold_context(protocol_type, group)?;
+    let counted = ctx.counted_group().unwrap_or_default();
+    Ok((serde_json::to_vec(&ctx)?, counted))
+}
+
+/// Initializes a threshold context; the data of the result is the group
+/// with the session counted in its usage, which is to be stored in place
+/// of the given group before the session starts, or empty if the group
+/// does not count its sessions
+#[no_mangle]
+pub unsafe extern "C" fn protocol_init(
+    proto_id: ProtocolId,
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let group_ser = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match init(proto_id.into(), group_ser) {
+        Ok((ctx_ser, counted)) => ProtocolResult::new(ctx_ser, counted),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+fn init_stored(
+    protocol_type: ProtocolType,
+    stored: &[u8],
+    key: &[u8],
+    group_id: &[u8],
+) -> protocol::Result<(Vec<u8>, Vec<u8>)> {
+    let ctx = storage::threshold_context(protocol_type, stored, key, group_id)?;
+    let counted = match ctx.counted_group() {
+        Some(group) => storage::encrypt_group(&group, key, group_id)?,
+        None => vec![],
+    };
+    Ok((serde_json::to_vec(&ctx)?, counted))
+}
+
+/// Initializes a threshold context from a group stored by `group_encrypt`
+/// without returning the group in plaintext; the data of the result is
+/// the counted group stored again, see `protocol_init`
+#[no_mangle]
+pub unsafe extern "C" fn protocol_init_stored(
+    proto_id: ProtocolId,
+    stored_ptr: *const u8,
+    stored_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    group_id_ptr: *const u8,
+    group_id_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let stored = unsafe { slice::from_raw_parts(stored_ptr, stored_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let group_id = unsafe { slice::from_raw_parts(group_id_ptr, group_id_len) };
+
+    match init_stored(proto_id.into(), stored, key, group_id) {
+        Ok((ctx_ser, counted)) => ProtocolResult::new(ctx_ser, counted),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+fn init_with<P: ThresholdProtocol + 'static>(group: &[u8]) -> protocol::Result<Vec<u8>> {
+    let ctx = protocol::resumable(Box::new(P::new(group)?));
+    Ok(serde_json::to_vec(&ctx)?)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_init_weighted(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let group_ser = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match init_with::<weighted::ThresholdContext>(group_ser) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn protocol_init_nested(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> ProtocolResult {
+    let group_ser = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match init_with::<nested::SignContext>(group_ser) {
+        Ok(ctx_ser) => ProtocolResult::new(ctx_ser, vec![]),
+        Err(error) => {
+            set_error(error_out, &*error);
+            ProtocolResult::new(vec![], vec![])
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn nested_split(
+    group_ptr: *const u8,
+    group_len: usize,
+    threshold: u16,
+    parties: u16,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match nested::split(group, threshold, parties) {
+        Ok(split) => serde_json::to_vec(&split).unwrap().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn nested_attach(
+    group_ptr: *const u8,
+    group_len: usize,
+    public_ptr: *const u8,
+    public_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+    let public = unsafe { slice::from_raw_parts(public_ptr, public_len) };
+
+    match nested::attach(group, public) {
+        Ok(group) => group.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn enroll_add_member(
+    group_ptr: *const u8,
+    group_len: usize,
+    index: u16,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match enroll::add_member(group, index) {
+        Ok(group) => group.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Returns the short fingerprint of a group result as UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn group_fingerprint(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match fingerprint::fingerprint(group) {
+        Ok(fingerprint) => fingerprint.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Returns the QR payload with the fingerprint of a group result as UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn group_qr_payload(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match fingerprint::qr_payload(group) {
+        Ok(payload) => payload.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Encrypts a group result at rest under a 32-byte storage key,
+/// bound to the identifier of the group, e.g. its public key
+#[no_mangle]
+pub unsafe extern "C" fn group_encrypt(
+    group_ptr: *const u8,
+    group_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    group_id_ptr: *const u8,
+    group_id_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let group_id = unsafe { slice::from_raw_parts(group_id_ptr, group_id_len) };
+
+    match storage::encrypt_group(group, key, group_id) {
+        Ok(stored) => stored.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Decrypts a stored group result; sessions should be started
+/// by `protocol_init_stored` instead, which keeps the group in the library
+#[no_mangle]
+pub unsafe extern "C" fn group_decrypt(
+    stored_ptr: *const u8,
+    stored_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    group_id_ptr: *const u8,
+    group_id_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let stored = unsafe { slice::from_raw_parts(stored_ptr, stored_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let group_id = unsafe { slice::from_raw_parts(group_id_ptr, group_id_len) };
+
+    match storage::decrypt_group(stored, key, group_id) {
+        Ok(group) => group.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Re-encrypts a stored group result under a new storage key
+/// without returning it in plaintext
+#[no_mangle]
+pub unsafe extern "C" fn group_rewrap(
+    stored_ptr: *const u8,
+    stored_len: usize,
+    old_key_ptr: *const u8,
+    old_key_len: usize,
+    new_key_ptr: *const u8,
+    new_key_len: usize,
+    group_id_ptr: *const u8,
+    group_id_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let stored = unsafe { slice::from_raw_parts(stored_ptr, stored_len) };
+    let old_key = unsafe { slice::from_raw_parts(old_key_ptr, old_key_len) };
+    let new_key = unsafe { slice::from_raw_parts(new_key_ptr, new_key_len) };
+    let group_id = unsafe { slice::from_raw_parts(group_id_ptr, group_id_len) };
+
+    match storage::rewrap(stored, old_key, new_key, group_id) {
+        Ok(stored) => stored.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn backup_split(
+    group_ptr: *const u8,
+    group_len: usize,
+    threshold: u8,
+    parties: u8,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match backup::split(group, threshold, parties) {
+        Ok(shares) => serde_json::to_vec(&shares).unwrap().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+fn backup_combine_json(shares: &[u8]) -> protocol::Result<Vec<u8>> {
+    let shares: Vec<Vec<u8>> = serde_json::from_slice(shares)?;
+    backup::combine(&shares)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn backup_combine(
+    shares_ptr: *const u8,
+    shares_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let shares = unsafe { slice::from_raw_parts(shares_ptr, shares_len) };
+
+    match backup_combine_json(shares) {
+        Ok(group) => group.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn backup_to_mnemonic(
+    share_ptr: *const u8,
+    share_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let share = unsafe { slice::from_raw_parts(share_ptr, share_len) };
+
+    match backup::to_mnemonic(share) {
+        Ok(words) => words.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn backup_from_mnemonic(
+    words: *const c_char,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let words = unsafe { CStr::from_ptr(words) }.to_string_lossy();
+
+    match backup::from_mnemonic(&words) {
+        Ok(share) => share.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "elgamal")]
+fn backup_export_json(group: &[u8], threshold: u8, recipients: &[u8]) -> protocol::Result<Vec<u8>> {
+    let recipients: Vec<Vec<u8>> = serde_json::from_slice(recipients)?;
+    Ok(serde_json::to_vec(&backup::export(
+        group,
+        threshold,
+        &recipients,
+    )?)?)
+}
+
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn backup_export(
+    group_ptr: *const u8,
+    group_len: usize,
+    threshold: u8,
+    recipients_ptr: *const u8,
+    recipients_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+    let recipients = unsafe { slice::from_raw_parts(recipients_ptr, recipients_len) };
+
+    match backup_export_json(group, threshold, recipients) {
+        Ok(shares) => shares.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn eip712_hash(
+    data_ptr: *const u8,
+    data_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let data = unsafe { slice::from_raw_parts(data_ptr, data_len) };
+
+    match eip712::hash_typed_data(data) {
+        Ok(digest) => digest.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Returns the digest a GG18 group signs to endorse the group
+/// succeeding it, given both group results
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn migration_digest(
+    old_ptr: *const u8,
+    old_len: usize,
+    new_ptr: *const u8,
+    new_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let old = unsafe { slice::from_raw_parts(old_ptr, old_len) };
+    let new = unsafe { slice::from_raw_parts(new_ptr, new_len) };
+
+    match migration::endorsement_digest(old, new) {
+        Ok(digest) => digest.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn ssh_public_key(
+    pk_ptr: *const u8,
+    pk_len: usize,
+    comment: *const c_char,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pk = unsafe { slice::from_raw_parts(pk_ptr, pk_len) };
+    let comment = unsafe { CStr::from_ptr(comment) }.to_string_lossy();
+
+    match ssh::public_key(pk, &comment) {
+        Ok(line) => line.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn ssh_signature_digest(
+    message_ptr: *const u8,
+    message_len: usize,
+    namespace: *const c_char,
+) -> Buffer {
+    let message = unsafe { slice::from_raw_parts(message_ptr, message_len) };
+    let namespace = unsafe { CStr::from_ptr(namespace) }.to_string_lossy();
+
+    ssh::signature_digest(message, &namespace).into()
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn ssh_armor_signature(
+    pk_ptr: *const u8,
+    pk_len: usize,
+    namespace: *const c_char,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pk = unsafe { slice::from_raw_parts(pk_ptr, pk_len) };
+    let namespace = unsafe { CStr::from_ptr(namespace) }.to_string_lossy();
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+
+    match ssh::armor_signature(pk, &namespace, signature) {
+        Ok(armored) => armored.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn webauthn_cose_key(
+    pk_ptr: *const u8,
+    pk_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pk = unsafe { slice::from_raw_parts(pk_ptr, pk_len) };
+
+    match webauthn::cose_key(pk) {
+        Ok(key) => key.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn webauthn_signature_digest(
+    auth_data_ptr: *const u8,
+    auth_data_len: usize,
+    client_data_ptr: *const u8,
+    client_data_len: usize,
+) -> Buffer {
+    let auth_data = unsafe { slice::from_raw_parts(auth_data_ptr, auth_data_len) };
+    let client_data = unsafe { slice::from_raw_parts(client_data_ptr, client_data_len) };
+
+    webauthn::signature_digest(auth_data, client_data).into()
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn webauthn_assertion(
+    id_ptr: *const u8,
+    id_len: usize,
+    auth_data_ptr: *const u8,
+    auth_data_len: usize,
+    client_data_ptr: *const u8,
+    client_data_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let id = unsafe { slice::from_raw_parts(id_ptr, id_len) };
+    let auth_data = unsafe { slice::from_raw_parts(auth_data_ptr, auth_data_len) };
+    let client_data = unsafe { slice::from_raw_parts(client_data_ptr, client_data_len) };
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+
+    match webauthn::assertion(id, auth_data, client_data, signature) {
+        Ok(credential) => credential.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn cms_signature_digest(
+    digest_ptr: *const u8,
+    digest_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+) -> Buffer {
+    let digest = unsafe { slice::from_raw_parts(digest_ptr, digest_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+
+    cms::signature_digest(digest, cert).into()
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn cms_detached(
+    digest_ptr: *const u8,
+    digest_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let digest = unsafe { slice::from_raw_parts(digest_ptr, digest_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+
+    match cms::detached(digest, cert, signature) {
+        Ok(signed_data) => signed_data.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn cms_timestamp_request(
+    signature_ptr: *const u8,
+    signature_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+
+    match cms::timestamp_request(signature) {
+        Ok(request) => request.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn cms_timestamped(
+    digest_ptr: *const u8,
+    digest_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    response_ptr: *const u8,
+    response_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let digest = unsafe { slice::from_raw_parts(digest_ptr, digest_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+    let response = unsafe { slice::from_raw_parts(response_ptr, response_len) };
+
+    match cms::timestamped(digest, cert, signature, response) {
+        Ok(signed_data) => signed_data.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn pdf_prepare(
+    pdf_ptr: *const u8,
+    pdf_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pdf = unsafe { slice::from_raw_parts(pdf_ptr, pdf_len) };
+
+    match pdf::prepare(pdf) {
+        Ok(prepared) => prepared.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn pdf_signature_digest(
+    pdf_ptr: *const u8,
+    pdf_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pdf = unsafe { slice::from_raw_parts(pdf_ptr, pdf_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+
+    match pdf::signature_digest(pdf, cert) {
+        Ok(digest) => digest.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn pdf_embed(
+    pdf_ptr: *const u8,
+    pdf_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pdf = unsafe { slice::from_raw_parts(pdf_ptr, pdf_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+
+    match pdf::embed(pdf, cert, signature) {
+        Ok(signed) => signed.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "gg18")]
+#[no_mangle]
+pub unsafe extern "C" fn pdf_embed_timestamped(
+    pdf_ptr: *const u8,
+    pdf_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    signature_ptr: *const u8,
+    signature_len: usize,
+    response_ptr: *const u8,
+    response_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let pdf = unsafe { slice::from_raw_parts(pdf_ptr, pdf_len) };
+    let cert = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+    let signature = unsafe { slice::from_raw_parts(signature_ptr, signature_len) };
+    let response = unsafe { slice::from_raw_parts(response_ptr, response_len) };
+
+    match pdf::embed_timestamped(pdf, cert, signature, response) {
+        Ok(signed) => signed.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[repr(C)]
+pub struct AuthKey {
+    key: Buffer,
+    csr: Buffer,
+}
+
+impl AuthKey {
+    pub fn new(key: Vec<u8>, csr: Vec<u8>) -> Self {
+        Self {
+            key: key.into(),
+            csr: csr.into(),
+        }
+    }
+}
+
+#[no_mangle]
+#[allow(unused_variables)]
+pub unsafe extern "C" fn auth_key_free(key: AuthKey) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn auth_keygen(name: *const c_char, error_out: *mut *mut c_char) -> AuthKey {
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(name) => name,
+        Err(error) => {
+            set_error(error_out, &error);
+            return AuthKey::new(vec![], vec![]);
+        }
+    };
+    match auth::gen_key_with_csr(name) {
+        Ok((key, csr)) => AuthKey::new(key, csr),
+        Err(error) => {
+            set_error(error_out, &error);
+            AuthKey::new(vec![], vec![])
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn auth_cert_key_to_pkcs12(
+    key_ptr: *const u8,
+    key_len: usize,
+    cert_ptr: *const u8,
+    cert_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let key_der = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let cert_der = unsafe { slice::from_raw_parts(cert_ptr, cert_len) };
+
+    match auth::cert_key_to_pkcs12(key_der, cert_der) {
+        Ok(pkcs12) => pkcs12.into(),
+        Err(error) => {
+            set_error(error_out, &error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn encrypt(
+    msg_ptr: *const u8,
+    msg_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let msg = unsafe { slice::from_raw_parts(msg_ptr, msg_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match elgamal::encrypt(msg, key) {
+        Ok(ciphertext) => ciphertext.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Encrypts a message bound to the associated data, which the decryption
+/// must be requested with
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn encrypt_with_aad(
+    msg_ptr: *const u8,
+    msg_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    aad_ptr: *const u8,
+    aad_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let msg = unsafe { slice::from_raw_parts(msg_ptr, msg_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let aad = unsafe { slice::from_raw_parts(aad_ptr, aad_len) };
+
+    match elgamal::encrypt_with_aad(msg, key, aad) {
+        Ok(ciphertext) => ciphertext.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Re-randomizes the encrypted key of the ciphertext; the result remains
+/// linkable to the original, see `elgamal::rerandomize`
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn rerandomize(
+    ct_ptr: *const u8,
+    ct_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let ct = unsafe { slice::from_raw_parts(ct_ptr, ct_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match elgamal::rerandomize(ct, key) {
+        Ok(ciphertext) => ciphertext.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+/// Returns the 16-byte content key followed by the header
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn stream_encrypt(
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match stream::encrypt_stream(key) {
+        Ok((content_key, header)) => [content_key, header].concat().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn stream_encrypt_chunk(
+    key_ptr: *const u8,
+    key_len: usize,
+    index: u32,
+    chunk_ptr: *const u8,
+    chunk_len: usize,
+    last: bool,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let chunk = unsafe { slice::from_raw_parts(chunk_ptr, chunk_len) };
+
+    match stream::encrypt_chunk(key, index, chunk, last) {
+        Ok(encrypted) => encrypted.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "elgamal")]
+#[no_mangle]
+pub unsafe extern "C" fn stream_decrypt_chunk(
+    key_ptr: *const u8,
+    key_len: usize,
+    index: u32,
+    chunk_ptr: *const u8,
+    chunk_len: usize,
+    last: bool,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+    let chunk = unsafe { slice::from_raw_parts(chunk_ptr, chunk_len) };
+
+    match stream::decrypt_chunk(key, index, chunk, last) {
+        Ok(decrypted) => decrypted.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn hpke_encrypt(
+    msg_ptr: *const u8,
+    msg_len: usize,
+    key_ptr: *const u8,
+    key_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let msg = unsafe { slice::from_raw_parts(msg_ptr, msg_len) };
+    let key = unsafe { slice::from_raw_parts(key_ptr, key_len) };
+
+    match hpke::encrypt(msg, key) {
+        Ok(ciphertext) => ciphertext.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn age_recipient(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match age::recipient(group) {
+        Ok(recipient) => recipient.into_bytes().into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn age_ephemeral_key(
+    stanza: *const c_char,
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let stanza = unsafe { CStr::from_ptr(stanza) }.to_string_lossy();
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match age::ephemeral_key(&stanza, group) {
+        Ok(key) => key.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn age_unwrap(
+    stanza: *const c_char,
+    group_ptr: *const u8,
+    group_len: usize,
+    secret_ptr: *const u8,
+    secret_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let stanza = unsafe { CStr::from_ptr(stanza) }.to_string_lossy();
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+    let secret = unsafe { slice::from_raw_parts(secret_ptr, secret_len) };
+
+    match age::unwrap(&stanza, group, secret) {
+        Ok(file_key) => file_key.into(),
+        Err(error) => {
+            set_error(error_out, &*error);
+            vec![].into()
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+#[no_mangle]
+pub unsafe extern "C" fn hpke_encryption_key(
+    group_ptr: *const u8,
+    group_len: usize,
+    error_out: *mut *mut c_char,
+) -> Buffer {
+    let group = unsafe { slice::from_raw_parts(group_ptr, group_len) };
+
+    match hpke::encryption_key(group) {
+        Ok(key) => key.into(),
         Err(error) => {
             set_error(error_out, &*error);
             vec![].into()
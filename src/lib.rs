@@ -1,7 +1,46 @@
 mod auth;
 pub mod c_api;
 pub mod protocol;
+#[cfg(feature = "python")]
+mod python_api;
+#[cfg(feature = "uniffi")]
+pub mod uniffi_api;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/meesign.rs"));
+
+    impl std::convert::TryFrom<i32> for ProtocolType {
+        type Error = Box<dyn std::error::Error>;
+
+        fn try_from(value: i32) -> Result<Self, Self::Error> {
+            Self::from_i32(value).ok_or_else(|| "unknown protocol type".into())
+        }
+    }
+
+    impl ProtocolType {
+        /// Fail unless the `protocol_type` field of a message is this type
+        pub(crate) fn check(self, protocol_type: i32) -> Result<(), Box<dyn std::error::Error>> {
+            if protocol_type != self as i32 {
+                return Err("wrong protocol type".into());
+            }
+            Ok(())
+        }
+
+        /// Fail unless the `protocol_type` field of a message is one of
+        /// `types`, such as the protocols whose groups a key generation
+        /// creates, returning the type
+        pub(crate) fn check_any(
+            types: &[Self],
+            protocol_type: i32,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            types
+                .iter()
+                .copied()
+                .find(|t| *t as i32 == protocol_type)
+                .ok_or_else(|| "wrong protocol type".into())
+        }
+    }
 }
@@ -0,0 +1,342 @@
+//! FROST adaptor signatures
+//!
+//! A FROST signing session initialized with an adaptor point `T` produces a
+//! pre-signature instead of a signature. The signers commit to hiding and
+//! binding nonces `(D_i, E_i)` and derive the binding factors `p_i` from the
+//! group key, the message and all of the commitments as in RFC 9591. The
+//! group commitment `R = sum(D_i + p_i E_i)` is offset by `T`, and the shares
+//! `z_i = d_i + e_i p_i + l_i s_i c` of the challenge `c = H2(R + T, Y, m)`
+//! sum up to the pre-signature `(R + T, z)`.
+//!
+//! The pre-signature is completed into a regular FROST signature
+//! `(R + T, z + t)` by `adapt` only with the discrete logarithm `t` of `T`,
+//! which anyone holding both can in turn recover by `extract_adaptor`, as
+//! with the MuSig2 pre-signatures of the `musig2` module.
+
+use crate::protocol::blind::{
+    from_element, from_scalar, lagrange, random, to_element, to_scalar, Element, Scalar,
+};
+use crate::protocol::frost::FrostCiphersuite;
+use crate::protocol::*;
+
+use frost_core::keys::{KeyPackage, PublicKeyPackage};
+use frost_core::{Ciphersuite, Field, Group, Identifier};
+use frost_p256::P256Sha256;
+use frost_secp256k1::Secp256K1Sha256;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Hiding and binding nonce commitments of a signer
+fn split_commitment<C: Ciphersuite>(commitment: &[u8]) -> Result<(Element<C>, Element<C>)> {
+    if commitment.len() % 2 != 0 {
+        return Err("invalid commitment".into());
+    }
+    let (hiding, binding) = commitment.split_at(commitment.len() / 2);
+    Ok((to_element::<C>(hiding)?, to_element::<C>(binding)?))
+}
+
+/// Binding factors of the signers, the group commitment offset by the
+/// `adaptor` point and the challenge, given the commitments of the signers
+/// ordered as their `indices`
+fn session<C: Ciphersuite>(
+    key: &[u8],
+    message: &[u8],
+    indices: &[u16],
+    commitments: &[Vec<u8>],
+    adaptor: &[u8],
+) -> Result<(Vec<Scalar<C>>, Element<C>, Scalar<C>)> {
+    let mut sorted: Vec<(u16, &Vec<u8>)> = indices.iter().cloned().zip(commitments).collect();
+    sorted.sort_by_key(|(index, _)| *index);
+    let mut encoded = Vec::new();
+    for (index, commitment) in sorted {
+        encoded.extend_from_slice(Identifier::<C>::try_from(index)?.serialize().as_ref());
+        encoded.extend_from_slice(commitment);
+    }
+    let prefix = [key, C::H4(message).as_ref(), C::H5(&encoded).as_ref()].concat();
+
+    let mut nonce = to_element::<C>(adaptor)?;
+    let mut factors = Vec::new();
+    for (index, commitment) in indices.iter().zip(commitments) {
+        let (hiding, binding) = split_commitment::<C>(commitment)?;
+        let identifier = Identifier::<C>::try_from(*index)?;
+        let factor = C::H1(&[&prefix[..], identifier.serialize().as_ref()].concat());
+        nonce = nonce + hiding + binding * factor;
+        factors.push(factor);
+    }
+    if nonce == C::Group::identity() {
+        return Err("invalid nonce".into());
+    }
+    let challenge = C::H2(&[&from_element::<C>(&nonce)[..], key, message].concat());
+    Ok((factors, nonce, challenge))
+}
+
+/// Adaptor session of a signer, kept by its `FrostSignContext`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Signer {
+    adaptor: Vec<u8>,
+    /// Secret hiding and binding nonces, taken once the share is produced
+    nonces: Option<[Vec<u8>; 2]>,
+    commitment: Vec<u8>,
+    /// Commitments of all the signers and the own share, once produced
+    share: Option<(Vec<Vec<u8>>, Vec<u8>)>,
+    presignature: Option<Vec<u8>>,
+}
+
+impl Signer {
+    /// Commit to fresh nonces, returning the message for the other signers
+    pub(crate) fn commit<C: FrostCiphersuite>(adaptor: &[u8]) -> Result<(Self, Vec<u8>)> {
+        to_element::<C>(adaptor)?;
+        let nonces = [random::<C>(), random::<C>()];
+        let commitment = [
+            from_element::<C>(&(C::Group::generator() * nonces[0])),
+            from_element::<C>(&(C::Group::generator() * nonces[1])),
+        ]
+        .concat();
+        let signer = Self {
+            adaptor: adaptor.to_vec(),
+            nonces: Some([from_scalar::<C>(&nonces[0]), from_scalar::<C>(&nonces[1])]),
+            commitment: commitment.clone(),
+            share: None,
+            presignature: None,
+        };
+        Ok((signer, commitment))
+    }
+
+    /// Process the messages of the other signers, ordered by their indices,
+    /// returning the own share after the commitments, and nothing once the
+    /// pre-signature is aggregated
+    pub(crate) fn advance<C: FrostCiphersuite>(
+        &mut self,
+        key: &KeyPackage<C>,
+        pubkey: &PublicKeyPackage<C>,
+        indices: &[u16],
+        message: &[u8],
+        msgs: Vec<Vec<u8>>,
+    ) -> Result<Option<Vec<u8>>> {
+        let position = indices
+            .iter()
+            .position(|x| Identifier::try_from(*x).ok().as_ref() == Some(key.identifier()))
+            .ok_or("participant index not included")?;
+        check_message_count(&msgs, indices.len() - 1)?;
+        if self.presignature.is_some() {
+            return Err("protocol already finished".into());
+        }
+        let group_key = pubkey.group_public().serialize();
+
+        match self.share.clone() {
+            None => {
+                let nonces = self.nonces.take().ok_or("nonces already used")?;
+                let mut commitments = msgs;
+                commitments.insert(position, self.commitment.clone());
+                let (factors, nonce, challenge) = session::<C>(
+                    group_key.as_ref(),
+                    message,
+                    indices,
+                    &commitments,
+                    &self.adaptor,
+                )?;
+                nonces::consume(
+                    nonces::session_id(&self.commitment)?,
+                    &[from_element::<C>(&nonce), from_scalar::<C>(&challenge)],
+                )?;
+
+                let secret = to_scalar::<C>(key.secret_share().serialize().as_ref())?;
+                let share = to_scalar::<C>(&nonces[0])?
+                    + to_scalar::<C>(&nonces[1])? * factors[position]
+                    + lagrange::<C>(indices[position], indices)? * secret * challenge;
+                let share = from_scalar::<C>(&share);
+                self.share = Some((commitments, share.clone()));
+                Ok(Some(share))
+            }
+            Some((commitments, share)) => {
+                let mut shares = msgs;
+                shares.insert(position, share);
+                let (factors, nonce, challenge) = session::<C>(
+                    group_key.as_ref(),
+                    message,
+                    indices,
+                    &commitments,
+                    &self.adaptor,
+                )?;
+
+                let mut sum = <<C::Group as Group>::Field as Field>::zero();
+                for (i, index) in indices.iter().enumerate() {
+                    let blame = || format!("invalid signature share from party {}", index);
+                    let share = to_scalar::<C>(&shares[i]).map_err(|_| blame())?;
+                    let verifying = pubkey
+                        .signer_pubkeys()
+                        .get(&Identifier::try_from(*index)?)
+                        .ok_or_else(|| format!("unknown party {}", index))?;
+                    let verifying = to_element::<C>(verifying.serialize().as_ref())?;
+                    let (hiding, binding) = split_commitment::<C>(&commitments[i])?;
+                    let expected = hiding
+                        + binding * factors[i]
+                        + verifying * (lagrange::<C>(*index, indices)? * challenge);
+                    if C::Group::generator() * share != expected {
+                        return Err(blame().into());
+                    }
+                    sum = sum + share;
+                }
+                self.presignature =
+                    Some([from_element::<C>(&nonce), from_scalar::<C>(&sum)].concat());
+                Ok(None)
+            }
+        }
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.presignature.is_some()
+    }
+
+    pub(crate) fn presignature(&self) -> Option<&[u8]> {
+        self.presignature.as_deref()
+    }
+}
+
+/// Offset group commitment and the share sum of a pre-signature
+fn split_presignature<C: Ciphersuite>(presignature: &[u8]) -> Result<(Element<C>, Scalar<C>)> {
+    let length = from_element::<C>(&C::Group::generator()).len();
+    if presignature.len() <= length {
+        return Err("malformed pre-signature".into());
+    }
+    let (nonce, sum) = presignature.split_at(length);
+    Ok((to_element::<C>(nonce)?, to_scalar::<C>(sum)?))
+}
+
+fn verify_adaptor_with<C: Ciphersuite>(
+    pk: &[u8],
+    msg: &[u8],
+    presignature: &[u8],
+    adaptor: &[u8],
+) -> Result<()> {
+    let (nonce, sum) = split_presignature::<C>(presignature)?;
+    let key = to_element::<C>(pk)?;
+    let challenge = C::H2(&[&from_element::<C>(&nonce)[..], pk, msg].concat());
+    if C::Group::generator() * sum + to_element::<C>(adaptor)? != nonce + key * challenge {
+        return Err("invalid pre-signature".into());
+    }
+    Ok(())
+}
+
+fn adapt_with<C: Ciphersuite>(presignature: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    let (nonce, sum) = split_presignature::<C>(presignature)?;
+    let sum = sum + to_scalar::<C>(secret)?;
+    Ok([from_element::<C>(&nonce), from_scalar::<C>(&sum)].concat())
+}
+
+fn extract_adaptor_with<C: Ciphersuite>(presignature: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    let (nonce, sum) = split_presignature::<C>(presignature)?;
+    let (completed, s) = split_presignature::<C>(signature).map_err(|_| "malformed signature")?;
+    if completed != nonce {
+        return Err("signature does not complete the pre-signature".into());
+    }
+    Ok(from_scalar::<C>(&(s - sum)))
+}
+
+/// Verify that the pre-signature under the group key `pk` completes into
+/// a signature with the secret of the `adaptor` point
+pub fn verify_adaptor(pk: &[u8], msg: &[u8], presignature: &[u8], adaptor: &[u8]) -> Result<()> {
+    verify_adaptor_with::<Secp256K1Sha256>(pk, msg, presignature, adaptor)
+}
+
+/// Complete the pre-signature with the adaptor `secret`
+pub fn adapt(presignature: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    adapt_with::<Secp256K1Sha256>(presignature, secret)
+}
+
+/// Recover the adaptor secret from the pre-signature and its completion
+pub fn extract_adaptor(presignature: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    extract_adaptor_with::<Secp256K1Sha256>(presignature, signature)
+}
+
+/// Verify a pre-signature of the P-256 signing protocol
+pub fn verify_adaptor_p256(
+    pk: &[u8],
+    msg: &[u8],
+    presignature: &[u8],
+    adaptor: &[u8],
+) -> Result<()> {
+    verify_adaptor_with::<P256Sha256>(pk, msg, presignature, adaptor)
+}
+
+/// Complete a pre-signature of the P-256 signing protocol
+pub fn adapt_p256(presignature: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    adapt_with::<P256Sha256>(presignature, secret)
+}
+
+/// Recover the adaptor secret of a P-256 pre-signature
+pub fn extract_adaptor_p256(presignature: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    extract_adaptor_with::<P256Sha256>(presignature, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolInit, ProtocolType, SignatureResult};
+    use crate::protocol::frost::{KeygenContext, SignContext};
+    use crate::protocol::tests::{advance_all, run_rounds, KeygenProtocolTest};
+    use prost::Message;
+
+    fn init(index: u32, indices: &[u32], adaptor: &[u8]) -> Vec<u8> {
+        ProtocolInit {
+            protocol_type: ProtocolType::Frost as i32,
+            indices: indices.to_vec(),
+            index,
+            data: b"hello".to_vec(),
+            adaptor: adaptor.to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn adaptor() {
+        type C = Secp256K1Sha256;
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let secret = random::<C>();
+        let point = from_element::<C>(&(<C as Ciphersuite>::Group::generator() * secret));
+        let secret = from_scalar::<C>(&secret);
+
+        let indices = [1, 3];
+        let mut ctxs: Vec<SignContext> = indices
+            .iter()
+            .map(|i| SignContext::new(&groups[*i as usize - 1]).unwrap())
+            .collect();
+        let inits = indices.iter().map(|i| init(*i, &indices, &point)).collect();
+        let messages = advance_all(&mut ctxs, inits);
+        run_rounds(&mut ctxs, messages, 2);
+        let results: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| Box::new(ctx).finish().unwrap())
+            .collect();
+        assert_eq!(results[0], results[1]);
+        let presignature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+
+        assert!(verify_adaptor(&pk, b"hello", &presignature, &point).is_ok());
+        assert!(verify_adaptor(&pk, b"other", &presignature, &point).is_err());
+        assert!(frost::verify(&pk, b"hello", &presignature).is_err());
+
+        let signature = adapt(&presignature, &secret).unwrap();
+        assert!(frost::verify(&pk, b"hello", &signature).is_ok());
+        assert_eq!(extract_adaptor(&presignature, &signature).unwrap(), secret);
+
+        let one = from_scalar::<C>(&<<<C as Ciphersuite>::Group as Group>::Field as Field>::one());
+        let wrong = adapt(&presignature, &one).unwrap();
+        assert!(frost::verify(&pk, b"hello", &wrong).is_err());
+    }
+
+    #[test]
+    fn single_party() {
+        type C = Secp256K1Sha256;
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let pk = GroupResult::decode(&ctx[..]).unwrap().public_key;
+        let point = from_element::<C>(&<C as Ciphersuite>::Group::generator());
+
+        let mut ctx = SignContext::new(&ctx).unwrap();
+        ctx.advance(&init(1, &[1], &point)).unwrap();
+        let result = Box::new(ctx).finish().unwrap();
+        let presignature = SignatureResult::decode(&result[..]).unwrap().signature;
+        assert!(verify_adaptor(&pk, b"hello", &presignature, &point).is_ok());
+    }
+}
@@ -0,0 +1,224 @@
+//! Files in the `age` format encrypted to the key of a FROST P-256 group
+//!
+//! The file key is wrapped in the `piv-p256` stanza of `age-plugin-yubikey`,
+//! so files are encrypted to a group by the standard `age` tooling with
+//! the plugin, given the `recipient` of the group. The stanza carries the
+//! file key encrypted by a key derived from the Diffie-Hellman secret of an
+//! ephemeral key and the decryption key of the group, see the `ecdh` module.
+//!
+//! The group unwraps the file key by an `Ecdh` session initialized with
+//! the `ephemeral_key` of the stanza as its data, whose result is passed
+//! to `unwrap` together with the stanza. The group never reconstructs its
+//! private key, and the server relaying the session learns nothing of the
+//! file key.
+
+use crate::protocol::ecdh::decryption_key;
+use crate::protocol::Result;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac::{Hmac, Mac};
+use openssl::base64;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+const STANZA_TAG: &str = "piv-p256";
+const RECIPIENT_HRP: &str = "age1yubikey";
+const FILE_KEY_LEN: usize = 16;
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// Encode `data` in bech32, as `age` encodes the recipients
+fn bech32(hrp: &str, data: &[u8]) -> String {
+    let mut values = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for byte in data {
+        acc = ((acc << 8) | *byte as u32) & 0xfff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            values.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        values.push(((acc << (5 - bits)) & 31) as u8);
+    }
+
+    let mut checked: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    checked.push(0);
+    checked.extend(hrp.bytes().map(|c| c & 31));
+    checked.extend(&values);
+    checked.extend([0u8; 6]);
+    let checksum = polymod(&checked) ^ 1;
+    values.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8));
+
+    let encoded: String = values
+        .iter()
+        .map(|value| BECH32_CHARSET[*value as usize] as char)
+        .collect();
+    format!("{}1{}", hrp, encoded)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    base64::encode_block(data).trim_end_matches('=').to_string()
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let padding = "=".repeat((4 - text.len() % 4) % 4);
+    base64::decode_block(&format!("{}{}", text, padding)).map_err(|_| "malformed stanza".into())
+}
+
+/// Decryption key of the group in the compressed SEC1 encoding
+fn group_key(group: &[u8]) -> Result<Vec<u8>> {
+    let key =
+        PublicKey::from_sec1_bytes(&decryption_key(group)?).map_err(|_| "invalid public key")?;
+    Ok(key.to_encoded_point(true).as_bytes().to_vec())
+}
+
+/// Key encrypting the file key, derived from the Diffie-Hellman secret
+/// of the ephemeral key `epk` and the group key `pk`, both compressed
+fn wrap_key(shared_secret: &[u8], epk: &[u8], pk: &[u8]) -> Vec<u8> {
+    let salt = [epk, pk].concat();
+    let prk = <Hmac<Sha256> as Mac>::new_from_slice(&salt)
+        .unwrap()
+        .chain_update(shared_secret)
+        .finalize()
+        .into_bytes();
+    <Hmac<Sha256> as Mac>::new_from_slice(&prk)
+        .unwrap()
+        .chain_update(STANZA_TAG.as_bytes())
+        .chain_update([1u8])
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+fn cipher(key: &[u8]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+/// Parse a stanza into its ephemeral key and its body
+fn parse(stanza: &str, pk: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut lines = stanza.lines();
+    let args: Vec<&str> = lines.next().ok_or("malformed stanza")?.split(' ').collect();
+    let (tag, epk) = match args[..] {
+        ["->", STANZA_TAG, tag, epk] => (decode_base64(tag)?, decode_base64(epk)?),
+        _ => return Err("not a piv-p256 stanza".into()),
+    };
+    if tag[..] != Sha256::digest(pk)[..4] {
+        return Err("stanza not for the group".into());
+    }
+    let body = decode_base64(lines.next().ok_or("malformed stanza")?)?;
+    Ok((epk, body))
+}
+
+/// Recipient of the group to encrypt the files to with `age`
+pub fn recipient(group: &[u8]) -> Result<String> {
+    Ok(bech32(RECIPIENT_HRP, &group_key(group)?))
+}
+
+/// Wrap a file key in a stanza for the group, as `age` does
+/// for the recipient of the group
+pub fn wrap(file_key: &[u8], group: &[u8]) -> Result<String> {
+    if file_key.len() != FILE_KEY_LEN {
+        return Err("invalid file key".into());
+    }
+    let pk = group_key(group)?;
+    let ephemeral = SecretKey::random(&mut OsRng);
+    let epk = ephemeral.public_key().to_encoded_point(true);
+    let point = PublicKey::from_sec1_bytes(&pk).map_err(|_| "invalid public key")?;
+    let dh = (point.to_projective() * *ephemeral.to_nonzero_scalar())
+        .to_affine()
+        .to_encoded_point(false);
+    let dh = dh.x().ok_or("invalid public key")?;
+
+    let key = wrap_key(dh, epk.as_bytes(), &pk);
+    let body = cipher(&key)
+        .encrypt(Nonce::from_slice(&[0; 12]), file_key)
+        .map_err(|_| "encryption failed")?;
+    Ok(format!(
+        "-> {} {} {}\n{}\n",
+        STANZA_TAG,
+        encode_base64(&Sha256::digest(&pk)[..4]),
+        encode_base64(epk.as_bytes()),
+        encode_base64(&body)
+    ))
+}
+
+/// Ephemeral key of a stanza for the group, the data of the `Ecdh`
+/// session which computes the shared secret for `unwrap`
+pub fn ephemeral_key(stanza: &str, group: &[u8]) -> Result<Vec<u8>> {
+    Ok(parse(stanza, &group_key(group)?)?.0)
+}
+
+/// Unwrap the file key of a stanza for the group, given the result
+/// of the `Ecdh` session with its `ephemeral_key`
+pub fn unwrap(stanza: &str, group: &[u8], shared_secret: &[u8]) -> Result<Vec<u8>> {
+    let pk = group_key(group)?;
+    let (epk, body) = parse(stanza, &pk)?;
+    let key = wrap_key(shared_secret, &epk, &pk);
+    let file_key = cipher(&key)
+        .decrypt(Nonce::from_slice(&[0; 12]), &body[..])
+        .map_err(|_| "decryption failed")?;
+    if file_key.len() != FILE_KEY_LEN {
+        return Err("invalid file key".into());
+    }
+    Ok(file_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ecdh::EcdhContext;
+    use crate::protocol::frost::FrostKeygenContext;
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+    use frost_p256::P256Sha256;
+
+    #[test]
+    fn recipient_encoding() {
+        // compressed generator of P-256, the public key of the scalar 1
+        let generator: Vec<u8> = (0..66)
+            .step_by(2)
+            .map(|i| {
+                let hex = "036b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+                u8::from_str_radix(&hex[i..i + 2], 16).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            bech32(RECIPIENT_HRP, &generator),
+            "age1yubikey1qd43050juykyy3lchnnw2caygre8wqmasyk7kvaq7jsnj3wcnrpfvpkgk82"
+        );
+    }
+
+    #[test]
+    fn threshold_unwrap() {
+        let (_, ctxs) = <FrostKeygenContext<P256Sha256> as KeygenProtocolTest>::run(2, 3);
+        assert!(recipient(&ctxs[0]).unwrap().starts_with("age1yubikey1"));
+
+        let file_key = [7; FILE_KEY_LEN];
+        let stanza = wrap(&file_key, &ctxs[0]).unwrap();
+        let epk = ephemeral_key(&stanza, &ctxs[1]).unwrap();
+        let results = <EcdhContext as ThresholdProtocolTest>::run(ctxs.clone(), vec![1, 2], epk);
+        for result in results {
+            assert_eq!(unwrap(&stanza, &ctxs[0], &result).unwrap(), file_key);
+        }
+        assert!(unwrap(&stanza, &ctxs[0], &[0; 32]).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Backup of a group context split among recovery contacts
+//!
+//! The context is split byte-wise by Shamir's secret sharing over GF(2^8),
+//! so that any `threshold` of the sub-shares restore it while fewer reveal
+//! nothing about it. A SHA-256 digest of the context is shared along with
+//! it to detect corrupted or mismatched sub-shares on recovery.
+//!
+//! A sub-share consists of the threshold, the index of the sub-share,
+//! and the shared bytes.
+//!
+//! The field arithmetic runs on the bytes of the context, so it takes
+//! the same time for every value and never branches on them.
+
+#[cfg(feature = "elgamal")]
+use crate::protocol::elgamal;
+use crate::protocol::{mnemonic, Result};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+const DIGEST_SIZE: usize = 32;
+
+pub(crate) fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        // all ones if the bit is set, all zeros otherwise
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+pub(crate) fn inv(a: u8) -> u8 {
+    // the multiplicative group has order 255, so a^254 is the inverse
+    let (mut result, mut base, mut exp) = (1, a, 254);
+    while exp > 0 {
+        if exp & 1 != 0 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Split `group` into `parties` sub-shares, any `threshold` of which
+/// restore it using `combine`
+pub fn split(group: &[u8], threshold: u8, parties: u8) -> Result<Vec<Vec<u8>>> {
+    if threshold == 0 || threshold > parties {
+        return Err("invalid backup parameters".into());
+    }
+
+    let mut secret = Sha256::digest(group).to_vec();
+    secret.extend_from_slice(group);
+
+    let degree = threshold as usize - 1;
+    let mut coefficients = vec![0u8; secret.len() * degree];
+    OsRng.fill_bytes(&mut coefficients);
+
+    let shares = (1..=parties)
+        .map(|x| {
+            let mut share = vec![threshold, x];
+            share.extend(secret.iter().enumerate().map(|(i, byte)| {
+                coefficients[i * degree..(i + 1) * degree]
+                    .iter()
+                    .rev()
+                    .chain(std::iter::once(byte))
+                    .fold(0, |acc, c| mul(acc, x) ^ c)
+            }));
+            share
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Restore a group context from at least `threshold` sub-shares
+/// produced by `split`
+pub fn combine(shares: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let first = shares.first().ok_or("not enough backup shares")?;
+    if first.len() < 2 + DIGEST_SIZE {
+        return Err("malformed backup share".into());
+    }
+    let threshold = first[0] as usize;
+    if shares.len() < threshold {
+        return Err("not enough backup shares".into());
+    }
+
+    let shares = &shares[..threshold];
+    let xs: Vec<u8> = shares.iter().map(|share| share[1]).collect();
+    for (i, share) in shares.iter().enumerate() {
+        if share.len() != first.len() || share[0] != first[0] || share[1] == 0 {
+            return Err("malformed backup share".into());
+        }
+        if xs[..i].contains(&share[1]) {
+            return Err("duplicate backup share".into());
+        }
+    }
+
+    // Lagrange coefficients for interpolation at zero
+    let coefficients: Vec<u8> = xs
+        .iter()
+        .map(|xi| {
+            let (numerator, denominator) = xs
+                .iter()
+                .filter(|xj| *xj != xi)
+                .fold((1, 1), |(num, den), xj| (mul(num, *xj), mul(den, xj ^ xi)));
+            mul(numerator, inv(denominator))
+        })
+        .collect();
+
+    let secret: Vec<u8> = (2..first.len())
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&coefficients)
+                .fold(0, |acc, (share, c)| acc ^ mul(share[i], *c))
+        })
+        .collect();
+
+    let (digest, group) = secret.split_at(DIGEST_SIZE);
+    if !bool::from(Sha256::digest(group).as_slice().ct_eq(digest)) {
+        return Err("backup shares do not match".into());
+    }
+    Ok(group.to_vec())
+}
+
+/// Split `group` among recovery contacts, encrypting each sub-share
+/// to the ElGamal public key of the respective contact; on recovery,
+/// the contacts decrypt their sub-shares to be passed to `combine`
+#[cfg(feature = "elgamal")]
+pub fn export(group: &[u8], threshold: u8, recipients: &[Vec<u8>]) -> Result<Vec<Vec<u8>>> {
+    if recipients.len() > u8::MAX as usize {
+        return Err("invalid backup parameters".into());
+    }
+    split(group, threshold, recipients.len() as u8)?
+        .iter()
+        .zip(recipients)
+        .map(|(share, recipient)| elgamal::encrypt(share, recipient))
+        .collect()
+}
+
+/// Encode a sub-share as a mnemonic for a paper backup; the sub-share
+/// is padded by a 0x80 byte and zeros to a multiple of 4 bytes
+pub fn to_mnemonic(share: &[u8]) -> Result<String> {
+    let mut padded = share.to_vec();
+    padded.push(0x80);
+    padded.resize((padded.len() + 3) / 4 * 4, 0);
+    mnemonic::encode(&padded)
+}
+
+/// Decode a sub-share from a mnemonic produced by `to_mnemonic`
+pub fn from_mnemonic(words: &str) -> Result<Vec<u8>> {
+    let mut share = mnemonic::decode(words)?;
+    while share.last() == Some(&0) {
+        share.pop();
+    }
+    if share.pop() != Some(0x80) {
+        return Err("malformed backup share".into());
+    }
+    Ok(share)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field() {
+        for a in 1..=255 {
+            assert_eq!(mul(a, inv(a)), 1);
+        }
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+    }
+
+    /// Welch's t-test between the timings of the multiplication by zero
+    /// and by random bytes, as in dudect; ignored as it depends on the load
+    /// and the optimizations, the CI runs it in release mode in a job of its
+    /// own which does not fail the workflow
+    #[test]
+    #[ignore]
+    fn constant_time() {
+        use std::hint::black_box;
+        use std::time::Instant;
+
+        // count, mean and sum of squared deviations of each class
+        let mut stats = [(0f64, 0f64, 0f64); 2];
+        for _ in 0..100_000 {
+            let class = (OsRng.next_u32() & 1) as usize;
+            let b = if class == 0 {
+                0
+            } else {
+                OsRng.next_u32() as u8
+            };
+            let a = OsRng.next_u32() as u8;
+            let start = Instant::now();
+            for _ in 0..100 {
+                black_box(mul(black_box(a), black_box(b)));
+            }
+            let time = start.elapsed().as_nanos() as f64;
+
+            let (count, mean, squares) = &mut stats[class];
+            *count += 1.0;
+            let delta = time - *mean;
+            *mean += delta / *count;
+            *squares += delta * (time - *mean);
+        }
+
+        let variance = |(count, _, squares): (f64, f64, f64)| squares / (count - 1.0) / count;
+        let t = (stats[0].1 - stats[1].1) / (variance(stats[0]) + variance(stats[1])).sqrt();
+        assert!(t.abs() < 10.0, "timing leaks the operand, t = {}", t);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let group = b"group context".to_vec();
+        for threshold in 1..5 {
+            for parties in threshold..5 {
+                let shares = split(&group, threshold, parties).unwrap();
+                for start in 0..=(parties - threshold) as usize {
+                    let subset = &shares[start..start + threshold as usize];
+                    assert_eq!(combine(subset).unwrap(), group);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn insufficient() {
+        let shares = split(b"group context", 3, 5).unwrap();
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+
+        let mut corrupted = shares.clone();
+        corrupted[0][10] ^= 1;
+        assert!(combine(&corrupted).is_err());
+    }
+
+    #[test]
+    fn paper_backup() {
+        let shares = split(b"group context", 2, 3).unwrap();
+        let words: Vec<String> = shares.iter().map(|s| to_mnemonic(s).unwrap()).collect();
+        let restored: Vec<Vec<u8>> = words.iter().map(|w| from_mnemonic(w).unwrap()).collect();
+        assert_eq!(restored, shares);
+        assert_eq!(combine(&restored[1..]).unwrap(), b"group context");
+    }
+}
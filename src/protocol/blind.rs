@@ -0,0 +1,431 @@
+//! Blind FROST signing
+//!
+//! In a signing session initialized with the `blind` flag the signers never
+//! see the message. Each signer sends a nonce commitment `D_i` to the
+//! requester, which computes `R' = R + aG + bY` of the group commitment `R`
+//! and the group key `Y` for its secret blinding factors `a` and `b`, and
+//! sends the blinded challenge `c = H2(R', Y, m) + b` back to the signers.
+//! The responses `z_i = k_i + l_i s_i c` are then verified and combined by
+//! the requester into `(R', z + a)`, a regular FROST signature of `m`.
+//!
+//! The signers only take part if they opt in by `ContextOptions.blind` when
+//! they create their contexts, as the flag is set by the server and blind
+//! Schnorr signatures are only secure when the signers do not run many
+//! sessions concurrently (Benhamouda et al., "On the (in)security of ROS").
+//! Neither the message nor the signature is known to the signers, so their
+//! `SignatureResult` carries an empty signature. The messages exchanged
+//! between a signer and the requester are packed one at a time.
+
+use crate::proto::ProtocolType;
+use crate::protocol::frost::FrostCiphersuite;
+use crate::protocol::*;
+
+use frost_core::keys::{KeyPackage, PublicKeyPackage};
+use frost_core::{Ciphersuite, Field, Group, Identifier};
+use frost_secp256k1::Secp256K1Sha256;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+
+pub(crate) type Scalar<C> = <<<C as Ciphersuite>::Group as Group>::Field as Field>::Scalar;
+pub(crate) type Element<C> = <<C as Ciphersuite>::Group as Group>::Element;
+
+pub(crate) fn to_scalar<C: Ciphersuite>(bytes: &[u8]) -> Result<Scalar<C>> {
+    let bytes = bytes.to_vec().try_into().map_err(|_| "invalid scalar")?;
+    <<C::Group as Group>::Field as Field>::deserialize(&bytes).map_err(|_| "invalid scalar".into())
+}
+
+pub(crate) fn from_scalar<C: Ciphersuite>(scalar: &Scalar<C>) -> Vec<u8> {
+    <<C::Group as Group>::Field as Field>::serialize(scalar)
+        .as_ref()
+        .to_vec()
+}
+
+pub(crate) fn to_element<C: Ciphersuite>(bytes: &[u8]) -> Result<Element<C>> {
+    let bytes = bytes.to_vec().try_into().map_err(|_| "invalid point")?;
+    C::Group::deserialize(&bytes).map_err(|_| "invalid point".into())
+}
+
+pub(crate) fn from_element<C: Ciphersuite>(element: &Element<C>) -> Vec<u8> {
+    C::Group::serialize(element).as_ref().to_vec()
+}
+
+pub(crate) fn random<C: Ciphersuite>() -> Scalar<C> {
+    <<C::Group as Group>::Field as Field>::random(&mut ProtocolRng)
+}
+
+/// Lagrange coefficient of `index` for interpolation at zero over `indices`
+pub(crate) fn lagrange<C: Ciphersuite>(index: u16, indices: &[u16]) -> Result<Scalar<C>> {
+    let to_x = |i: u16| to_scalar::<C>(Identifier::<C>::try_from(i)?.serialize().as_ref());
+    let x = to_x(index)?;
+    let mut numerator = <<C::Group as Group>::Field as Field>::one();
+    let mut denominator = <<C::Group as Group>::Field as Field>::one();
+    for other in indices.iter().filter(|other| **other != index) {
+        let other = to_x(*other)?;
+        numerator = numerator * other;
+        denominator = denominator * (other - x);
+    }
+    let inverse = <<C::Group as Group>::Field as Field>::invert(&denominator)
+        .map_err(|_| "duplicate index")?;
+    Ok(numerator * inverse)
+}
+
+/// The helpers above for the shares of `frost_secp256k1`, for the protocols
+/// which compute with its points and scalars directly
+pub(crate) mod secp256k1 {
+    use crate::protocol::Result;
+
+    use frost_secp256k1::keys::{SigningShare, VerifyingShare};
+    use k256::elliptic_curve::{sec1::ToEncodedPoint, Field, PrimeField};
+    use k256::{ProjectivePoint, Scalar};
+    use std::convert::TryFrom;
+
+    pub(crate) fn to_scalar(share: &SigningShare) -> Result<Scalar> {
+        Option::from(Scalar::from_repr(share.serialize().into()))
+            .ok_or_else(|| "invalid share".into())
+    }
+
+    pub(crate) fn from_scalar(scalar: Scalar) -> Result<SigningShare> {
+        Ok(SigningShare::deserialize(scalar.to_bytes().into())?)
+    }
+
+    pub(crate) fn to_point(share: &VerifyingShare) -> Result<ProjectivePoint> {
+        let key = k256::PublicKey::from_sec1_bytes(&share.serialize())
+            .map_err(|_| "invalid verifying share")?;
+        Ok(key.to_projective())
+    }
+
+    pub(crate) fn from_point(point: ProjectivePoint) -> Result<VerifyingShare> {
+        // the identity encodes to a single byte
+        let bytes = <[u8; 33]>::try_from(point.to_affine().to_encoded_point(true).as_bytes())
+            .map_err(|_| "invalid verifying share")?;
+        Ok(VerifyingShare::deserialize(bytes)?)
+    }
+
+    /// Lagrange coefficient of `x` for interpolation at zero over `xs`
+    pub(crate) fn lagrange(x: u16, xs: &[u16]) -> Result<Scalar> {
+        let xs: Vec<Scalar> = xs.iter().map(|x| Scalar::from(*x as u64)).collect();
+        lagrange_at(Scalar::ZERO, Scalar::from(x as u64), &xs)
+    }
+
+    /// Lagrange coefficient of `x` for interpolation at `at` over `xs`
+    pub(crate) fn lagrange_at(at: Scalar, x: Scalar, xs: &[Scalar]) -> Result<Scalar> {
+        let mut numerator = Scalar::ONE;
+        let mut denominator = Scalar::ONE;
+        for other in xs.iter().filter(|other| **other != x) {
+            numerator *= at - other;
+            denominator *= x - other;
+        }
+        Option::<Scalar>::from(denominator.invert())
+            .map(|inverse| numerator * inverse)
+            .ok_or_else(|| "duplicate index".into())
+    }
+}
+
+fn pack_one(msg: Vec<u8>, protocol_type: ProtocolType) -> Vec<u8> {
+    pack(vec![msg.into()], protocol_type)
+}
+
+fn unpack_one(data: &[u8]) -> Result<Vec<u8>> {
+    let msgs = unpack(data)?;
+    check_message_count(&msgs, 1)?;
+    Ok(msgs[0].to_vec())
+}
+
+/// Blind session of a signer, kept by its `FrostSignContext`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Signer {
+    /// Secret nonce, taken once the response is produced
+    nonce: Option<Vec<u8>>,
+    commitment: Vec<u8>,
+}
+
+impl Signer {
+    /// Commit to a fresh nonce, returning the message for the requester
+    pub(crate) fn commit<C: FrostCiphersuite>() -> (Self, Vec<u8>) {
+        let nonce = random::<C>();
+        let commitment = from_element::<C>(&(C::Group::generator() * nonce));
+        let signer = Self {
+            nonce: Some(from_scalar::<C>(&nonce)),
+            commitment: commitment.clone(),
+        };
+        (signer, pack_one(commitment, C::PROTOCOL_TYPE))
+    }
+
+    /// Answer the blinded challenge of the requester
+    pub(crate) fn respond<C: FrostCiphersuite>(
+        &mut self,
+        key: &KeyPackage<C>,
+        index: u16,
+        indices: &[u16],
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let nonce = self.nonce.take().ok_or("protocol already finished")?;
+        let challenge = unpack_one(data)?;
+        nonces::consume(nonces::session_id(&self.commitment)?, &challenge)?;
+        let challenge = to_scalar::<C>(&challenge)?;
+
+        let secret = to_scalar::<C>(key.secret_share().serialize().as_ref())?;
+        let response =
+            to_scalar::<C>(&nonce)? + lagrange::<C>(index, indices)? * secret * challenge;
+        Ok(pack_one(from_scalar::<C>(&response), C::PROTOCOL_TYPE))
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.nonce.is_none()
+    }
+}
+
+/// Requester of a blind signature, which alone knows the message,
+/// from the signers of a group of the ciphersuite `C`
+pub struct Requester<C: FrostCiphersuite = Secp256K1Sha256> {
+    pubkey: PublicKeyPackage<C>,
+    indices: Vec<u16>,
+    message: Vec<u8>,
+    /// Commitments of the signers, the blinding factor `a`, the blinded
+    /// nonce and the challenge, once the challenge is computed
+    session: Option<(Vec<Vec<u8>>, Vec<u8>, Vec<u8>, Vec<u8>)>,
+}
+
+impl<C: FrostCiphersuite> Requester<C> {
+    /// Request a signature of `message` by the signers with `indices` of the
+    /// group with the public key package exported by
+    /// `coordinator::public_package`
+    pub fn new(pubkey: &[u8], indices: Vec<u16>, message: Vec<u8>) -> Result<Self> {
+        if indices.is_empty() {
+            return Err("no signers".into());
+        }
+        Ok(Self {
+            pubkey: serde_json::from_slice(pubkey)?,
+            indices,
+            message,
+            session: None,
+        })
+    }
+
+    fn group_key(&self) -> Result<Element<C>> {
+        to_element::<C>(self.pubkey.group_public().serialize().as_ref())
+    }
+
+    /// Blind the challenge for the commitments of the signers, ordered by
+    /// their indices, returning the message for all of the signers
+    pub fn challenge(&mut self, commitments: &[Vec<u8>]) -> Result<Vec<u8>> {
+        if self.session.is_some() {
+            return Err("challenge already computed".into());
+        }
+        check_message_count(commitments, self.indices.len())?;
+
+        let commitments = commitments
+            .iter()
+            .map(|data| unpack_one(data))
+            .collect::<Result<Vec<_>>>()?;
+        let mut nonce = <C as Ciphersuite>::Group::identity();
+        for commitment in &commitments {
+            nonce = nonce + to_element::<C>(commitment)?;
+        }
+
+        let key = self.group_key()?;
+        let (a, b) = (random::<C>(), random::<C>());
+        let blinded = nonce + <C as Ciphersuite>::Group::generator() * a + key * b;
+        let preimage = [
+            from_element::<C>(&blinded),
+            from_element::<C>(&key),
+            self.message.clone(),
+        ]
+        .concat();
+        let challenge = C::H2(&preimage) + b;
+
+        let challenge = from_scalar::<C>(&challenge);
+        self.session = Some((
+            commitments,
+            from_scalar::<C>(&a),
+            from_element::<C>(&blinded),
+            challenge.clone(),
+        ));
+        Ok(pack_one(challenge, C::PROTOCOL_TYPE))
+    }
+
+    /// Verify the responses of the signers, ordered by their indices,
+    /// and unblind them into the serialized signature of the message
+    pub fn unblind(&self, responses: &[Vec<u8>]) -> Result<Vec<u8>> {
+        let (commitments, a, blinded, challenge) =
+            self.session.as_ref().ok_or("challenge not computed")?;
+        check_message_count(responses, self.indices.len())?;
+        let challenge = to_scalar::<C>(challenge)?;
+
+        let mut sum = to_scalar::<C>(a)?;
+        for ((index, commitment), data) in self.indices.iter().zip(commitments).zip(responses) {
+            let response = to_scalar::<C>(&unpack_one(data)?)?;
+            let share = self
+                .pubkey
+                .signer_pubkeys()
+                .get(&Identifier::try_from(*index)?)
+                .ok_or_else(|| format!("unknown party {}", index))?;
+            let share = to_element::<C>(share.serialize().as_ref())?;
+            let expected = to_element::<C>(commitment)?
+                + share * (lagrange::<C>(*index, &self.indices)? * challenge);
+            if <C as Ciphersuite>::Group::generator() * response != expected {
+                return Err(format!("invalid response of party {}", index).into());
+            }
+            sum = sum + response;
+        }
+
+        let signature = [blinded.clone(), from_scalar::<C>(&sum)].concat();
+        let pk = self.pubkey.group_public().serialize();
+        frost::verify_with::<C>(pk.as_ref(), &self.message, &signature)?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolInit, SignatureResult};
+    use crate::protocol::frost::{
+        FrostKeygenContext, FrostSignContext, KeygenContext, SignContext,
+    };
+    use crate::protocol::tests::KeygenProtocolTest;
+    use frost_p256::P256Sha256;
+    use prost::Message;
+
+    fn init(index: u32, indices: &[u32]) -> Vec<u8> {
+        ProtocolInit {
+            protocol_type: ProtocolType::Frost as i32,
+            indices: indices.to_vec(),
+            index,
+            blind: true,
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn signer(group: &[u8]) -> SignContext {
+        let mut ctx = SignContext::new(group).unwrap();
+        ctx.configure(&ContextOptions {
+            blind: true,
+            ..Default::default()
+        });
+        ctx
+    }
+
+    #[test]
+    fn blind() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let pubkey = roast::public_package(&groups[0]).unwrap();
+
+        let indices = [1, 3];
+        let mut ctxs: Vec<SignContext> = indices
+            .iter()
+            .map(|i| signer(&groups[*i as usize - 1]))
+            .collect();
+        let commitments: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .zip(indices)
+            .map(|(ctx, i)| ctx.advance(&init(i, &indices)).unwrap())
+            .collect();
+
+        let mut requester =
+            Requester::<Secp256K1Sha256>::new(&pubkey, vec![1, 3], b"hello".to_vec()).unwrap();
+        let challenge = requester.challenge(&commitments).unwrap();
+        let mut responses: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .map(|ctx| ctx.advance(&challenge).unwrap())
+            .collect();
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
+
+        let signature = requester.unblind(&responses).unwrap();
+        assert!(frost::verify(&pk, b"hello", &signature).is_ok());
+
+        for ctx in ctxs {
+            let result = Box::new(ctx).finish().unwrap();
+            assert!(SignatureResult::decode(&result[..])
+                .unwrap()
+                .signature
+                .is_empty());
+        }
+
+        responses.swap(0, 1);
+        assert!(requester.unblind(&responses).is_err());
+    }
+
+    #[test]
+    fn single_use() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pubkey = roast::public_package(&groups[0]).unwrap();
+
+        let mut ctx = signer(&groups[0]);
+        let commitment = ctx.advance(&init(1, &[1, 2])).unwrap();
+        let copy: SignContext =
+            serde_json::from_value(serde_json::to_value(&ctx).unwrap()).unwrap();
+
+        let mut requester =
+            Requester::<Secp256K1Sha256>::new(&pubkey, vec![1, 2], b"hello".to_vec()).unwrap();
+        let challenge = requester
+            .challenge(&[commitment.clone(), commitment])
+            .unwrap();
+        assert!(ctx.advance(&challenge).is_ok());
+        assert!(ctx.advance(&challenge).is_err());
+
+        let mut copy = copy;
+        assert!(copy.advance(&challenge).is_err());
+    }
+
+    #[test]
+    fn opt_in() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        let mut ctx = SignContext::new(&groups[0]).unwrap();
+        let error = ctx.advance(&init(1, &[1, 2])).unwrap_err();
+        assert_eq!(error.to_string(), "blind sessions not allowed");
+
+        let options = ContextOptions {
+            blind: true,
+            ..Default::default()
+        };
+        let mut ctx = threshold_context_with(ProtocolType::Frost, &groups[0], &options).unwrap();
+        assert!(ctx.advance(&init(1, &[1, 2])).is_ok());
+    }
+
+    #[test]
+    fn blind_p256() {
+        type C = P256Sha256;
+        let (_, groups) = <FrostKeygenContext<C> as KeygenProtocolTest>::run(2, 2);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let pubkey = coordinator::public_package(ProtocolType::FrostP256, &groups[0]).unwrap();
+
+        let mut ctxs: Vec<FrostSignContext<C>> = groups
+            .iter()
+            .map(|group| {
+                let mut ctx = FrostSignContext::<C>::new(group).unwrap();
+                ctx.configure(&ContextOptions {
+                    blind: true,
+                    ..Default::default()
+                });
+                ctx
+            })
+            .collect();
+        let init = |index| {
+            ProtocolInit {
+                protocol_type: ProtocolType::FrostP256 as i32,
+                indices: vec![1, 2],
+                index,
+                blind: true,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        let commitments: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .zip(1..)
+            .map(|(ctx, i)| ctx.advance(&init(i)).unwrap())
+            .collect();
+
+        let mut requester = Requester::<C>::new(&pubkey, vec![1, 2], b"hello".to_vec()).unwrap();
+        let challenge = requester.challenge(&commitments).unwrap();
+        let responses: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .map(|ctx| ctx.advance(&challenge).unwrap())
+            .collect();
+        let signature = requester.unblind(&responses).unwrap();
+        assert!(frost::verify_p256(&pk, b"hello", &signature).is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+//! Integrity-protected snapshots of protocol contexts
+//!
+//! A snapshot consists of an HMAC-SHA256 tag followed by the serialized
+//! context, so that a tampered or truncated snapshot fails to restore
+//! instead of silently loading a corrupted context. Contexts stored without
+//! the tag are loaded by `try_deserialize`, which rejects malformed ones.
+
+use crate::protocol::{check_context_size, Protocol, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+const TAG_SIZE: usize = 32;
+
+fn mac(key: &[u8]) -> Result<Hmac<Sha256>> {
+    if key.is_empty() {
+        return Err("empty checkpoint key".into());
+    }
+    Ok(Hmac::<Sha256>::new_from_slice(key)?)
+}
+
+/// Deserialize a context, failing on malformed or oversized input
+pub fn try_deserialize(ser: &[u8]) -> Result<Box<dyn Protocol>> {
+    check_context_size(ser)?;
+    serde_json::from_slice(ser).map_err(|e| format!("malformed context: {}", e).into())
+}
+
+/// Serialize the context into a snapshot authenticated under `key`
+pub fn checkpoint(ctx: &dyn Protocol, key: &[u8]) -> Result<Vec<u8>> {
+    let ser = serde_json::to_vec(ctx)?;
+    let mut mac = mac(key)?;
+    mac.update(&ser);
+
+    let mut snapshot = mac.finalize().into_bytes().to_vec();
+    snapshot.extend(ser);
+    Ok(snapshot)
+}
+
+/// Restore a context from a snapshot produced by `checkpoint`,
+/// failing if it was not authenticated under `key`
+pub fn restore(snapshot: &[u8], key: &[u8]) -> Result<Box<dyn Protocol>> {
+    if snapshot.len() < TAG_SIZE {
+        return Err("truncated checkpoint".into());
+    }
+    let (tag, ser) = snapshot.split_at(TAG_SIZE);
+    check_context_size(ser)?;
+
+    let mut mac = mac(key)?;
+    mac.update(ser);
+    mac.verify_slice(tag)
+        .map_err(|_| "checkpoint integrity check failed")?;
+
+    try_deserialize(ser)
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::ProtocolType;
+    use crate::protocol::keygen_context;
+
+    #[test]
+    fn roundtrip() {
+        let ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let snapshot = checkpoint(&*ctx, b"key").unwrap();
+
+        let restored = restore(&snapshot, b"key").unwrap();
+        assert_eq!(
+            serde_json::to_vec(&restored).unwrap(),
+            serde_json::to_vec(&ctx).unwrap()
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let snapshot = checkpoint(&*ctx, b"key").unwrap();
+
+        assert!(restore(&snapshot, b"other").is_err());
+        assert!(restore(&snapshot[..snapshot.len() - 1], b"key").is_err());
+        assert!(restore(&snapshot[..TAG_SIZE - 1], b"key").is_err());
+
+        let mut modified = snapshot.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(restore(&modified, b"key").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        let ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let ser = serde_json::to_vec(&ctx).unwrap();
+        assert!(try_deserialize(&ser).is_ok());
+        assert!(try_deserialize(&ser[..ser.len() - 1]).is_err());
+        let error = try_deserialize(br#"{"unknown":{}}"#).err().unwrap();
+        assert!(error.to_string().starts_with("malformed context"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Detached CMS (PKCS #7) signatures made by a GG18 group
+//!
+//! The group signs the digest returned by `signature_digest`, which covers
+//! the digest of the content and the group certificate, and `detached` wraps
+//! the resulting signature and the certificate into a SignedData structure
+//! verifiable by standard tooling, e.g. `openssl cms -verify`.
+//!
+//! A trusted time of signing may be added by a timestamp authority: the
+//! request from `timestamp_request` is sent to it, and its response is passed
+//! to `timestamped`, which includes the RFC 3161 timestamp token as an
+//! unsigned attribute of the signature.
+//!
+//! FROST signatures are not supported, as there is no algorithm identifier
+//! for Schnorr signatures over secp256k1 that CMS verifiers would recognize.
+
+use crate::protocol::Result;
+use openssl::x509::X509;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const OID_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01];
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+const OID_CONTENT_TYPE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+const OID_MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+const OID_SIGNING_CERTIFICATE_V2: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x02, 0x2f,
+];
+const OID_TIMESTAMP_TOKEN: &[u8] = &[
+    0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x02, 0x0e,
+];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        let len = (content.len() as u64).to_be_bytes();
+        let len = &len[len.iter().position(|&b| b != 0).unwrap()..];
+        out.push(0x80 | len.len() as u8);
+        out.extend(len);
+    }
+    out.extend(content);
+    out
+}
+
+fn sequence(items: &[&[u8]]) -> Vec<u8> {
+    tlv(0x30, &items.concat())
+}
+
+/// Encode a SET OF, whose elements DER orders by their encodings
+fn set(items: &[&[u8]]) -> Vec<u8> {
+    let mut items = items.to_vec();
+    items.sort();
+    tlv(0x31, &items.concat())
+}
+
+fn integer(value: &[u8]) -> Vec<u8> {
+    let start = value
+        .iter()
+        .position(|&b| b != 0)
+        .unwrap_or(value.len().saturating_sub(1));
+    let mut content = Vec::new();
+    if value.get(start).map_or(true, |b| b & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend(&value[start..]);
+    tlv(0x02, &content)
+}
+
+fn oid(value: &[u8]) -> Vec<u8> {
+    tlv(0x06, value)
+}
+
+/// Split the first element off `data`, returning its tag, its content
+/// and its whole encoding
+fn read_tlv<'a>(data: &mut &'a [u8]) -> Result<(u8, &'a [u8], &'a [u8])> {
+    let input = *data;
+    let (&tag, rest) = input.split_first().ok_or("truncated DER")?;
+    let (&first, mut rest) = rest.split_first().ok_or("truncated DER")?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let size = (first & 0x7f) as usize;
+        if size == 0 || size > 4 || rest.len() < size {
+            return Err("malformed DER length".into());
+        }
+        let (len, tail) = rest.split_at(size);
+        rest = tail;
+        len.iter().fold(0, |acc, &b| acc << 8 | b as usize)
+    };
+    if rest.len() < len {
+        return Err("truncated DER".into());
+    }
+    let header = input.len() - rest.len();
+    *data = &rest[len..];
+    Ok((tag, &rest[..len], &input[..header + len]))
+}
+
+fn attribute(kind: &[u8], value: &[u8]) -> Vec<u8> {
+    sequence(&[&oid(kind), &set(&[value])])
+}
+
+/// Encode the signed attributes binding the signature to the digest
+/// of the content and to the certificate of the group
+fn signed_attributes(content_digest: &[u8], certificate: &[u8]) -> Vec<u8> {
+    let cert_id = sequence(&[&tlv(0x04, &Sha256::digest(certificate))]);
+    let signing_certificate = sequence(&[&sequence(&[&cert_id])]);
+    set(&[
+        &attribute(OID_CONTENT_TYPE, &oid(OID_DATA)),
+        &attribute(OID_MESSAGE_DIGEST, &tlv(0x04, content_digest)),
+        &attribute(OID_SIGNING_CERTIFICATE_V2, &signing_certificate),
+    ])
+}
+
+/// Compute the digest to be signed by the group holding the key
+/// of `certificate`, given the SHA-256 digest of the content
+pub fn signature_digest(content_digest: &[u8], certificate: &[u8]) -> Vec<u8> {
+    Sha256::digest(signed_attributes(content_digest, certificate)).to_vec()
+}
+
+/// Encode the 64-byte `r || s` signature as an ECDSA-Sig-Value
+fn signature_value(signature: &[u8]) -> Result<Vec<u8>> {
+    if signature.len() != 64 {
+        return Err("malformed signature".into());
+    }
+    Ok(sequence(&[
+        &integer(&signature[..32]),
+        &integer(&signature[32..]),
+    ]))
+}
+
+fn signed_data(
+    content_digest: &[u8],
+    certificate: &[u8],
+    signature: &[u8],
+    unsigned_attributes: &[&[u8]],
+) -> Result<Vec<u8>> {
+    let cert = X509::from_der(certificate)?;
+    let issuer_and_serial = sequence(&[
+        &cert.issuer_name().to_der()?,
+        &integer(&cert.serial_number().to_bn()?.to_vec()),
+    ]);
+    let digest_algorithm = sequence(&[&oid(OID_SHA256)]);
+
+    // the attributes are signed as a SET but included as [0] IMPLICIT
+    let mut attributes = signed_attributes(content_digest, certificate);
+    attributes[0] = 0xa0;
+
+    let mut signer_info = [
+        integer(&[1]),
+        issuer_and_serial,
+        digest_algorithm.clone(),
+        attributes,
+        sequence(&[&oid(OID_ECDSA_SHA256)]),
+        tlv(0x04, &signature_value(signature)?),
+    ]
+    .concat();
+    if !unsigned_attributes.is_empty() {
+        let mut attributes = set(unsigned_attributes);
+        attributes[0] = 0xa1;
+        signer_info.extend(attributes);
+    }
+
+    let signed_data = sequence(&[
+        &integer(&[1]),
+        &set(&[&digest_algorithm]),
+        &sequence(&[&oid(OID_DATA)]),
+        &tlv(0xa0, certificate),
+        &set(&[&tlv(0x30, &signer_info)]),
+    ]);
+    Ok(sequence(&[&oid(OID_SIGNED_DATA), &tlv(0xa0, &signed_data)]))
+}
+
+/// Encode a detached SignedData with the signature produced by the group
+/// over the digest from `signature_digest`
+pub fn detached(content_digest: &[u8], certificate: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    signed_data(content_digest, certificate, signature, &[])
+}
+
+fn message_imprint(signature: &[u8]) -> Result<Vec<u8>> {
+    Ok(sequence(&[
+        &sequence(&[&oid(OID_SHA256)]),
+        &tlv(0x04, &Sha256::digest(signature_value(signature)?)),
+    ]))
+}
+
+/// Encode an RFC 3161 request for a timestamp of the signature
+pub fn timestamp_request(signature: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; 8];
+    OsRng.fill_bytes(&mut nonce);
+    Ok(sequence(&[
+        &integer(&[1]),
+        &message_imprint(signature)?,
+        &integer(&nonce),
+        // request the certificate of the authority to be included
+        &tlv(0x01, &[0xff]),
+    ]))
+}
+
+/// Encode a detached SignedData like `detached`, including the timestamp
+/// token from the `response` of the timestamp authority
+pub fn timestamped(
+    content_digest: &[u8],
+    certificate: &[u8],
+    signature: &[u8],
+    response: &[u8],
+) -> Result<Vec<u8>> {
+    let (_, mut response, _) = read_tlv(&mut &response[..])?;
+    let (_, mut status, _) = read_tlv(&mut response)?;
+    let (_, status, _) = read_tlv(&mut status)?;
+    // the status is either granted (0) or granted with modifications (1)
+    if status.len() != 1 || status[0] > 1 {
+        return Err("timestamp request rejected".into());
+    }
+    let (_, _, token) = read_tlv(&mut response)?;
+
+    // the token is not verified here, but it has to cover the signature
+    let imprint = message_imprint(signature)?;
+    if !token.windows(imprint.len()).any(|window| window == imprint) {
+        return Err("timestamp does not match the signature".into());
+    }
+    let attribute = attribute(OID_TIMESTAMP_TOKEN, token);
+    signed_data(content_digest, certificate, signature, &[&attribute])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::cms::{CMSOptions, CmsContentInfo};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::X509Name;
+
+    fn certificate() -> (EcKey<Private>, Vec<u8>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+        let pkey = PKey::from_ec_key(key.clone()).unwrap();
+
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "group").unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        let serial = BigNum::from_u32(0x80c0ffee)
+            .unwrap()
+            .to_asn1_integer()
+            .unwrap();
+        cert.set_serial_number(&serial).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&pkey).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&pkey, MessageDigest::sha256()).unwrap();
+        (key, cert.build().to_der().unwrap())
+    }
+
+    /// Sign a digest as the group would, producing `r || s`
+    fn sign(key: &EcKey<Private>, digest: &[u8]) -> Vec<u8> {
+        let signature = EcdsaSig::sign(digest, key).unwrap();
+        let mut out = signature.r().to_vec_padded(32).unwrap();
+        out.extend(signature.s().to_vec_padded(32).unwrap());
+        out
+    }
+
+    #[test]
+    fn verify() {
+        let (key, cert) = certificate();
+        let content: &[u8] = b"signed document";
+        let content_digest = Sha256::digest(content);
+
+        let signature = sign(&key, &signature_digest(&content_digest, &cert));
+        let der = detached(&content_digest, &cert, &signature).unwrap();
+
+        let flags = CMSOptions::BINARY | CMSOptions::NO_SIGNER_CERT_VERIFY;
+        let mut cms = CmsContentInfo::from_der(&der).unwrap();
+        assert!(cms.verify(None, None, Some(content), None, flags).is_ok());
+        let mut cms = CmsContentInfo::from_der(&der).unwrap();
+        assert!(cms
+            .verify(None, None, Some(&b"other"[..]), None, flags)
+            .is_err());
+
+        assert!(detached(&content_digest, &cert, &signature[1..]).is_err());
+        assert!(detached(&content_digest, b"not a certificate", &signature).is_err());
+    }
+
+    #[test]
+    fn timestamp() {
+        let (key, cert) = certificate();
+        let content: &[u8] = b"signed document";
+        let content_digest = Sha256::digest(content);
+        let signature = sign(&key, &signature_digest(&content_digest, &cert));
+
+        let request = timestamp_request(&signature).unwrap();
+        let imprint = message_imprint(&signature).unwrap();
+        assert!(request
+            .windows(imprint.len())
+            .any(|window| window == imprint));
+
+        // a stand-in for the token, which merely contains the imprint
+        let token = sequence(&[&oid(OID_SIGNED_DATA), &imprint]);
+        let response = |status: u8| sequence(&[&sequence(&[&integer(&[status])]), &token]);
+
+        let der = timestamped(&content_digest, &cert, &signature, &response(0)).unwrap();
+        assert!(der.windows(token.len()).any(|window| window == token));
+        let flags = CMSOptions::BINARY | CMSOptions::NO_SIGNER_CERT_VERIFY;
+        let mut cms = CmsContentInfo::from_der(&der).unwrap();
+        assert!(cms.verify(None, None, Some(content), None, flags).is_ok());
+
+        assert!(timestamped(&content_digest, &cert, &signature, &response(2)).is_err());
+        let other = sign(&key, &signature_digest(&content_digest, &cert));
+        assert!(timestamped(&content_digest, &cert, &other, &response(0)).is_err());
+    }
+
+    #[test]
+    fn der() {
+        assert_eq!(tlv(0x04, &[0; 3]), [0x04, 3, 0, 0, 0]);
+        assert_eq!(tlv(0x04, &[0; 200])[..3], [0x04, 0x81, 200]);
+        assert_eq!(tlv(0x04, &[0; 300])[..4], [0x04, 0x82, 1, 44]);
+        assert_eq!(integer(&[0, 0, 1]), [0x02, 1, 1]);
+        assert_eq!(integer(&[0x80]), [0x02, 2, 0, 0x80]);
+        assert_eq!(integer(&[0, 0]), [0x02, 1, 0]);
+        assert_eq!(set(&[&[2], &[1]]), [0x31, 2, 1, 2]);
+
+        let mut data = &[0x04, 0x81, 1, 7, 0x05, 0][..];
+        assert_eq!(
+            read_tlv(&mut data).unwrap(),
+            (0x04, &[7][..], &[0x04, 0x81, 1, 7][..])
+        );
+        assert_eq!(
+            read_tlv(&mut data).unwrap(),
+            (0x05, &[][..], &[0x05, 0][..])
+        );
+        assert!(read_tlv(&mut data).is_err());
+        assert!(read_tlv(&mut &[0x04, 2, 0][..]).is_err());
+    }
+}
@@ -0,0 +1,708 @@
+//! Encoding of the messages exchanged through the server
+//!
+//! Messages are passed around as reference-counted `Bytes`, so that
+//! a broadcast is serialized once and shared by all of its recipients,
+//! and unpacked messages are slices of a single copy of the input.
+//!
+//! Packed messages may be compressed with deflate, as announced by the
+//! `compression` field of their envelope. Compression of outgoing messages
+//! is off by default and enabled by `set_compression` once all clients of
+//! a deployment understand it; incoming messages are always decompressed
+//! according to their envelope. Each party decides on its own whether to
+//! compress, so the server relaying the messages of several senders in one
+//! envelope carries the flag of each of them in its `compressions` field.
+//!
+//! The server may name the sender of each relayed message in the
+//! `senders` field of the envelope. The messages are then put in the order
+//! of their senders, so they need not be relayed in the positional order,
+//! and `unpack_from` pairs them with the senders a context expects, failing
+//! on a missing one instead of attributing a message to the wrong party.
+//! Named senders must be among the other parties of the session, which
+//! the contexts of every protocol know by `with_senders`, see `pairing`.
+//!
+//! Round messages are encoded by bincode after a zero byte and the version
+//! of the encoding. The JSON encoding, which never starts with a zero byte,
+//! is still accepted from clients which do not send the binary one yet.
+//!
+//! Each envelope announces the version of the protocol its sender speaks,
+//! from which the server picks the version of a session, see `InitVersion`.
+//! Within a session of the first version, spoken by clients which precede
+//! the announcement, messages are sent in JSON and never compressed.
+//!
+//! No encoding depends on the platform, so that contexts and messages of
+//! the native library and of its wasm32 build are interchangeable: bincode
+//! writes integers as little-endian varints, `usize` as a 64-bit one, and
+//! the digests take integers big-endian. The fixtures in the tests pin
+//! the bytes on every target the tests run on.
+
+use crate::proto::{
+    Compression, DecryptResult, GroupResult, ProtocolMessage, ProtocolType, PublicShare,
+    SignatureResult,
+};
+use crate::protocol::telemetry::telemetry;
+use crate::protocol::{one_based, Result, MAX_PARTIES};
+use bincode::Options;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use prost::bytes::Bytes;
+use prost::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static MAX_MESSAGE_SIZE: AtomicUsize = AtomicUsize::new(16 << 20);
+static MAX_CONTEXT_SIZE: AtomicUsize = AtomicUsize::new(16 << 20);
+static COMPRESSION: AtomicBool = AtomicBool::new(false);
+
+/// Version of the binary encoding of round messages
+const WIRE_VERSION: u8 = 1;
+
+/// Version of the protocol with JSON round messages and no compression
+pub(crate) const VERSION_JSON: u32 = 1;
+/// Latest version of the protocol, with binary round messages
+pub(crate) const PROTOCOL_VERSION: u32 = 2;
+
+thread_local! {
+    /// Version of the session whose context is being advanced
+    static SESSION_VERSION: Cell<u32> = Cell::new(PROTOCOL_VERSION);
+}
+
+/// Run `f` with the messages encoded for a session of the given version
+pub(crate) fn with_version<T>(version: u32, f: impl FnOnce() -> T) -> T {
+    let previous = SESSION_VERSION.with(|v| v.replace(version));
+    let result = f();
+    SESSION_VERSION.with(|v| v.set(previous));
+    result
+}
+
+fn session_version() -> u32 {
+    SESSION_VERSION.with(Cell::get)
+}
+
+thread_local! {
+    /// Other parties of the session whose context is being advanced,
+    /// from which the named senders of its messages must come
+    static SESSION_SENDERS: RefCell<Option<Vec<u32>>> = RefCell::new(None);
+}
+
+/// Run `f` with the named senders of the unpacked messages checked
+/// against the other parties of the session, if they are known
+pub(crate) fn with_senders<T>(senders: Option<&[u32]>, f: impl FnOnce() -> T) -> T {
+    let previous = SESSION_SENDERS.with(|s| s.replace(senders.map(<[u32]>::to_vec)));
+    let result = f();
+    SESSION_SENDERS.with(|s| s.replace(previous));
+    result
+}
+
+fn check_senders(named: &[u32]) -> Result<()> {
+    SESSION_SENDERS.with(|senders| match &*senders.borrow() {
+        Some(senders) => match named.iter().find(|sender| !senders.contains(sender)) {
+            Some(sender) => Err(format!("unexpected sender {}", sender).into()),
+            None => Ok(()),
+        },
+        None => Ok(()),
+    })
+}
+
+/// Set the maximum size of a packed message from the server
+/// and of a serialized protocol context
+pub fn set_size_limits(message: usize, context: usize) {
+    MAX_MESSAGE_SIZE.store(message, Ordering::Relaxed);
+    MAX_CONTEXT_SIZE.store(context, Ordering::Relaxed);
+}
+
+/// Enable the compression of the messages packed for the server
+pub fn set_compression(enabled: bool) {
+    COMPRESSION.store(enabled, Ordering::Relaxed);
+}
+
+/// Error returned when an input exceeds the configured size limit
+#[derive(Debug)]
+pub struct SizeLimitError {
+    pub kind: &'static str,
+    pub size: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for SizeLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes exceeds the limit of {} bytes",
+            self.kind, self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for SizeLimitError {}
+
+fn check_size(kind: &'static str, size: usize, limit: &AtomicUsize) -> Result<()> {
+    let limit = limit.load(Ordering::Relaxed);
+    if size > limit {
+        telemetry!(warn, kind, size, limit, "size limit exceeded");
+        return Err(Box::new(SizeLimitError { kind, size, limit }));
+    }
+    Ok(())
+}
+
+/// Check a serialized protocol context against the configured size limit
+pub fn check_context_size(ctx: &[u8]) -> Result<()> {
+    check_size("context", ctx.len(), &MAX_CONTEXT_SIZE)
+}
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_little_endian()
+        .with_varint_encoding()
+        .reject_trailing_bytes()
+}
+
+/// Serialize a round message in the binary encoding,
+/// or in JSON within a session of the first version
+pub(crate) fn serialize_msg<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    if session_version() <= VERSION_JSON {
+        return Ok(serde_json::to_vec(value)?);
+    }
+    let mut ser = vec![0, WIRE_VERSION];
+    bincode_options().serialize_into(&mut ser, value)?;
+    Ok(ser)
+}
+
+/// Deserialize a round message in the binary or in the legacy JSON encoding
+pub(crate) fn deserialize_msg<'de, T: Deserialize<'de>>(msg: &'de [u8]) -> Result<T> {
+    check_size("message", msg.len(), &MAX_MESSAGE_SIZE)?;
+    match msg {
+        [0, WIRE_VERSION, data @ ..] => Ok(bincode_options()
+            .with_limit(data.len() as u64)
+            .deserialize(data)?),
+        [0, ..] => Err("unsupported message version".into()),
+        _ => Ok(serde_json::from_slice(msg)?),
+    }
+}
+
+pub(crate) fn deserialize_vec<'de, T: Deserialize<'de>, M: AsRef<[u8]>>(
+    vec: &'de [M],
+) -> Result<Vec<T>> {
+    vec.iter()
+        .map(|item| deserialize_msg(item.as_ref()))
+        .collect()
+}
+
+/// Repeat the message n times, sharing a single buffer
+pub(crate) fn inflate(value: impl Into<Bytes>, n: usize) -> Vec<Bytes> {
+    std::iter::repeat(value.into()).take(n).collect()
+}
+
+/// Serialize value and repeat the result n times,
+/// as the current server always expects one message for each party
+pub(crate) fn serialize_bcast<T: Serialize>(value: &T, n: usize) -> Result<Vec<Bytes>> {
+    let ser = serialize_msg(value)?;
+    Ok(inflate(ser, n))
+}
+
+/// Serialize vector of unicast messages
+pub(crate) fn serialize_uni<T: Serialize>(vec: Vec<T>) -> Result<Vec<Bytes>> {
+    vec.iter()
+        .map(|item| serialize_msg(item).map(Bytes::from))
+        .collect()
+}
+
+/// Check that a message was received from each of the other parties
+pub(crate) fn check_message_count<M>(msgs: &[M], peers: usize) -> Result<()> {
+    if msgs.len() != peers {
+        return Err("unexpected number of messages".into());
+    }
+    Ok(())
+}
+
+fn compress(msg: &[u8]) -> Bytes {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    // writing into a vector cannot fail
+    encoder.write_all(msg).unwrap();
+    encoder.finish().unwrap().into()
+}
+
+/// Decompress a message, stopping as soon as it exceeds the size limit
+fn decompress(msg: &[u8]) -> Result<Bytes> {
+    let limit = MAX_MESSAGE_SIZE.load(Ordering::Relaxed);
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(msg)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    check_size("message", decompressed.len(), &MAX_MESSAGE_SIZE)?;
+    Ok(decompressed.into())
+}
+
+/// Decode a protobuf message from the server into the messages and the
+/// indices of their senders, if named; the messages share a single copy
+/// of `data` unless they were compressed
+fn decode_message(data: &[u8]) -> Result<(Vec<Bytes>, Vec<u32>)> {
+    check_size("packed message", data.len(), &MAX_MESSAGE_SIZE)?;
+    let msg = ProtocolMessage::decode(Bytes::copy_from_slice(data))?;
+    let compressions = if msg.compressions.is_empty() {
+        vec![msg.compression; msg.message.len()]
+    } else if msg.compressions.len() == msg.message.len() {
+        msg.compressions
+    } else {
+        return Err("unexpected number of compression flags".into());
+    };
+    let msgs = msg
+        .message
+        .into_iter()
+        .zip(compressions)
+        .map(
+            |(m, compression)| match Compression::from_i32(compression) {
+                Some(Compression::None) => Ok(m),
+                Some(Compression::Deflate) => decompress(&m),
+                None => Err("unsupported compression".into()),
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
+    if !msg.senders.is_empty() && msg.senders.len() != msgs.len() {
+        return Err("unexpected number of senders".into());
+    }
+    check_senders(&msg.senders)?;
+    Ok((msgs, msg.senders))
+}
+
+/// Decode a protobuf message from the server, ordering the messages
+/// by their senders if the server named them
+pub(crate) fn unpack(data: &[u8]) -> Result<Vec<Bytes>> {
+    let (msgs, senders) = decode_message(data)?;
+    if senders.is_empty() {
+        return Ok(msgs);
+    }
+    let mut paired: Vec<(u32, Bytes)> = senders.into_iter().zip(msgs).collect();
+    paired.sort_by_key(|(sender, _)| *sender);
+    if paired.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err("duplicate sender".into());
+    }
+    Ok(paired.into_iter().map(|(_, msg)| msg).collect())
+}
+
+/// Check the envelope of a packed message without any secrets, so that
+/// a relay can reject a malformed message early instead of forwarding it
+/// to the parties: its protocol type must be known, its compression
+/// supported and flagged for each message or for all of them, its size
+/// within the limits, and its senders, if named,
+/// distinct and as many as its messages, of which there may be one
+/// for each other party at most
+pub fn validate_envelope(data: &[u8]) -> Result<()> {
+    check_size("packed message", data.len(), &MAX_MESSAGE_SIZE)?;
+    let msg = ProtocolMessage::decode(data)?;
+    ProtocolType::try_from(msg.protocol_type)?;
+    let msgs = unpack(data)?;
+    if msgs.len() >= MAX_PARTIES as usize {
+        return Err("unexpected number of messages".into());
+    }
+    Ok(())
+}
+
+/// Decode a protobuf message from the server, pairing the messages with
+/// the `senders` they are expected from, in which order they are relayed
+/// unless the server named the sender of each message
+pub(crate) fn unpack_from(data: &[u8], senders: &[u32]) -> Result<Vec<(u32, Bytes)>> {
+    let (msgs, named) = decode_message(data)?;
+    check_message_count(&msgs, senders.len())?;
+    if named.is_empty() {
+        return Ok(senders.iter().cloned().zip(msgs).collect());
+    }
+    let mut paired: Vec<(u32, Bytes)> = named.into_iter().zip(msgs).collect();
+    senders
+        .iter()
+        .map(|sender| {
+            let position = paired
+                .iter()
+                .position(|(named, _)| named == sender)
+                .ok_or_else(|| format!("missing message from party {}", sender))?;
+            Ok(paired.swap_remove(position))
+        })
+        .collect()
+}
+
+/// Encode msgs as a protobuf message, compressing them if `deflate`
+/// is set and it makes the message smaller
+fn encode_message(msgs: Vec<Bytes>, protocol_type: ProtocolType, deflate: bool) -> Vec<u8> {
+    let mut message = ProtocolMessage {
+        protocol_type: protocol_type.into(),
+        message: msgs,
+        compression: Compression::None.into(),
+        version: session_version(),
+        ..Default::default()
+    };
+    if deflate {
+        // a broadcast shares a single buffer, which is compressed only once
+        let mut last: Option<(Bytes, Bytes)> = None;
+        let compressed: Vec<Bytes> = message
+            .message
+            .iter()
+            .map(|msg| match &last {
+                Some((prev, out)) if prev.as_ptr() == msg.as_ptr() && prev.len() == msg.len() => {
+                    out.clone()
+                }
+                _ => {
+                    let out = compress(msg);
+                    last = Some((msg.clone(), out.clone()));
+                    out
+                }
+            })
+            .collect();
+        let size = |msgs: &[Bytes]| msgs.iter().map(Bytes::len).sum::<usize>();
+        if size(&compressed) < size(&message.message) {
+            message.message = compressed;
+            message.compression = Compression::Deflate.into();
+        }
+    }
+    message.encode_to_vec()
+}
+
+/// Encode msgs as a protobuf message for the server
+pub(crate) fn pack(msgs: Vec<Bytes>, protocol_type: ProtocolType) -> Vec<u8> {
+    let deflate = COMPRESSION.load(Ordering::Relaxed) && session_version() > VERSION_JSON;
+    encode_message(msgs, protocol_type, deflate)
+}
+
+/// Encode the result of a key generation for the server, wrapping
+/// the group context to be passed to the threshold protocols
+pub(crate) fn pack_group(
+    protocol_type: ProtocolType,
+    public_key: Vec<u8>,
+    context: Vec<u8>,
+) -> Vec<u8> {
+    pack_group_shares(protocol_type, public_key, context, Vec::new(), Vec::new())
+}
+
+/// Encode the result of a key generation with the public key shares
+/// of the parties and the commitments to the sharing, see `pack_group`
+pub(crate) fn pack_group_shares(
+    protocol_type: ProtocolType,
+    public_key: Vec<u8>,
+    context: Vec<u8>,
+    shares: Vec<PublicShare>,
+    commitments: Vec<Vec<u8>>,
+) -> Vec<u8> {
+    GroupResult {
+        protocol_type: protocol_type.into(),
+        public_key,
+        context,
+        shares,
+        commitments,
+        format: GROUP_FORMAT,
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Commitment to the set of parties which produced a signature,
+/// which binds the indices of the parties to the signature
+pub fn signers_commitment(
+    protocol_type: ProtocolType,
+    indices: &[u32],
+    signature: &[u8],
+) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"meesign signers");
+    hasher.update((protocol_type as i32 as u32).to_be_bytes());
+    hasher.update((indices.len() as u32).to_be_bytes());
+    for index in indices {
+        hasher.update(index.to_be_bytes());
+    }
+    hasher.update(signature);
+    hasher.finalize().to_vec()
+}
+
+/// Encode the result of a signing protocol for the server,
+/// along with the indices of the participating parties,
+/// which are reported counted from one
+pub(crate) fn pack_signature(
+    protocol_type: ProtocolType,
+    signature: Vec<u8>,
+    indices: Vec<u32>,
+) -> Vec<u8> {
+    let indices = one_based(protocol_type, &indices);
+    SignatureResult {
+        protocol_type: protocol_type.into(),
+        signers_commitment: signers_commitment(protocol_type, &indices, &signature),
+        signature,
+        indices,
+        pq_signature: Vec::new(),
+        signers_attestation: Vec::new(),
+    }
+    .encode_to_vec()
+}
+
+/// Encode the result of a decryption protocol for the server
+pub(crate) fn pack_plaintexts(protocol_type: ProtocolType, plaintexts: Vec<Vec<u8>>) -> Vec<u8> {
+    DecryptResult {
+        protocol_type: protocol_type.into(),
+        plaintexts,
+    }
+    .encode_to_vec()
+}
+
+/// Version of the layout of the `GroupResult`s produced by the library
+pub(crate) const GROUP_FORMAT: u32 = 1;
+
+/// Extract the group context from the result of a key generation;
+/// bare contexts produced before the results were wrapped are JSON
+/// objects or arrays, which are returned unchanged
+pub fn group_context(group: &[u8]) -> Result<Vec<u8>> {
+    if let Some(b'{' | b'[') = group.first() {
+        return Ok(group.to_vec());
+    }
+    let result = GroupResult::decode(group).map_err(|_| "not a group result")?;
+    if result.format > GROUP_FORMAT {
+        return Err("unsupported group format".into());
+    }
+    Ok(result.context)
+}
+
+/// Deserialize the group context from the result of a key generation,
+/// see `group_context`
+pub(crate) fn parse_group_context<T: DeserializeOwned>(group: &[u8]) -> Result<T> {
+    Ok(serde_json::from_slice(&group_context(group)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn pack_roundtrip(
+            msgs in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 0..8),
+            protocol_type in 0..5i32,
+        ) {
+            let protocol_type = ProtocolType::from_i32(protocol_type).unwrap();
+            let packed = pack(msgs.iter().cloned().map(Bytes::from).collect(), protocol_type);
+            prop_assert_eq!(unpack(&packed).unwrap(), msgs);
+        }
+
+        #[test]
+        fn bcast_roundtrip(value in any::<Vec<u32>>(), n in 0..8usize) {
+            let msgs = serialize_bcast(&value, n).unwrap();
+            prop_assert_eq!(deserialize_vec::<Vec<u32>, _>(&msgs).unwrap(), vec![value; n]);
+        }
+
+        #[test]
+        fn uni_roundtrip(values in any::<Vec<(u16, String)>>()) {
+            let msgs = serialize_uni(values.clone()).unwrap();
+            prop_assert_eq!(deserialize_vec::<(u16, String), _>(&msgs).unwrap(), values);
+        }
+    }
+
+    #[test]
+    fn binary_encoding() {
+        let value = (vec![7u8; 32], String::from("share"), 3u16);
+        let ser = serialize_msg(&value).unwrap();
+        assert_eq!(&ser[..2], &[0, WIRE_VERSION]);
+        assert!(ser.len() < serde_json::to_vec(&value).unwrap().len());
+        assert_eq!(
+            deserialize_msg::<(Vec<u8>, String, u16)>(&ser).unwrap(),
+            value
+        );
+
+        let legacy = serde_json::to_vec(&value).unwrap();
+        assert_eq!(
+            deserialize_msg::<(Vec<u8>, String, u16)>(&legacy).unwrap(),
+            value
+        );
+
+        let mut future = ser.clone();
+        future[1] += 1;
+        assert!(deserialize_msg::<(Vec<u8>, String, u16)>(&future).is_err());
+        let mut trailing = ser;
+        trailing.push(0);
+        assert!(deserialize_msg::<(Vec<u8>, String, u16)>(&trailing).is_err());
+    }
+
+    #[test]
+    fn wire_fixture() {
+        let value = (
+            0x0102u16,
+            0x01020304u32,
+            0x0102030405060708u64,
+            300usize,
+            vec![1u8, 2],
+            String::from("ab"),
+        );
+        let ser = serialize_msg(&value).unwrap();
+        assert_eq!(
+            ser,
+            [
+                0, 1, 251, 2, 1, 252, 4, 3, 2, 1, 253, 8, 7, 6, 5, 4, 3, 2, 1, 251, 44, 1, 2, 1, 2,
+                2, 97, 98
+            ]
+        );
+        assert_eq!(
+            deserialize_msg::<(u16, u32, u64, usize, Vec<u8>, String)>(&ser).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn commitment_fixture() {
+        let commitment = signers_commitment(ProtocolType::Frost, &[1, 256], &[0xaa, 0xbb]);
+        let expected = "aefd8fdf38b835ebf22ab6aef9848363cfd5fa2fad50dca3069b396ac163716a";
+        let hex: String = commitment.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, expected);
+    }
+
+    #[test]
+    fn group_context_legacy() {
+        let ctx = br#"{"round":"R0"}"#.to_vec();
+        assert_eq!(group_context(&ctx).unwrap(), ctx);
+        let ctx = br#"[{"key":1},{"pubkey":2}]"#.to_vec();
+        assert_eq!(group_context(&ctx).unwrap(), ctx);
+        let packed = pack_group(ProtocolType::Frost, vec![2; 33], ctx.clone());
+        assert_eq!(group_context(&packed).unwrap(), ctx);
+
+        let untagged = GroupResult {
+            context: ctx.clone(),
+            ..Default::default()
+        };
+        assert_eq!(group_context(&untagged.encode_to_vec()).unwrap(), ctx);
+        let future = GroupResult {
+            context: ctx,
+            format: GROUP_FORMAT + 1,
+            ..Default::default()
+        };
+        assert!(group_context(&future.encode_to_vec()).is_err());
+        assert!(group_context(b"\xffgarbage").is_err());
+    }
+
+    #[test]
+    fn pack_fixture() {
+        // envelopes of senders which precede the announcement of versions
+        let packed = vec![8, 2, 18, 2, 97, 98];
+        let legacy = with_version(0, || pack(vec![Bytes::from("ab")], ProtocolType::Frost));
+        assert_eq!(legacy, packed);
+        assert_eq!(unpack(&packed).unwrap(), vec![b"ab".to_vec()]);
+
+        let packed = vec![8, 2, 18, 2, 97, 98, 40, 2];
+        assert_eq!(pack(vec![Bytes::from("ab")], ProtocolType::Frost), packed);
+        assert_eq!(unpack(&packed).unwrap(), vec![b"ab".to_vec()]);
+    }
+
+    #[test]
+    fn senders() {
+        let envelope = |senders: Vec<u32>| {
+            (ProtocolMessage {
+                message: vec![Bytes::from("c"), Bytes::from("a"), Bytes::from("b")],
+                senders,
+                ..Default::default()
+            })
+            .encode_to_vec()
+        };
+
+        let packed = envelope(vec![4, 1, 2]);
+        assert_eq!(
+            unpack(&packed).unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+        let paired = unpack_from(&packed, &[4, 2, 1]).unwrap();
+        assert_eq!(paired[0], (4, Bytes::from("c")));
+        assert_eq!(paired[1], (2, Bytes::from("b")));
+        let error = unpack_from(&packed, &[1, 2, 3]).unwrap_err();
+        assert_eq!(error.to_string(), "missing message from party 3");
+
+        // without named senders the messages come in the expected order
+        let paired = unpack_from(&envelope(Vec::new()), &[1, 2, 3]).unwrap();
+        assert_eq!(paired[0], (1, Bytes::from("c")));
+
+        assert!(unpack(&envelope(vec![1, 1, 2])).is_err());
+        assert!(unpack(&envelope(vec![1, 2])).is_err());
+
+        // the senders must be among the other parties of the session
+        with_senders(Some(&[1, 2, 4]), || {
+            assert!(unpack(&packed).is_ok());
+            assert!(unpack(&envelope(Vec::new())).is_ok());
+            let error = unpack(&envelope(vec![3, 1, 2])).unwrap_err();
+            assert_eq!(error.to_string(), "unexpected sender 3");
+        });
+        assert!(unpack(&envelope(vec![3, 1, 2])).is_ok());
+    }
+
+    #[test]
+    fn envelope() {
+        let packed = pack(inflate(Bytes::from("ab"), 2), ProtocolType::Frost);
+        assert!(validate_envelope(&packed).is_ok());
+        assert!(validate_envelope(b"garbage").is_err());
+
+        let mut message = ProtocolMessage::decode(&packed[..]).unwrap();
+        message.protocol_type = 1000;
+        let error = validate_envelope(&message.encode_to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "unknown protocol type");
+
+        message.protocol_type = ProtocolType::Frost as i32;
+        message.senders = vec![1, 1];
+        assert!(validate_envelope(&message.encode_to_vec()).is_err());
+        message.senders = vec![1];
+        assert!(validate_envelope(&message.encode_to_vec()).is_err());
+    }
+
+    #[test]
+    fn compression() {
+        let msg = serde_json::to_vec(&vec!["commitment"; 100]).unwrap();
+        let msgs = inflate(msg.clone(), 3);
+        let packed = encode_message(msgs.clone(), ProtocolType::Frost, true);
+        assert!(packed.len() < pack(msgs, ProtocolType::Frost).len());
+        assert_eq!(unpack(&packed).unwrap(), vec![msg; 3]);
+
+        // incompressible messages are sent as they are
+        let msgs = vec![Bytes::from("ab")];
+        assert_eq!(
+            encode_message(msgs.clone(), ProtocolType::Frost, true),
+            pack(msgs, ProtocolType::Frost)
+        );
+
+        let mut message = ProtocolMessage::decode(&packed[..]).unwrap();
+        message.compression = 2;
+        assert!(unpack(&message.encode_to_vec()).is_err());
+
+        // the server relays the messages of senders which compressed differently
+        let compressed = ProtocolMessage::decode(&packed[..]).unwrap().message;
+        let message = ProtocolMessage {
+            protocol_type: ProtocolType::Frost.into(),
+            message: vec![compressed[0].clone(), Bytes::from(msg.clone())],
+            compressions: vec![Compression::Deflate.into(), Compression::None.into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            unpack(&message.encode_to_vec()).unwrap(),
+            vec![msg.clone(); 2]
+        );
+
+        let mut message = message;
+        message.compressions.pop();
+        assert!(unpack(&message.encode_to_vec()).is_err());
+        assert!(validate_envelope(&message.encode_to_vec()).is_err());
+    }
+
+    #[test]
+    fn compression_bomb() {
+        let limit = MAX_MESSAGE_SIZE.load(Ordering::Relaxed);
+        let bomb = vec![Bytes::from(vec![0u8; limit + 1])];
+        let packed = encode_message(bomb, ProtocolType::Frost, true);
+        assert!(packed.len() < limit);
+
+        let error = unpack(&packed).unwrap_err();
+        assert!(error.downcast_ref::<SizeLimitError>().is_some());
+    }
+
+    #[test]
+    fn size_limits() {
+        let oversized = vec![0u8; MAX_MESSAGE_SIZE.load(Ordering::Relaxed) + 1];
+
+        let error = unpack(&oversized).unwrap_err();
+        assert!(error.downcast_ref::<SizeLimitError>().is_some());
+
+        let error = deserialize_vec::<Vec<u8>, _>(&[oversized]).unwrap_err();
+        assert!(error.downcast_ref::<SizeLimitError>().is_some());
+    }
+}
@@ -0,0 +1,167 @@
+//! Helpers for the server relaying the sessions
+//!
+//! The server holds no shares, so it cannot take part in a session, but it
+//! can check the outputs of the parties before relaying them, aggregate the
+//! signature of a FROST session whose shares it collects, and verify the
+//! signatures it hands out, without linking another implementation of the
+//! protocols. None of the helpers keeps any state between the calls.
+
+use crate::proto::{ProtocolMessage, ProtocolType};
+use crate::protocol::*;
+use prost::Message;
+
+/// Check the packed outputs of a round, one from each party of the session,
+/// before relaying them: each must pass `validate_envelope`, be a message
+/// of the given protocol, and carry a message for each of the other parties,
+/// or none at all, as in the last round of most protocols
+pub fn check_round<M: AsRef<[u8]>>(protocol_type: ProtocolType, outputs: &[M]) -> Result<()> {
+    let peers = outputs.len().saturating_sub(1);
+    for (party, output) in outputs.iter().enumerate() {
+        let blame = |e: Box<dyn std::error::Error>| format!("output of party {}: {}", party, e);
+        validate_envelope(output.as_ref()).map_err(blame)?;
+        let msg = ProtocolMessage::decode(output.as_ref()).map_err(|e| blame(e.into()))?;
+        protocol_type.check(msg.protocol_type).map_err(blame)?;
+        let msgs = unpack(output.as_ref()).map_err(blame)?;
+        if !msgs.is_empty() {
+            check_message_count(&msgs, peers).map_err(blame)?;
+        }
+    }
+    Ok(())
+}
+
+/// Export the public key package needed by `aggregate`,
+/// given the group of any of its members
+#[cfg(feature = "frost")]
+pub fn public_package(protocol_type: ProtocolType, group: &[u8]) -> Result<Vec<u8>> {
+    use frost_p256::P256Sha256;
+    use frost_secp256k1::Secp256K1Sha256;
+
+    match protocol_type {
+        ProtocolType::Frost => frost::public_package_with::<Secp256K1Sha256>(group),
+        ProtocolType::FrostP256 => frost::public_package_with::<P256Sha256>(group),
+        _ => Err("protocol not aggregated by the server".into()),
+    }
+}
+
+/// Aggregate the signature of a FROST session from the round messages
+/// of its signers, given as pairs of their indices and the messages;
+/// the commitments are in the order of the indices of the session.
+/// Returns the encoded `SignatureResult` the parties would produce
+#[cfg(feature = "frost")]
+pub fn aggregate(
+    protocol_type: ProtocolType,
+    package: &[u8],
+    message: &[u8],
+    commitments: &[(u16, Vec<u8>)],
+    shares: &[(u16, Vec<u8>)],
+) -> Result<Vec<u8>> {
+    use frost_p256::P256Sha256;
+    use frost_secp256k1::Secp256K1Sha256;
+
+    match protocol_type {
+        ProtocolType::Frost => {
+            frost::aggregate_with::<Secp256K1Sha256>(package, message, commitments, shares)
+        }
+        ProtocolType::FrostP256 => {
+            frost::aggregate_with::<P256Sha256>(package, message, commitments, shares)
+        }
+        _ => Err("protocol not aggregated by the server".into()),
+    }
+}
+
+/// Verify a signature produced by the given signing protocol
+pub fn verify(protocol_type: ProtocolType, pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    match protocol_type {
+        #[cfg(feature = "gg18")]
+        ProtocolType::Gg18 => gg18::verify(pk, msg, signature),
+        #[cfg(feature = "frost")]
+        ProtocolType::Frost | ProtocolType::Roast => frost::verify(pk, msg, signature),
+        #[cfg(feature = "frost")]
+        ProtocolType::FrostP256 => frost::verify_p256(pk, msg, signature),
+        #[cfg(feature = "musig2")]
+        ProtocolType::Musig2 => musig2::verify(pk, msg, signature),
+        #[cfg(feature = "mldsa")]
+        ProtocolType::MlDsa => mldsa::verify(pk, msg, signature),
+        _ => Err("not a signing protocol".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::bytes::Bytes;
+
+    #[test]
+    fn round_outputs() {
+        let output = |n| pack(inflate(Bytes::from_static(b"msg"), n), ProtocolType::Frost);
+        assert!(check_round(ProtocolType::Frost, &[output(2), output(2), output(2)]).is_ok());
+        assert!(check_round(ProtocolType::Frost, &[output(0), output(0)]).is_ok());
+
+        let error = check_round(ProtocolType::Frost, &[output(1), output(2)]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "output of party 1: unexpected number of messages"
+        );
+        let error = check_round(ProtocolType::Gg18, &[output(1), output(1)]).unwrap_err();
+        assert_eq!(error.to_string(), "output of party 0: wrong protocol type");
+        assert!(check_round(ProtocolType::Frost, &[b"garbage".to_vec()]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "frost")]
+    fn frost_aggregation() {
+        use crate::proto::{GroupResult, ProtocolInit, SignatureResult};
+        use crate::protocol::frost::{KeygenContext, SignContext};
+        use crate::protocol::tests::{advance_all, run_rounds, KeygenProtocolTest};
+
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let indices = [1u16, 3];
+        let data = b"hello".to_vec();
+        let mut ctxs: Vec<SignContext> = indices
+            .iter()
+            .map(|index| SignContext::new(&groups[*index as usize - 1]).unwrap())
+            .collect();
+        let inits = indices
+            .iter()
+            .map(|index| {
+                (ProtocolInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    indices: indices.iter().map(|i| *i as u32).collect(),
+                    index: *index as u32,
+                    data: data.clone(),
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let commitments = advance_all(&mut ctxs, inits);
+        let shares = run_rounds(&mut ctxs, commitments.clone(), 1);
+        let first = |msgs: Vec<Vec<Vec<u8>>>| -> Vec<(u16, Vec<u8>)> {
+            indices
+                .iter()
+                .cloned()
+                .zip(msgs)
+                .map(|(i, m)| (i, m[0].clone()))
+                .collect()
+        };
+
+        let package = public_package(ProtocolType::Frost, &groups[0]).unwrap();
+        let (commitments, mut shares) = (first(commitments), first(shares));
+        let result =
+            aggregate(ProtocolType::Frost, &package, &data, &commitments, &shares).unwrap();
+        let result = SignatureResult::decode(&result[..]).unwrap();
+        assert_eq!(result.indices, [1, 3]);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        assert!(verify(ProtocolType::Frost, &pk, &data, &result.signature).is_ok());
+        assert!(verify(ProtocolType::Frost, &pk, b"other", &result.signature).is_err());
+
+        shares.swap(0, 1);
+        shares[0].0 = 1;
+        shares[1].0 = 3;
+        let error = aggregate(ProtocolType::Frost, &package, &data, &commitments, &shares);
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid signature share from party"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Introspection of the contexts for diagnosing stuck sessions
+//!
+//! `Protocol::debug_state` reports where a context stands without exposing
+//! any of its secrets, so that clients can include it in their logs, and
+//! `state_diagram` draws the rounds a protocol goes through.
+
+use crate::proto::ProtocolType;
+use crate::protocol::{protocol_info, Result};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// State of a context, see `Protocol::debug_state`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DebugState {
+    /// Name of the innermost context, or its type if it does not report one
+    pub context: String,
+    /// Name of the round the context is in, `unknown` if it does not report it
+    pub round: String,
+    pub expected_messages: Option<usize>,
+    /// Number of messages of the other parties consumed so far,
+    /// as counted by `resumable` contexts
+    pub consumed: usize,
+    /// Names of the wrapping contexts, the outermost first
+    pub layers: Vec<String>,
+}
+
+impl DebugState {
+    pub fn new(context: &str, round: &str, expected_messages: Option<usize>) -> Self {
+        Self {
+            context: context.into(),
+            round: round.into(),
+            expected_messages,
+            consumed: 0,
+            layers: Vec::new(),
+        }
+    }
+
+    /// State of the wrapped context as seen through the wrapper `layer`
+    pub(crate) fn wrapped(mut self, layer: &str) -> Self {
+        self.layers.insert(0, layer.into());
+        self
+    }
+}
+
+/// Graphviz diagram of the rounds of the key generation and the threshold
+/// sessions of a protocol; every edge is a call of `advance`, and a session
+/// of unbounded rounds repeats its first round of messages
+pub fn state_diagram(protocol_type: ProtocolType) -> Result<String> {
+    let info = protocol_info(protocol_type)?;
+    let mut out = format!("digraph {} {{\n", info.name);
+    for (session, rounds) in [
+        ("keygen", Some(info.keygen_rounds)),
+        ("threshold", info.threshold_rounds),
+    ] {
+        let rounds = match rounds {
+            Some(rounds) => rounds,
+            None => {
+                writeln!(
+                    out,
+                    "    \"{0} R1\" -> \"{0} R1\" [label=\"messages\"];",
+                    session
+                )?;
+                2
+            }
+        };
+        for round in 0..rounds {
+            let next = if round + 1 == rounds {
+                "Done".to_string()
+            } else {
+                format!("R{}", round + 1)
+            };
+            let label = if round == 0 { "init" } else { "messages" };
+            writeln!(
+                out,
+                "    \"{0} R{1}\" -> \"{0} {2}\" [label=\"{3}\"];",
+                session, round, next, label
+            )?;
+        }
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::ProtocolGroupInit;
+    use crate::protocol::keygen_context;
+    use prost::Message;
+
+    #[test]
+    fn keygen_state() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let state = ctx.debug_state();
+        assert_eq!(state.context, "frost_keygen");
+        assert_eq!(state.round, "R0");
+        assert_eq!(state.layers, ["replay", "version", "metadata"]);
+
+        let init = (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        })
+        .encode_to_vec();
+        ctx.advance(&init).unwrap();
+        let state = ctx.debug_state();
+        assert_eq!(state.round, "R1");
+        assert_eq!(state.expected_messages, Some(2));
+        assert_eq!(state.consumed, 0);
+    }
+
+    #[test]
+    fn diagram() {
+        let diagram = state_diagram(ProtocolType::Frost).unwrap();
+        assert!(diagram.contains("\"keygen R0\" -> \"keygen R1\" [label=\"init\"];"));
+        assert!(diagram.contains("\"threshold R2\" -> \"threshold Done\""));
+
+        let diagram = state_diagram(ProtocolType::Roast).unwrap();
+        assert!(diagram.contains("\"threshold R1\" -> \"threshold R1\""));
+        assert!(diagram.contains("\"threshold R1\" -> \"threshold Done\""));
+    }
+}
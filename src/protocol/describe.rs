@@ -0,0 +1,206 @@
+//! Summaries of the documents signed in a session, for user approval
+//!
+//! A client approving a signing session should show what is being signed,
+//! yet the session only carries a digest. `describe` recomputes the digest
+//! of the document the session claims to sign, fails unless it is the data
+//! of the `ProtocolInit`, and summarizes the document as labeled fields,
+//! so that what the user approves is what the group signs.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+#[cfg(feature = "gg18")]
+use crate::protocol::pdf;
+use crate::protocol::{eip712, one_based, psbt, Result};
+
+#[cfg(feature = "gg18")]
+use openssl::x509::X509;
+use prost::Message;
+use serde::Serialize;
+use serde_json::Value;
+use std::convert::TryFrom;
+
+/// Document signed in a session, in one of the supported formats
+pub enum Document<'a> {
+    /// EIP-712 typed data in the JSON format of `eth_signTypedData_v4`
+    Eip712(&'a [u8]),
+    /// Document processed by `pdf::prepare` and the signing certificate
+    #[cfg(feature = "gg18")]
+    Pdf {
+        pdf: &'a [u8],
+        certificate: &'a [u8],
+    },
+    /// PSBT whose input at the given index is spent by the key path
+    Psbt { psbt: &'a [u8], input: usize },
+}
+
+#[derive(Serialize)]
+pub struct Description {
+    pub format: &'static str,
+    pub protocol: String,
+    pub signers: Vec<u32>,
+    /// Labeled values in the order they should be shown
+    pub fields: Vec<(String, String)>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn eip712_fields(typed_data: &[u8]) -> Result<Vec<(String, String)>> {
+    let typed_data: Value = serde_json::from_slice(typed_data)?;
+    let primary = typed_data["primaryType"].as_str().unwrap_or_default();
+    let mut fields = vec![("primaryType".to_string(), primary.to_string())];
+    for section in ["domain", "message"] {
+        let members = typed_data[section]
+            .as_object()
+            .ok_or("malformed typed data")?;
+        for (name, value) in members {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            fields.push((format!("{}.{}", section, name), value));
+        }
+    }
+    Ok(fields)
+}
+
+#[cfg(feature = "gg18")]
+fn pdf_fields(pdf: &[u8], certificate: &[u8]) -> Result<Vec<(String, String)>> {
+    let certificate = X509::from_der(certificate)?;
+    let subject = certificate
+        .subject_name()
+        .entries()
+        .map(|entry| {
+            let name = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().as_utf8().map(|value| value.to_string());
+            Ok(format!("{}={}", name, value?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(vec![
+        ("size".to_string(), pdf.len().to_string()),
+        ("signer".to_string(), subject.join(", ")),
+    ])
+}
+
+fn psbt_fields(tx: &psbt::Transaction, input: usize) -> Result<Vec<(String, String)>> {
+    let spent = &tx.inputs[input];
+    let mut txid = spent.txid;
+    txid.reverse();
+    let mut fields = vec![(
+        "input".to_string(),
+        format!("{}:{}", hex(&txid), spent.vout),
+    )];
+    for (i, output) in tx.outputs.iter().enumerate() {
+        let value = format!("{} sat to {}", output.value, hex(&output.script));
+        fields.push((format!("output.{}", i), value));
+    }
+    if let Some(fee) = tx.fee()? {
+        fields.push(("fee".to_string(), format!("{} sat", fee)));
+    }
+    Ok(fields)
+}
+
+/// Summarize the document signed in the session initialized by `init`,
+/// failing unless the session signs the digest of the document, which
+/// a blind session does not
+pub fn describe(init: &[u8], document: Document) -> Result<Description> {
+    let msg = ProtocolInit::decode(init)?;
+    let protocol = ProtocolType::try_from(msg.protocol_type)?;
+    if msg.blind {
+        return Err("blind session signs an unknown message".into());
+    }
+
+    let (format, digest, fields) = match document {
+        Document::Eip712(typed_data) => (
+            "eip712",
+            eip712::hash_typed_data(typed_data)?,
+            eip712_fields(typed_data)?,
+        ),
+        #[cfg(feature = "gg18")]
+        Document::Pdf { pdf, certificate } => (
+            "pdf",
+            pdf::signature_digest(pdf, certificate)?,
+            pdf_fields(pdf, certificate)?,
+        ),
+        Document::Psbt { psbt, input } => {
+            let tx = psbt::parse(psbt)?;
+            let digest = psbt::sighash(&tx, input)?;
+            ("psbt", digest, psbt_fields(&tx, input)?)
+        }
+    };
+    if msg.data != digest {
+        return Err("session does not sign the document".into());
+    }
+
+    Ok(Description {
+        format,
+        protocol: protocol.as_str_name().to_lowercase(),
+        signers: one_based(protocol, &msg.indices),
+        fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init(data: Vec<u8>) -> Vec<u8> {
+        ProtocolInit {
+            protocol_type: ProtocolType::Musig2 as i32,
+            indices: vec![1, 2],
+            index: 1,
+            data,
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn psbt() {
+        let psbt = psbt::tests::example();
+        let digest = psbt::sighash(&psbt::parse(&psbt).unwrap(), 0).unwrap();
+        let document = || Document::Psbt {
+            psbt: &psbt,
+            input: 0,
+        };
+
+        let description = describe(&init(digest.clone()), document()).unwrap();
+        assert_eq!(description.format, "psbt");
+        assert_eq!(description.protocol, "musig2");
+        assert_eq!(description.signers, vec![1, 2]);
+        assert_eq!(
+            description.fields.last().unwrap(),
+            &("fee".to_string(), "1000 sat".to_string())
+        );
+
+        assert!(describe(&init(vec![0; 32]), document()).is_err());
+
+        let mut blind = ProtocolInit::decode(&init(digest.clone())[..]).unwrap();
+        blind.blind = true;
+        assert!(describe(&blind.encode_to_vec(), document()).is_err());
+    }
+
+    #[test]
+    fn eip712() {
+        let typed_data = br#"{
+            "types": {
+                "EIP712Domain": [{"name": "name", "type": "string"}],
+                "Order": [{"name": "amount", "type": "uint256"}]
+            },
+            "primaryType": "Order",
+            "domain": {"name": "Exchange"},
+            "message": {"amount": 42}
+        }"#;
+        let digest = eip712::hash_typed_data(typed_data).unwrap();
+
+        let description = describe(&init(digest), Document::Eip712(typed_data)).unwrap();
+        assert_eq!(
+            description.fields,
+            vec![
+                ("primaryType".to_string(), "Order".to_string()),
+                ("domain.name".to_string(), "Exchange".to_string()),
+                ("message.amount".to_string(), "42".to_string()),
+            ]
+        );
+    }
+}
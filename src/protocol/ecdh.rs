@@ -0,0 +1,404 @@
+//! Threshold ECDH with the key of a FROST P-256 group
+//!
+//! The group computes the shared secret with a counterparty public key
+//! without reconstructing its private key. Each participant multiplies the
+//! counterparty key by its share and proves the partial result consistent
+//! with its verifying share by a Chaum-Pedersen proof. The partial results
+//! are then combined by Lagrange interpolation into the shared point, whose
+//! x-coordinate is the result of the session, as in `DHKEM(P-256)` of HPKE.
+//!
+//! Groups are created by the `FrostP256` key generation, and the session is
+//! initialized with the SEC1-encoded counterparty public key as its data.
+//! The shared secret is only returned by `finish` and never sent out.
+//!
+//! Groups whose metadata has `separate_keys` set decrypt with their key
+//! shifted by a tweak hashed from the group key, which shifts every share
+//! by the same amount, so that the partial results never give away anything
+//! computed with the shares used for signing. The shifted public key is
+//! given by `decryption_key`.
+
+use crate::proto::{GroupResult, ProtocolInit, ProtocolType};
+use crate::protocol::frost::FrostKeygenContext;
+use crate::protocol::*;
+
+use frost_p256::keys::{KeyPackage, PublicKeyPackage};
+use frost_p256::{Identifier, P256Sha256};
+use p256::elliptic_curve::{ops::Reduce, sec1::ToEncodedPoint, Field, PrimeField};
+use p256::{ProjectivePoint, PublicKey, Scalar, U256};
+use prost::{bytes::Bytes, Message};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+const DOMAIN: &[u8] = b"meesign-ecdh-dleq";
+const TWEAK_DOMAIN: &[u8] = b"meesign-decryption-key";
+
+/// Partial result of a participant with the proof of its correctness
+#[derive(Serialize, Deserialize)]
+struct Partial {
+    point: Vec<u8>,
+    challenge: Vec<u8>,
+    response: Vec<u8>,
+}
+
+fn to_point(bytes: &[u8]) -> Result<ProjectivePoint> {
+    let key = PublicKey::from_sec1_bytes(bytes).map_err(|_| "invalid public key")?;
+    Ok(key.to_projective())
+}
+
+fn encode(point: &ProjectivePoint) -> Vec<u8> {
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+fn to_scalar(bytes: &[u8]) -> Result<Scalar> {
+    if bytes.len() != 32 {
+        return Err("invalid scalar".into());
+    }
+    Option::from(Scalar::from_repr(*p256::FieldBytes::from_slice(bytes)))
+        .ok_or_else(|| "invalid scalar".into())
+}
+
+/// Tweak shifting the key `group` of a group to its decryption key
+fn tweak(group: &ProjectivePoint) -> Scalar {
+    let digest = Sha256::new()
+        .chain_update(TWEAK_DOMAIN)
+        .chain_update(encode(group))
+        .finalize();
+    <Scalar as Reduce<U256>>::reduce_bytes(&digest)
+}
+
+fn separates_keys(group: &GroupResult) -> bool {
+    group
+        .metadata
+        .as_ref()
+        .map_or(false, |metadata| metadata.separate_keys)
+}
+
+/// Public key the group decrypts with, in the uncompressed SEC1 encoding;
+/// it differs from the signing key if the group separates the keys
+pub(crate) fn decryption_key(group: &[u8]) -> Result<Vec<u8>> {
+    let group = GroupResult::decode(group)?;
+    let mut key = to_point(&group.public_key)?;
+    if separates_keys(&group) {
+        key += ProjectivePoint::GENERATOR * tweak(&key);
+    }
+    Ok(key.to_affine().to_encoded_point(false).as_bytes().to_vec())
+}
+
+/// Challenge of the proof that `share` and `partial` have the same
+/// discrete logarithm to the generator and `point`, respectively
+fn challenge(
+    share: &ProjectivePoint,
+    point: &ProjectivePoint,
+    partial: &ProjectivePoint,
+    commitments: [&ProjectivePoint; 2],
+) -> Scalar {
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    for element in [share, point, partial, commitments[0], commitments[1]] {
+        hasher.update(encode(element));
+    }
+    <Scalar as Reduce<U256>>::reduce_bytes(&hasher.finalize())
+}
+
+fn prove(secret: &Scalar, point: &ProjectivePoint) -> Partial {
+    let partial = *point * secret;
+    let nonce = Scalar::random(&mut ProtocolRng);
+    let c = challenge(
+        &(ProjectivePoint::GENERATOR * *secret),
+        point,
+        &partial,
+        [&(ProjectivePoint::GENERATOR * nonce), &(*point * nonce)],
+    );
+    Partial {
+        point: encode(&partial),
+        challenge: c.to_bytes().to_vec(),
+        response: (nonce + c * secret).to_bytes().to_vec(),
+    }
+}
+
+fn verify(
+    partial: &Partial,
+    share: &ProjectivePoint,
+    point: &ProjectivePoint,
+) -> Result<ProjectivePoint> {
+    let result = to_point(&partial.point)?;
+    let c = to_scalar(&partial.challenge)?;
+    let z = to_scalar(&partial.response)?;
+    let commitments = [
+        ProjectivePoint::GENERATOR * z - *share * c,
+        *point * z - result * c,
+    ];
+    if challenge(share, point, &result, [&commitments[0], &commitments[1]]) != c {
+        return Err("invalid partial result".into());
+    }
+    Ok(result)
+}
+
+/// Lagrange coefficient of `x` for interpolation at zero over `xs`
+fn lagrange(x: u16, xs: &[u16]) -> Result<Scalar> {
+    let x_scalar = Scalar::from(x as u64);
+    let mut numerator = Scalar::ONE;
+    let mut denominator = Scalar::ONE;
+    for other in xs.iter().filter(|other| **other != x) {
+        let other = Scalar::from(*other as u64);
+        numerator *= other;
+        denominator *= other - x_scalar;
+    }
+    Option::<Scalar>::from(denominator.invert())
+        .map(|inverse| numerator * inverse)
+        .ok_or_else(|| "duplicate index".into())
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EcdhContext {
+    key: KeyPackage,
+    pubkey: PublicKeyPackage,
+    indices: Vec<u16>,
+    /// Counterparty public key, set once initialized
+    point: Option<Vec<u8>>,
+    partial: Option<Vec<u8>>,
+    result: Option<Vec<u8>>,
+    /// Whether to decrypt with the tweaked key, see `decryption_key`
+    #[serde(default)]
+    separate_keys: bool,
+}
+
+impl EcdhContext {
+    fn index(&self) -> Result<u16> {
+        self.indices
+            .iter()
+            .copied()
+            .find(|i| Identifier::try_from(*i).ok().as_ref() == Some(self.key.identifier()))
+            .ok_or_else(|| "participant index not included".into())
+    }
+
+    /// Tweak of the shares, zero unless the group separates the keys
+    fn tweak(&self) -> Result<Scalar> {
+        if !self.separate_keys {
+            return Ok(Scalar::ZERO);
+        }
+        Ok(tweak(&to_point(&self.pubkey.group_public().serialize())?))
+    }
+
+    /// Group public key for decryption in the uncompressed SEC1 encoding
+    pub(crate) fn public_key(&self) -> Result<Vec<u8>> {
+        let key = to_point(&self.pubkey.group_public().serialize())?
+            + ProjectivePoint::GENERATOR * self.tweak()?;
+        Ok(key.to_affine().to_encoded_point(false).as_bytes().to_vec())
+    }
+
+    /// Compute the partial result with the counterparty key `point`,
+    /// returning the messages for the other participants
+    pub(crate) fn start(&mut self, indices: Vec<u16>, point: Vec<u8>) -> Result<Vec<Bytes>> {
+        self.indices = indices;
+        self.index()?;
+        let secret = to_scalar(&self.key.secret_share().serialize())? + self.tweak()?;
+        let partial = prove(&secret, &to_point(&point)?);
+
+        let ser = serialize_bcast(&partial, self.indices.len() - 1)?;
+        self.point = Some(point);
+        self.partial = Some(partial.point);
+        Ok(ser)
+    }
+
+    /// Verify the partial results of the other participants and combine
+    /// them into the shared secret
+    pub(crate) fn combine<M: AsRef<[u8]>>(&mut self, msgs: &[M]) -> Result<Vec<u8>> {
+        if self.result.is_some() {
+            return Err("protocol already finished".into());
+        }
+        let point = to_point(self.point.as_ref().ok_or("protocol not initialized")?)?;
+
+        check_message_count(msgs, self.indices.len() - 1)?;
+        let partials: Vec<Partial> = deserialize_vec(msgs)?;
+
+        let index = self.index()?;
+        let tweak = ProjectivePoint::GENERATOR * self.tweak()?;
+        let others = self.indices.iter().filter(|i| **i != index);
+        let mut shared =
+            to_point(self.partial.as_ref().unwrap())? * lagrange(index, &self.indices)?;
+        for (other, partial) in others.zip(&partials) {
+            let share = self
+                .pubkey
+                .signer_pubkeys()
+                .get(&Identifier::try_from(*other)?)
+                .ok_or_else(|| format!("unknown party {}", other))?;
+            let share = to_point(&share.serialize())? + tweak;
+            let partial =
+                verify(partial, &share, &point).map_err(|e| format!("party {}: {}", other, e))?;
+            shared += partial * lagrange(*other, &self.indices)?;
+        }
+
+        let encoded = shared.to_affine().to_encoded_point(false);
+        let x = encoded.x().ok_or("shared secret is the identity")?.to_vec();
+        self.result = Some(x.clone());
+        Ok(x)
+    }
+
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::Ecdh.check(msg.protocol_type)?;
+
+        let indices = msg.indices.iter().map(|i| *i as u16).collect();
+        let ser = self.start(indices, msg.data)?;
+
+        if self.indices.len() == 1 {
+            return run_locally(1, ProtocolType::Ecdh, |data| self.update(data));
+        }
+        Ok(pack(ser, ProtocolType::Ecdh))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.combine(&unpack(data)?)?;
+        Ok(pack(Vec::new(), ProtocolType::Ecdh))
+    }
+}
+
+#[typetag::serde(name = "ecdh")]
+impl Protocol for EcdhContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.point.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.result.ok_or_else(|| "protocol not finished".into())
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.point.is_none() || self.result.is_some() {
+            return None;
+        }
+        Some(self.indices.len() - 1)
+    }
+}
+
+impl ThresholdProtocol for EcdhContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        let separate_keys =
+            GroupResult::decode(group).map_or(false, |group| separates_keys(&group));
+        let (key, pubkey): (KeyPackage, PublicKeyPackage) = parse_group_context(group)?;
+        Ok(Self {
+            key,
+            pubkey,
+            indices: Vec::new(),
+            point: None,
+            partial: None,
+            result: None,
+            separate_keys,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Ecdh,
+    name: "ecdh",
+    curve: "secp256r1",
+    scheme: "ecdh",
+    index_base: 1,
+    keygen_rounds: 3,
+    threshold_rounds: Some(2),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<FrostKeygenContext<P256Sha256>>,
+    threshold: registry::threshold::<EcdhContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::GroupMetadata;
+    use crate::protocol::tests::{run_keygen, KeygenProtocolTest, ThresholdProtocolTest};
+    use p256::SecretKey;
+    use rand::rngs::OsRng;
+
+    impl ThresholdProtocolTest for EcdhContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::Ecdh;
+        const ROUNDS: usize = 2;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    #[test]
+    fn agreement() {
+        // the key generation is initialized for the ECDH protocol itself
+        let (_, ctxs) = run_keygen::<FrostKeygenContext<P256Sha256>>(ProtocolType::Ecdh, 3, 2, 3);
+        let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.protocol_type, ProtocolType::Ecdh as i32);
+        let group = PublicKey::from_sec1_bytes(&group.public_key).unwrap();
+        let counterparty = SecretKey::random(&mut OsRng);
+        let shared = group.to_projective() * *counterparty.to_nonzero_scalar();
+        let expected = shared
+            .to_affine()
+            .to_encoded_point(false)
+            .x()
+            .unwrap()
+            .to_vec();
+
+        for indices in [vec![0, 1], vec![1, 2], vec![0, 1, 2]] {
+            let results = <EcdhContext as ThresholdProtocolTest>::run(
+                ctxs.clone(),
+                indices,
+                counterparty.public_key().to_sec1_bytes().to_vec(),
+            );
+            for result in results {
+                assert_eq!(result, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn separate_keys() {
+        let (_, ctxs) = <FrostKeygenContext<P256Sha256> as KeygenProtocolTest>::run(2, 3);
+        let ctxs: Vec<_> = ctxs
+            .iter()
+            .map(|ctx| {
+                let mut group = GroupResult::decode(&ctx[..]).unwrap();
+                group.metadata = Some(GroupMetadata {
+                    separate_keys: true,
+                    ..Default::default()
+                });
+                group.encode_to_vec()
+            })
+            .collect();
+        let key = decryption_key(&ctxs[0]).unwrap();
+        let signing_key = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        assert_ne!(to_point(&key).unwrap(), to_point(&signing_key).unwrap());
+
+        let counterparty = SecretKey::random(&mut OsRng);
+        let shared = to_point(&key).unwrap() * *counterparty.to_nonzero_scalar();
+        let expected = shared
+            .to_affine()
+            .to_encoded_point(false)
+            .x()
+            .unwrap()
+            .to_vec();
+        let results = <EcdhContext as ThresholdProtocolTest>::run(
+            ctxs,
+            vec![0, 2],
+            counterparty.public_key().to_sec1_bytes().to_vec(),
+        );
+        for result in results {
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn proof() {
+        let secret = Scalar::random(&mut OsRng);
+        let share = ProjectivePoint::GENERATOR * secret;
+        let point = ProjectivePoint::GENERATOR * Scalar::random(&mut OsRng);
+
+        let mut partial = prove(&secret, &point);
+        assert!(verify(&partial, &share, &point).is_ok());
+        assert!(verify(&partial, &point, &share).is_err());
+        partial.point = encode(&(point * (secret + Scalar::ONE)));
+        assert!(verify(&partial, &share, &point).is_err());
+    }
+}
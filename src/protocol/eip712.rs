@@ -0,0 +1,326 @@
+//! Hashing of EIP-712 typed structured data
+//!
+//! The typed data is passed in the JSON format of `eth_signTypedData_v4`,
+//! i.e. an object with the `types`, `primaryType`, `domain` and `message`
+//! members, and hashed into the digest to be signed. Integers may be given
+//! as JSON numbers, decimal strings, or `0x`-prefixed hexadecimal strings,
+//! and must fit the range of their type, e.g. `uint8` or `int128`.
+//!
+//! Ethereum expects recoverable ECDSA signatures over secp256k1, which none
+//! of the signing protocols produce: GG18 signs over P-256 and FROST produces
+//! Schnorr signatures. The digest is thus only usable with verifiers of
+//! the respective signature schemes, such as smart contract wallets.
+
+use crate::protocol::Result;
+use serde_json::{Map, Value};
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeSet;
+
+type Types = Map<String, Value>;
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn fields<'a>(types: &'a Types, name: &str) -> Result<Vec<(&'a str, &'a str)>> {
+    types
+        .get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("unknown type {}", name))?
+        .iter()
+        .map(|field| {
+            let name = field.get("name").and_then(Value::as_str);
+            let kind = field.get("type").and_then(Value::as_str);
+            name.zip(kind).ok_or_else(|| "malformed type field".into())
+        })
+        .collect()
+}
+
+/// Strip array suffixes from a type, e.g. `Person[][2]` to `Person`
+fn base_type(kind: &str) -> &str {
+    kind.split('[').next().unwrap_or(kind)
+}
+
+fn dependencies<'a>(types: &'a Types, name: &'a str, found: &mut BTreeSet<&'a str>) -> Result<()> {
+    if found.contains(name) || !types.contains_key(name) {
+        return Ok(());
+    }
+    found.insert(name);
+    for (_, kind) in fields(types, name)? {
+        dependencies(types, base_type(kind), found)?;
+    }
+    Ok(())
+}
+
+fn encode_type(types: &Types, name: &str) -> Result<String> {
+    let mut found = BTreeSet::new();
+    dependencies(types, name, &mut found)?;
+    found.remove(name);
+
+    let mut encoded = String::new();
+    for name in std::iter::once(name).chain(found) {
+        let members: Vec<String> = fields(types, name)?
+            .iter()
+            .map(|(name, kind)| format!("{} {}", kind, name))
+            .collect();
+        encoded += &format!("{}({})", name, members.join(","));
+    }
+    Ok(encoded)
+}
+
+/// Number of the significant bits of a big-endian integer
+fn bit_length(bytes: &[u8; 32]) -> usize {
+    bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .map_or(0, |i| (32 - i) * 8 - bytes[i].leading_zeros() as usize)
+}
+
+/// Signedness and width of an integer type such as `uint64` or `int`
+fn int_type(kind: &str) -> Result<(bool, usize)> {
+    let (signed, bits) = match kind.strip_prefix("uint") {
+        Some(bits) => (false, bits),
+        None => (true, kind.strip_prefix("int").ok_or("unknown type")?),
+    };
+    let bits = match bits {
+        "" => 256,
+        bits => bits.parse().map_err(|_| "unknown type")?,
+    };
+    if bits == 0 || bits > 256 || bits % 8 != 0 {
+        return Err(format!("unknown type {}", kind).into());
+    }
+    Ok((signed, bits))
+}
+
+/// Parse an integer of the given type into its 256-bit two's complement
+/// big-endian encoding, failing if it does not fit the type
+fn parse_int(kind: &str, value: &Value) -> Result<[u8; 32]> {
+    let (signed, bits) = int_type(kind)?;
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return Err("malformed integer".into()),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(digits) => (16, digits),
+        None => (10, digits),
+    };
+    if digits.is_empty() {
+        return Err("malformed integer".into());
+    }
+
+    let mut result = [0u8; 32];
+    for digit in digits.chars() {
+        let mut carry = digit.to_digit(radix).ok_or("malformed integer")?;
+        for byte in result.iter_mut().rev() {
+            let value = *byte as u32 * radix + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            return Err("integer out of range".into());
+        }
+    }
+
+    // the magnitude of a negative signed integer may reach 2^(bits - 1)
+    let length = bit_length(&result);
+    let in_range = match (signed, negative) {
+        (false, false) => length <= bits,
+        (false, true) => length == 0,
+        (true, false) => length < bits,
+        (true, true) => {
+            length < bits
+                || (length == bits && result.iter().map(|b| b.count_ones()).sum::<u32>() == 1)
+        }
+    };
+    if !in_range {
+        return Err("integer out of range".into());
+    }
+    if negative {
+        let mut carry = 1;
+        for byte in result.iter_mut().rev() {
+            let value = (!*byte) as u32 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+    }
+    Ok(result)
+}
+
+fn parse_bytes(value: &Value) -> Result<Vec<u8>> {
+    let text = value.as_str().ok_or("malformed bytes")?;
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    if text.len() % 2 != 0 {
+        return Err("malformed bytes".into());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| "malformed bytes".into())
+        })
+        .collect()
+}
+
+fn encode_value(types: &Types, kind: &str, value: &Value) -> Result<[u8; 32]> {
+    if let Some(element) = kind.strip_suffix(']') {
+        let element = &element[..element.rfind('[').ok_or("malformed array type")?];
+        let mut encoded = Vec::new();
+        for item in value.as_array().ok_or("malformed array")? {
+            encoded.extend(encode_value(types, element, item)?);
+        }
+        return Ok(keccak(&encoded));
+    }
+    if types.contains_key(kind) {
+        return hash_struct(types, kind, value);
+    }
+
+    match kind {
+        "string" => Ok(keccak(value.as_str().ok_or("malformed string")?.as_bytes())),
+        "bytes" => Ok(keccak(&parse_bytes(value)?)),
+        "bool" => {
+            let mut encoded = [0u8; 32];
+            encoded[31] = value.as_bool().ok_or("malformed bool")? as u8;
+            Ok(encoded)
+        }
+        "address" => {
+            let address = parse_bytes(value)?;
+            if address.len() != 20 {
+                return Err("malformed address".into());
+            }
+            let mut encoded = [0u8; 32];
+            encoded[12..].copy_from_slice(&address);
+            Ok(encoded)
+        }
+        _ if kind.starts_with("bytes") => {
+            let bytes = parse_bytes(value)?;
+            let size: usize = kind[5..].parse().map_err(|_| "unknown type")?;
+            if bytes.len() != size || size == 0 || size > 32 {
+                return Err("malformed fixed-size bytes".into());
+            }
+            let mut encoded = [0u8; 32];
+            encoded[..size].copy_from_slice(&bytes);
+            Ok(encoded)
+        }
+        _ if kind.starts_with("uint") || kind.starts_with("int") => parse_int(kind, value),
+        _ => Err(format!("unknown type {}", kind).into()),
+    }
+}
+
+fn hash_struct(types: &Types, name: &str, value: &Value) -> Result<[u8; 32]> {
+    let value = value.as_object().ok_or("malformed struct")?;
+    let mut encoded = keccak(encode_type(types, name)?.as_bytes()).to_vec();
+    for (field, kind) in fields(types, name)? {
+        let member = value
+            .get(field)
+            .ok_or_else(|| format!("missing member {}", field))?;
+        encoded.extend(encode_value(types, kind, member)?);
+    }
+    Ok(keccak(&encoded))
+}
+
+/// Compute the digest of EIP-712 typed data to be signed
+pub fn hash_typed_data(typed_data: &[u8]) -> Result<Vec<u8>> {
+    let typed_data: Value = serde_json::from_slice(typed_data)?;
+    let types = typed_data
+        .get("types")
+        .and_then(Value::as_object)
+        .ok_or("missing types")?;
+    let primary = typed_data
+        .get("primaryType")
+        .and_then(Value::as_str)
+        .ok_or("missing primary type")?;
+    let domain = typed_data.get("domain").ok_or("missing domain")?;
+    let message = typed_data.get("message").ok_or("missing message")?;
+
+    let mut encoded = vec![0x19, 0x01];
+    encoded.extend(hash_struct(types, "EIP712Domain", domain)?);
+    encoded.extend(hash_struct(types, primary, message)?);
+    Ok(keccak(&encoded).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example from the EIP-712 specification
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn mail() {
+        let typed_data: Value = serde_json::from_str(MAIL).unwrap();
+        let types = typed_data["types"].as_object().unwrap();
+        assert_eq!(
+            encode_type(types, "Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex(&hash_typed_data(MAIL.as_bytes()).unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn integers() {
+        let mut minus_one = [0xff; 32];
+        assert_eq!(parse_int("int256", &Value::from(-1)).unwrap(), minus_one);
+        minus_one[31] = 0x10;
+        assert_eq!(parse_int("int", &Value::from("-0xf0")).unwrap(), minus_one);
+        let large = Value::from(format!("0x1{}", "0".repeat(64)));
+        assert!(parse_int("uint256", &large).is_err());
+
+        assert!(parse_int("uint8", &Value::from(255)).is_ok());
+        assert!(parse_int("uint8", &Value::from(256)).is_err());
+        assert!(parse_int("uint8", &Value::from(-1)).is_err());
+        assert!(parse_int("uint8", &Value::from("-0")).is_ok());
+        assert!(parse_int("int8", &Value::from(127)).is_ok());
+        assert!(parse_int("int8", &Value::from(128)).is_err());
+        assert!(parse_int("int8", &Value::from(-128)).is_ok());
+        assert!(parse_int("int8", &Value::from(-129)).is_err());
+        assert!(parse_int("int8", &Value::from("-0x81")).is_err());
+        let max = Value::from(format!("0x{}", "f".repeat(64)));
+        assert!(parse_int("uint256", &max).is_ok());
+        assert!(parse_int("int256", &max).is_err());
+        assert!(parse_int("uint7", &Value::from(1)).is_err());
+        assert!(parse_int("int264", &Value::from(1)).is_err());
+    }
+}
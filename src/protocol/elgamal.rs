@@ -1,16 +1,20 @@
 use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::protocol::proofs::{self, Proof};
+use crate::protocol::telemetry::telemetry;
 use crate::protocol::*;
 use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
     ristretto::{CompressedRistretto, RistrettoPoint},
     scalar::Scalar,
+    traits::Identity,
 };
 use elastic_elgamal::{
     dkg::*,
     group::{ElementOps, Ristretto},
-    sharing::{ActiveParticipant, Params},
+    sharing::{ActiveParticipant, Params, PublicKeySet},
     Ciphertext, LogEqualityProof, PublicKey, VerifiableDecryption,
 };
-use rand::rngs::OsRng;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
 
 use aes_gcm::{
     aead::{Aead, AeadCore, KeyInit, Payload},
@@ -19,9 +23,178 @@ use aes_gcm::{
 use prost::Message;
 use serde::{Deserialize, Serialize};
 
+/// Hybrid ciphertext as produced by `encrypt`: the ElGamal-encrypted AES key,
+/// the nonce, and the AES-GCM ciphertext. Once re-encrypted to another group,
+/// the original encrypted key is carried along, as the AES-GCM ciphertext
+/// remains bound to it. Ciphertexts of the current version carry a `Label`
+/// binding their encrypted key to the associated data; those of older
+/// versions carry none and are decrypted only by the parties which opted
+/// in by `ContextOptions::legacy_ciphertexts`, and only in the sessions
+/// without associated data, so that a label cannot be stripped to pass
+/// a ciphertext off under other associated data.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EncryptedData {
+    Direct(Vec<u8>, Vec<u8>, Vec<u8>),
+    Reencrypted(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>),
+    Labelled(Vec<u8>, Vec<u8>, Vec<u8>, Label),
+    LabelledReencrypted(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, Label),
+}
+
+impl EncryptedData {
+    fn encrypted_key(&self) -> &[u8] {
+        match self {
+            EncryptedData::Direct(key, _, _)
+            | EncryptedData::Reencrypted(key, _, _, _)
+            | EncryptedData::Labelled(key, _, _, _)
+            | EncryptedData::LabelledReencrypted(key, _, _, _, _) => key,
+        }
+    }
+
+    fn nonce(&self) -> &[u8] {
+        match self {
+            EncryptedData::Direct(_, nonce, _)
+            | EncryptedData::Reencrypted(_, nonce, _, _)
+            | EncryptedData::Labelled(_, nonce, _, _)
+            | EncryptedData::LabelledReencrypted(_, nonce, _, _, _) => nonce,
+        }
+    }
+
+    fn ciphertext(&self) -> &[u8] {
+        match self {
+            EncryptedData::Direct(_, _, ct)
+            | EncryptedData::Reencrypted(_, _, ct, _)
+            | EncryptedData::Labelled(_, _, ct, _)
+            | EncryptedData::LabelledReencrypted(_, _, ct, _, _) => ct,
+        }
+    }
+
+    fn aad(&self) -> &[u8] {
+        match self {
+            EncryptedData::Direct(key, _, _) | EncryptedData::Labelled(key, _, _, _) => key,
+            EncryptedData::Reencrypted(_, _, _, aad)
+            | EncryptedData::LabelledReencrypted(_, _, _, aad, _) => aad,
+        }
+    }
+
+    fn label(&self) -> Option<&Label> {
+        match self {
+            EncryptedData::Labelled(_, _, _, label)
+            | EncryptedData::LabelledReencrypted(_, _, _, _, label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Check that the ciphertext is bound to `aad`, accepting one without
+    /// a label if `legacy` is set and there is no associated data
+    fn check_label(
+        &self,
+        encrypted_key: &Ciphertext<Ristretto>,
+        aad: &[u8],
+        legacy: bool,
+    ) -> Result<()> {
+        match self.label() {
+            Some(label) => label.verify(encrypted_key, aad),
+            None if legacy && aad.is_empty() => Ok(()),
+            None => Err("ciphertext not bound to associated data".into()),
+        }
+    }
+}
+
+/// Associated data of the AES-GCM ciphertext, the encrypted key followed by
+/// the associated data of the caller, if any; the NUL byte separating them
+/// never occurs in the JSON encoding of the key
+fn bound_aad(key: &[u8], aad: &[u8]) -> Vec<u8> {
+    if aad.is_empty() {
+        return key.to_vec();
+    }
+    [key, b"\0", aad].concat()
+}
+
+const LABEL_DOMAIN: &[u8] = b"meesign elgamal label";
+const RERANDOMIZE_DOMAIN: &[u8] = b"meesign elgamal rerandomize";
+
+/// Binds an encrypted key to the associated data, in the manner of TDH2:
+/// its random element is split into parts, each with a proof of knowledge
+/// of its discrete logarithm. The parts of the encryptor, or of each party
+/// of a re-encryption, are proven with the associated data as the context,
+/// while those added by `rerandomize` are proven in a context of their own.
+/// As no one can tell the randomness of a ciphertext encrypted by someone
+/// else, a ciphertext cannot be passed off under other associated data,
+/// and the parties check the label before releasing their shares.
+#[derive(Clone, Serialize, Deserialize)]
+struct Label {
+    parts: Vec<([u8; 32], Proof)>,
+    rerandomized: Vec<([u8; 32], Proof)>,
+}
+
+/// Prove the knowledge of `randomness` behind its multiple of the generator
+fn prove_randomness(domain: &[u8], context: &[u8], randomness: Scalar) -> ([u8; 32], Proof) {
+    let point = randomness * RISTRETTO_BASEPOINT_POINT;
+    let proof = proofs::prove(
+        domain,
+        context,
+        &[vec![RISTRETTO_BASEPOINT_POINT]],
+        &[point],
+        &[randomness],
+    );
+    (point.compress().to_bytes(), proof)
+}
+
+fn verify_randomness(
+    domain: &[u8],
+    context: &[u8],
+    part: &([u8; 32], Proof),
+) -> Result<RistrettoPoint> {
+    let point = proofs::decode_point(&part.0)?;
+    proofs::verify(
+        domain,
+        context,
+        &[vec![RISTRETTO_BASEPOINT_POINT]],
+        &[point],
+        &part.1,
+    )?;
+    Ok(point)
+}
+
+impl Label {
+    /// Check that the parts add up to the random element of `encrypted_key`
+    /// and that the proofs of those of the encryptors are bound to `aad`
+    fn verify(&self, encrypted_key: &Ciphertext<Ristretto>, aad: &[u8]) -> Result<()> {
+        if self.parts.is_empty() {
+            return Err("ciphertext not bound to associated data".into());
+        }
+        let mut sum = RistrettoPoint::identity();
+        for part in &self.parts {
+            sum += verify_randomness(LABEL_DOMAIN, aad, part)
+                .map_err(|_| "ciphertext not bound to associated data")?;
+        }
+        for part in &self.rerandomized {
+            sum += verify_randomness(RERANDOMIZE_DOMAIN, b"", part)
+                .map_err(|_| "invalid re-randomization")?;
+        }
+        if sum != encrypted_key.random_element() {
+            return Err("ciphertext not bound to associated data".into());
+        }
+        Ok(())
+    }
+}
+
+/// Protocols whose groups the ElGamal key generation creates
+const GROUP_TYPES: &[ProtocolType] = &[
+    ProtocolType::Elgamal,
+    ProtocolType::ElgamalReencrypt,
+    ProtocolType::Ppss,
+];
+
 #[derive(Serialize, Deserialize)]
 pub(crate) struct KeygenContext {
     round: KeygenRound,
+    /// Protocol of the group being generated, one of `GROUP_TYPES`,
+    /// which tags the messages and the result; unknown for contexts
+    /// created by older versions, which generate ElGamal groups only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol_type: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,24 +207,37 @@ enum KeygenRound {
 }
 
 impl KeygenContext {
+    fn protocol_type(&self) -> ProtocolType {
+        self.protocol_type
+            .and_then(ProtocolType::from_i32)
+            .unwrap_or(ProtocolType::Elgamal)
+    }
+
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let msg = ProtocolGroupInit::decode(data)?;
 
-        if msg.protocol_type != ProtocolType::Elgamal as i32 {
-            return Err("wrong protocol type".into());
-        }
+        let protocol_type = ProtocolType::check_any(GROUP_TYPES, msg.protocol_type)?;
+        check_group_params(&msg, 0)?;
+        self.protocol_type = Some(protocol_type as i32);
 
         let (parties, threshold, index) =
             (msg.parties as u16, msg.threshold as u16, msg.index as u16);
 
         let params = Params::new(parties.into(), threshold.into());
 
-        let dkg =
-            ParticipantCollectingCommitments::<Ristretto>::new(params, index.into(), &mut OsRng);
+        let dkg = ParticipantCollectingCommitments::<Ristretto>::new(
+            params,
+            index.into(),
+            &mut ProtocolRng,
+        );
         let c = dkg.commitment();
         let ser = serialize_bcast(&c, msg.parties as usize - 1)?;
         self.round = KeygenRound::R1(dkg, index);
-        Ok(pack(ser, ProtocolType::Elgamal))
+
+        if parties == 1 {
+            return run_locally(3, protocol_type, |data| self.update(data));
+        }
+        Ok(pack(ser, protocol_type))
     }
 
     fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
@@ -62,6 +248,7 @@ impl KeygenContext {
             KeygenRound::R0 => return Err("protocol not initialized".into()),
             KeygenRound::R1(dkg, idx) => {
                 let mut dkg = dkg.clone();
+                check_message_count(&msgs, dkg.missing_commitments().count())?;
                 let data = deserialize_vec(&msgs)?;
                 for (mut i, msg) in data.into_iter().enumerate() {
                     if i >= *idx as usize {
@@ -80,6 +267,7 @@ impl KeygenContext {
             }
             KeygenRound::R2(dkg, idx) => {
                 let mut dkg = dkg.clone();
+                check_message_count(&msgs, dkg.missing_public_polynomials().count())?;
                 let data = deserialize_vec(&msgs)?;
                 for (mut i, msg) in data.into_iter().enumerate() {
                     if i >= *idx as usize {
@@ -106,6 +294,7 @@ impl KeygenContext {
             }
             KeygenRound::R3(dkg, idx) => {
                 let mut dkg = dkg.clone();
+                check_message_count(&msgs, dkg.missing_shares().count())?;
                 let data = deserialize_vec(&msgs)?;
                 for (mut i, msg) in data.into_iter().enumerate() {
                     if i >= *idx as usize {
@@ -125,7 +314,7 @@ impl KeygenContext {
         };
 
         self.round = c;
-        Ok(pack(ser, ProtocolType::Elgamal))
+        Ok(pack(ser, self.protocol_type()))
     }
 }
 
@@ -140,53 +329,97 @@ impl Protocol for KeygenContext {
     }
 
     fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let protocol_type = self.protocol_type();
         match self.round {
-            KeygenRound::Done(ctx) => Ok(serde_json::to_vec(&ctx)?),
+            KeygenRound::Done(ctx) => Ok(pack_group(
+                protocol_type,
+                ctx.key_set().shared_key().as_bytes().to_vec(),
+                serde_json::to_vec(&ctx)?,
+            )),
             _ => Err("protocol not finished".into()),
         }
     }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match &self.round {
+            KeygenRound::R0 | KeygenRound::Done(_) => None,
+            KeygenRound::R1(dkg, _) => Some(dkg.missing_commitments().count()),
+            KeygenRound::R2(dkg, _) => Some(dkg.missing_public_polynomials().count()),
+            KeygenRound::R3(dkg, _) => Some(dkg.missing_shares().count()),
+        }
+    }
 }
 
 impl KeygenProtocol for KeygenContext {
     fn new() -> Self {
         Self {
             round: KeygenRound::R0,
+            protocol_type: None,
         }
     }
 }
 
+/// Decrypts either a single ciphertext produced by `encrypt` or a batch
+/// of them passed as a JSON array, in which case the result is a JSON
+/// array of the plaintexts in the same order
 #[derive(Serialize, Deserialize)]
 pub(crate) struct DecryptContext {
     ctx: ActiveParticipant<Ristretto>,
-    encrypted_key: Ciphertext<Ristretto>,
-    data: (Vec<u8>, Vec<u8>, Vec<u8>),
+    encrypted_keys: Vec<Ciphertext<Ristretto>>,
+    data: Vec<EncryptedData>,
+    batch: bool,
     indices: Vec<u16>,
-    shares: Vec<(usize, VerifiableDecryption<Ristretto>)>,
+    shares: Vec<Vec<(usize, VerifiableDecryption<Ristretto>)>>,
     result: Option<Vec<u8>>,
+    /// Associated data the ciphertexts are expected to be bound to
+    #[serde(default)]
+    aad: Vec<u8>,
+    #[serde(default)]
+    options: ContextOptions,
 }
 
 impl DecryptContext {
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let msg = ProtocolInit::decode(data)?;
 
-        if msg.protocol_type != ProtocolType::Elgamal as i32 {
-            return Err("wrong protocol type".into());
-        }
+        ProtocolType::Elgamal.check(msg.protocol_type)?;
 
         self.indices = msg.indices.clone().into_iter().map(|i| i as u16).collect();
-        self.data = serde_json::from_slice(&msg.data)?;
-        self.encrypted_key = serde_json::from_slice(&self.data.0)?;
+        if !self.indices.contains(&(self.ctx.index() as u16)) {
+            return Err("participant index not included".into());
+        }
+        let (data, batch) = parse_request(&msg.data)?;
+        self.encrypted_keys = encrypted_keys(&data)?;
+        // no share is released for a ciphertext bound to other associated data
+        for (data, encrypted_key) in data.iter().zip(&self.encrypted_keys) {
+            data.check_label(encrypted_key, &msg.aad, self.options.legacy_ciphertexts)?;
+        }
+        self.data = data;
+        self.batch = batch;
+        self.aad = msg.aad;
 
-        let (share, proof) = self.ctx.decrypt_share(self.encrypted_key, &mut OsRng);
+        let decryptions: Vec<_> = self
+            .encrypted_keys
+            .iter()
+            .map(|encrypted_key| self.ctx.decrypt_share(*encrypted_key, &mut ProtocolRng))
+            .collect();
 
-        let ser = serialize_bcast(
-            &serde_json::to_string(&(share, proof))?.as_bytes(),
-            self.indices.len() - 1,
-        )?;
+        let payload = if self.batch {
+            serde_json::to_string(&decryptions)?
+        } else {
+            serde_json::to_string(&decryptions[0])?
+        };
+        let ser = serialize_bcast(&payload.as_bytes(), self.indices.len() - 1)?;
 
-        let share = (self.ctx.index(), share);
-        self.shares.push(share);
+        let index = self.ctx.index();
+        self.shares = decryptions
+            .into_iter()
+            .map(|(share, _)| vec![(index, share)])
+            .collect();
 
+        if self.indices.len() == 1 {
+            return run_locally(1, ProtocolType::Elgamal, |data| self.update(data));
+        }
         Ok(pack(ser, ProtocolType::Elgamal))
     }
 
@@ -199,6 +432,7 @@ impl DecryptContext {
         }
 
         let msgs = unpack(data)?;
+        check_message_count(&msgs, self.indices.len() - 1)?;
 
         let data: Vec<Vec<u8>> = deserialize_vec(&msgs)?;
         let local_index = self
@@ -212,33 +446,60 @@ impl DecryptContext {
             if i >= local_index {
                 i += 1;
             }
-            let msg: (VerifiableDecryption<Ristretto>, LogEqualityProof<Ristretto>) =
-                serde_json::from_slice(&msg)?;
-            self.ctx
-                .key_set()
-                .verify_share(
-                    msg.0.into(),
-                    self.encrypted_key,
-                    self.indices[i].into(),
-                    &msg.1,
-                )
-                .unwrap();
-            self.shares.push((self.indices[i].into(), msg.0));
-        }
-
-        let mut key = [0u8; 16];
-        key.copy_from_slice(&decode(
-            self.encrypted_key.blinded_element()
-                - self
-                    .ctx
-                    .key_set()
-                    .params()
-                    .combine_shares(self.shares.clone())
-                    .unwrap()
-                    .as_element(),
-        ));
+            let decryptions = parse_decryptions(&msg, self.batch)?;
+            verify_decryptions(
+                self.ctx.key_set(),
+                &self.encrypted_keys,
+                self.indices[i],
+                &decryptions,
+            )?;
+
+            for ((share, _), shares) in decryptions.into_iter().zip(self.shares.iter_mut()) {
+                shares.push((self.indices[i].into(), share));
+            }
+        }
+
+        let mut msgs = self
+            .encrypted_keys
+            .iter()
+            .zip(&self.shares)
+            .zip(&self.data)
+            .map(|((encrypted_key, shares), data)| self.decrypt(encrypted_key, shares, data))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.result = Some(pack_plaintexts(ProtocolType::Elgamal, msgs.clone()));
+
+        let msg = if self.batch {
+            serde_json::to_vec(&msgs)?
+        } else {
+            msgs.remove(0)
+        };
+
+        let ser = inflate(msg, self.indices.len() - 1);
+        Ok(pack(ser, ProtocolType::Elgamal))
+    }
+
+    fn decrypt(
+        &self,
+        encrypted_key: &Ciphertext<Ristretto>,
+        shares: &[(usize, VerifiableDecryption<Ristretto>)],
+        data: &EncryptedData,
+    ) -> Result<Vec<u8>> {
+        let combined = self
+            .ctx
+            .key_set()
+            .params()
+            .combine_shares(shares.to_vec())
+            .ok_or("could not combine decryption shares")?;
+        let key = decode(encrypted_key.blinded_element() - combined.as_element());
+        if key.len() != 16 || data.nonce().len() != 12 {
+            return Err("malformed ciphertext".into());
+        }
         let mut nonce = [0u8; 12];
-        nonce.copy_from_slice(&self.data.1);
+        nonce.copy_from_slice(data.nonce());
+        let mut key_bytes = [0u8; 16];
+        key_bytes.copy_from_slice(&key);
+        let key = key_bytes;
 
         let cipher = Aes128Gcm::new(&key.into());
 
@@ -246,17 +507,83 @@ impl DecryptContext {
             .decrypt(
                 &nonce.into(),
                 Payload {
-                    msg: &self.data.2,
-                    aad: &self.data.0,
+                    msg: data.ciphertext(),
+                    aad: &bound_aad(data.aad(), &self.aad),
                 },
             )
-            .unwrap();
+            .map_err(|_| "decryption failed")?;
 
-        self.result = Some(msg.clone());
+        Ok(msg)
+    }
+}
 
-        let ser = inflate(msg, self.indices.len() - 1);
-        Ok(pack(ser, ProtocolType::Elgamal))
+type Decryption = (VerifiableDecryption<Ristretto>, LogEqualityProof<Ristretto>);
+
+/// Parse the data of a decryption request, which is either
+/// a single ciphertext or a JSON array of them
+fn parse_request(data: &[u8]) -> Result<(Vec<EncryptedData>, bool)> {
+    let (data, batch) = match serde_json::from_slice(data) {
+        Ok(batch) => (batch, true),
+        Err(_) => (vec![serde_json::from_slice(data)?], false),
+    };
+    if data.is_empty() {
+        return Err("no ciphertexts to decrypt".into());
+    }
+    Ok((data, batch))
+}
+
+fn encrypted_keys(data: &[EncryptedData]) -> Result<Vec<Ciphertext<Ristretto>>> {
+    Ok(data
+        .iter()
+        .map(|data| serde_json::from_slice(data.encrypted_key()))
+        .collect::<serde_json::Result<_>>()?)
+}
+
+fn parse_decryptions(msg: &[u8], batch: bool) -> Result<Vec<Decryption>> {
+    Ok(if batch {
+        serde_json::from_slice(msg)?
+    } else {
+        vec![serde_json::from_slice(msg)?]
+    })
+}
+
+/// Check the proofs of correct decryption sent by the party `index`
+fn verify_decryptions(
+    key_set: &PublicKeySet<Ristretto>,
+    encrypted_keys: &[Ciphertext<Ristretto>],
+    index: u16,
+    decryptions: &[Decryption],
+) -> Result<()> {
+    if decryptions.len() != encrypted_keys.len() {
+        return Err(format!("wrong number of decryption shares from party {}", index).into());
+    }
+    for ((share, proof), encrypted_key) in decryptions.iter().zip(encrypted_keys) {
+        key_set
+            .verify_share((*share).into(), *encrypted_key, index.into(), proof)
+            .map_err(|_| {
+                telemetry!(warn, party = index, "invalid decryption share");
+                format!("invalid decryption share from party {}", index)
+            })?;
     }
+    Ok(())
+}
+
+/// Extract the public part of a group context, which is needed
+/// to audit decryptions using `verify_decryption_share`
+pub fn public_key_set(group: &[u8]) -> Result<Vec<u8>> {
+    let ctx: ActiveParticipant<Ristretto> = parse_group_context(group)?;
+    Ok(serde_json::to_vec(ctx.key_set())?)
+}
+
+/// Verify a decryption share message relayed from the party `index`
+/// for the request `data`, so that third parties can check a decryption
+/// transcript without access to any secrets
+pub fn verify_decryption_share(key_set: &[u8], data: &[u8], index: u16, msg: &[u8]) -> Result<()> {
+    let key_set: PublicKeySet<Ristretto> = serde_json::from_slice(key_set)?;
+    let (data, batch) = parse_request(data)?;
+    let msg: Vec<u8> = serde_json::from_slice(msg)?;
+    let decryptions = parse_decryptions(&msg, batch)?;
+    verify_decryptions(&key_set, &encrypted_keys(&data)?, index, &decryptions)
 }
 
 #[typetag::serde(name = "elgamal_decrypt")]
@@ -271,26 +598,358 @@ impl Protocol for DecryptContext {
     }
 
     fn finish(self: Box<Self>) -> Result<Vec<u8>> {
-        if self.result.is_none() {
-            return Err("protocol not finished".into());
+        self.result.ok_or_else(|| "protocol not finished".into())
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.shares.is_empty() || self.result.is_some() {
+            return None;
         }
-        Ok(self.result.unwrap())
+        Some(self.indices.len() - 1)
     }
 }
 
 impl ThresholdProtocol for DecryptContext {
-    fn new(group: &[u8]) -> Self {
-        Self {
-            ctx: serde_json::from_slice(group).expect("could not deserialize group context"),
-            encrypted_key: Ciphertext::zero(),
-            data: (Vec::new(), Vec::new(), Vec::new()),
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            ctx: parse_group_context(group)?,
+            encrypted_keys: Vec::new(),
+            data: Vec::new(),
+            batch: false,
             indices: Vec::new(),
             shares: Vec::new(),
             result: None,
+            aad: Vec::new(),
+            options: ContextOptions::default(),
+        })
+    }
+
+    fn configure(&mut self, options: &ContextOptions) {
+        self.options = *options;
+    }
+}
+
+/// Transforms a ciphertext encrypted to this group into one encrypted to
+/// the target public key without revealing the plaintext to anyone.
+///
+/// Each party encrypts its Lagrange-weighted decryption share, negated, to the
+/// target key; the first party additionally includes the blinded element of
+/// the original ciphertext. The sum of these ciphertexts then encrypts the
+/// original message to the target key. Each part comes with a proof that it
+/// was computed so by the share behind the public key share of its party,
+/// which the other parties check before adding it up. The label of the
+/// ciphertext is checked against the associated data of the request before
+/// any part is released, and each party labels the random element of its
+/// part, so that the result is bound to the same associated data.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReencryptContext {
+    ctx: ActiveParticipant<Ristretto>,
+    data: Option<EncryptedData>,
+    /// Key the ciphertext is re-encrypted to, in which the parts are checked
+    #[serde(default)]
+    target: Vec<u8>,
+    indices: Vec<u16>,
+    parts: Vec<Ciphertext<Ristretto>>,
+    result: Option<Vec<u8>>,
+    /// Associated data the ciphertext and the result are bound to
+    #[serde(default)]
+    aad: Vec<u8>,
+    /// Label of the random element of the own part
+    #[serde(default)]
+    label: Option<([u8; 32], Proof)>,
+    #[serde(default)]
+    options: ContextOptions,
+}
+
+const REENCRYPT_DOMAIN: &[u8] = b"meesign elgamal reencrypt";
+
+impl ReencryptContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+
+        ProtocolType::ElgamalReencrypt.check(msg.protocol_type)?;
+
+        self.indices = msg.indices.into_iter().map(|i| i as u16).collect();
+        let (data, target): (Vec<u8>, Vec<u8>) = serde_json::from_slice(&msg.data)?;
+        let data: EncryptedData = serde_json::from_slice(&data)?;
+        let target_key: PublicKey<Ristretto> =
+            PublicKey::from_bytes(&target).map_err(|_| "invalid target public key")?;
+        let encrypted_key: Ciphertext<Ristretto> = serde_json::from_slice(data.encrypted_key())?;
+        data.check_label(&encrypted_key, &msg.aad, self.options.legacy_ciphertexts)?;
+
+        let index = self.ctx.index() as u16;
+        let secret = *self.ctx.secret_share().expose_scalar();
+        let coefficient = lagrange_coefficient(index, &self.indices)?;
+        let mut element = -(coefficient * secret * encrypted_key.random_element());
+        if self.indices[0] == index {
+            element += encrypted_key.blinded_element();
+        }
+        let (part, randomness) = encrypt_known(&target_key, element)?;
+        let (bases, images) =
+            self.reencryption_statement(index, &part, &encrypted_key, &target_key)?;
+        let proof = proofs::prove(
+            REENCRYPT_DOMAIN,
+            b"",
+            &bases,
+            &images,
+            &[randomness, secret],
+        );
+        let label = prove_randomness(LABEL_DOMAIN, &msg.aad, randomness);
+
+        let ser = serialize_bcast(&(&part, &proof, &label), self.indices.len() - 1)?;
+        self.data = Some(data);
+        self.target = target;
+        self.parts.push(part);
+        self.aad = msg.aad;
+        self.label = Some(label);
+
+        if self.indices.len() == 1 {
+            return run_locally(1, ProtocolType::ElgamalReencrypt, |data| self.update(data));
+        }
+        Ok(pack(ser, ProtocolType::ElgamalReencrypt))
+    }
+
+    /// Statement of the part of the party `index`: its random element is
+    /// a multiple of the generator by some randomness, and its blinded
+    /// element, less that of the original ciphertext for the first party,
+    /// is the target key by the randomness plus the random element of the
+    /// original ciphertext by the negated Lagrange-weighted share, which
+    /// is also behind the public key share of the party
+    fn reencryption_statement(
+        &self,
+        index: u16,
+        part: &Ciphertext<Ristretto>,
+        encrypted_key: &Ciphertext<Ristretto>,
+        target: &PublicKey<Ristretto>,
+    ) -> Result<(Vec<Vec<RistrettoPoint>>, Vec<RistrettoPoint>)> {
+        let coefficient = lagrange_coefficient(index, &self.indices)?;
+        let key = self
+            .ctx
+            .key_set()
+            .participant_key(index as usize)
+            .ok_or("participant index not included")?
+            .as_element();
+        let mut blinded = part.blinded_element();
+        if self.indices[0] == index {
+            blinded -= encrypted_key.blinded_element();
+        }
+        let zero = RistrettoPoint::identity();
+        Ok((
+            vec![
+                vec![RISTRETTO_BASEPOINT_POINT, zero],
+                vec![
+                    target.as_element(),
+                    -(coefficient * encrypted_key.random_element()),
+                ],
+                vec![zero, RISTRETTO_BASEPOINT_POINT],
+            ],
+            vec![part.random_element(), blinded, key],
+        ))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.result.is_some() {
+            return Err("protocol already finished".into());
+        }
+        let original = self.data.as_ref().ok_or("protocol not initialized")?;
+        let encrypted_key: Ciphertext<Ristretto> =
+            serde_json::from_slice(original.encrypted_key())?;
+        let target: PublicKey<Ristretto> =
+            PublicKey::from_bytes(&self.target).map_err(|_| "invalid target public key")?;
+
+        let msgs = unpack(data)?;
+        if msgs.len() + 1 != self.indices.len() {
+            return Err("wrong number of re-encryption shares".into());
+        }
+        let parts: Vec<(Ciphertext<Ristretto>, Proof, ([u8; 32], Proof))> = deserialize_vec(&msgs)?;
+        let local_index = self
+            .indices
+            .iter()
+            .position(|x| *x as usize == self.ctx.index())
+            .ok_or("participant index not included")?;
+        let label = self.label.clone().ok_or("protocol not initialized")?;
+
+        let mut sum = self.parts[0];
+        let mut labels = Vec::new();
+        for (mut i, (part, proof, label)) in parts.into_iter().enumerate() {
+            if i >= local_index {
+                i += 1;
+            }
+            let index = self.indices[i];
+            let (bases, images) =
+                self.reencryption_statement(index, &part, &encrypted_key, &target)?;
+            proofs::verify(REENCRYPT_DOMAIN, b"", &bases, &images, &proof).map_err(|_| {
+                telemetry!(warn, party = index, "invalid re-encryption share");
+                format!("invalid re-encryption share from party {}", index)
+            })?;
+            match verify_randomness(LABEL_DOMAIN, &self.aad, &label) {
+                Ok(point) if point == part.random_element() => {}
+                _ => return Err(format!("invalid label from party {}", index).into()),
+            }
+            sum = sum + part;
+            labels.push(label);
+        }
+        labels.insert(local_index, label);
+
+        let result = serde_json::to_vec(&EncryptedData::LabelledReencrypted(
+            serde_json::to_vec(&sum)?,
+            original.nonce().to_vec(),
+            original.ciphertext().to_vec(),
+            original.aad().to_vec(),
+            Label {
+                parts: labels,
+                rerandomized: Vec::new(),
+            },
+        ))?;
+        self.result = Some(result.clone());
+
+        let ser = inflate(result, self.indices.len() - 1);
+        Ok(pack(ser, ProtocolType::ElgamalReencrypt))
+    }
+}
+
+#[typetag::serde(name = "elgamal_reencrypt")]
+impl Protocol for ReencryptContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = if self.data.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }?;
+        Ok(data)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.result.ok_or_else(|| "protocol not finished".into())
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.data.is_none() || self.result.is_some() {
+            return None;
         }
+        Some(self.indices.len() - 1)
     }
 }
 
+impl ThresholdProtocol for ReencryptContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            ctx: parse_group_context(group)?,
+            data: None,
+            target: Vec::new(),
+            indices: Vec::new(),
+            parts: Vec::new(),
+            result: None,
+            aad: Vec::new(),
+            label: None,
+            options: ContextOptions::default(),
+        })
+    }
+
+    fn configure(&mut self, options: &ContextOptions) {
+        self.options = *options;
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[
+    ProtocolInfo {
+        protocol_type: ProtocolType::Elgamal,
+        name: "elgamal",
+        curve: "ristretto255",
+        scheme: "elgamal",
+        index_base: 0,
+        keygen_rounds: 4,
+        threshold_rounds: Some(2),
+        max_parties: MAX_PARTIES,
+        min_threshold: Some(1),
+        prehashed: false,
+        all_sign: false,
+        resharing: false,
+        keygen: registry::keygen::<KeygenContext>,
+        threshold: registry::threshold::<DecryptContext>,
+    },
+    ProtocolInfo {
+        protocol_type: ProtocolType::ElgamalReencrypt,
+        name: "elgamal_reencrypt",
+        curve: "ristretto255",
+        scheme: "elgamal",
+        index_base: 0,
+        keygen_rounds: 4,
+        threshold_rounds: Some(2),
+        max_parties: MAX_PARTIES,
+        min_threshold: Some(1),
+        prehashed: false,
+        all_sign: false,
+        resharing: false,
+        keygen: registry::keygen::<KeygenContext>,
+        threshold: registry::threshold::<ReencryptContext>,
+    },
+];
+
+/// Lagrange coefficient of the party `index` for interpolation at zero
+/// over the parties in `indices`
+pub(crate) fn lagrange_coefficient(index: u16, indices: &[u16]) -> Result<Scalar> {
+    if !indices.contains(&index) {
+        return Err("participant index not included".into());
+    }
+    let x = Scalar::from(index as u64 + 1);
+    let mut coefficient = Scalar::one();
+    for other in indices.iter().filter(|i| **i != index) {
+        let other = Scalar::from(*other as u64 + 1);
+        coefficient *= other * (other - x).invert();
+    }
+    Ok(coefficient)
+}
+
+/// Source of randomness replaying the given bytes, falling back to
+/// `ProtocolRng` once they are used up
+struct ReplayRng(Vec<u8>);
+
+impl RngCore for ReplayRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let n = dest.len().min(self.0.len());
+        dest[..n].copy_from_slice(&self.0[..n]);
+        self.0.drain(..n);
+        ProtocolRng.fill_bytes(&mut dest[n..]);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ReplayRng {}
+
+/// Encrypt `element` to `key`, returning the randomness of the ciphertext
+/// for the proofs about it; as the ciphertexts of elastic_elgamal hide their
+/// randomness, it is drawn from bytes known beforehand and checked after
+fn encrypt_known(
+    key: &PublicKey<Ristretto>,
+    element: RistrettoPoint,
+) -> Result<(Ciphertext<Ristretto>, Scalar)> {
+    let mut bytes = [0u8; 64];
+    ProtocolRng.fill_bytes(&mut bytes);
+    let randomness = Scalar::from_bytes_mod_order_wide(&bytes);
+    let ciphertext = key.encrypt_element(element, &mut ReplayRng(bytes.to_vec()));
+    if ciphertext.random_element() != randomness * RISTRETTO_BASEPOINT_POINT {
+        return Err("could not encrypt with known randomness".into());
+    }
+    Ok((ciphertext, randomness))
+}
+
 fn try_encode(message: &[u8]) -> Option<RistrettoPoint> {
     if message.len() > 30 {
         return None;
@@ -317,16 +976,28 @@ fn try_encode(message: &[u8]) -> Option<RistrettoPoint> {
 fn decode(p: RistrettoPoint) -> Vec<u8> {
     let scalar = Scalar::from_bytes_mod_order(p.compress().to_bytes()).reduce();
     let scalar_bytes = &scalar.as_bytes()[1..];
-    scalar_bytes[1..(scalar_bytes[0] as usize + 1)].to_vec()
+    let len = (scalar_bytes[0] as usize).min(scalar_bytes.len() - 1);
+    scalar_bytes[1..(len + 1)].to_vec()
 }
 
 pub fn encrypt(msg: &[u8], pk: &[u8]) -> Result<Vec<u8>> {
-    let pk: PublicKey<Ristretto> = PublicKey::from_bytes(pk).unwrap();
+    encrypt_with_aad(msg, pk, &[])
+}
+
+/// Encrypt a message bound to the associated data `aad`, such as the
+/// identifier of the task, which the decryption must be requested with
+pub fn encrypt_with_aad(msg: &[u8], pk: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let pk: PublicKey<Ristretto> = PublicKey::from_bytes(pk).map_err(|_| "invalid public key")?;
     let key = Aes128Gcm::generate_key(&mut OsRng);
 
     let encoded_key: <Ristretto as ElementOps>::Element =
         try_encode(&key).ok_or("encoding failed")?;
-    let encrypted_key = serde_json::to_vec(&pk.encrypt_element(encoded_key, &mut OsRng))?;
+    let (encrypted_key, randomness) = encrypt_known(&pk, encoded_key)?;
+    let label = Label {
+        parts: vec![prove_randomness(LABEL_DOMAIN, aad, randomness)],
+        rerandomized: Vec::new(),
+    };
+    let encrypted_key = serde_json::to_vec(&encrypted_key)?;
 
     let cipher = Aes128Gcm::new(&key);
     let nonce = Aes128Gcm::generate_nonce(&mut OsRng);
@@ -335,20 +1006,71 @@ pub fn encrypt(msg: &[u8], pk: &[u8]) -> Result<Vec<u8>> {
             &nonce,
             Payload {
                 msg,
-                aad: &encrypted_key,
+                aad: &bound_aad(&encrypted_key, aad),
             },
         )
         .unwrap();
 
-    Ok(serde_json::to_vec(&(&encrypted_key, &nonce.to_vec(), &ct))?)
+    Ok(serde_json::to_vec(&EncryptedData::Labelled(
+        encrypted_key,
+        nonce.to_vec(),
+        ct,
+        label,
+    ))?)
+}
+
+/// Re-randomize the encrypted key of a ciphertext produced by `encrypt` or
+/// by a re-encryption by adding an encryption of the identity to `pk`, whose
+/// randomness is added to the label.
+///
+/// The result is NOT unlinkable to the original: the AES-GCM part cannot be
+/// re-encrypted without the key, so its nonce and ciphertext, as well as the
+/// original encrypted key it is bound to and the parts of the label, are
+/// carried along unchanged. Only the encrypted key decrypted by the group
+/// is fresh, e.g. so that decryption shares for it cannot be reused.
+/// A ciphertext of an older version stays without a label.
+pub fn rerandomize(ciphertext: &[u8], pk: &[u8]) -> Result<Vec<u8>> {
+    let pk: PublicKey<Ristretto> = PublicKey::from_bytes(pk).map_err(|_| "invalid public key")?;
+    let data: EncryptedData = serde_json::from_slice(ciphertext)?;
+    let encrypted_key: Ciphertext<Ristretto> = serde_json::from_slice(data.encrypted_key())?;
+
+    let (identity, randomness) = encrypt_known(&pk, Ristretto::identity())?;
+    let mut label = match data.label() {
+        Some(label) => label.clone(),
+        None => {
+            return Ok(serde_json::to_vec(&EncryptedData::Reencrypted(
+                serde_json::to_vec(&(encrypted_key + identity))?,
+                data.nonce().to_vec(),
+                data.ciphertext().to_vec(),
+                data.aad().to_vec(),
+            ))?)
+        }
+    };
+    label
+        .rerandomized
+        .push(prove_randomness(RERANDOMIZE_DOMAIN, b"", randomness));
+    Ok(serde_json::to_vec(&EncryptedData::LabelledReencrypted(
+        serde_json::to_vec(&(encrypted_key + identity))?,
+        data.nonce().to_vec(),
+        data.ciphertext().to_vec(),
+        data.aad().to_vec(),
+        label,
+    ))?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+    use crate::proto::{DecryptResult, GroupResult};
+    use crate::protocol::tests::{
+        load_fixture, run_keygen, KeygenProtocolTest, ThresholdProtocolTest,
+    };
     use rand::seq::IteratorRandom;
 
+    fn plaintexts(result: &[u8]) -> Vec<Vec<u8>> {
+        DecryptResult::decode(result).unwrap().plaintexts
+    }
+
     impl KeygenProtocolTest for KeygenContext {
         const PROTOCOL_TYPE: ProtocolType = ProtocolType::Elgamal;
         const ROUNDS: usize = 4;
@@ -396,9 +1118,314 @@ mod tests {
                     <DecryptContext as ThresholdProtocolTest>::run(ctxs, indices, ct.to_vec());
 
                 for result in results {
-                    assert_eq!(&msg.to_vec(), &result);
+                    assert_eq!(plaintexts(&result), vec![msg.to_vec()]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_batch() {
+        let (threshold, parties) = (2, 3);
+        let (pks, ctxs) =
+            <KeygenContext as KeygenProtocolTest>::run(threshold as u32, parties as u32);
+        let msgs: Vec<Vec<u8>> = vec![b"hello".to_vec(), b"".to_vec(), b"world".to_vec()];
+        let cts: Vec<EncryptedData> = msgs
+            .iter()
+            .map(|msg| serde_json::from_slice(&encrypt(msg, &pks[0]).unwrap()).unwrap())
+            .collect();
+
+        let mut indices = (0..parties as u16).choose_multiple(&mut OsRng, threshold);
+        indices.sort();
+        let results = <DecryptContext as ThresholdProtocolTest>::run(
+            ctxs,
+            indices,
+            serde_json::to_vec(&cts).unwrap(),
+        );
+
+        for result in results {
+            assert_eq!(plaintexts(&result), msgs);
+        }
+    }
+
+    impl ThresholdProtocolTest for ReencryptContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::ElgamalReencrypt;
+        const ROUNDS: usize = 2;
+    }
+
+    #[test]
+    fn reencrypt() {
+        for threshold in 2..5 {
+            for parties in threshold..5 {
+                let (pks_a, ctxs_a) = run_keygen::<KeygenContext>(
+                    ProtocolType::ElgamalReencrypt,
+                    4,
+                    threshold as u32,
+                    parties as u32,
+                );
+                let (pks_b, ctxs_b) =
+                    <KeygenContext as KeygenProtocolTest>::run(threshold as u32, parties as u32);
+                let msg = b"hello";
+                let ct = encrypt(msg, &pks_a[0]).unwrap();
+
+                let mut indices = (0..parties as u16).choose_multiple(&mut OsRng, threshold);
+                indices.sort();
+                let results = <ReencryptContext as ThresholdProtocolTest>::run(
+                    ctxs_a,
+                    indices,
+                    serde_json::to_vec(&(ct, &pks_b[0])).unwrap(),
+                );
+                for result in &results {
+                    assert_eq!(&results[0], result);
+                }
+
+                let mut indices = (0..parties as u16).choose_multiple(&mut OsRng, threshold);
+                indices.sort();
+                let results = <DecryptContext as ThresholdProtocolTest>::run(
+                    ctxs_b,
+                    indices,
+                    results[0].clone(),
+                );
+                for result in results {
+                    assert_eq!(plaintexts(&result), vec![msg.to_vec()]);
                 }
             }
         }
     }
+
+    #[test]
+    fn invalid_reencryption() {
+        let (pks, ctxs) = run_keygen::<KeygenContext>(ProtocolType::ElgamalReencrypt, 4, 2, 2);
+        let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.protocol_type, ProtocolType::ElgamalReencrypt as i32);
+        let ct = encrypt(b"hello", &pks[0]).unwrap();
+
+        let outputs: Vec<Vec<u8>> = ctxs
+            .iter()
+            .enumerate()
+            .map(|(i, ctx)| {
+                // the second party re-encrypts to another key than requested
+                let other = <KeygenContext as KeygenProtocolTest>::run_single();
+                let other = GroupResult::decode(&other[..]).unwrap().public_key;
+                let target = if i == 0 { &pks[0] } else { &other };
+                let init = ProtocolInit {
+                    protocol_type: ProtocolType::ElgamalReencrypt as i32,
+                    indices: vec![0, 1],
+                    index: i as u32,
+                    data: serde_json::to_vec(&(&ct, target)).unwrap(),
+                    ..Default::default()
+                };
+                let mut ctx = ReencryptContext::new(ctx).unwrap();
+                let out = ctx.advance(&init.encode_to_vec()).unwrap();
+                if i == 0 {
+                    serde_json::to_vec(&ctx).unwrap()
+                } else {
+                    out
+                }
+            })
+            .collect();
+
+        let mut ctx: ReencryptContext = serde_json::from_slice(&outputs[0]).unwrap();
+        let error = ctx.advance(&outputs[1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid re-encryption share from party 1"
+        );
+    }
+
+    #[test]
+    fn verify_share() {
+        let (pks, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let data = encrypt(b"hello", &pks[0]).unwrap();
+
+        let mut ctx = DecryptContext::new(&ctxs[0]).unwrap();
+        let out = ctx
+            .advance(
+                &(ProtocolInit {
+                    protocol_type: ProtocolType::Elgamal as i32,
+                    indices: vec![0, 1],
+                    index: 0,
+                    data: data.clone(),
+                    ..Default::default()
+                })
+                .encode_to_vec(),
+            )
+            .unwrap();
+        let msg = unpack(&out).unwrap().remove(0);
+
+        let key_set = public_key_set(&ctxs[0]).unwrap();
+        assert!(verify_decryption_share(&key_set, &data, 0, &msg).is_ok());
+        assert!(verify_decryption_share(&key_set, &data, 1, &msg).is_err());
+    }
+
+    #[test]
+    fn single_party() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let msg = b"hello";
+        let ct = encrypt(msg, &group.public_key).unwrap();
+
+        let result = <DecryptContext as ThresholdProtocolTest>::run_single(ctx, ct);
+        assert_eq!(plaintexts(&result), vec![msg.to_vec()]);
+    }
+
+    #[test]
+    fn rerandomized() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let ct = encrypt(b"hello", &group.public_key).unwrap();
+        let rerandomized = rerandomize(&ct, &group.public_key).unwrap();
+        let again = rerandomize(&rerandomized, &group.public_key).unwrap();
+        assert_ne!(ct, rerandomized);
+        assert_ne!(rerandomized, again);
+
+        // the symmetric part is carried along, which links the ciphertexts
+        let parse = |ct: &[u8]| serde_json::from_slice::<EncryptedData>(ct).unwrap();
+        let (original, linked) = (parse(&ct), parse(&again));
+        assert_ne!(original.encrypted_key(), linked.encrypted_key());
+        assert_eq!(original.ciphertext(), linked.ciphertext());
+        assert_eq!(original.aad(), linked.aad());
+
+        for ct in [rerandomized, again] {
+            let result = <DecryptContext as ThresholdProtocolTest>::run_single(ctx.clone(), ct);
+            assert_eq!(plaintexts(&result), vec![b"hello".to_vec()]);
+        }
+        assert!(rerandomize(&ct, &[0; 32][1..]).is_err());
+    }
+
+    #[test]
+    fn associated_data() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let ct = encrypt_with_aad(b"hello", &group.public_key, b"task 1").unwrap();
+        let init = |ct: &[u8], aad: &[u8]| {
+            ProtocolInit {
+                protocol_type: ProtocolType::Elgamal as i32,
+                indices: vec![0, 1],
+                index: 0,
+                data: ct.to_vec(),
+                aad: aad.to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        let decrypt = |ct: &[u8], aad: &[u8]| {
+            let mut ctx = DecryptContext::new(&ctx).unwrap();
+            let mut init = ProtocolInit::decode(&init(ct, aad)[..]).unwrap();
+            init.indices = vec![0];
+            ctx.advance(&init.encode_to_vec())?;
+            Box::new(ctx).finish()
+        };
+
+        assert_eq!(
+            plaintexts(&decrypt(&ct, b"task 1").unwrap()),
+            vec![b"hello".to_vec()]
+        );
+        let rerandomized = rerandomize(&ct, &group.public_key).unwrap();
+        assert!(decrypt(&rerandomized, b"task 1").is_ok());
+
+        // no share is released for the ciphertext under other associated data
+        for aad in [&b"task 2"[..], b""] {
+            for ct in [&ct, &rerandomized] {
+                let mut ctx = DecryptContext::new(&ctx).unwrap();
+                assert!(ctx.advance(&init(ct, aad)).is_err());
+            }
+        }
+
+        // nor once its label is stripped
+        let data: EncryptedData = serde_json::from_slice(&ct).unwrap();
+        let stripped = serde_json::to_vec(&EncryptedData::Direct(
+            data.encrypted_key().to_vec(),
+            data.nonce().to_vec(),
+            data.ciphertext().to_vec(),
+        ))
+        .unwrap();
+        let mut ctx = DecryptContext::new(&ctx).unwrap();
+        assert!(ctx.advance(&init(&stripped, b"")).is_err());
+        let rerandomized = rerandomize(&stripped, &group.public_key).unwrap();
+        let mut ctx = DecryptContext::new(&ctx).unwrap();
+        assert!(ctx.advance(&init(&rerandomized, b"")).is_err());
+        // not even by a party accepting the ciphertexts of older versions
+        let mut ctx = DecryptContext::new(&ctx).unwrap();
+        ctx.configure(&ContextOptions {
+            legacy_ciphertexts: true,
+            ..Default::default()
+        });
+        assert!(ctx.advance(&init(&stripped, b"task 2")).is_err());
+    }
+
+    /// Ciphertext in the format of `encrypt` before the labels
+    fn encrypt_legacy(msg: &[u8], pk: &[u8]) -> Vec<u8> {
+        let pk: PublicKey<Ristretto> = PublicKey::from_bytes(pk).unwrap();
+        let key = Aes128Gcm::generate_key(&mut OsRng);
+        let encoded_key = try_encode(&key).unwrap();
+        let encrypted_key =
+            serde_json::to_vec(&pk.encrypt_element(encoded_key, &mut OsRng)).unwrap();
+        let nonce = Aes128Gcm::generate_nonce(&mut OsRng);
+        let ct = Aes128Gcm::new(&key)
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg,
+                    aad: &encrypted_key,
+                },
+            )
+            .unwrap();
+        serde_json::to_vec(&(&encrypted_key, &nonce.to_vec(), &ct)).unwrap()
+    }
+
+    #[test]
+    fn legacy_ciphertext() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let ct = encrypt_legacy(b"hello", &group.public_key);
+        let decrypt = |ct: &[u8], aad: &[u8], legacy: bool| {
+            let mut ctx = DecryptContext::new(&ctx).unwrap();
+            ctx.configure(&ContextOptions {
+                legacy_ciphertexts: legacy,
+                ..Default::default()
+            });
+            let init = ProtocolInit {
+                protocol_type: ProtocolType::Elgamal as i32,
+                indices: vec![0],
+                index: 0,
+                data: ct.to_vec(),
+                aad: aad.to_vec(),
+                ..Default::default()
+            };
+            ctx.advance(&init.encode_to_vec())?;
+            Box::new(ctx).finish()
+        };
+
+        assert!(decrypt(&ct, b"", false).is_err());
+        assert_eq!(
+            plaintexts(&decrypt(&ct, b"", true).unwrap()),
+            vec![b"hello".to_vec()]
+        );
+        assert!(decrypt(&ct, b"task 1", true).is_err());
+
+        let rerandomized = rerandomize(&ct, &group.public_key).unwrap();
+        assert_ne!(rerandomized, ct);
+        assert_eq!(
+            plaintexts(&decrypt(&rerandomized, b"", true).unwrap()),
+            vec![b"hello".to_vec()]
+        );
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(feature = "deterministic")]
+    fn generate_fixture() {
+        crate::protocol::tests::store_fixture::<KeygenContext>("elgamal");
+    }
+
+    #[test]
+    fn fixture() {
+        let groups = load_fixture("elgamal");
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let msg = b"hello";
+        let ct = encrypt(msg, &pk).unwrap();
+        let results = <DecryptContext as ThresholdProtocolTest>::run(groups, vec![1, 2], ct);
+        assert_eq!(plaintexts(&results[0]), vec![msg.to_vec()]);
+    }
 }
@@ -0,0 +1,197 @@
+//! Execution of the contexts inside a trusted execution environment
+//!
+//! A server-side co-signer may run its contexts inside an enclave, such as
+//! an Intel SGX enclave or an ARM TrustZone trusted application, so that
+//! its shares stay protected even from the host. The platform is reached
+//! through the `Enclave` trait, implemented on top of its SDK.
+//!
+//! `seal` encrypts a context by AES-256-GCM under the sealing key of the
+//! enclave, which the platform derives from the identity of the enclave
+//! code, so only the same code unseals it. `attest` binds the fingerprint
+//! of a group to an attestation report, by which the other parties check
+//! that the shares of the group are held by the attested code. They do so
+//! by `verify_attestation`, through the `ReportVerifier` of the platform,
+//! which checks the report itself, e.g. the signature chain of an SGX quote.
+
+use crate::protocol::{check_context_size, fingerprint, Protocol, Result};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha512};
+
+const DOMAIN: &[u8] = b"meesign-enclave";
+const NONCE_SIZE: usize = 12;
+
+/// Trusted execution environment the contexts run in
+pub trait Enclave {
+    /// Key derived by the platform from the identity of the enclave code,
+    /// e.g. by `EGETKEY` under the MRENCLAVE policy on SGX
+    fn sealing_key(&self) -> Result<[u8; 32]>;
+    /// Measurement of the enclave code, as reported in its attestations
+    fn measurement(&self) -> Vec<u8>;
+    /// Attestation report of the platform carrying `report_data`,
+    /// e.g. an SGX quote, to be verified by the other parties
+    fn report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+}
+
+/// Verification of the attestation reports of a platform, implemented on
+/// top of its verification library or service, e.g. SGX DCAP
+pub trait ReportVerifier {
+    /// Check the authenticity of `report` and return the measurement
+    /// of the attested code and the data the report carries
+    fn verify_report(&self, report: &[u8]) -> Result<(Vec<u8>, [u8; 64])>;
+}
+
+fn cipher(enclave: &dyn Enclave) -> Result<Aes256Gcm> {
+    let key = enclave.sealing_key()?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+/// Serialize the context and encrypt it under the sealing key of `enclave`
+pub fn seal(ctx: &dyn Protocol, enclave: &dyn Enclave) -> Result<Vec<u8>> {
+    let ser = serde_json::to_vec(ctx)?;
+    let aad = [DOMAIN, &enclave.measurement()].concat();
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let ct = cipher(enclave)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ser,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| "sealing failed")?;
+    Ok([&nonce[..], &ct].concat())
+}
+
+/// Restore a context sealed by `seal` within the same enclave
+pub fn unseal(sealed: &[u8], enclave: &dyn Enclave) -> Result<Box<dyn Protocol>> {
+    if sealed.len() < NONCE_SIZE {
+        return Err("truncated sealed context".into());
+    }
+    let (nonce, ct) = sealed.split_at(NONCE_SIZE);
+    let aad = [DOMAIN, &enclave.measurement()].concat();
+
+    let ser = cipher(enclave)?
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ct, aad: &aad })
+        .map_err(|_| "context not sealed by this enclave")?;
+    check_context_size(&ser)?;
+    Ok(serde_json::from_slice(&ser)?)
+}
+
+/// Data an attestation of the group result `group` carries,
+/// against which the other parties check the report
+pub fn report_data(group: &[u8]) -> Result<[u8; 64]> {
+    let (_, digest) = fingerprint::digest(group)?;
+    let mut hasher = Sha512::new();
+    hasher.update(DOMAIN);
+    hasher.update(digest);
+    let mut data = [0u8; 64];
+    data.copy_from_slice(&hasher.finalize());
+    Ok(data)
+}
+
+/// Attest that the group result `group` is held by the code of `enclave`
+pub fn attest(group: &[u8], enclave: &dyn Enclave) -> Result<Vec<u8>> {
+    enclave.report(&report_data(group)?)
+}
+
+/// Verify an attestation produced by `attest`, i.e. that the report is
+/// authentic, that it comes from the code with the expected `measurement`,
+/// and that it carries the fingerprint of the group result `group`
+pub fn verify_attestation(
+    report: &[u8],
+    group: &[u8],
+    measurement: &[u8],
+    verifier: &dyn ReportVerifier,
+) -> Result<()> {
+    let (attested, data) = verifier.verify_report(report)?;
+    if attested != measurement {
+        return Err("attested code does not match the measurement".into());
+    }
+    if data != report_data(group)? {
+        return Err("attestation does not match the group".into());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolType};
+    use crate::protocol::keygen_context;
+    use prost::Message;
+
+    struct TestEnclave(u8);
+
+    impl Enclave for TestEnclave {
+        fn sealing_key(&self) -> Result<[u8; 32]> {
+            Ok([self.0; 32])
+        }
+
+        fn measurement(&self) -> Vec<u8> {
+            vec![self.0; 32]
+        }
+
+        fn report(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+            Ok([&self.measurement()[..], report_data].concat())
+        }
+    }
+
+    struct TestVerifier;
+
+    impl ReportVerifier for TestVerifier {
+        fn verify_report(&self, report: &[u8]) -> Result<(Vec<u8>, [u8; 64])> {
+            if report.len() != 32 + 64 {
+                return Err("invalid report".into());
+            }
+            let mut data = [0u8; 64];
+            data.copy_from_slice(&report[32..]);
+            Ok((report[..32].to_vec(), data))
+        }
+    }
+
+    #[test]
+    fn sealing() {
+        let ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let sealed = seal(&*ctx, &TestEnclave(1)).unwrap();
+
+        let unsealed = unseal(&sealed, &TestEnclave(1)).unwrap();
+        assert_eq!(
+            serde_json::to_vec(&unsealed).unwrap(),
+            serde_json::to_vec(&ctx).unwrap()
+        );
+        assert!(unseal(&sealed, &TestEnclave(2)).is_err());
+        assert!(unseal(&sealed[..NONCE_SIZE - 1], &TestEnclave(1)).is_err());
+    }
+
+    #[test]
+    fn attestation() {
+        let group = GroupResult {
+            protocol_type: ProtocolType::Frost as i32,
+            public_key: vec![2; 33],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let report = attest(&group, &TestEnclave(1)).unwrap();
+        assert_eq!(report[32..], report_data(&group).unwrap());
+        assert!(attest(b"not a group", &TestEnclave(1)).is_err());
+
+        let measurement = TestEnclave(1).measurement();
+        assert!(verify_attestation(&report, &group, &measurement, &TestVerifier).is_ok());
+        let other = TestEnclave(2).measurement();
+        assert!(verify_attestation(&report, &group, &other, &TestVerifier).is_err());
+        let other_group = GroupResult {
+            protocol_type: ProtocolType::Frost as i32,
+            public_key: vec![3; 33],
+            ..Default::default()
+        }
+        .encode_to_vec();
+        assert!(verify_attestation(&report, &other_group, &measurement, &TestVerifier).is_err());
+        assert!(verify_attestation(&report[1..], &group, &measurement, &TestVerifier).is_err());
+    }
+}
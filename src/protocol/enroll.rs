@@ -0,0 +1,623 @@
+//! Enrollment of new members into FROST groups
+//!
+//! A `t`-of-`n` FROST group grows by a new member `j` without a new key
+//! generation, so the group key and the certificates issued for it stay
+//! valid. Any `t` members (the helpers) jointly compute the share `f(j)`
+//! of the sharing polynomial, i.e. `sum l_i(j) s_i` over the helpers. Each
+//! helper splits its term `l_i(j) s_i` into random parts, one for each
+//! helper, and only the sums of the received parts reach the new member,
+//! so no one learns the share of a helper.
+//!
+//! The parts are encrypted to the verifying shares of the helpers they are
+//! for, see `seal`, and the sums to a key the new member sends in its first
+//! message, so the server relaying them learns neither. The helpers accept
+//! the key of the new member only in an `authenticated` session, as the
+//! server could otherwise substitute its own, and only if there are at
+//! least as many of them as the threshold of the group.
+//!
+//! The helpers run `EnrollContext` with the new member among the indices
+//! and its index as the data. The new member runs `JoinContext` with the
+//! parameters of the enlarged group and the `encrypt` flag, which is
+//! required. Both return a FROST group context.
+//! The members who did not help add the new member by `add_member`.
+
+use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::protocol::blind::secp256k1::{
+    from_point, from_scalar, lagrange_at, to_point, to_scalar,
+};
+use crate::protocol::*;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use frost::keys::{KeyPackage, PublicKeyPackage};
+use frost::Identifier;
+use frost_secp256k1 as frost;
+use k256::elliptic_curve::{sec1::ToEncodedPoint, Field, PrimeField};
+use k256::{ProjectivePoint, Scalar};
+use prost::{bytes::Bytes, Message};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::{TryFrom, TryInto};
+use subtle::ConstantTimeEq;
+
+fn parse_point(bytes: &[u8]) -> Result<ProjectivePoint> {
+    let key = k256::PublicKey::from_sec1_bytes(bytes).map_err(|_| "invalid public key")?;
+    Ok(key.to_projective())
+}
+
+const SEAL_DOMAIN: &[u8] = b"meesign-frost-seal";
+/// Length of the ephemeral point of a sealed message, a compressed point
+const SEALED_LEN: usize = 33;
+
+fn seal_cipher(ephemeral: &[u8], key: ProjectivePoint, dh: ProjectivePoint) -> Aes256Gcm {
+    let digest = Sha256::new()
+        .chain_update(SEAL_DOMAIN)
+        .chain_update(ephemeral)
+        .chain_update(key.to_affine().to_encoded_point(true))
+        .chain_update(dh.to_affine().to_encoded_point(true))
+        .finalize();
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&digest))
+}
+
+/// Encrypt a point-to-point message to the secp256k1 `key` of a single
+/// party, as a compressed ephemeral point followed by the AES-GCM
+/// ciphertext under a key hashed from their Diffie-Hellman secret;
+/// each key encrypts a single message, so the nonce is fixed
+pub(crate) fn seal(msg: &[u8], key: ProjectivePoint) -> Result<Vec<u8>> {
+    let ephemeral = Scalar::random(&mut ProtocolRng);
+    let point = (ProjectivePoint::GENERATOR * ephemeral).to_affine();
+    let point = point.to_encoded_point(true);
+    let ct = seal_cipher(point.as_bytes(), key, key * ephemeral)
+        .encrypt(Nonce::from_slice(&[0; 12]), msg)
+        .map_err(|_| "encryption failed")?;
+    Ok([point.as_bytes(), &ct].concat())
+}
+
+/// Decrypt a message sealed to the key of the `secret`, see `seal`
+pub(crate) fn open(ciphertext: &[u8], secret: &Scalar) -> Result<Vec<u8>> {
+    if ciphertext.len() < SEALED_LEN {
+        return Err("malformed ciphertext".into());
+    }
+    let (ephemeral, ct) = ciphertext.split_at(SEALED_LEN);
+    let point = parse_point(ephemeral).map_err(|_| "malformed ciphertext")?;
+    seal_cipher(
+        ephemeral,
+        ProjectivePoint::GENERATOR * secret,
+        point * secret,
+    )
+    .decrypt(Nonce::from_slice(&[0; 12]), ct)
+    .map_err(|_| "decryption failed".into())
+}
+
+fn parse_scalar(bytes: &[u8]) -> Result<Scalar> {
+    if bytes.len() != 32 {
+        return Err("invalid share".into());
+    }
+    Option::from(Scalar::from_repr(*k256::FieldBytes::from_slice(bytes)))
+        .ok_or_else(|| "invalid share".into())
+}
+
+fn identifier_scalar(identifier: &Identifier) -> Result<Scalar> {
+    Option::from(Scalar::from_repr(identifier.serialize().into()))
+        .ok_or_else(|| "invalid identifier".into())
+}
+
+/// Threshold of the group, the least number of the verifying shares of its
+/// members which determine all the others by interpolation, so that it
+/// follows from the group context rather than the word of the server
+pub(crate) fn group_threshold(pubkey: &PublicKeyPackage) -> Result<usize> {
+    let shares = pubkey
+        .signer_pubkeys()
+        .iter()
+        .map(|(identifier, share)| Ok((identifier_scalar(identifier)?, to_point(share)?)))
+        .collect::<Result<Vec<_>>>()?;
+    'degree: for threshold in 1..shares.len() {
+        let xs: Vec<Scalar> = shares[..threshold].iter().map(|(x, _)| *x).collect();
+        for (at, share) in &shares[threshold..] {
+            let mut point = ProjectivePoint::IDENTITY;
+            for (x, other) in &shares[..threshold] {
+                point += *other * lagrange_at(*at, *x, &xs)?;
+            }
+            if point != *share {
+                continue 'degree;
+            }
+        }
+        return Ok(threshold);
+    }
+    Ok(shares.len())
+}
+
+/// Add the verifying share of the member `index`, interpolated from the
+/// verifying shares of all the current members
+fn with_member(pubkey: &PublicKeyPackage, index: u16) -> Result<PublicKeyPackage> {
+    let identifier = Identifier::try_from(index)?;
+    if pubkey.signer_pubkeys().contains_key(&identifier) {
+        return Err("party already a member".into());
+    }
+
+    let xs = pubkey
+        .signer_pubkeys()
+        .keys()
+        .map(identifier_scalar)
+        .collect::<Result<Vec<_>>>()?;
+    let at = Scalar::from(index as u64);
+    let mut share = ProjectivePoint::IDENTITY;
+    for (x, (_, verifying)) in xs.iter().zip(pubkey.signer_pubkeys()) {
+        share += to_point(verifying)? * lagrange_at(at, *x, &xs)?;
+    }
+
+    let mut pubkeys = pubkey.signer_pubkeys().clone();
+    pubkeys.insert(identifier, from_point(share)?);
+    Ok(PublicKeyPackage::new(pubkeys, *pubkey.group_public()))
+}
+
+fn pack_frost_group(key: &KeyPackage, pubkey: &PublicKeyPackage) -> Result<Vec<u8>> {
+    Ok(pack_group(
+        ProtocolType::Frost,
+        pubkey.group_public().serialize().as_ref().to_vec(),
+        serde_json::to_vec(&(key, pubkey))?,
+    ))
+}
+
+/// Add the member `index` enrolled by other parties to a FROST group
+/// context, returning the updated context
+pub fn add_member(group: &[u8], index: u16) -> Result<Vec<u8>> {
+    let (key, pubkey): (KeyPackage, PublicKeyPackage) = parse_group_context(group)?;
+    pack_frost_group(&key, &with_member(&pubkey, index)?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EnrollContext {
+    key: KeyPackage,
+    pubkey: PublicKeyPackage,
+    /// Public key package including the new member, once initialized
+    enlarged: Option<PublicKeyPackage>,
+    /// Whether the messages of the other parties are authenticated,
+    /// see `Protocol::set_authenticated`
+    #[serde(default)]
+    authenticated: bool,
+    round: EnrollRound,
+}
+
+#[derive(Serialize, Deserialize)]
+enum EnrollRound {
+    R0,
+    /// Indices of the other parties, the position of the new member
+    /// among them and the part of the own term kept by this helper
+    R1(Vec<u16>, usize, Vec<u8>),
+    R2(Vec<u16>),
+    Done,
+}
+
+impl EnrollContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::FrostEnroll.check(msg.protocol_type)?;
+        let newcomer = u16::from_be_bytes(
+            msg.data
+                .as_slice()
+                .try_into()
+                .map_err(|_| "invalid new member index")?,
+        );
+        let index = u16::try_from(msg.index)?;
+        if Identifier::try_from(index)? != *self.key.identifier() {
+            return Err("participant index does not match the group".into());
+        }
+
+        let indices = msg
+            .indices
+            .iter()
+            .map(|i| Ok(u16::try_from(*i)?))
+            .collect::<Result<Vec<u16>>>()?;
+        if !indices.contains(&index) || !indices.contains(&newcomer) {
+            return Err("participant index not included".into());
+        }
+        if !self.authenticated {
+            return Err("enrollment requires authenticated messages".into());
+        }
+        let enlarged = with_member(&self.pubkey, newcomer)?;
+        let helpers: Vec<Scalar> = indices
+            .iter()
+            .filter(|i| **i != newcomer)
+            .map(|i| Scalar::from(*i as u64))
+            .collect();
+        if helpers.len() < group_threshold(&self.pubkey)? {
+            return Err("fewer helpers than the threshold".into());
+        }
+
+        let term = lagrange_at(
+            Scalar::from(newcomer as u64),
+            Scalar::from(index as u64),
+            &helpers,
+        )? * to_scalar(self.key.secret_share())?;
+
+        let others: Vec<u16> = indices.into_iter().filter(|i| *i != index).collect();
+        let position = others
+            .iter()
+            .position(|i| *i == newcomer)
+            .ok_or("participant index not included")?;
+        let mut kept = term;
+        let mut msgs = Vec::new();
+        for other in &others {
+            if *other == newcomer {
+                msgs.push(Bytes::new());
+            } else {
+                let part = Scalar::random(&mut ProtocolRng);
+                kept -= part;
+                let share = self
+                    .pubkey
+                    .signer_pubkeys()
+                    .get(&Identifier::try_from(*other)?)
+                    .ok_or_else(|| format!("unknown party {}", other))?;
+                msgs.push(Bytes::from(seal(&part.to_bytes(), to_point(share)?)?));
+            }
+        }
+
+        self.enlarged = Some(enlarged);
+        self.round = EnrollRound::R1(others, position, kept.to_bytes().to_vec());
+        Ok(pack(msgs, ProtocolType::FrostEnroll))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let (round, msgs) = match &self.round {
+            EnrollRound::R0 => return Err("protocol not initialized".into()),
+            EnrollRound::R1(others, position, kept) => {
+                let data = unpack(data)?;
+                check_message_count(&data, others.len())?;
+
+                let secret = to_scalar(self.key.secret_share())?;
+                let mut sum = parse_scalar(kept)?;
+                for (i, msg) in data.iter().enumerate() {
+                    if i != *position {
+                        sum += parse_scalar(&open(msg, &secret)?)?;
+                    }
+                }
+                let key: Vec<u8> = deserialize_msg(&data[*position])?;
+                let key = parse_point(&key)?;
+
+                let share = (sum.to_bytes().to_vec(), serde_json::to_vec(&self.pubkey)?);
+                let msgs = (0..others.len())
+                    .map(|i| {
+                        if i == *position {
+                            Ok(Bytes::from(seal(&serialize_msg(&share)?, key)?))
+                        } else {
+                            Ok(Bytes::new())
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (EnrollRound::R2(others.clone()), msgs)
+            }
+            EnrollRound::R2(others) => {
+                check_message_count(&unpack(data)?, others.len())?;
+                (EnrollRound::Done, inflate(Bytes::new(), others.len()))
+            }
+            EnrollRound::Done => return Err("protocol already finished".into()),
+        };
+        self.round = round;
+        Ok(pack(msgs, ProtocolType::FrostEnroll))
+    }
+}
+
+#[typetag::serde(name = "frost_enroll")]
+impl Protocol for EnrollContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            EnrollRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match (&self.round, &self.enlarged) {
+            (EnrollRound::Done, Some(enlarged)) => pack_frost_group(&self.key, enlarged),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match &self.round {
+            EnrollRound::R0 | EnrollRound::Done => None,
+            EnrollRound::R1(others, _, _) | EnrollRound::R2(others) => Some(others.len()),
+        }
+    }
+
+    fn set_authenticated(&mut self) {
+        self.authenticated = true;
+    }
+}
+
+impl ThresholdProtocol for EnrollContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        let (key, pubkey) = parse_group_context(group)?;
+        Ok(Self {
+            key,
+            pubkey,
+            enlarged: None,
+            authenticated: false,
+            round: EnrollRound::R0,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct JoinContext {
+    round: JoinRound,
+}
+
+#[derive(Serialize, Deserialize)]
+enum JoinRound {
+    R0,
+    /// Own index, the number of helpers and the secret of the key
+    /// the helpers encrypt to
+    R1(u16, usize, Vec<u8>),
+    R2(u16, usize, Vec<u8>),
+    Done(KeyPackage, PublicKeyPackage),
+}
+
+impl JoinContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolGroupInit::decode(data)?;
+        ProtocolType::FrostEnroll.check(msg.protocol_type)?;
+        check_group_params(&msg, 1)?;
+        if msg.parties < 2 {
+            return Err("invalid group parameters".into());
+        }
+        if !msg.encrypt {
+            return Err("enrollment is always encrypted".into());
+        }
+
+        let helpers = msg.threshold as usize;
+        let secret = Scalar::random(&mut ProtocolRng);
+        let key = (ProjectivePoint::GENERATOR * secret).to_affine();
+        let key = key.to_encoded_point(true).as_bytes().to_vec();
+        self.round = JoinRound::R1(msg.index as u16, helpers, secret.to_bytes().to_vec());
+        Ok(pack(
+            serialize_bcast(&key, helpers)?,
+            ProtocolType::FrostEnroll,
+        ))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let (round, msgs) = match &self.round {
+            JoinRound::R0 => return Err("protocol not initialized".into()),
+            JoinRound::R1(index, helpers, key) => {
+                check_message_count(&unpack(data)?, *helpers)?;
+                (
+                    JoinRound::R2(*index, *helpers, key.clone()),
+                    inflate(Bytes::new(), *helpers),
+                )
+            }
+            JoinRound::R2(index, helpers, key) => {
+                let (index, helpers) = (*index, *helpers);
+                let data = unpack(data)?;
+                check_message_count(&data, helpers)?;
+
+                let key = parse_scalar(key)?;
+                let mut secret = Scalar::ZERO;
+                let mut group: Option<PublicKeyPackage> = None;
+                for msg in &data {
+                    let (part, pubkey): (Vec<u8>, Vec<u8>) = deserialize_msg(&open(msg, &key)?)?;
+                    secret += parse_scalar(&part)?;
+
+                    let pubkey: PublicKeyPackage = serde_json::from_slice(&pubkey)?;
+                    if let Some(group) = &group {
+                        if group.group_public() != pubkey.group_public()
+                            || group.signer_pubkeys() != pubkey.signer_pubkeys()
+                        {
+                            return Err("helpers disagree on the group".into());
+                        }
+                    }
+                    group = Some(pubkey);
+                }
+
+                let pubkey = with_member(&group.ok_or("no helpers")?, index)?;
+                let identifier = Identifier::try_from(index)?;
+                let verifying = pubkey.signer_pubkeys()[&identifier];
+                if !bool::from((ProjectivePoint::GENERATOR * secret).ct_eq(&to_point(&verifying)?))
+                {
+                    return Err("share does not match the group".into());
+                }
+                let key = KeyPackage::new(
+                    identifier,
+                    from_scalar(secret)?,
+                    verifying,
+                    *pubkey.group_public(),
+                );
+                (JoinRound::Done(key, pubkey), inflate(Bytes::new(), helpers))
+            }
+            JoinRound::Done(_, _) => return Err("protocol already finished".into()),
+        };
+        self.round = round;
+        Ok(pack(msgs, ProtocolType::FrostEnroll))
+    }
+}
+
+#[typetag::serde(name = "frost_join")]
+impl Protocol for JoinContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            JoinRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match &self.round {
+            JoinRound::Done(key, pubkey) => pack_frost_group(key, pubkey),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match self.round {
+            JoinRound::R0 | JoinRound::Done(_, _) => None,
+            JoinRound::R1(_, helpers, _) | JoinRound::R2(_, helpers, _) => Some(helpers),
+        }
+    }
+}
+
+impl KeygenProtocol for JoinContext {
+    fn new() -> Self {
+        Self {
+            round: JoinRound::R0,
+        }
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::FrostEnroll,
+    name: "frost_enroll",
+    curve: "secp256k1",
+    scheme: "enrollment",
+    index_base: 1,
+    keygen_rounds: 3,
+    threshold_rounds: Some(3),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: true,
+    keygen: registry::keygen::<JoinContext>,
+    threshold: registry::threshold::<EnrollContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::frost::{verify, KeygenContext, SignContext};
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+
+    /// Enroll the party `newcomer` into the group by the `helpers`,
+    /// returning the contexts of the session ordered by the indices
+    fn enroll(groups: &[Vec<u8>], helpers: &[u32], newcomer: u32) -> Result<Vec<Vec<u8>>> {
+        let mut indices: Vec<u32> = helpers.iter().cloned().chain([newcomer]).collect();
+        indices.sort_unstable();
+
+        let mut ctxs: Vec<Box<dyn Protocol>> = Vec::new();
+        let mut inputs = Vec::new();
+        for index in &indices {
+            if *index == newcomer {
+                ctxs.push(Box::new(JoinContext::new()));
+                inputs.push(
+                    ProtocolGroupInit {
+                        protocol_type: ProtocolType::FrostEnroll as i32,
+                        index: newcomer,
+                        parties: groups.len() as u32 + 1,
+                        threshold: helpers.len() as u32,
+                        encrypt: true,
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                );
+            } else {
+                let group = &groups[*index as usize - 1];
+                // as if run under `authenticated`
+                let mut ctx = EnrollContext::new(group).unwrap();
+                ctx.set_authenticated();
+                ctxs.push(Box::new(ctx));
+                inputs.push(
+                    ProtocolInit {
+                        protocol_type: ProtocolType::FrostEnroll as i32,
+                        indices: indices.clone(),
+                        index: *index,
+                        data: (newcomer as u16).to_be_bytes().to_vec(),
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                );
+            }
+        }
+
+        for _ in 0..3 {
+            let outputs = ctxs
+                .iter_mut()
+                .zip(&inputs)
+                .map(|(ctx, input)| unpack(&ctx.advance(input)?))
+                .collect::<Result<Vec<_>>>()?;
+            inputs = (0..ctxs.len())
+                .map(|idx| {
+                    let relay = outputs
+                        .iter()
+                        .enumerate()
+                        .filter(|(sender, _)| *sender != idx)
+                        .map(|(sender, msgs)| {
+                            msgs[if sender < idx { idx - 1 } else { idx }].clone()
+                        })
+                        .collect();
+                    pack(relay, ProtocolType::FrostEnroll)
+                })
+                .collect();
+        }
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
+        ctxs.into_iter().map(|ctx| ctx.finish()).collect()
+    }
+
+    #[test]
+    fn enroll_and_sign() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+
+        let enrolled = enroll(&groups, &[1, 3], 4).unwrap();
+        for group in &enrolled {
+            assert_eq!(GroupResult::decode(&group[..]).unwrap().public_key, pk);
+        }
+        let second = add_member(&groups[1], 4).unwrap();
+
+        let ctxs = vec![
+            enrolled[0].clone(),
+            second,
+            enrolled[1].clone(),
+            enrolled[2].clone(),
+        ];
+        for indices in [vec![1, 3], vec![0, 3]] {
+            let results =
+                <SignContext as ThresholdProtocolTest>::run(ctxs.clone(), indices, b"hi".to_vec());
+            for result in results {
+                let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+                assert!(verify(&pk, b"hi", &signature).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn enroll_invalid() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        assert!(enroll(&groups, &[1, 2], 3).is_err());
+        assert!(enroll(&groups, &[1], 4).is_err());
+        assert!(add_member(&groups[0], 2).is_err());
+
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::FrostEnroll as i32,
+            indices: vec![1, 2, 4],
+            index: 1,
+            data: 4u16.to_be_bytes().to_vec(),
+            ..Default::default()
+        };
+        assert!(EnrollContext::new(&groups[0])
+            .unwrap()
+            .advance(&init.encode_to_vec())
+            .is_err());
+    }
+
+    #[test]
+    fn threshold() {
+        for (threshold, parties) in [(2, 3), (3, 3), (2, 4)] {
+            let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(threshold, parties);
+            let (_, pubkey): (KeyPackage, PublicKeyPackage) =
+                parse_group_context(&groups[0]).unwrap();
+            assert_eq!(group_threshold(&pubkey).unwrap(), threshold as usize);
+        }
+    }
+
+    #[test]
+    fn sealed() {
+        let secret = Scalar::random(&mut ProtocolRng);
+        let ciphertext = seal(b"part", ProjectivePoint::GENERATOR * secret).unwrap();
+        assert_eq!(open(&ciphertext, &secret).unwrap(), b"part");
+        assert!(open(&ciphertext, &(secret + Scalar::ONE)).is_err());
+        assert!(open(&ciphertext[..SEALED_LEN], &secret).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Fingerprints of groups for the out-of-band comparison between devices
+//!
+//! The fingerprint commits to the protocol, the group public key, the
+//! public key shares of the current members and the ML-DSA public key of
+//! a hybrid group, so that two devices showing the same fingerprint
+//! computed the same group. The short form is meant to be read aloud,
+//! the QR payload carries the whole digest.
+
+use crate::proto::{GroupResult, ProtocolType};
+use crate::protocol::Result;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+const DOMAIN: &[u8] = b"meesign-fingerprint";
+
+/// Number of digest bytes shown in the short fingerprint
+const SHORT_LEN: usize = 16;
+
+pub(crate) fn digest(group: &[u8]) -> Result<(ProtocolType, Vec<u8>)> {
+    let group = GroupResult::decode(group).map_err(|_| "not a group result")?;
+    let protocol = ProtocolType::try_from(group.protocol_type)?;
+    if group.public_key.is_empty() {
+        return Err("group public key unknown".into());
+    }
+
+    let mut shares = group.shares;
+    shares.retain(|share| !group.removed.contains(&share.index));
+    shares.sort_by_key(|share| share.index);
+
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    hasher.update(group.protocol_type.to_be_bytes());
+    hasher.update((group.public_key.len() as u32).to_be_bytes());
+    hasher.update(&group.public_key);
+    for share in shares {
+        hasher.update(share.index.to_be_bytes());
+        hasher.update((share.public_key.len() as u32).to_be_bytes());
+        hasher.update(&share.public_key);
+    }
+    if !group.pq_public_key.is_empty() {
+        hasher.update((group.pq_public_key.len() as u32).to_be_bytes());
+        hasher.update(&group.pq_public_key);
+    }
+    Ok((protocol, hasher.finalize().to_vec()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Short fingerprint of a group result, in groups of four hex digits
+pub fn fingerprint(group: &[u8]) -> Result<String> {
+    let (_, digest) = digest(group)?;
+    let groups: Vec<String> = digest[..SHORT_LEN].chunks(2).map(hex).collect();
+    Ok(groups.join(" "))
+}
+
+/// Payload of a QR code with the whole fingerprint of a group result
+pub fn qr_payload(group: &[u8]) -> Result<String> {
+    let (protocol, digest) = digest(group)?;
+    Ok(format!(
+        "meesign:group/{}/{}",
+        protocol.as_str_name().to_lowercase(),
+        hex(&digest)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::PublicShare;
+    use crate::protocol::GROUP_FORMAT;
+
+    fn group(removed: Vec<u32>) -> Vec<u8> {
+        GroupResult {
+            protocol_type: ProtocolType::Frost as i32,
+            public_key: vec![2; 33],
+            context: b"context of a single party".to_vec(),
+            removed,
+            shares: (1..=3)
+                .map(|index| PublicShare {
+                    index,
+                    public_key: vec![index as u8; 33],
+                })
+                .collect(),
+            commitments: Vec::new(),
+            metadata: None,
+            pq_key: Vec::new(),
+            pq_public_key: Vec::new(),
+            usage: None,
+            format: GROUP_FORMAT,
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn fingerprints() {
+        let short = fingerprint(&group(Vec::new())).unwrap();
+        assert_eq!(short.len(), 8 * 4 + 7);
+        assert!(qr_payload(&group(Vec::new()))
+            .unwrap()
+            .starts_with("meesign:group/frost/"));
+
+        // the context of the party is not part of the fingerprint
+        let mut other = GroupResult::decode(&group(Vec::new())[..]).unwrap();
+        other.context = b"context of another party".to_vec();
+        assert_eq!(fingerprint(&other.encode_to_vec()).unwrap(), short);
+
+        assert_ne!(fingerprint(&group(vec![3])).unwrap(), short);
+        assert!(fingerprint(b"{\"legacy\": \"context\"}").is_err());
+    }
+}
@@ -1,85 +1,310 @@
-use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+//! FROST threshold Schnorr signatures, generic over the ciphersuite
+//!
+//! The contexts are implemented once for any `FrostCiphersuite`, and each
+//! supported ciphersuite is instantiated by `frost_protocol!` under its own
+//! protocol type and context names.
+//!
+//! The round messages are the serde encodings of the `frost_core` types
+//! of each round, so that other implementations built on `frost_core`,
+//! such as third-party coordinators, can take part in a session.
+//!
+//! A signing session may designate a coordinator in its `ProtocolInit`.
+//! The signature shares are then sent to the coordinator only, which
+//! aggregates the signature and broadcasts it to the others in an extra
+//! fourth round, instead of every party receiving all of the shares.
+//!
+//! A key generation initialized with the `encrypt` flag encrypts the round 2
+//! packages, which carry the secret shares, to P-256 keys the parties
+//! broadcast with their round 1 packages, so that the server relaying them
+//! does not learn the shares; see `hpke::encrypt`. The round 1 messages are
+//! then the encodings of the packages paired with the SEC1 encoded keys.
+//! As the server could substitute its own keys, the context only takes part
+//! in such a key generation if it is `authenticated` by the devices.
+//!
+//! A session initialized with the `blind` flag signs a challenge blinded
+//! by the requester instead of the message, see the `blind` module, which
+//! the party only takes part in if it opted in by `ContextOptions`, and
+//! one initialized with an adaptor point produces a pre-signature instead
+//! of a signature, see the `adaptor` module.
+
+use crate::proto::{GroupResult, ProtocolGroupInit, ProtocolInit, ProtocolType, PublicShare};
+use crate::protocol::telemetry::telemetry;
 use crate::protocol::*;
 
-use frost::keys::dkg::{self, round1, round2};
-use frost::keys::{KeyPackage, PublicKeyPackage};
-use frost::round1::{SigningCommitments, SigningNonces};
-use frost::round2::SignatureShare;
-use frost::{Identifier, Signature, SigningPackage};
-use prost::Message;
+use frost_core::keys::dkg::{self, round1, round2};
+use frost_core::keys::{KeyPackage, PublicKeyPackage, SigningShare, VerifyingShare};
+use frost_core::round1::{SigningCommitments, SigningNonces};
+use frost_core::round2::SignatureShare;
+use frost_core::{
+    Ciphersuite, Field, Group, Identifier, Signature, SigningKey, SigningPackage, VerifyingKey,
+};
+use frost_p256::P256Sha256;
+use frost_secp256k1::Secp256K1Sha256;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use prost::{bytes::Bytes, Message};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
 
-use frost_secp256k1 as frost;
-use rand::rngs::OsRng;
+/// Ciphersuite of FROST exposed as a protocol of its own
+pub trait FrostCiphersuite: Ciphersuite + 'static {
+    const PROTOCOL_TYPE: ProtocolType;
+    /// Protocols whose groups the key generation of the ciphersuite creates
+    /// under their own types
+    const GROUP_TYPES: &'static [ProtocolType];
+}
+
+impl FrostCiphersuite for Secp256K1Sha256 {
+    const PROTOCOL_TYPE: ProtocolType = ProtocolType::Frost;
+    const GROUP_TYPES: &'static [ProtocolType] = &[
+        ProtocolType::Frost,
+        ProtocolType::Roast,
+        ProtocolType::FrostRemove,
+    ];
+}
+
+impl FrostCiphersuite for P256Sha256 {
+    const PROTOCOL_TYPE: ProtocolType = ProtocolType::FrostP256;
+    const GROUP_TYPES: &'static [ProtocolType] = &[
+        ProtocolType::FrostP256,
+        ProtocolType::Ecdh,
+        ProtocolType::Hpke,
+    ];
+}
 
 #[derive(Serialize, Deserialize)]
-pub(crate) struct KeygenContext {
-    round: KeygenRound,
+#[serde(bound = "C: Ciphersuite")]
+pub(crate) struct FrostKeygenContext<C: FrostCiphersuite> {
+    round: KeygenRound<C>,
+    /// Number of the other parties, unknown for contexts created
+    /// by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peers: Option<usize>,
+    /// Number of coefficient commitments expected in each round 1 package,
+    /// unknown for contexts created by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    threshold: Option<u16>,
+    /// Secret key the round 2 packages are encrypted to, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_key: Option<Vec<u8>>,
+    /// Feldman commitments of the own polynomial, replaced by those of the
+    /// group polynomial once the key is generated; unknown for contexts
+    /// created by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commitments: Option<Vec<Vec<u8>>>,
+    /// Protocol of the group being generated, one of `C::GROUP_TYPES`,
+    /// which tags the messages and the result; unknown for contexts created
+    /// by older versions, which generate the groups of `C::PROTOCOL_TYPE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protocol_type: Option<i32>,
+    /// Whether the messages of the other parties are authenticated,
+    /// see `Protocol::set_authenticated`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    authenticated: bool,
 }
 
 #[derive(Serialize, Deserialize)]
-enum KeygenRound {
+#[serde(bound = "C: Ciphersuite")]
+enum KeygenRound<C: Ciphersuite> {
     R0,
-    R1(round1::SecretPackage),
-    R2(round2::SecretPackage, HashMap<Identifier, round1::Package>),
-    Done(KeyPackage, PublicKeyPackage),
+    R1(round1::SecretPackage<C>),
+    R2(
+        round2::SecretPackage<C>,
+        HashMap<Identifier<C>, round1::Package<C>>,
+    ),
+    Done(KeyPackage<C>, PublicKeyPackage<C>),
+}
+
+impl<C: Ciphersuite> KeygenRound<C> {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::R0 => "R0",
+            Self::R1(..) => "R1",
+            Self::R2(..) => "R2",
+            Self::Done(..) => "Done",
+        }
+    }
 }
 
-impl KeygenContext {
+impl<C: FrostCiphersuite> FrostKeygenContext<C> {
+    fn protocol_type(&self) -> ProtocolType {
+        self.protocol_type
+            .and_then(ProtocolType::from_i32)
+            .unwrap_or(C::PROTOCOL_TYPE)
+    }
+
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let msg = ProtocolGroupInit::decode(data)?;
-        if msg.protocol_type != ProtocolType::Frost as i32 {
-            return Err("wrong protocol type".into());
+        let protocol_type = ProtocolType::check_any(C::GROUP_TYPES, msg.protocol_type)?;
+        check_group_params(&msg, 1)?;
+        if msg.encrypt && !self.authenticated {
+            return Err("encrypted key generation requires authenticated messages".into());
         }
+        self.protocol_type = Some(protocol_type as i32);
 
-        let (parties, threshold, index) = (
-            msg.parties as u16,
-            msg.threshold as u16,
-            (msg.index as u16).try_into()?,
-        );
+        let (parties, threshold) = (msg.parties as u16, msg.threshold as u16);
+        let index: Identifier<C> = (msg.index as u16).try_into()?;
 
-        let (secret_package, public_package) = dkg::part1(index, parties, threshold, OsRng)?;
+        if parties == 1 {
+            return self.init_single(index, threshold);
+        }
 
-        let msgs = serialize_bcast(&public_package, (parties - 1) as usize)?;
+        let (secret_package, public_package) = dkg::part1(index, parties, threshold, ProtocolRng)?;
+
+        let msgs = if msg.encrypt {
+            let key = p256::SecretKey::random(&mut ProtocolRng);
+            let public = key.public_key().to_encoded_point(true).as_bytes().to_vec();
+            self.session_key = Some(key.to_bytes().to_vec());
+            serialize_bcast(&(&public_package, public), (parties - 1) as usize)?
+        } else {
+            serialize_bcast(&public_package, (parties - 1) as usize)?
+        };
+        let commitments = public_package.commitment().serialize();
+        self.commitments = Some(commitments.iter().map(|c| c.as_ref().to_vec()).collect());
         self.round = KeygenRound::R1(secret_package);
-        Ok(pack(msgs, ProtocolType::Frost))
+        self.peers = Some((parties - 1) as usize);
+        self.threshold = Some(threshold);
+        Ok(pack(msgs, self.protocol_type()))
+    }
+
+    /// A single party has the whole secret as its share, so there is nothing
+    /// to be distributed; it signs alone, so its threshold must be one, and
+    /// the sharing polynomial is the constant committed to by the public key
+    fn init_single(&mut self, identifier: Identifier<C>, threshold: u16) -> Result<Vec<u8>> {
+        if threshold != 1 {
+            return Err("invalid group parameters".into());
+        }
+        let signing_key = SigningKey::<C>::new(&mut ProtocolRng);
+        let group_public = VerifyingKey::from(&signing_key);
+        let secret = SigningShare::deserialize(signing_key.serialize())?;
+        let public = VerifyingShare::deserialize(group_public.serialize())?;
+
+        let key = KeyPackage::new(identifier, secret, public, group_public);
+        let pubkey = PublicKeyPackage::new(HashMap::from([(identifier, public)]), group_public);
+        self.commitments = Some(vec![group_public.serialize().as_ref().to_vec()]);
+        self.round = KeygenRound::Done(key, pubkey);
+        self.peers = Some(0);
+        self.threshold = Some(threshold);
+        Ok(pack(Vec::new(), self.protocol_type()))
+    }
+
+    /// Error naming the party who sent an invalid round 1 package
+    fn blame(&self, identifier: &Identifier<C>) -> Box<dyn std::error::Error> {
+        let parties = self.peers.unwrap_or_default() as u16 + 1;
+        let index =
+            (1..=parties).find(|i| Identifier::try_from(*i).ok().as_ref() == Some(identifier));
+        telemetry!(warn, party = ?index, "invalid package");
+        match index {
+            Some(index) => format!("invalid package from party {}", index).into(),
+            None => "invalid package".into(),
+        }
     }
 
-    fn index_to_identifier(mut index: usize, local_identifier: &Identifier) -> Identifier {
+    fn index_to_identifier(
+        mut index: usize,
+        local_identifier: &Identifier<C>,
+    ) -> Result<Identifier<C>> {
         index += 1;
-        if &Identifier::try_from(index as u16).unwrap() >= local_identifier {
+        if &Identifier::try_from(index as u16)? >= local_identifier {
             index += 1
         };
-        Identifier::try_from(index as u16).unwrap()
+        Ok(Identifier::try_from(index as u16)?)
+    }
+
+    /// Pair the messages from the other parties with their identifiers,
+    /// relying on the positional order for contexts of older versions
+    fn receive(
+        &self,
+        data: &[u8],
+        local_identifier: &Identifier<C>,
+    ) -> Result<Vec<(Identifier<C>, Bytes)>> {
+        let peers = match self.peers {
+            Some(peers) => peers as u32,
+            None => {
+                return unpack(data)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, msg)| Ok((Self::index_to_identifier(i, local_identifier)?, msg)))
+                    .collect()
+            }
+        };
+        let others: Vec<u32> = (1..=peers + 1)
+            .filter(|i| Identifier::try_from(*i as u16).ok().as_ref() != Some(local_identifier))
+            .collect();
+        unpack_from(data, &others)?
+            .into_iter()
+            .map(|(index, msg)| Ok((Identifier::try_from(index as u16)?, msg)))
+            .collect()
     }
 
     fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let (c, msgs) = match &self.round {
             KeygenRound::R0 => return Err("protocol not initialized".into()),
             KeygenRound::R1(secret) => {
-                let data: Vec<round1::Package> = deserialize_vec(&unpack(data)?)?;
-                let round1: HashMap<Identifier, round1::Package> = data
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, msg)| (Self::index_to_identifier(i, secret.identifier()), msg))
-                    .collect();
-                let (secret, round2) = dkg::part2(secret.clone(), &round1)?;
+                let mut round1 = HashMap::new();
+                let mut keys = HashMap::new();
+                for (identifier, msg) in self.receive(data, secret.identifier())? {
+                    let package: round1::Package<C> = if self.session_key.is_some() {
+                        let (package, key): (round1::Package<C>, Vec<u8>) =
+                            deserialize_msg(&msg).map_err(|_| self.blame(&identifier))?;
+                        p256::PublicKey::from_sec1_bytes(&key)
+                            .map_err(|_| self.blame(&identifier))?;
+                        keys.insert(identifier, key);
+                        package
+                    } else {
+                        deserialize_msg(&msg).map_err(|_| self.blame(&identifier))?
+                    };
+                    let commitments = package.commitment().serialize().len();
+                    if self.threshold.map_or(false, |t| commitments != t as usize) {
+                        return Err(self.blame(&identifier));
+                    }
+                    round1.insert(identifier, package);
+                }
+                let (secret, round2) =
+                    dkg::part2(secret.clone(), &round1).map_err(|e| match e {
+                        frost_core::Error::InvalidProofOfKnowledge { culprit, .. } => {
+                            self.blame(&culprit)
+                        }
+                        e => e.into(),
+                    })?;
                 let mut round2: Vec<_> = round2.into_iter().collect();
                 round2.sort_by_key(|(i, _)| *i);
-                let round2: Vec<_> = round2.into_iter().map(|(_, p)| p).collect();
+                let msgs: Vec<Bytes> = if self.session_key.is_some() {
+                    round2
+                        .into_iter()
+                        .map(|(i, p)| Ok(hpke::encrypt(&serialize_msg(&p)?, &keys[&i])?.into()))
+                        .collect::<Result<_>>()?
+                } else {
+                    serialize_uni(round2.into_iter().map(|(_, p)| p).collect())?
+                };
 
-                (KeygenRound::R2(secret, round1), serialize_uni(round2)?)
+                (KeygenRound::R2(secret, round1), msgs)
             }
             KeygenRound::R2(secret, round1) => {
-                let data: Vec<round2::Package> = deserialize_vec(&unpack(data)?)?;
-                let round2: HashMap<Identifier, round2::Package> = data
+                let session_key = match &self.session_key {
+                    Some(key) => {
+                        Some(p256::SecretKey::from_slice(key).map_err(|_| "invalid session key")?)
+                    }
+                    None => None,
+                };
+                let round2: HashMap<Identifier<C>, round2::Package<C>> = self
+                    .receive(data, secret.identifier())?
                     .into_iter()
-                    .enumerate()
-                    .map(|(i, msg)| (Self::index_to_identifier(i, secret.identifier()), msg))
-                    .collect();
-                let (key, pubkey) = frost::keys::dkg::part3(secret, round1, &round2)?;
+                    .map(|(identifier, msg)| {
+                        let package = match &session_key {
+                            Some(key) => deserialize_msg(
+                                &hpke::decrypt(&msg, key).map_err(|_| self.blame(&identifier))?,
+                            ),
+                            None => deserialize_msg(&msg),
+                        };
+                        Ok((identifier, package?))
+                    })
+                    .collect::<Result<_>>()?;
+                let (key, pubkey) = dkg::part3(secret, round1, &round2)?;
+                self.session_key = None;
+                if let Some(own) = &self.commitments {
+                    self.commitments = Some(group_commitments::<C>(own, round1.values())?);
+                }
 
                 let msgs = inflate(serde_json::to_vec(&pubkey.group_public())?, round2.len());
                 (KeygenRound::Done(key, pubkey), msgs)
@@ -88,56 +313,116 @@ impl KeygenContext {
         };
         self.round = c;
 
-        Ok(pack(msgs, ProtocolType::Frost))
+        Ok(pack(msgs, self.protocol_type()))
     }
-}
 
-#[typetag::serde(name = "frost_keygen")]
-impl Protocol for KeygenContext {
-    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        let data = match self.round {
+    fn step(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
             KeygenRound::R0 => self.init(data),
             _ => self.update(data),
-        }?;
-        Ok(data)
+        }
     }
 
-    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+    fn result(self) -> Result<Vec<u8>> {
+        let protocol_type = self.protocol_type();
         match self.round {
             KeygenRound::Done(key_package, pubkey_package) => {
-                Ok(serde_json::to_vec(&(key_package, pubkey_package))?)
+                // the parties of a fresh group have the identifiers 1..=n
+                let parties = pubkey_package.signer_pubkeys().len() as u16;
+                let shares = (1..=parties)
+                    .map(|index| {
+                        let identifier = Identifier::try_from(index)?;
+                        let share = pubkey_package
+                            .signer_pubkeys()
+                            .get(&identifier)
+                            .ok_or("missing verifying share")?;
+                        Ok(PublicShare {
+                            index: index as u32,
+                            public_key: share.serialize().as_ref().to_vec(),
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(pack_group_shares(
+                    protocol_type,
+                    pubkey_package.group_public().serialize().as_ref().to_vec(),
+                    serde_json::to_vec(&(key_package, pubkey_package))?,
+                    shares,
+                    self.commitments.unwrap_or_default(),
+                ))
             }
             _ => Err("protocol not finished".into()),
         }
     }
-}
 
-impl KeygenProtocol for KeygenContext {
-    fn new() -> Self {
-        Self {
-            round: KeygenRound::R0,
+    fn expected(&self) -> Option<usize> {
+        match &self.round {
+            KeygenRound::R0 | KeygenRound::Done(_, _) => None,
+            KeygenRound::R1(_) => self.peers,
+            KeygenRound::R2(_, round1) => Some(round1.len()),
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
-pub(crate) struct SignContext {
-    key: KeyPackage,
-    pubkey: PublicKeyPackage,
+#[serde(bound = "C: Ciphersuite")]
+pub(crate) struct FrostSignContext<C: FrostCiphersuite> {
+    key: KeyPackage<C>,
+    pubkey: PublicKeyPackage<C>,
     message: Option<Vec<u8>>,
     indices: Option<Vec<u16>>,
-    round: SignRound,
+    /// Index of the only party aggregating the signature, if designated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    coordinator: Option<u16>,
+    /// Session signing a blinded challenge, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blind: Option<blind::Signer>,
+    /// Session producing an adaptor pre-signature, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adaptor: Option<adaptor::Signer>,
+    #[serde(default)]
+    options: ContextOptions,
+    round: SignRound<C>,
 }
 
 #[derive(Serialize, Deserialize)]
-enum SignRound {
+#[serde(bound = "C: Ciphersuite")]
+enum SignRound<C: Ciphersuite> {
     R0,
-    R1(SigningNonces, SigningCommitments),
-    R2(SigningPackage, SignatureShare),
-    Done(Signature),
+    R1(SigningNonces<C>, SigningCommitments<C>),
+    R2(SigningPackage<C>, SignatureShare<C>),
+    /// Distribution of the signature aggregated by the coordinator
+    R3(SigningPackage<C>, Option<Signature<C>>),
+    Done(Signature<C>),
+}
+
+impl<C: Ciphersuite> SignRound<C> {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::R0 => "R0",
+            Self::R1(..) => "R1",
+            Self::R2(..) => "R2",
+            Self::R3(..) => "R3",
+            Self::Done(..) => "Done",
+        }
+    }
 }
 
-impl SignContext {
+impl<C: FrostCiphersuite> FrostSignContext<C> {
+    fn from_group(group: &[u8]) -> Result<Self> {
+        let (key, pubkey): (KeyPackage<C>, PublicKeyPackage<C>) = parse_group_context(group)?;
+        Ok(Self {
+            key,
+            pubkey,
+            message: None,
+            indices: None,
+            coordinator: None,
+            blind: None,
+            adaptor: None,
+            options: ContextOptions::default(),
+            round: SignRound::R0,
+        })
+    }
+
     fn local_index(&self) -> Result<usize> {
         let identifier = self.key.identifier();
         self.indices
@@ -145,127 +430,583 @@ impl SignContext {
             .and_then(|indices| {
                 indices
                     .iter()
-                    .position(|x| &Identifier::try_from(*x).unwrap() == identifier)
+                    .position(|x| Identifier::try_from(*x).ok().as_ref() == Some(identifier))
             })
             .ok_or("participant index not included".into())
     }
 
+    /// Whether the party aggregates the signature, which all parties do
+    /// unless a coordinator is designated
+    fn aggregates(&self) -> Result<bool> {
+        let index = self.indices.as_ref().unwrap()[self.local_index()?];
+        Ok(self
+            .coordinator
+            .map_or(true, |coordinator| coordinator == index))
+    }
+
+    /// Messages carrying `value` to the coordinator, and nothing to the others
+    fn to_coordinator<T: Serialize>(&self, value: &T) -> Result<Vec<Bytes>> {
+        let msg = Bytes::from(serialize_msg(value)?);
+        let local_index = self.local_index()?;
+        Ok(self
+            .indices
+            .as_ref()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != local_index)
+            .map(|(_, index)| {
+                if Some(*index) == self.coordinator {
+                    msg.clone()
+                } else {
+                    Bytes::new()
+                }
+            })
+            .collect())
+    }
+
+    /// Pair the messages from the other parties with their identifiers
+    fn receive(&self, data: &[u8]) -> Result<Vec<(Identifier<C>, Bytes)>> {
+        let local_index = self.local_index()?;
+        let others: Vec<u32> = self
+            .indices
+            .as_ref()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != local_index)
+            .map(|(_, index)| *index as u32)
+            .collect();
+        unpack_from(data, &others)?
+            .into_iter()
+            .map(|(index, msg)| Ok((Identifier::try_from(index as u16)?, msg)))
+            .collect()
+    }
+
+    /// Error naming the signer who sent an invalid signature share
+    fn blame(&self, identifier: &Identifier<C>) -> Box<dyn std::error::Error> {
+        let index = self.indices.as_ref().and_then(|indices| {
+            indices
+                .iter()
+                .find(|x| Identifier::try_from(**x).ok().as_ref() == Some(identifier))
+        });
+        telemetry!(warn, party = ?index, "invalid signature share");
+        match index {
+            Some(index) => format!("invalid signature share from party {}", index).into(),
+            None => "invalid signature share".into(),
+        }
+    }
+
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if !matches!(self.round, SignRound::R0) || self.blind.is_some() || self.adaptor.is_some() {
+            return Err("nonces already committed".into());
+        }
         let msg = ProtocolInit::decode(data)?;
-        if msg.protocol_type != ProtocolType::Frost as i32 {
-            return Err("wrong protocol type".into());
+        C::PROTOCOL_TYPE.check(msg.protocol_type)?;
+        if msg.blind && !self.options.blind {
+            return Err("blind sessions not allowed".into());
         }
+        policy::check(C::PROTOCOL_TYPE, &msg)?;
 
         self.indices = Some(msg.indices.iter().map(|i| *i as u16).collect());
         self.message = Some(msg.data);
+        self.local_index()?;
+        if msg.blind {
+            if !msg.adaptor.is_empty() {
+                return Err("blind adaptor signatures not supported".into());
+            }
+            let (signer, msg) = blind::Signer::commit::<C>();
+            self.blind = Some(signer);
+            return Ok(msg);
+        }
+        if !msg.adaptor.is_empty() {
+            if msg.coordinator != 0 && msg.indices.len() > 1 {
+                return Err("adaptor signatures cannot be coordinated".into());
+            }
+            let (signer, commitment) = adaptor::Signer::commit::<C>(&msg.adaptor)?;
+            self.adaptor = Some(signer);
+            let peers = msg.indices.len() - 1;
+            if peers == 0 {
+                return run_locally(2, C::PROTOCOL_TYPE, |data| self.step(data));
+            }
+            return Ok(pack(inflate(commitment, peers), C::PROTOCOL_TYPE));
+        }
+        if msg.coordinator != 0 && msg.indices.len() > 1 {
+            if !msg.indices.contains(&msg.coordinator) {
+                return Err("coordinator not included".into());
+            }
+            self.coordinator = Some(msg.coordinator as u16);
+        }
 
-        let (nonces, commitments) = frost::round1::commit(self.key.secret_share(), &mut OsRng);
+        let (nonces, commitments) = if self.options.hedged {
+            let secret = self.key.secret_share().serialize();
+            let indices: Vec<u8> = msg.indices.iter().flat_map(|i| i.to_be_bytes()).collect();
+            let session = [&self.message.as_ref().unwrap()[..], &indices];
+            let mut rng = HedgedRng::new(secret.as_ref(), &session);
+            frost_core::round1::commit(self.key.secret_share(), &mut rng)
+        } else {
+            frost_core::round1::commit(self.key.secret_share(), &mut ProtocolRng)
+        };
 
         let msgs = serialize_bcast(&commitments, self.indices.as_ref().unwrap().len() - 1)?;
         self.round = SignRound::R1(nonces, commitments);
-        Ok(pack(msgs, ProtocolType::Frost))
+
+        if self.indices.as_ref().unwrap().len() == 1 {
+            return run_locally(2, C::PROTOCOL_TYPE, |data| self.update(data));
+        }
+        Ok(pack(msgs, C::PROTOCOL_TYPE))
     }
 
     fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         match &self.round {
             SignRound::R0 => Err("protocol not initialized".into()),
             SignRound::R1(nonces, commitments) => {
-                let local_index = self.local_index()?;
-                let data: Vec<SigningCommitments> = deserialize_vec(&unpack(data)?)?;
-
-                let mut commitments_map: BTreeMap<Identifier, SigningCommitments> = data
+                let mut commitments_map: BTreeMap<Identifier<C>, SigningCommitments<C>> = self
+                    .receive(data)?
                     .into_iter()
-                    .enumerate()
-                    .map(|(i, msg)| {
-                        (
-                            Identifier::try_from(
-                                self.indices.as_ref().unwrap()
-                                    [if i >= local_index { i + 1 } else { i }],
-                            )
-                            .unwrap(),
-                            msg,
-                        )
-                    })
-                    .collect();
+                    .map(|(identifier, msg)| Ok((identifier, deserialize_msg(&msg)?)))
+                    .collect::<Result<_>>()?;
                 commitments_map.insert(*self.key.identifier(), *commitments);
 
                 let signing_package =
-                    frost::SigningPackage::new(commitments_map, self.message.as_ref().unwrap());
-                let share = frost::round2::sign(&signing_package, nonces, &self.key)?;
+                    SigningPackage::new(commitments_map, self.message.as_ref().unwrap());
+                nonces::consume(nonces::session_id(commitments)?, &signing_package)?;
+                let share = frost_core::round2::sign(&signing_package, nonces, &self.key)?;
 
-                let msgs = serialize_bcast(&share, self.indices.as_ref().unwrap().len() - 1)?;
+                let msgs = match self.coordinator {
+                    Some(_) => self.to_coordinator(&share)?,
+                    None => serialize_bcast(&share, self.indices.as_ref().unwrap().len() - 1)?,
+                };
                 self.round = SignRound::R2(signing_package, share);
-                Ok(pack(msgs, ProtocolType::Frost))
+                Ok(pack(msgs, C::PROTOCOL_TYPE))
             }
             SignRound::R2(signing_package, share) => {
-                let local_index = self.local_index()?;
-                let data: Vec<SignatureShare> = deserialize_vec(&unpack(data)?)?;
-
-                let mut shares: HashMap<Identifier, SignatureShare> = data
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, msg)| {
-                        (
-                            Identifier::try_from(
-                                self.indices.as_ref().unwrap()
-                                    [if i >= local_index { i + 1 } else { i }],
-                            )
-                            .unwrap(),
-                            msg,
-                        )
-                    })
-                    .collect();
+                let msgs = self.receive(data)?;
+                let peers = self.indices.as_ref().unwrap().len() - 1;
+                if !self.aggregates()? {
+                    self.round = SignRound::R3(signing_package.clone(), None);
+                    return Ok(pack(inflate(Bytes::new(), peers), C::PROTOCOL_TYPE));
+                }
+                let mut shares = HashMap::new();
+                for (identifier, msg) in msgs {
+                    let share: SignatureShare<C> =
+                        deserialize_msg(&msg).map_err(|_| self.blame(&identifier))?;
+                    shares.insert(identifier, share);
+                }
                 shares.insert(*self.key.identifier(), *share);
 
-                let signature = frost::aggregate(signing_package, &shares, &self.pubkey)?;
+                // each share is checked against the commitments and the
+                // verifying share of its signer if the aggregate is invalid
+                let signature = frost_core::aggregate(signing_package, &shares, &self.pubkey)
+                    .map_err(|e| match e {
+                        frost_core::Error::InvalidSignatureShare { culprit, .. } => {
+                            self.blame(&culprit)
+                        }
+                        e => e.into(),
+                    })?;
 
-                let msgs = serialize_bcast(&signature, self.indices.as_ref().unwrap().len() - 1)?;
+                let msgs = serialize_bcast(&signature, peers)?;
+                self.round = match self.coordinator {
+                    Some(_) => SignRound::R3(signing_package.clone(), Some(signature)),
+                    None => SignRound::Done(signature),
+                };
+                Ok(pack(msgs, C::PROTOCOL_TYPE))
+            }
+            SignRound::R3(_, signature) => {
+                let msgs = self.receive(data)?;
+                let signature = match signature {
+                    Some(signature) => *signature,
+                    None => {
+                        let coordinator = Identifier::try_from(self.coordinator.unwrap())?;
+                        let (_, msg) = msgs
+                            .into_iter()
+                            .find(|(identifier, _)| *identifier == coordinator)
+                            .ok_or("coordinator not included")?;
+                        let signature: Signature<C> = deserialize_msg(&msg)?;
+                        self.pubkey
+                            .group_public()
+                            .verify(self.message.as_ref().unwrap(), &signature)?;
+                        signature
+                    }
+                };
                 self.round = SignRound::Done(signature);
-                Ok(pack(msgs, ProtocolType::Frost))
+                Ok(pack(Vec::new(), C::PROTOCOL_TYPE))
             }
             SignRound::Done(_) => Err("protocol already finished".into()),
         }
     }
-}
 
-#[typetag::serde(name = "frost_sign")]
-impl Protocol for SignContext {
-    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        let data = match self.round {
+    fn step(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.blind.is_some() {
+            let indices = self.indices.as_ref().unwrap();
+            let index = indices[self.local_index()?];
+            let signer = self.blind.as_mut().unwrap();
+            return signer.respond(&self.key, index, indices, data);
+        }
+        if self.adaptor.is_some() {
+            let msgs = self
+                .receive(data)?
+                .into_iter()
+                .map(|(_, msg)| msg.to_vec())
+                .collect();
+            let indices = self.indices.as_ref().unwrap();
+            let signer = self.adaptor.as_mut().unwrap();
+            let msgs = match signer.advance(
+                &self.key,
+                &self.pubkey,
+                indices,
+                self.message.as_ref().unwrap(),
+                msgs,
+            )? {
+                Some(share) => inflate(share, indices.len() - 1),
+                None => Vec::new(),
+            };
+            return Ok(pack(msgs, C::PROTOCOL_TYPE));
+        }
+        match self.round {
             SignRound::R0 => self.init(data),
             _ => self.update(data),
-        }?;
-        Ok(data)
+        }
     }
 
-    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+    fn result(self) -> Result<Vec<u8>> {
+        if let Some(signer) = &self.blind {
+            if !signer.finished() {
+                return Err("protocol not finished".into());
+            }
+            let indices = self.indices.unwrap_or_default();
+            let indices = indices.into_iter().map(u32::from).collect();
+            return Ok(pack_signature(C::PROTOCOL_TYPE, Vec::new(), indices));
+        }
+        if let Some(signer) = &self.adaptor {
+            let presignature = signer.presignature().ok_or("protocol not finished")?;
+            let indices = self.indices.clone().unwrap_or_default();
+            let indices = indices.into_iter().map(u32::from).collect();
+            return Ok(pack_signature(
+                C::PROTOCOL_TYPE,
+                presignature.to_vec(),
+                indices,
+            ));
+        }
         match self.round {
-            SignRound::Done(sig) => Ok(serde_json::to_vec(&sig)?),
+            SignRound::Done(sig) => Ok(pack_signature(
+                C::PROTOCOL_TYPE,
+                sig.serialize().as_ref().to_vec(),
+                self.indices
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(u32::from)
+                    .collect(),
+            )),
             _ => Err("protocol not finished".into()),
         }
     }
+
+    fn expected(&self) -> Option<usize> {
+        if let Some(signer) = &self.blind {
+            return if signer.finished() { None } else { Some(1) };
+        }
+        if let Some(signer) = &self.adaptor {
+            if signer.finished() {
+                return None;
+            }
+            return self.indices.as_ref().map(|indices| indices.len() - 1);
+        }
+        match self.round {
+            SignRound::R0 | SignRound::Done(_) => None,
+            _ => self.indices.as_ref().map(|indices| indices.len() - 1),
+        }
+    }
 }
 
-impl ThresholdProtocol for SignContext {
-    fn new(group: &[u8]) -> Self {
-        let (key, pubkey): (KeyPackage, PublicKeyPackage) =
-            serde_json::from_slice(group).expect("could not deserialize group context");
-        Self {
-            key,
-            pubkey,
-            message: None,
-            indices: None,
-            round: SignRound::R0,
+/// Implement the protocol traits of the contexts of a ciphersuite,
+/// registering them under the given names
+macro_rules! frost_protocol {
+    ($suite:ty, $keygen:tt, $sign:tt) => {
+        #[typetag::serde(name = $keygen)]
+        impl Protocol for FrostKeygenContext<$suite> {
+            fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+                self.step(data)
+            }
+
+            fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+                self.result()
+            }
+
+            fn expected_messages(&self) -> Option<usize> {
+                self.expected()
+            }
+
+            fn set_authenticated(&mut self) {
+                self.authenticated = true;
+            }
+
+            fn debug_state(&self) -> DebugState {
+                DebugState::new($keygen, self.round.name(), self.expected())
+            }
         }
+
+        impl KeygenProtocol for FrostKeygenContext<$suite> {
+            fn new() -> Self {
+                Self {
+                    round: KeygenRound::R0,
+                    peers: None,
+                    threshold: None,
+                    session_key: None,
+                    commitments: None,
+                    protocol_type: None,
+                    authenticated: false,
+                }
+            }
+        }
+
+        #[typetag::serde(name = $sign)]
+        impl Protocol for FrostSignContext<$suite> {
+            fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+                self.step(data)
+            }
+
+            fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+                self.result()
+            }
+
+            fn expected_messages(&self) -> Option<usize> {
+                self.expected()
+            }
+
+            fn debug_state(&self) -> DebugState {
+                DebugState::new($sign, self.round.name(), self.expected())
+            }
+        }
+
+        impl ThresholdProtocol for FrostSignContext<$suite> {
+            fn new(group: &[u8]) -> Result<Self> {
+                Self::from_group(group)
+            }
+
+            fn configure(&mut self, options: &ContextOptions) {
+                self.options = *options;
+            }
+        }
+    };
+}
+
+frost_protocol!(Secp256K1Sha256, "frost_keygen", "frost_sign");
+frost_protocol!(P256Sha256, "frost_p256_keygen", "frost_p256_sign");
+
+pub(crate) type KeygenContext = FrostKeygenContext<Secp256K1Sha256>;
+pub(crate) type SignContext = FrostSignContext<Secp256K1Sha256>;
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[
+    ProtocolInfo {
+        protocol_type: ProtocolType::Frost,
+        name: "frost",
+        curve: "secp256k1",
+        scheme: "schnorr",
+        index_base: 1,
+        keygen_rounds: 3,
+        threshold_rounds: Some(3),
+        max_parties: MAX_PARTIES,
+        min_threshold: Some(1),
+        prehashed: false,
+        all_sign: false,
+        resharing: false,
+        keygen: registry::keygen::<KeygenContext>,
+        threshold: registry::threshold::<SignContext>,
+    },
+    ProtocolInfo {
+        protocol_type: ProtocolType::FrostP256,
+        name: "frost_p256",
+        curve: "secp256r1",
+        scheme: "schnorr",
+        index_base: 1,
+        keygen_rounds: 3,
+        threshold_rounds: Some(3),
+        max_parties: MAX_PARTIES,
+        min_threshold: Some(1),
+        prehashed: false,
+        all_sign: false,
+        resharing: false,
+        keygen: registry::keygen::<FrostKeygenContext<P256Sha256>>,
+        threshold: registry::threshold::<FrostSignContext<P256Sha256>>,
+    },
+];
+
+type Element<C> = <<C as Ciphersuite>::Group as Group>::Element;
+
+fn to_element<C: Ciphersuite>(bytes: &[u8]) -> Result<Element<C>> {
+    let bytes = bytes
+        .to_vec()
+        .try_into()
+        .map_err(|_| "invalid commitment")?;
+    C::Group::deserialize(&bytes).map_err(|_| "invalid commitment".into())
+}
+
+fn from_element<C: Ciphersuite>(element: &Element<C>) -> Vec<u8> {
+    C::Group::serialize(element).as_ref().to_vec()
+}
+
+/// Sum the own Feldman commitments with those of the round 1 packages
+/// into the commitments of the group polynomial
+fn group_commitments<'a, C: Ciphersuite + 'a>(
+    own: &[Vec<u8>],
+    packages: impl Iterator<Item = &'a round1::Package<C>>,
+) -> Result<Vec<Vec<u8>>> {
+    let mut sum = own
+        .iter()
+        .map(|c| to_element::<C>(c))
+        .collect::<Result<Vec<_>>>()?;
+    for package in packages {
+        for (acc, c) in sum.iter_mut().zip(package.commitment().serialize()) {
+            *acc = *acc + to_element::<C>(c.as_ref())?;
+        }
+    }
+    Ok(sum.iter().map(from_element::<C>).collect())
+}
+
+/// Check the verifying shares of a group result against the commitments
+/// of the group polynomial, evaluated at the index of each party
+fn verify_shares_with<C: Ciphersuite>(group: &GroupResult) -> Result<()> {
+    if group.commitments.is_empty() {
+        return Err("commitments not available".into());
+    }
+    let commitments = group
+        .commitments
+        .iter()
+        .map(|c| to_element::<C>(c))
+        .collect::<Result<Vec<_>>>()?;
+    if from_element::<C>(&commitments[0]) != group.public_key {
+        return Err("commitments do not match the group key".into());
+    }
+    for share in &group.shares {
+        let one = <<C::Group as Group>::Field as Field>::one();
+        let x = (0..share.index).fold(<<C::Group as Group>::Field as Field>::zero(), |x, _| {
+            x + one
+        });
+        let value = commitments
+            .iter()
+            .rev()
+            .fold(C::Group::identity(), |acc, c| acc * x + *c);
+        if from_element::<C>(&value) != share.public_key {
+            return Err(format!("invalid verifying share of party {}", share.index).into());
+        }
+    }
+    Ok(())
+}
+
+/// Verify the verifying shares in the result of a FROST key generation
+/// against the Feldman commitments it exports, so that an auditor need not
+/// trust the parties which computed them
+pub fn verify_shares(group: &[u8]) -> Result<()> {
+    let group = GroupResult::decode(group)?;
+    match ProtocolType::from_i32(group.protocol_type) {
+        Some(t) if Secp256K1Sha256::GROUP_TYPES.contains(&t) => {
+            verify_shares_with::<Secp256K1Sha256>(&group)
+        }
+        Some(t) if P256Sha256::GROUP_TYPES.contains(&t) => verify_shares_with::<P256Sha256>(&group),
+        _ => Err("not a FROST group".into()),
+    }
+}
+
+/// Public key package of a group of the given ciphersuite,
+/// see `coordinator::public_package`
+pub(crate) fn public_package_with<C: FrostCiphersuite>(group: &[u8]) -> Result<Vec<u8>> {
+    let (_, pubkey): (KeyPackage<C>, PublicKeyPackage<C>) = parse_group_context(group)?;
+    Ok(serde_json::to_vec(&pubkey)?)
+}
+
+/// Aggregate the signature shares of a session of the given ciphersuite,
+/// see `coordinator::aggregate`
+pub(crate) fn aggregate_with<C: FrostCiphersuite>(
+    package: &[u8],
+    message: &[u8],
+    commitments: &[(u16, Vec<u8>)],
+    shares: &[(u16, Vec<u8>)],
+) -> Result<Vec<u8>> {
+    let pubkey: PublicKeyPackage<C> = serde_json::from_slice(package)?;
+    let commitments_map: BTreeMap<Identifier<C>, SigningCommitments<C>> = commitments
+        .iter()
+        .map(|(index, msg)| Ok((Identifier::try_from(*index)?, deserialize_msg(msg)?)))
+        .collect::<Result<_>>()?;
+    let shares_map: HashMap<Identifier<C>, SignatureShare<C>> = shares
+        .iter()
+        .map(|(index, msg)| {
+            let share = deserialize_msg(msg)
+                .map_err(|_| format!("invalid signature share from party {}", index))?;
+            Ok((Identifier::try_from(*index)?, share))
+        })
+        .collect::<Result<_>>()?;
+    if commitments_map.len() != shares_map.len()
+        || shares_map
+            .keys()
+            .any(|identifier| !commitments_map.contains_key(identifier))
+    {
+        return Err("signers of the shares do not match the commitments".into());
     }
+
+    let signing_package = SigningPackage::new(commitments_map, message);
+    let signature = frost_core::aggregate(&signing_package, &shares_map, &pubkey).map_err(
+        |e| -> Box<dyn std::error::Error> {
+            match e {
+                frost_core::Error::InvalidSignatureShare { culprit, .. } => {
+                    let index = shares
+                        .iter()
+                        .find(|(index, _)| Identifier::try_from(*index).ok() == Some(culprit));
+                    match index {
+                        Some((index, _)) => {
+                            format!("invalid signature share from party {}", index).into()
+                        }
+                        None => "invalid signature share".into(),
+                    }
+                }
+                e => e.into(),
+            }
+        },
+    )?;
+    let indices = commitments.iter().map(|(index, _)| *index as u32).collect();
+    Ok(pack_signature(
+        C::PROTOCOL_TYPE,
+        signature.serialize().as_ref().to_vec(),
+        indices,
+    ))
+}
+
+/// Verify a signature of the given ciphersuite
+pub(crate) fn verify_with<C: FrostCiphersuite>(
+    pk: &[u8],
+    msg: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let pk = pk.to_vec().try_into().map_err(|_| "invalid public key")?;
+    let pk = VerifyingKey::<C>::deserialize(pk)?;
+    let signature = signature
+        .to_vec()
+        .try_into()
+        .map_err(|_| "malformed signature")?;
+    let signature = Signature::<C>::deserialize(signature)?;
+    Ok(pk.verify(msg, &signature)?)
+}
+
+/// Verify a signature produced by the signing protocol, given the
+/// compressed group public key and the serialized signature
+pub fn verify(pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    verify_with::<Secp256K1Sha256>(pk, msg, signature)
+}
+
+/// Verify a signature produced by the P-256 signing protocol
+pub fn verify_p256(pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    verify_with::<P256Sha256>(pk, msg, signature)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
-    use frost::VerifyingKey;
-    use rand::seq::IteratorRandom;
+    use crate::proto::{GroupResult, ProtocolMessage, SignatureResult};
+    use crate::protocol::tests::{
+        advance_all, advance_unpacked, load_fixture, run_keygen, run_rounds, KeygenProtocolTest,
+        ThresholdProtocolTest,
+    };
+    use rand::{rngs::OsRng, seq::IteratorRandom};
 
     impl KeygenProtocolTest for KeygenContext {
         const PROTOCOL_TYPE: ProtocolType = ProtocolType::Frost;
@@ -279,6 +1020,18 @@ mod tests {
         const INDEX_OFFSET: u32 = 1;
     }
 
+    impl KeygenProtocolTest for FrostKeygenContext<P256Sha256> {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::FrostP256;
+        const ROUNDS: usize = 3;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    impl ThresholdProtocolTest for FrostSignContext<P256Sha256> {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::FrostP256;
+        const ROUNDS: usize = 3;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
     #[test]
     fn keygen() {
         for threshold in 2..6 {
@@ -286,7 +1039,7 @@ mod tests {
                 let (pks, _) =
                     <KeygenContext as KeygenProtocolTest>::run(threshold as u32, parties as u32);
 
-                let pks: Vec<VerifyingKey> = pks
+                let pks: Vec<VerifyingKey<Secp256K1Sha256>> = pks
                     .iter()
                     .map(|x| serde_json::from_slice(&x).unwrap())
                     .collect();
@@ -298,6 +1051,156 @@ mod tests {
         }
     }
 
+    #[test]
+    fn public_shares() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let groups: Vec<GroupResult> = ctxs
+            .iter()
+            .map(|ctx| GroupResult::decode(&ctx[..]).unwrap())
+            .collect();
+
+        let indices: Vec<u32> = groups[0].shares.iter().map(|s| s.index).collect();
+        assert_eq!(indices, vec![1, 2, 3]);
+        for group in &groups {
+            assert_eq!(group.shares, groups[0].shares);
+        }
+        for share in &groups[0].shares {
+            VerifyingShare::<Secp256K1Sha256>::deserialize(
+                share.public_key.clone().try_into().unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn feldman_commitments() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        for ctx in &ctxs {
+            verify_shares(ctx).unwrap();
+        }
+
+        let mut group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.commitments.len(), 2);
+        group.shares.swap(0, 1);
+        group.shares[0].index = 1;
+        group.shares[1].index = 2;
+        let error = verify_shares(&group.encode_to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid verifying share of party 1");
+    }
+
+    #[test]
+    fn invalid_package() {
+        let init = |index, threshold| {
+            (ProtocolGroupInit {
+                protocol_type: ProtocolType::Frost as i32,
+                index,
+                parties: 3,
+                threshold,
+                weights: Vec::new(),
+                encrypt: false,
+                metadata: None,
+                version: 0,
+            })
+            .encode_to_vec()
+        };
+        let round1 = |threshold| {
+            let mut ctxs: Vec<KeygenContext> = (0..3).map(|_| KeygenContext::new()).collect();
+            let inits = (1..=3).map(|index| init(index, threshold)).collect();
+            (advance_all(&mut ctxs, inits), ctxs)
+        };
+
+        // party 3 sends the package of party 2 to party 1
+        let (packages, mut ctxs) = round1(2);
+        let input = pack(
+            vec![packages[1][0].clone().into(), packages[1][0].clone().into()],
+            ProtocolType::Frost,
+        );
+        let error = ctxs[0].advance(&input).unwrap_err();
+        assert_eq!(error.to_string(), "invalid package from party 3");
+
+        // the packages commit to fewer coefficients than the threshold needs
+        let mut ctx = KeygenContext::new();
+        ctx.advance(&init(1, 3)).unwrap();
+        let input = pack(
+            vec![packages[1][0].clone().into(), packages[2][0].clone().into()],
+            ProtocolType::Frost,
+        );
+        let error = ctx.advance(&input).unwrap_err();
+        assert_eq!(error.to_string(), "invalid package from party 2");
+    }
+
+    #[test]
+    fn named_senders() {
+        let mut ctxs: Vec<KeygenContext> = (0..3).map(|_| KeygenContext::new()).collect();
+        let inits = (1..=3)
+            .map(|index| {
+                (ProtocolGroupInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    index,
+                    parties: 3,
+                    threshold: 2,
+                    weights: Vec::new(),
+                    encrypt: false,
+                    metadata: None,
+                    version: 0,
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let packages = advance_all(&mut ctxs, inits);
+        let relay = |senders| {
+            (ProtocolMessage {
+                protocol_type: ProtocolType::Frost as i32,
+                message: vec![packages[2][0].clone().into(), packages[1][0].clone().into()],
+                senders,
+                ..Default::default()
+            })
+            .encode_to_vec()
+        };
+
+        // the packages of parties 3 and 2 are relayed out of order
+        let error = ctxs[0].advance(&relay(vec![3, 3])).unwrap_err();
+        assert_eq!(error.to_string(), "missing message from party 2");
+        ctxs[0].advance(&relay(vec![3, 2])).unwrap();
+    }
+
+    #[test]
+    fn encrypted_keygen() {
+        let mut ctxs: Vec<KeygenContext> = (0..3).map(|_| KeygenContext::new()).collect();
+        let inits: Vec<Vec<u8>> = (1..=3)
+            .map(|index| {
+                (ProtocolGroupInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    index,
+                    parties: 3,
+                    threshold: 2,
+                    weights: Vec::new(),
+                    encrypt: true,
+                    metadata: None,
+                    version: 0,
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        // the keys could be substituted unless authenticated by the devices
+        assert!(KeygenContext::new().advance(&inits[0]).is_err());
+        for ctx in &mut ctxs {
+            ctx.set_authenticated();
+        }
+        let packages = advance_all(&mut ctxs, inits);
+
+        // the server only relays ciphertexts of the round 2 packages
+        let shares = run_rounds(&mut ctxs, packages, 1);
+        assert!(deserialize_msg::<round2::Package<Secp256K1Sha256>>(&shares[0][0]).is_err());
+
+        run_rounds(&mut ctxs, shares, 1);
+        let groups: Vec<GroupResult> = ctxs
+            .into_iter()
+            .map(|ctx| GroupResult::decode(&Box::new(ctx).finish().unwrap()[..]).unwrap())
+            .collect();
+        assert!(groups.iter().all(|g| g.public_key == groups[0].public_key));
+    }
+
     #[test]
     fn sign() {
         for threshold in 2..6 {
@@ -305,21 +1208,339 @@ mod tests {
                 let (pks, ctxs) =
                     <KeygenContext as KeygenProtocolTest>::run(threshold as u32, parties as u32);
                 let msg = b"hello";
-                let pk: VerifyingKey = serde_json::from_slice(&pks[0]).unwrap();
+                let pk: VerifyingKey<Secp256K1Sha256> = serde_json::from_slice(&pks[0]).unwrap();
+                let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+                assert_eq!(group.public_key, pk.serialize().to_vec());
 
                 let mut indices = (0..parties as u16).choose_multiple(&mut OsRng, threshold);
                 indices.sort();
+                let signers: Vec<u32> = indices.iter().map(|i| *i as u32 + 1).collect();
                 let results =
                     <SignContext as ThresholdProtocolTest>::run(ctxs, indices, msg.to_vec());
 
-                let signature: Signature = serde_json::from_slice(&results[0]).unwrap();
-
-                for result in results {
-                    assert_eq!(signature, serde_json::from_slice(&result).unwrap());
+                for result in &results {
+                    assert_eq!(&results[0], result);
                 }
 
-                assert!(pk.verify(msg, &signature).is_ok());
+                let result = SignatureResult::decode(&results[0][..]).unwrap();
+                assert_eq!(result.indices, signers);
+                let signature = result.signature;
+                assert!(verify(&group.public_key, msg, &signature).is_ok());
+                assert!(verify(&group.public_key, b"other", &signature).is_err());
             }
         }
     }
+
+    #[test]
+    fn single_party() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let msg = b"hello";
+
+        let result = <SignContext as ThresholdProtocolTest>::run_single(ctx, msg.to_vec());
+        let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+
+        assert!(verify(&group.public_key, msg, &signature).is_ok());
+        assert_eq!(group.commitments, vec![group.public_key]);
+
+        let mut ctx = KeygenContext::new();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 1,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        };
+        assert!(ctx.advance(&init.encode_to_vec()).is_err());
+        assert!(ctx.init_single(1u16.try_into().unwrap(), 2).is_err());
+    }
+
+    #[test]
+    fn group_types() {
+        let (_, ctxs) = run_keygen::<KeygenContext>(ProtocolType::Roast, 3, 2, 3);
+        let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.protocol_type, ProtocolType::Roast as i32);
+        assert!(verify_shares(&ctxs[0]).is_ok());
+
+        // the groups of the other ciphersuite are not created
+        let mut ctx = KeygenContext::new();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Ecdh as i32,
+            index: 1,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        };
+        assert!(ctx.advance(&init.encode_to_vec()).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(feature = "deterministic")]
+    fn generate_fixture() {
+        crate::protocol::tests::store_fixture::<KeygenContext>("frost");
+    }
+
+    #[test]
+    fn fixture() {
+        let groups = load_fixture("frost");
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let msg = b"hello";
+        let results = <SignContext as ThresholdProtocolTest>::run(groups, vec![0, 2], msg.to_vec());
+        let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+        assert!(verify(&pk, msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn sign_p256() {
+        let (_, ctxs) = <FrostKeygenContext<P256Sha256> as KeygenProtocolTest>::run(2, 3);
+        let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.protocol_type, ProtocolType::FrostP256 as i32);
+        let msg = b"hello";
+
+        let results = <FrostSignContext<P256Sha256> as ThresholdProtocolTest>::run(
+            ctxs,
+            vec![0, 2],
+            msg.to_vec(),
+        );
+        let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+        assert!(verify_p256(&group.public_key, msg, &signature).is_ok());
+        assert!(verify_p256(&group.public_key, b"other", &signature).is_err());
+        assert!(verify(&group.public_key, msg, &signature).is_err());
+    }
+
+    #[test]
+    fn interoperability() {
+        // party 1 runs this crate, party 2 the reference implementation
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let (key, pubkey): (
+            KeyPackage<Secp256K1Sha256>,
+            PublicKeyPackage<Secp256K1Sha256>,
+        ) = parse_group_context(&ctxs[1]).unwrap();
+        let ids = [Identifier::try_from(1).unwrap(), *key.identifier()];
+        let msg = b"hello";
+
+        let mut ctx = SignContext::new(&ctxs[0]).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Frost as i32,
+            indices: vec![1, 2],
+            index: 1,
+            data: msg.to_vec(),
+            ..Default::default()
+        };
+        let out = advance_unpacked(&mut ctx, &init.encode_to_vec());
+        let commitments: SigningCommitments<Secp256K1Sha256> = deserialize_msg(&out[0]).unwrap();
+
+        let (nonces, reference_commitments) =
+            frost_core::round1::commit(key.secret_share(), &mut OsRng);
+        let input = pack(
+            serialize_bcast(&reference_commitments, 1).unwrap(),
+            ProtocolType::Frost,
+        );
+        let out = advance_unpacked(&mut ctx, &input);
+        let share: SignatureShare<Secp256K1Sha256> = deserialize_msg(&out[0]).unwrap();
+
+        let package = SigningPackage::new(
+            BTreeMap::from([(ids[0], commitments), (ids[1], reference_commitments)]),
+            msg,
+        );
+        let reference_share = frost_core::round2::sign(&package, &nonces, &key).unwrap();
+        let shares = HashMap::from([(ids[0], share), (ids[1], reference_share)]);
+        let signature = frost_core::aggregate(&package, &shares, &pubkey).unwrap();
+
+        let input = pack(
+            serialize_bcast(&reference_share, 1).unwrap(),
+            ProtocolType::Frost,
+        );
+        let out = advance_unpacked(&mut ctx, &input);
+        let aggregated: Signature<Secp256K1Sha256> = deserialize_msg(&out[0]).unwrap();
+        assert_eq!(aggregated, signature);
+        assert!(pubkey.group_public().verify(msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn coordinator() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(3, 4);
+        let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        let msg = b"hello";
+
+        let indices = [1, 2, 4];
+        let mut ctxs: Vec<SignContext> = indices
+            .iter()
+            .map(|i| SignContext::new(&ctxs[*i as usize - 1]).unwrap())
+            .collect();
+        let inits = indices
+            .iter()
+            .map(|index| {
+                (ProtocolInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    indices: indices.to_vec(),
+                    index: *index,
+                    data: msg.to_vec(),
+                    coordinator: 2,
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let commitments = advance_all(&mut ctxs, inits);
+
+        // only the coordinator receives the signature shares
+        let shares = run_rounds(&mut ctxs, commitments, 1);
+        assert!(!shares[0][0].is_empty() && shares[0][1].is_empty());
+        assert!(shares[1].iter().all(Vec::is_empty));
+        assert!(shares[2][0].is_empty() && !shares[2][1].is_empty());
+
+        run_rounds(&mut ctxs, shares, 2);
+        let results: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| Box::new(ctx).finish().unwrap())
+            .collect();
+        for result in &results {
+            assert_eq!(&results[0], result);
+        }
+        let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+        assert!(verify(&pk, msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn hedged_nonces() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        let msg = b"hello";
+
+        let options = ContextOptions {
+            hedged: true,
+            ..Default::default()
+        };
+        let mut ctxs: Vec<SignContext> = ctxs
+            .iter()
+            .map(|ctx| {
+                let mut ctx = SignContext::new(ctx).unwrap();
+                ctx.configure(&options);
+                ctx
+            })
+            .collect();
+        let inits = (1..=2)
+            .map(|index| {
+                (ProtocolInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    indices: vec![1, 2],
+                    index,
+                    data: msg.to_vec(),
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let commitments = advance_all(&mut ctxs, inits);
+        run_rounds(&mut ctxs, commitments, 2);
+
+        let result = Box::new(ctxs.remove(0)).finish().unwrap();
+        let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+        assert!(verify(&pk, msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn retried_share() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        let mut ctxs: Vec<SignContext> = ctxs
+            .iter()
+            .map(|ctx| SignContext::new(ctx).unwrap())
+            .collect();
+        let inits = (1..=2)
+            .map(|index| {
+                (ProtocolInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    indices: vec![1, 2],
+                    index,
+                    data: b"hello".to_vec(),
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let commitments = advance_all(&mut ctxs, inits);
+
+        // the states persisted before the shares were lost in transport
+        let mut persisted: Vec<SignContext> = ctxs
+            .iter()
+            .map(|ctx| serde_json::from_slice(&serde_json::to_vec(ctx).unwrap()).unwrap())
+            .collect();
+        let shares = run_rounds(&mut ctxs, commitments.clone(), 1);
+        assert_eq!(run_rounds(&mut persisted, commitments, 1), shares);
+    }
+
+    #[test]
+    fn invalid_share() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let indices = [1, 3];
+        let mut ctxs: Vec<SignContext> = indices
+            .iter()
+            .map(|i| SignContext::new(&ctxs[*i as usize - 1]).unwrap())
+            .collect();
+        let inits = indices
+            .iter()
+            .map(|index| {
+                (ProtocolInit {
+                    protocol_type: ProtocolType::Frost as i32,
+                    indices: indices.to_vec(),
+                    index: *index,
+                    data: b"hello".to_vec(),
+                    ..Default::default()
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let commitments = advance_all(&mut ctxs, inits);
+        let shares = run_rounds(&mut ctxs, commitments, 1);
+
+        // party 3 sends the share of party 1 instead of its own
+        let input = pack(vec![Bytes::from(shares[0][0].clone())], ProtocolType::Frost);
+        let error = ctxs[0].advance(&input).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature share from party 3");
+
+        let input = pack(vec![Bytes::from(vec![0xff; 3])], ProtocolType::Frost);
+        let error = ctxs[0].advance(&input).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature share from party 3");
+    }
+
+    #[test]
+    fn nonce_reuse() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        let init = |index| {
+            (ProtocolInit {
+                protocol_type: ProtocolType::Frost as i32,
+                indices: vec![1, 2],
+                index,
+                data: b"hello".to_vec(),
+                ..Default::default()
+            })
+            .encode_to_vec()
+        };
+        let mut ctx = SignContext::new(&ctxs[0]).unwrap();
+        ctx.advance(&init(1)).unwrap();
+        assert!(ctx.init(&init(1)).is_err());
+
+        // a copy of the context must not sign with the same nonces again
+        let mut copy: SignContext =
+            serde_json::from_slice(&serde_json::to_vec(&ctx).unwrap()).unwrap();
+        let mut results = Vec::new();
+        for ctx in [&mut ctx, &mut copy] {
+            let mut peer = SignContext::new(&ctxs[1]).unwrap();
+            let commitments = advance_unpacked(&mut peer, &init(2));
+            let input = pack(
+                commitments.into_iter().map(Bytes::from).collect(),
+                ProtocolType::Frost,
+            );
+            results.push(ctx.advance(&input).is_ok());
+        }
+        assert_eq!(results, vec![true, false]);
+    }
 }
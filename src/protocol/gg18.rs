@@ -1,6 +1,7 @@
-use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType, SignatureEncoding};
 use crate::protocol::*;
 use mpecdsa::{gg18_key_gen::*, gg18_sign::*};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
 use prost::Message;
 // TODO: use bincode instead?
 use serde::{Deserialize, Serialize};
@@ -8,6 +9,10 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub(crate) struct KeygenContext {
     round: KeygenRound,
+    /// Number of the other parties, unknown for contexts created
+    /// by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peers: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -25,6 +30,9 @@ impl KeygenContext {
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let msg = ProtocolGroupInit::decode(data)?;
 
+        ProtocolType::Gg18.check(msg.protocol_type)?;
+        check_group_params(&msg, 0)?;
+
         let (parties, threshold, index) =
             (msg.parties as u16, msg.threshold as u16, msg.index as u16);
 
@@ -32,6 +40,11 @@ impl KeygenContext {
         let ser = serialize_bcast(&out, msg.parties as usize - 1)?;
 
         self.round = KeygenRound::R1(c1);
+        self.peers = Some(parties as usize - 1);
+
+        if parties == 1 {
+            return run_locally(5, ProtocolType::Gg18, |data| self.update(data));
+        }
         Ok(pack(ser, ProtocolType::Gg18))
     }
 
@@ -66,7 +79,7 @@ impl KeygenContext {
                 let ser = inflate(c.pk.to_bytes(false).to_vec(), n);
                 (KeygenRound::Done(c), ser)
             }
-            KeygenRound::Done(_) => todo!(),
+            KeygenRound::Done(_) => return Err("protocol already finished".into()),
         };
         self.round = c;
         Ok(pack(ser, ProtocolType::Gg18))
@@ -85,16 +98,28 @@ impl Protocol for KeygenContext {
 
     fn finish(self: Box<Self>) -> Result<Vec<u8>> {
         match self.round {
-            KeygenRound::Done(ctx) => Ok(serde_json::to_vec(&ctx).unwrap()),
+            KeygenRound::Done(ctx) => Ok(pack_group(
+                ProtocolType::Gg18,
+                ctx.pk.to_bytes(false).to_vec(),
+                serde_json::to_vec(&ctx)?,
+            )),
             _ => Err("protocol not finished".into()),
         }
     }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match self.round {
+            KeygenRound::R0 | KeygenRound::Done(_) => None,
+            _ => self.peers,
+        }
+    }
 }
 
 impl KeygenProtocol for KeygenContext {
     fn new() -> Self {
         Self {
             round: KeygenRound::R0,
+            peers: None,
         }
     }
 }
@@ -102,6 +127,33 @@ impl KeygenProtocol for KeygenContext {
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SignContext {
     round: SignRound,
+    /// Indices of the participating parties, unknown for contexts
+    /// created by older versions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    indices: Vec<u32>,
+    /// Form of the signature in the result, if other than raw
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<SignatureOutput>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignatureOutput {
+    low_s: bool,
+    encoding: i32,
+}
+
+impl SignatureOutput {
+    fn encode(&self, signature: &[u8]) -> Result<Vec<u8>> {
+        let mut signature = Signature::from_slice(signature).map_err(|_| "malformed signature")?;
+        if self.low_s {
+            signature = signature.normalize_s().unwrap_or(signature);
+        }
+        match SignatureEncoding::from_i32(self.encoding) {
+            Some(SignatureEncoding::Raw) => Ok(signature.to_vec()),
+            Some(SignatureEncoding::Der) => Ok(signature.to_der().as_bytes().to_vec()),
+            None => Err("unknown signature encoding".into()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -123,9 +175,24 @@ impl SignContext {
     fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         let msg = ProtocolInit::decode(data)?;
 
+        ProtocolType::Gg18.check(msg.protocol_type)?;
+        policy::check(ProtocolType::Gg18, &msg)?;
+        if SignatureEncoding::from_i32(msg.encoding).is_none() {
+            return Err("unknown signature encoding".into());
+        }
+        if msg.low_s || msg.encoding != SignatureEncoding::Raw as i32 {
+            self.output = Some(SignatureOutput {
+                low_s: msg.low_s,
+                encoding: msg.encoding,
+            });
+        }
+
         let indices: Vec<u16> = msg.indices.clone().into_iter().map(|i| i as u16).collect();
         let parties = indices.len();
-        let local_index = indices.iter().position(|&i| i == msg.index as u16).unwrap();
+        let local_index = indices
+            .iter()
+            .position(|&i| i == msg.index as u16)
+            .ok_or("participant index not included")?;
 
         let c0 = match &self.round {
             SignRound::R0(c0) => c0.clone(),
@@ -135,6 +202,11 @@ impl SignContext {
         let (out, c1) = gg18_sign1(c0, indices, local_index, msg.data)?;
         let ser = serialize_bcast(&out, parties - 1)?;
         self.round = SignRound::R1(c1);
+        self.indices = msg.indices;
+
+        if parties == 1 {
+            return run_locally(9, ProtocolType::Gg18, |data| self.update(data));
+        }
         Ok(pack(ser, ProtocolType::Gg18))
     }
 
@@ -189,7 +261,7 @@ impl SignContext {
                 let ser = inflate(sig.clone(), n);
                 (SignRound::Done(sig), ser)
             }
-            SignRound::Done(_) => todo!(),
+            SignRound::Done(_) => return Err("protocol already finished".into()),
         };
 
         self.round = c;
@@ -209,28 +281,70 @@ impl Protocol for SignContext {
 
     fn finish(self: Box<Self>) -> Result<Vec<u8>> {
         match self.round {
-            SignRound::Done(sig) => Ok(sig),
+            SignRound::Done(sig) => {
+                let sig = match &self.output {
+                    Some(output) => output.encode(&sig)?,
+                    None => sig,
+                };
+                Ok(pack_signature(ProtocolType::Gg18, sig, self.indices))
+            }
             _ => Err("protocol not finished".into()),
         }
     }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match self.round {
+            SignRound::R0(_) | SignRound::Done(_) => None,
+            _ if self.indices.is_empty() => None,
+            _ => Some(self.indices.len() - 1),
+        }
+    }
 }
 
 impl ThresholdProtocol for SignContext {
-    fn new(group: &[u8]) -> Self {
-        Self {
-            round: SignRound::R0(serde_json::from_slice(group).unwrap()),
-        }
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            round: SignRound::R0(parse_group_context(group)?),
+            indices: Vec::new(),
+            output: None,
+        })
     }
 }
 
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Gg18,
+    name: "gg18",
+    curve: "secp256r1",
+    scheme: "ecdsa",
+    index_base: 0,
+    keygen_rounds: 6,
+    threshold_rounds: Some(10),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: true,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<KeygenContext>,
+    threshold: registry::threshold::<SignContext>,
+}];
+
+/// Verify a signature produced by the signing protocol, which signs
+/// the SHA-256 digest of `msg`
+pub fn verify(pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    let pk = VerifyingKey::from_sec1_bytes(pk).map_err(|_| "invalid public key")?;
+    let signature = Signature::from_slice(signature).map_err(|_| "malformed signature")?;
+    pk.verify(msg, &signature)
+        .map_err(|_| "invalid signature".into())
+}
+
 #[cfg(test)]
 mod tests {
-    use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
     use rand::{rngs::OsRng, seq::IteratorRandom};
     use sha2::Digest;
 
     use super::*;
-    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::tests::{run_wycheproof, KeygenProtocolTest, ThresholdProtocolTest};
 
     impl KeygenProtocolTest for KeygenContext {
         const PROTOCOL_TYPE: ProtocolType = ProtocolType::Gg18;
@@ -272,18 +386,82 @@ mod tests {
                 let results =
                     <SignContext as ThresholdProtocolTest>::run(ctxs, indices, dgst.to_vec());
 
-                let signature = results[0].clone();
-
-                for result in results {
-                    assert_eq!(&signature, &result);
+                for result in &results {
+                    assert_eq!(&results[0], result);
                 }
+                let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
 
                 let mut buffer = [0u8; 64];
                 buffer.copy_from_slice(&signature);
                 let signature = Signature::from_bytes(&buffer.into()).unwrap();
 
                 assert!(pk.verify(msg, &signature).is_ok());
+                assert!(verify(&pks[0], msg, &signature.to_bytes()).is_ok());
+                assert!(verify(&pks[0], b"other", &signature.to_bytes()).is_err());
             }
         }
     }
+
+    #[test]
+    fn single_party() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let pk = VerifyingKey::from_sec1_bytes(&group.public_key).unwrap();
+        let msg = b"hello";
+        let dgst = sha2::Sha256::digest(msg);
+
+        let result = <SignContext as ThresholdProtocolTest>::run_single(ctx, dgst.to_vec());
+        let result = SignatureResult::decode(&result[..]).unwrap().signature;
+        let mut buffer = [0u8; 64];
+        buffer.copy_from_slice(&result);
+        let signature = Signature::from_bytes(&buffer.into()).unwrap();
+
+        assert!(pk.verify(msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn der_low_s() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let group = GroupResult::decode(&ctx[..]).unwrap();
+        let pk = VerifyingKey::from_sec1_bytes(&group.public_key).unwrap();
+        let msg = b"hello";
+
+        let mut ctx = SignContext::new(&ctx).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Gg18 as i32,
+            indices: vec![0],
+            index: 0,
+            data: sha2::Sha256::digest(msg).to_vec(),
+            low_s: true,
+            encoding: SignatureEncoding::Der as i32,
+            ..Default::default()
+        };
+        ctx.advance(&init.encode_to_vec()).unwrap();
+        let result = Box::new(ctx).finish().unwrap();
+        let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+
+        let signature = Signature::from_der(&signature).unwrap();
+        assert!(signature.normalize_s().is_none());
+        assert!(pk.verify(msg, &signature).is_ok());
+    }
+
+    #[test]
+    fn wycheproof() {
+        run_wycheproof(
+            include_str!("../../vectors/ecdsa_secp256r1_sha256_p1363.json"),
+            verify,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the upstream vectors, see vectors/fetch-wycheproof.sh"]
+    fn wycheproof_upstream() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/vectors/wycheproof/ecdsa_secp256r1_sha256_p1363_test.json"
+        );
+        let vectors = std::fs::read_to_string(path)
+            .expect("upstream vectors missing, run vectors/fetch-wycheproof.sh");
+        run_wycheproof(&vectors, verify);
+    }
 }
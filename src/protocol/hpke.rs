@@ -0,0 +1,311 @@
+//! HPKE encryption to the key of a FROST P-256 group
+//!
+//! Messages are encrypted in the base mode of RFC 9180 with the
+//! `DHKEM(P-256, HKDF-SHA256)`, `HKDF-SHA256` and `AES-128-GCM` suite and
+//! an empty `info`, so any HPKE implementation can encrypt to the group.
+//! The ciphertext is the encapsulated key followed by the AEAD ciphertext.
+//!
+//! The group decrypts by computing the Diffie-Hellman secret of the
+//! decapsulation by threshold ECDH, see the `ecdh` module, without
+//! reconstructing its private key. Messages for a group are encrypted
+//! to its `encryption_key`, which is not its public key if the group
+//! separates the keys for signing and decryption.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+use crate::protocol::ecdh::{decryption_key, EcdhContext};
+use crate::protocol::frost::FrostKeygenContext;
+use crate::protocol::*;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes128Gcm, Key, Nonce,
+};
+use frost_p256::P256Sha256;
+use hmac::{Hmac, Mac};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use prost::Message;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Length of the encapsulated key, an uncompressed P-256 point
+const ENC_LEN: usize = 65;
+const KEM_SUITE: &[u8] = b"KEM\x00\x10";
+const HPKE_SUITE: &[u8] = b"HPKE\x00\x10\x00\x01\x00\x01";
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn labeled_extract(suite: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    hmac(salt, &[b"HPKE-v1", suite, label, ikm])
+}
+
+fn labeled_expand(suite: &[u8], prk: &[u8], label: &[u8], info: &[u8], len: u16) -> Vec<u8> {
+    let info = [&len.to_be_bytes()[..], b"HPKE-v1", suite, label, info].concat();
+    let mut okm = Vec::new();
+    let mut block = Vec::new();
+    for counter in 1..=(len as usize + 31) / 32 {
+        block = hmac(prk, &[&block, &info, &[counter as u8]]);
+        okm.extend(&block);
+    }
+    okm.truncate(len as usize);
+    okm
+}
+
+/// Derive the shared secret of the KEM from the Diffie-Hellman secret
+fn shared_secret(dh: &[u8], enc: &[u8], pk: &[u8]) -> Vec<u8> {
+    let prk = labeled_extract(KEM_SUITE, b"", b"eae_prk", dh);
+    let context = [enc, pk].concat();
+    labeled_expand(KEM_SUITE, &prk, b"shared_secret", &context, 32)
+}
+
+/// Derive the AEAD key and nonce of the base mode with an empty `info`
+fn key_schedule(shared_secret: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let context = [
+        &[0u8][..],
+        &labeled_extract(HPKE_SUITE, b"", b"psk_id_hash", b""),
+        &labeled_extract(HPKE_SUITE, b"", b"info_hash", b""),
+    ]
+    .concat();
+    let secret = labeled_extract(HPKE_SUITE, shared_secret, b"secret", b"");
+    (
+        labeled_expand(HPKE_SUITE, &secret, b"key", &context, 16),
+        labeled_expand(HPKE_SUITE, &secret, b"base_nonce", &context, 12),
+    )
+}
+
+fn open(ciphertext: &[u8], dh: &[u8], pk: &[u8]) -> Result<Vec<u8>> {
+    let (enc, ct) = ciphertext.split_at(ENC_LEN);
+    let (key, nonce) = key_schedule(&shared_secret(dh, enc, pk));
+    Aes128Gcm::new(Key::<Aes128Gcm>::from_slice(&key))
+        .decrypt(Nonce::from_slice(&nonce), ct)
+        .map_err(|_| "decryption failed".into())
+}
+
+/// Public key to encrypt the messages for a group to, given its group
+/// result, in the uncompressed SEC1 encoding
+pub fn encryption_key(group: &[u8]) -> Result<Vec<u8>> {
+    decryption_key(group)
+}
+
+/// Encrypt a message to the SEC1-encoded group public key, see `encryption_key`
+pub fn encrypt(msg: &[u8], pk: &[u8]) -> Result<Vec<u8>> {
+    let pk = PublicKey::from_sec1_bytes(pk).map_err(|_| "invalid public key")?;
+    let ephemeral = SecretKey::random(&mut OsRng);
+    let enc = ephemeral.public_key().to_encoded_point(false);
+    let dh = (pk.to_projective() * *ephemeral.to_nonzero_scalar())
+        .to_affine()
+        .to_encoded_point(false);
+    let dh = dh.x().ok_or("invalid public key")?;
+    let pk = pk.to_encoded_point(false);
+
+    let (key, nonce) = key_schedule(&shared_secret(dh, enc.as_bytes(), pk.as_bytes()));
+    let ct = Aes128Gcm::new(Key::<Aes128Gcm>::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), msg)
+        .map_err(|_| "encryption failed")?;
+    Ok([enc.as_bytes(), &ct].concat())
+}
+
+/// Decrypt a message encrypted to the public key of a single party
+pub(crate) fn decrypt(ciphertext: &[u8], sk: &SecretKey) -> Result<Vec<u8>> {
+    if ciphertext.len() < ENC_LEN {
+        return Err("malformed ciphertext".into());
+    }
+    let enc =
+        PublicKey::from_sec1_bytes(&ciphertext[..ENC_LEN]).map_err(|_| "malformed ciphertext")?;
+    let dh = (enc.to_projective() * *sk.to_nonzero_scalar())
+        .to_affine()
+        .to_encoded_point(false);
+    let dh = dh.x().ok_or("malformed ciphertext")?;
+    open(
+        ciphertext,
+        dh,
+        sk.public_key().to_encoded_point(false).as_bytes(),
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DecryptContext {
+    ecdh: EcdhContext,
+    ciphertext: Option<Vec<u8>>,
+    result: Option<Vec<u8>>,
+}
+
+impl DecryptContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::Hpke.check(msg.protocol_type)?;
+        if msg.data.len() < ENC_LEN {
+            return Err("malformed ciphertext".into());
+        }
+
+        let indices: Vec<u16> = msg.indices.iter().map(|i| *i as u16).collect();
+        let parties = indices.len();
+        let ser = self.ecdh.start(indices, msg.data[..ENC_LEN].to_vec())?;
+        self.ciphertext = Some(msg.data);
+
+        if parties == 1 {
+            return run_locally(1, ProtocolType::Hpke, |data| self.update(data));
+        }
+        Ok(pack(ser, ProtocolType::Hpke))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = self.ciphertext.as_ref().ok_or("protocol not initialized")?;
+        let dh = self.ecdh.combine(&unpack(data)?)?;
+        let msg = open(ciphertext, &dh, &self.ecdh.public_key()?)?;
+
+        self.result = Some(pack_plaintexts(ProtocolType::Hpke, vec![msg]));
+        Ok(pack(Vec::new(), ProtocolType::Hpke))
+    }
+}
+
+#[typetag::serde(name = "hpke_decrypt")]
+impl Protocol for DecryptContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.ciphertext.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.result.ok_or_else(|| "protocol not finished".into())
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.ciphertext.is_none() {
+            return None;
+        }
+        self.ecdh.expected_messages()
+    }
+}
+
+impl ThresholdProtocol for DecryptContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            ecdh: EcdhContext::new(group)?,
+            ciphertext: None,
+            result: None,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Hpke,
+    name: "hpke",
+    curve: "secp256r1",
+    scheme: "hpke",
+    index_base: 1,
+    keygen_rounds: 3,
+    threshold_rounds: Some(2),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<FrostKeygenContext<P256Sha256>>,
+    threshold: registry::threshold::<DecryptContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{DecryptResult, GroupResult};
+    use crate::protocol::tests::{run_keygen, KeygenProtocolTest, ThresholdProtocolTest};
+
+    impl ThresholdProtocolTest for DecryptContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::Hpke;
+        const ROUNDS: usize = 2;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn decap(key: &SecretKey, ct: &[u8]) -> Result<Vec<u8>> {
+        let enc = PublicKey::from_sec1_bytes(&ct[..ENC_LEN]).unwrap();
+        let dh = (enc.to_projective() * *key.to_nonzero_scalar())
+            .to_affine()
+            .to_encoded_point(false);
+        let pk = key.public_key().to_encoded_point(false);
+        open(ct, dh.x().unwrap(), pk.as_bytes())
+    }
+
+    #[test]
+    fn single_key() {
+        let key = SecretKey::random(&mut OsRng);
+        let ct = encrypt(b"hello", &key.public_key().to_sec1_bytes()).unwrap();
+        assert_eq!(ct.len(), ENC_LEN + 5 + 16);
+        assert_eq!(decap(&key, &ct).unwrap(), b"hello");
+        assert!(decap(&key, &ct[..ct.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn interoperability() {
+        // produced by the HPKE implementation of pyca/cryptography
+        let key = SecretKey::from_slice(&unhex(
+            "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ))
+        .unwrap();
+        let ct = unhex(
+            "04da66d2ebe9b015a2aa313416030b44458fa2c060d6fc2f9bc9b0c78255f1db8f\
+             38ac6c1179154ee87e5b16211c1ac2657ce87bd526909de860caad011a732068d2\
+             57dd9f377e4ec69591c9edbe20a1b1c9506f20e2435d",
+        );
+        assert_eq!(decap(&key, &ct).unwrap(), b"meesign");
+    }
+
+    #[test]
+    fn decrypt() {
+        // the key generation is initialized for the HPKE protocol itself
+        let (_, ctxs) = run_keygen::<FrostKeygenContext<P256Sha256>>(ProtocolType::Hpke, 3, 2, 3);
+        let group = GroupResult::decode(&ctxs[0][..]).unwrap();
+        assert_eq!(group.protocol_type, ProtocolType::Hpke as i32);
+        let pk = group.public_key;
+        let ct = encrypt(b"hello", &pk).unwrap();
+
+        for indices in [vec![0, 2], vec![0, 1, 2]] {
+            let results =
+                <DecryptContext as ThresholdProtocolTest>::run(ctxs.clone(), indices, ct.clone());
+            for result in results {
+                let plaintexts = DecryptResult::decode(&result[..]).unwrap().plaintexts;
+                assert_eq!(plaintexts, vec![b"hello".to_vec()]);
+            }
+        }
+    }
+
+    #[test]
+    fn single_party() {
+        let ctx = <FrostKeygenContext<P256Sha256> as KeygenProtocolTest>::run_single();
+        let pk = GroupResult::decode(&ctx[..]).unwrap().public_key;
+        let ct = encrypt(b"hello", &pk).unwrap();
+
+        let result = <DecryptContext as ThresholdProtocolTest>::run_single(ctx.clone(), ct.clone());
+        let plaintexts = DecryptResult::decode(&result[..]).unwrap().plaintexts;
+        assert_eq!(plaintexts, vec![b"hello".to_vec()]);
+
+        let mut tampered = ct;
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut ctx = DecryptContext::new(&ctx).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Hpke as i32,
+            indices: vec![1],
+            index: 1,
+            data: tampered,
+            ..Default::default()
+        };
+        assert!(ctx.advance(&init.encode_to_vec()).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Hybrid signatures by a classical and a post-quantum key
+//!
+//! A group in the hybrid mode holds an ML-DSA-65 key pair next to its
+//! classical key. The pair is generated by `deal` and attached by `attach`
+//! to the group result of every member, as no threshold ML-DSA is available
+//! yet, so the post-quantum key is only as safe as the least safe member.
+//! A signing session initialized with the `hybrid` flag then also signs its
+//! data by the ML-DSA key when finished, and its result carries both
+//! signatures; a composite signature is valid only if both of them are.
+
+use crate::proto::{GroupResult, ProtocolInit, SignatureResult};
+use crate::protocol::{DebugState, Protocol, Result};
+use fips204::ml_dsa_65::{self, PrivateKey, PublicKey, PK_LEN, SIG_LEN, SK_LEN};
+use fips204::traits::{SerDes, Signer, Verifier};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// Context string of the ML-DSA signatures of the library
+const CONTEXT: &[u8] = b"meesign-hybrid";
+
+fn private_key(bytes: &[u8]) -> Result<PrivateKey> {
+    let bytes: [u8; SK_LEN] = bytes.try_into().map_err(|_| "invalid ML-DSA key")?;
+    Ok(PrivateKey::try_from_bytes(bytes)?)
+}
+
+fn public_key(bytes: &[u8]) -> Result<PublicKey> {
+    let bytes: [u8; PK_LEN] = bytes.try_into().map_err(|_| "invalid ML-DSA key")?;
+    Ok(PublicKey::try_from_bytes(bytes)?)
+}
+
+/// Generate the ML-DSA key pair of a group, returning the public key
+/// and the secret key to be passed to `attach` by every member
+pub fn deal() -> Result<(Vec<u8>, Vec<u8>)> {
+    let (pk, sk) = ml_dsa_65::try_keygen()?;
+    Ok((pk.into_bytes().to_vec(), sk.into_bytes().to_vec()))
+}
+
+/// Attach the ML-DSA key pair from `deal` to the group result of a member,
+/// after checking that the keys belong together
+pub fn attach(group: &[u8], public_key: &[u8], secret_key: &[u8]) -> Result<Vec<u8>> {
+    let mut result = GroupResult::decode(group).map_err(|_| "not a group result")?;
+    let signature = private_key(secret_key)?.try_sign(public_key, CONTEXT)?;
+    verify(public_key, public_key, &signature)?;
+    result.pq_key = secret_key.to_vec();
+    result.pq_public_key = public_key.to_vec();
+    Ok(result.encode_to_vec())
+}
+
+/// Verify the ML-DSA signature of the data of a hybrid session
+pub fn verify(public_key: &[u8], data: &[u8], signature: &[u8]) -> Result<()> {
+    let signature: [u8; SIG_LEN] = signature
+        .try_into()
+        .map_err(|_| "malformed ML-DSA signature")?;
+    if !self::public_key(public_key)?.verify(data, &signature, CONTEXT) {
+        return Err("invalid ML-DSA signature".into());
+    }
+    Ok(())
+}
+
+/// Wrap the threshold context of a group which holds an ML-DSA key,
+/// leaving the contexts of the other groups as they are
+pub(crate) fn wrap(ctx: Box<dyn Protocol>, group: &[u8]) -> Box<dyn Protocol> {
+    match GroupResult::decode(group) {
+        Ok(result) if !result.pq_key.is_empty() => Box::new(HybridContext {
+            inner: ctx,
+            key: result.pq_key,
+            data: None,
+            initialized: false,
+        }),
+        _ => ctx,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HybridContext {
+    inner: Box<dyn Protocol>,
+    key: Vec<u8>,
+    /// Data to be signed by the ML-DSA key, if the session is hybrid
+    data: Option<Vec<u8>>,
+    initialized: bool,
+}
+
+#[typetag::serde(name = "hybrid")]
+impl Protocol for HybridContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if !self.initialized {
+            // a malformed message is left for the wrapped context to reject
+            match ProtocolInit::decode(data) {
+                Ok(msg) if msg.hybrid => self.data = Some(msg.data),
+                _ => {}
+            }
+        }
+        let out = self.inner.advance(data)?;
+        self.initialized = true;
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let result = self.inner.finish()?;
+        match self.data {
+            Some(data) => {
+                let mut result = SignatureResult::decode(&result[..])?;
+                let signature = private_key(&self.key)?.try_sign(&data, CONTEXT)?;
+                result.pq_signature = signature.to_vec();
+                Ok(result.encode_to_vec())
+            }
+            None => Ok(result),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("hybrid")
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{ProtocolGroupInit, ProtocolType};
+    use crate::protocol::{coordinator, keygen_context, threshold_context};
+
+    #[test]
+    fn composite_signature() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 1,
+            threshold: 1,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        };
+        ctx.advance(&init.encode_to_vec()).unwrap();
+        let group = ctx.finish().unwrap();
+
+        let (pk, sk) = deal().unwrap();
+        let (other_pk, _) = deal().unwrap();
+        assert!(attach(&group, &other_pk, &sk).is_err());
+        let group = attach(&group, &pk, &sk).unwrap();
+
+        let mut ctx = threshold_context(ProtocolType::Frost, &group).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Frost as i32,
+            indices: vec![1],
+            index: 1,
+            data: b"hello".to_vec(),
+            hybrid: true,
+            ..Default::default()
+        };
+        ctx.advance(&init.encode_to_vec()).unwrap();
+        let result = SignatureResult::decode(&ctx.finish().unwrap()[..]).unwrap();
+
+        let classical = GroupResult::decode(&group[..]).unwrap().public_key;
+        coordinator::verify(ProtocolType::Frost, &classical, b"hello", &result.signature).unwrap();
+        verify(&pk, b"hello", &result.pq_signature).unwrap();
+        assert!(verify(&pk, b"other", &result.pq_signature).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Delivery of the messages of a round one peer at a time
+//!
+//! A relay client may pass each message on as it arrives instead of
+//! waiting for the whole round. `feed` buffers the envelope holding the
+//! message of a peer as the server relayed it, rejecting malformed
+//! envelopes, unexpected senders and conflicting duplicates right away,
+//! and once `ready` reports that all the peers were heard from, `advance`
+//! with an empty input delivers the buffered messages in one envelope,
+//! which keeps the compression and the named sender of each of them.
+//! A complete packed input may still be delivered at once, which discards
+//! the buffer.
+//!
+//! The wrapper must be applied last, so that the wrapped contexts, such as
+//! the transcript, see the packed input rather than the empty one.
+
+use crate::proto::ProtocolMessage;
+use crate::protocol::{validate_envelope, DebugState, Protocol, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct InboxContext {
+    inner: Box<dyn Protocol>,
+    /// Envelopes of the messages of the other parties, one message each,
+    /// in the order of the packed input
+    #[serde(default)]
+    envelopes: Vec<Option<Vec<u8>>>,
+    /// Protocol type of the last output, under which the buffered
+    /// messages are packed
+    protocol_type: i32,
+}
+
+impl InboxContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>) -> Self {
+        Self {
+            inner,
+            envelopes: Vec::new(),
+            protocol_type: 0,
+        }
+    }
+
+    /// Pack the buffered messages into a single envelope, keeping the
+    /// compression and the sender of each of them
+    fn merge(&self) -> Result<Vec<u8>> {
+        let envelopes = self
+            .envelopes
+            .iter()
+            .flatten()
+            .map(|envelope| ProtocolMessage::decode(&envelope[..]))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let named = envelopes.iter().filter(|e| !e.senders.is_empty()).count();
+        if named != 0 && named != envelopes.len() {
+            return Err("senders named inconsistently".into());
+        }
+
+        let mut merged = ProtocolMessage {
+            protocol_type: self.protocol_type,
+            version: envelopes.first().map_or(0, |envelope| envelope.version),
+            ..Default::default()
+        };
+        for envelope in envelopes {
+            let compression = match envelope.compressions.first() {
+                Some(compression) => *compression,
+                None => envelope.compression,
+            };
+            merged.compressions.push(compression);
+            merged.message.extend(envelope.message);
+            merged.senders.extend(envelope.senders);
+        }
+        Ok(merged.encode_to_vec())
+    }
+}
+
+#[typetag::serde(name = "inbox")]
+impl Protocol for InboxContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let out = if data.is_empty() {
+            if !self.ready() {
+                return Err("messages missing".into());
+            }
+            self.inner.advance(&self.merge()?)?
+        } else {
+            self.inner.advance(data)?
+        };
+
+        self.envelopes.clear();
+        if let Ok(msg) = ProtocolMessage::decode(&out[..]) {
+            self.protocol_type = msg.protocol_type;
+        }
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("inbox")
+    }
+
+    fn feed(&mut self, sender: usize, msg: &[u8]) -> Result<()> {
+        let peers = self
+            .inner
+            .expected_messages()
+            .ok_or("no messages expected")?;
+        if sender >= peers {
+            return Err("unknown sender".into());
+        }
+        validate_envelope(msg)?;
+        let envelope = ProtocolMessage::decode(msg)?;
+        if envelope.message.len() != 1 {
+            return Err("expected a single message".into());
+        }
+        if envelope.protocol_type != self.protocol_type {
+            return Err("unexpected protocol type".into());
+        }
+
+        self.envelopes.resize(peers, None);
+        if let Some(previous) = &self.envelopes[sender] {
+            if previous != msg {
+                return Err("conflicting messages from the same sender".into());
+            }
+        }
+        if let Some(named) = envelope.senders.first() {
+            let duplicate = self
+                .envelopes
+                .iter()
+                .enumerate()
+                .filter(|(position, _)| *position != sender)
+                .flat_map(|(_, other)| other)
+                .filter_map(|other| ProtocolMessage::decode(&other[..]).ok())
+                .any(|other| other.senders.first() == Some(named));
+            if duplicate {
+                return Err("duplicate sender".into());
+            }
+        }
+        self.envelopes[sender] = Some(msg.to_vec());
+        Ok(())
+    }
+
+    fn ready(&self) -> bool {
+        self.inner.expected_messages().map_or(false, |peers| {
+            self.envelopes.len() == peers && self.envelopes.iter().all(Option::is_some)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{Compression, GroupResult, ProtocolGroupInit, ProtocolType};
+    use crate::protocol::{keygen_context, progressive};
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    fn init(index: u32) -> Vec<u8> {
+        (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        })
+        .encode_to_vec()
+    }
+
+    /// Envelope of the message at `position` in the output of the party
+    /// `sender`, as the server relays it on its own, compressed if `deflate`
+    fn envelope(out: &[u8], position: usize, sender: u32, deflate: bool) -> Vec<u8> {
+        let mut msg = ProtocolMessage::decode(out).unwrap();
+        msg.message = vec![msg.message[position].clone()];
+        msg.senders = vec![sender];
+        if deflate {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&msg.message[0]).unwrap();
+            msg.message = vec![encoder.finish().unwrap().into()];
+            msg.compression = Compression::Deflate.into();
+        }
+        msg.encode_to_vec()
+    }
+
+    #[test]
+    fn keygen() {
+        let mut ctxs: Vec<Box<dyn Protocol>> = (0..3)
+            .map(|_| progressive(keygen_context(ProtocolType::Frost).unwrap()))
+            .collect();
+        let mut outputs: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| ctx.advance(&init(i as u32 + 1)).unwrap())
+            .collect();
+
+        for _ in 0..2 {
+            for (idx, ctx) in ctxs.iter_mut().enumerate() {
+                // the messages arrive in the reverse order of the senders,
+                // and only the first party compresses
+                for sender in (0..3).rev().filter(|sender| *sender != idx) {
+                    assert!(!ctx.ready());
+                    let position = if idx < sender { idx } else { idx - 1 };
+                    let msg = envelope(&outputs[sender], position, sender as u32 + 1, sender == 0);
+                    let position = if sender < idx { sender } else { sender - 1 };
+                    ctx.feed(position, &msg).unwrap();
+                }
+                assert!(ctx.ready());
+            }
+            outputs = ctxs
+                .iter_mut()
+                .map(|ctx| ctx.advance(&[]).unwrap())
+                .collect();
+        }
+
+        let pks: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| {
+                GroupResult::decode(&ctx.finish().unwrap()[..])
+                    .unwrap()
+                    .public_key
+            })
+            .collect();
+        assert!(pks.iter().all(|pk| *pk == pks[0]));
+    }
+
+    #[test]
+    fn invalid_feed() {
+        let single = |protocol_type: ProtocolType, msgs: &[&[u8]], senders: Vec<u32>| {
+            ProtocolMessage {
+                protocol_type: protocol_type as i32,
+                message: msgs.iter().map(|msg| msg.to_vec().into()).collect(),
+                senders,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        let frost = |msg: &[u8], sender: u32| single(ProtocolType::Frost, &[msg], vec![sender]);
+
+        let mut ctx = progressive(keygen_context(ProtocolType::Frost).unwrap());
+        assert!(ctx.feed(0, &frost(b"early", 2)).is_err());
+
+        ctx.advance(&init(1)).unwrap();
+        assert!(ctx.feed(2, &frost(b"unknown", 2)).is_err());
+        assert!(ctx.feed(0, b"\xff").is_err());
+        assert!(ctx
+            .feed(
+                0,
+                &single(ProtocolType::Frost, &[&b"one"[..], &b"two"[..]], vec![])
+            )
+            .is_err());
+        assert!(ctx
+            .feed(0, &single(ProtocolType::Gg18, &[&b"first"[..]], vec![2]))
+            .is_err());
+        let mut deflated = ProtocolMessage::decode(&frost(b"first", 2)[..]).unwrap();
+        deflated.compression = Compression::Deflate.into();
+        assert!(ctx.feed(0, &deflated.encode_to_vec()).is_err());
+
+        ctx.feed(0, &frost(b"first", 2)).unwrap();
+        ctx.feed(0, &frost(b"first", 2)).unwrap();
+        assert!(ctx.feed(0, &frost(b"second", 2)).is_err());
+        assert!(ctx.feed(1, &frost(b"third", 2)).is_err());
+        assert!(ctx.advance(&[]).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Metadata of groups for the users and the operations they allow
+//!
+//! The `GroupMetadata` of a `ProtocolGroupInit` is carried into the group
+//! result, with the threshold filled in, so that clients can show a group
+//! by its name and the labels of its members. `threshold_context` refuses
+//! the sessions of the operations the group does not allow, so that e.g.
+//! a group meant for decryption only never signs.
+//!
+//! Each threshold context of a group with metadata counts its session in
+//! the `GroupUsage` of the group result by `UsageContext`, and hands out
+//! the counted group by `Protocol::counted_group`, which the member stores
+//! in place of its group before the session starts, so that the sessions
+//! which fail count as well. `threshold_context` also refuses the sessions
+//! of the groups whose `max_uses` the count reached.
+
+use crate::proto::{
+    GroupMetadata, GroupOperation, GroupResult, GroupUsage, ProtocolGroupInit, ProtocolType,
+};
+use crate::protocol::{DebugState, Protocol, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MetadataContext {
+    inner: Box<dyn Protocol>,
+    /// Encoded metadata of the group, if the initialization message has it
+    metadata: Option<Vec<u8>>,
+    initialized: bool,
+}
+
+impl MetadataContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>) -> Self {
+        Self {
+            inner,
+            metadata: None,
+            initialized: false,
+        }
+    }
+}
+
+#[typetag::serde(name = "metadata")]
+impl Protocol for MetadataContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if !self.initialized {
+            // a malformed message is left for the wrapped context to reject
+            if let Ok(msg) = ProtocolGroupInit::decode(data) {
+                self.metadata = msg.metadata.map(|mut metadata| {
+                    metadata.threshold = msg.threshold;
+                    metadata.encode_to_vec()
+                });
+            }
+        }
+        let out = self.inner.advance(data)?;
+        self.initialized = true;
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let result = self.inner.finish()?;
+        match self.metadata {
+            Some(metadata) => {
+                let mut group = GroupResult::decode(&result[..])?;
+                group.metadata = Some(GroupMetadata::decode(&metadata[..])?);
+                Ok(group.encode_to_vec())
+            }
+            None => Ok(result),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("metadata")
+    }
+}
+
+fn operation(protocol_type: ProtocolType) -> GroupOperation {
+    match protocol_type {
+        ProtocolType::Elgamal
+        | ProtocolType::ElgamalReencrypt
+        | ProtocolType::Ppss
+        | ProtocolType::Ecdh
+        | ProtocolType::Hpke => GroupOperation::Decrypt,
+        ProtocolType::FrostEnroll | ProtocolType::FrostRemove => GroupOperation::Manage,
+        _ => GroupOperation::Sign,
+    }
+}
+
+/// Counter of the sessions of the operation, if they are counted
+fn counter(usage: &mut GroupUsage, operation: GroupOperation) -> Option<&mut u64> {
+    match operation {
+        GroupOperation::Sign => Some(&mut usage.signatures),
+        GroupOperation::Decrypt => Some(&mut usage.decryptions),
+        GroupOperation::Manage => None,
+    }
+}
+
+/// Fail if the metadata of the group does not allow the sessions
+/// of the given protocol, or no more of them
+fn check_operation(protocol_type: ProtocolType, group: &[u8]) -> Result<()> {
+    let (metadata, mut usage) = match GroupResult::decode(group) {
+        Ok(GroupResult {
+            metadata: Some(metadata),
+            usage,
+            ..
+        }) => (metadata, usage.unwrap_or_default()),
+        _ => return Ok(()),
+    };
+    let operation = operation(protocol_type);
+    if !metadata.operations.is_empty() && !metadata.operations.contains(&(operation as i32)) {
+        return Err("operation not allowed for the group".into());
+    }
+    let used = counter(&mut usage, operation).map_or(0, |count| *count);
+    if metadata.max_uses > 0 && used >= metadata.max_uses {
+        return Err("usage limit of the group reached".into());
+    }
+    Ok(())
+}
+
+/// Count a session of the given protocol in the usage of the group,
+/// failing if the group does not allow it, see `check_operation`;
+/// `None` if the group has no metadata to count the session in
+pub(crate) fn count_use(protocol_type: ProtocolType, group: &[u8]) -> Result<Option<Vec<u8>>> {
+    check_operation(protocol_type, group)?;
+    let mut result = match GroupResult::decode(group) {
+        Ok(result) if result.metadata.is_some() => result,
+        _ => return Ok(None),
+    };
+    let usage = result.usage.get_or_insert_with(GroupUsage::default);
+    if let Some(count) = counter(usage, operation(protocol_type)) {
+        *count = count.saturating_add(1);
+    }
+    Ok(Some(result.encode_to_vec()))
+}
+
+/// Threshold context which carries the group result with its session
+/// counted, see `count_use`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct UsageContext {
+    inner: Box<dyn Protocol>,
+    group: Vec<u8>,
+}
+
+impl UsageContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>, group: Vec<u8>) -> Self {
+        Self { inner, group }
+    }
+}
+
+#[typetag::serde(name = "usage")]
+impl Protocol for UsageContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.inner.advance(data)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        Some(self.group.clone())
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("usage")
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::protocol::{keygen_context, threshold_context};
+
+    #[test]
+    fn decrypt_only() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 1,
+            threshold: 1,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: Some(GroupMetadata {
+                name: "family".into(),
+                labels: vec!["phone".into()],
+                operations: vec![GroupOperation::Decrypt as i32],
+                ..Default::default()
+            }),
+            version: 0,
+        };
+        ctx.advance(&init.encode_to_vec()).unwrap();
+        let group = ctx.finish().unwrap();
+
+        let metadata = GroupResult::decode(&group[..]).unwrap().metadata.unwrap();
+        assert_eq!(metadata.name, "family");
+        assert_eq!(metadata.threshold, 1);
+
+        let error = threshold_context(ProtocolType::Frost, &group)
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "operation not allowed for the group");
+        assert!(check_operation(ProtocolType::Ecdh, &group).is_ok());
+    }
+
+    #[test]
+    fn usage_limit() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 1,
+            threshold: 1,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: Some(GroupMetadata {
+                max_uses: 2,
+                ..Default::default()
+            }),
+            version: 0,
+        };
+        ctx.advance(&init.encode_to_vec()).unwrap();
+        let mut group = ctx.finish().unwrap();
+
+        for _ in 0..2 {
+            let ctx = threshold_context(ProtocolType::Frost, &group).unwrap();
+            group = ctx.counted_group().unwrap();
+        }
+        let usage = GroupResult::decode(&group[..]).unwrap().usage.unwrap();
+        assert_eq!((usage.signatures, usage.decryptions), (2, 0));
+
+        let error = threshold_context(ProtocolType::Frost, &group)
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "usage limit of the group reached");
+        assert!(count_use(ProtocolType::Frost, &group).is_err());
+        // the limit applies to each operation on its own
+        assert!(count_use(ProtocolType::Ecdh, &group).unwrap().is_some());
+    }
+}
@@ -0,0 +1,87 @@
+//! Migration of GG18 groups to a newer protocol
+//!
+//! GG18 groups are Shamir-shared P-256 keys like those of FROST P-256, but
+//! mpecdsa keeps the shares in its own opaque format, which this library
+//! does not take apart, so the key is not carried over. A group migrates
+//! by a fresh key generation of the new protocol among the same members,
+//! e.g. of FROST P-256, after which the old group endorses the new one:
+//! it signs the digest
+//! returned by `endorsement_digest` in a regular GG18 session, with the
+//! raw signature encoding. The signature is the continuity metadata by
+//! which anyone trusting the old group key checks that the new group
+//! succeeds it, see `verify_endorsement`.
+
+use crate::proto::{GroupResult, ProtocolType};
+use crate::protocol::{fingerprint, gg18, Result};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+const DOMAIN: &[u8] = b"meesign-migration";
+
+/// Statement that the group `new` succeeds the GG18 group `old`,
+/// both given as group results
+fn statement(old: &[u8], new: &[u8]) -> Result<Vec<u8>> {
+    let (protocol, old_digest) = fingerprint::digest(old)?;
+    if protocol != ProtocolType::Gg18 {
+        return Err("not a GG18 group".into());
+    }
+    let (_, new_digest) = fingerprint::digest(new)?;
+    Ok([DOMAIN, &old_digest, &new_digest].concat())
+}
+
+/// Digest the old group signs to endorse the new one,
+/// to be passed as the data of the `ProtocolInit`
+pub fn endorsement_digest(old: &[u8], new: &[u8]) -> Result<Vec<u8>> {
+    Ok(Sha256::digest(statement(old, new)?).to_vec())
+}
+
+/// Check that the old group endorsed the new one by `signature`
+pub fn verify_endorsement(old: &[u8], new: &[u8], signature: &[u8]) -> Result<()> {
+    let statement = statement(old, new)?;
+    let pk = GroupResult::decode(old)?.public_key;
+    gg18::verify(&pk, &statement, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::SignatureResult;
+    use crate::protocol::gg18::{KeygenContext, SignContext};
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+
+    #[test]
+    fn endorsement() {
+        let old = <KeygenContext as KeygenProtocolTest>::run_single();
+        let new = <KeygenContext as KeygenProtocolTest>::run_single();
+
+        let digest = endorsement_digest(&old, &new).unwrap();
+        let result = <SignContext as ThresholdProtocolTest>::run_single(old.clone(), digest);
+        let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+
+        assert!(verify_endorsement(&old, &new, &signature).is_ok());
+        assert!(verify_endorsement(&new, &old, &signature).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "frost")]
+    fn frost_p256() {
+        use crate::protocol::frost::FrostKeygenContext;
+        use frost_p256::P256Sha256;
+
+        let (_, olds) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let (_, news) = <FrostKeygenContext<P256Sha256> as KeygenProtocolTest>::run(2, 3);
+        let (old, new) = (olds[0].clone(), news[0].clone());
+        let protocol_type = GroupResult::decode(&new[..]).unwrap().protocol_type;
+        assert_eq!(protocol_type, ProtocolType::FrostP256 as i32);
+
+        let digest = endorsement_digest(&old, &new).unwrap();
+        let results = <SignContext as ThresholdProtocolTest>::run(olds, vec![0, 2], digest);
+        let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+
+        assert!(verify_endorsement(&old, &new, &signature).is_ok());
+        assert!(verify_endorsement(&new, &old, &signature).is_err());
+        let mut other = GroupResult::decode(&new[..]).unwrap();
+        other.public_key[1] ^= 1;
+        assert!(verify_endorsement(&old, &other.encode_to_vec(), &signature).is_err());
+    }
+}
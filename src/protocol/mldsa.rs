@@ -0,0 +1,698 @@
+//! Experimental threshold ML-DSA-65 signatures
+//!
+//! No threshold scheme for ML-DSA is standardized, and the rejection
+//! sampling of its signing does not split among the parties as Schnorr
+//! signing does. The group therefore shares the seed its key pair is derived
+//! from rather than the key itself: every party contributes a random seed
+//! shared byte-wise by Shamir's secret sharing over GF(2^8), as in the
+//! `backup` module, and the seed of the group is the sum of the
+//! contributions, so that no party chooses it.
+//!
+//! The first party of a session collects the seed shares of the signers,
+//! derives the key pair and signs, while the other signers check the
+//! signature against the key of the group. The key generation derives the
+//! public key of the group the same way at its first party. The seed is
+//! thus reconstructed by a single party for the duration of a round, which
+//! has to be trusted to forget it, so the protocol is only meant for trials
+//! of post-quantum groups, of up to 255 parties.
+//!
+//! The shares never pass the server in plaintext: every party broadcasts
+//! a P-256 key in the first round of the key generation, to which the
+//! others encrypt the shares they send it, see `hpke::encrypt`. The keys
+//! are kept in the group, so the signers encrypt their seed shares to the
+//! key of the first signer. As the server could substitute its own keys,
+//! the context only takes part in a key generation of several parties if
+//! it is `authenticated` by the devices.
+//!
+//! The signatures are plain ML-DSA-65 signatures with an empty context.
+
+use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::protocol::backup::{inv, mul};
+use crate::protocol::*;
+use fips204::ml_dsa_65::{self, PublicKey, PK_LEN, SIG_LEN};
+use fips204::traits::{KeyGen, SerDes, Signer, Verifier};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use prost::{bytes::Bytes, Message};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use subtle::ConstantTimeEq;
+
+const SEED_LEN: usize = 32;
+
+/// Largest number of parties, as the parties are the points of GF(2^8)
+const MAX_GROUP_PARTIES: u32 = u8::MAX as u32;
+
+type Seed = [u8; SEED_LEN];
+
+/// Share `secret` among the parties `1..=parties`, any `threshold`
+/// of which recover it by `interpolate`
+fn split(secret: &Seed, threshold: u8, parties: u8) -> Vec<Seed> {
+    let degree = threshold as usize - 1;
+    let mut coefficients = vec![0u8; SEED_LEN * degree];
+    ProtocolRng.fill_bytes(&mut coefficients);
+
+    (1..=parties)
+        .map(|x| {
+            let mut share = [0; SEED_LEN];
+            for (i, byte) in secret.iter().enumerate() {
+                share[i] = coefficients[i * degree..(i + 1) * degree]
+                    .iter()
+                    .rev()
+                    .chain(std::iter::once(byte))
+                    .fold(0, |acc, c| mul(acc, x) ^ c);
+            }
+            share
+        })
+        .collect()
+}
+
+/// Evaluate the polynomial through the `shares` of distinct parties at `x`
+fn interpolate(shares: &[(u8, Seed)], x: u8) -> Seed {
+    let mut result = [0; SEED_LEN];
+    for (xi, share) in shares {
+        let (numerator, denominator) = shares
+            .iter()
+            .filter(|(xj, _)| xj != xi)
+            .fold((1, 1), |(num, den), (xj, _)| {
+                (mul(num, xj ^ x), mul(den, xj ^ xi))
+            });
+        let coefficient = mul(numerator, inv(denominator));
+        for (byte, share) in result.iter_mut().zip(share) {
+            *byte ^= mul(*share, coefficient);
+        }
+    }
+    result
+}
+
+fn to_seed(bytes: &[u8]) -> Result<Seed> {
+    bytes.try_into().map_err(|_| "invalid seed share".into())
+}
+
+/// Fresh key pair the shares for a party are encrypted to,
+/// the secret key and the compressed SEC1 public key
+fn encryption_key() -> (Vec<u8>, Vec<u8>) {
+    let key = p256::SecretKey::random(&mut ProtocolRng);
+    let public = key.public_key().to_encoded_point(true).as_bytes().to_vec();
+    (key.to_bytes().to_vec(), public)
+}
+
+/// Decrypt a seed share encrypted to the secret key `key`
+fn decrypt_seed(msg: &[u8], key: &[u8]) -> Result<Seed> {
+    let key = p256::SecretKey::from_slice(key).map_err(|_| "invalid decryption key")?;
+    to_seed(&hpke::decrypt(msg, &key)?)
+}
+
+fn public_key(bytes: &[u8]) -> Result<PublicKey> {
+    let bytes: [u8; PK_LEN] = bytes.try_into().map_err(|_| "invalid ML-DSA key")?;
+    Ok(PublicKey::try_from_bytes(bytes)?)
+}
+
+/// Verify an ML-DSA-65 signature by the key of a group
+pub fn verify(pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    let signature: [u8; SIG_LEN] = signature
+        .try_into()
+        .map_err(|_| "malformed ML-DSA signature")?;
+    if !public_key(pk)?.verify(msg, &signature, b"") {
+        return Err("invalid ML-DSA signature".into());
+    }
+    Ok(())
+}
+
+/// Group context of a party
+#[derive(Serialize, Deserialize)]
+struct Group {
+    index: u8,
+    threshold: u8,
+    /// Share of the seed of the key pair of the group
+    share: Seed,
+    public_key: Vec<u8>,
+    /// Secret key the seed shares for this party are encrypted to
+    #[serde(default)]
+    decryption_key: Vec<u8>,
+    /// Keys the seed shares for the parties `1..=n` are encrypted to,
+    /// empty for groups created by older versions
+    #[serde(default)]
+    encryption_keys: Vec<Vec<u8>>,
+}
+
+/// Parameters of the group being generated
+#[derive(Clone, Serialize, Deserialize)]
+struct Params {
+    index: u8,
+    threshold: u8,
+    parties: u8,
+}
+
+impl Params {
+    /// Indices of the other parties, in the order of their messages
+    fn others(&self) -> Vec<u32> {
+        (1..=self.parties as u32)
+            .filter(|i| *i != self.index as u32)
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum KeygenRound {
+    R0,
+    /// Waiting for the encryption keys of the others
+    R1(Params),
+    /// Waiting for the shares of their contributions
+    R2(Params, Seed),
+    /// Waiting for the seed shares, at the first party
+    R3(Params, Seed),
+    /// Waiting for the public key derived by the first party
+    R4(Params, Seed, Option<Vec<u8>>),
+    Done(Group),
+}
+
+impl KeygenRound {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::R0 => "R0",
+            Self::R1(..) => "R1",
+            Self::R2(..) => "R2",
+            Self::R3(..) => "R3",
+            Self::R4(..) => "R4",
+            Self::Done(..) => "Done",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MlDsaKeygenContext {
+    round: KeygenRound,
+    /// Secret key the shares for this party are encrypted to
+    #[serde(default)]
+    decryption_key: Vec<u8>,
+    /// Keys the shares for the parties `1..=n` are encrypted to
+    #[serde(default)]
+    encryption_keys: Vec<Vec<u8>>,
+    /// Whether the messages of the other parties are authenticated,
+    /// see `Protocol::set_authenticated`
+    #[serde(default)]
+    authenticated: bool,
+}
+
+impl MlDsaKeygenContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolGroupInit::decode(data)?;
+        ProtocolType::MlDsa.check(msg.protocol_type)?;
+        check_group_params(&msg, 1)?;
+        let params = Params {
+            index: msg.index as u8,
+            threshold: msg.threshold as u8,
+            parties: msg.parties as u8,
+        };
+
+        let (decryption_key, encryption_key) = encryption_key();
+        if params.parties == 1 {
+            let mut seed = [0; SEED_LEN];
+            ProtocolRng.fill_bytes(&mut seed);
+            let (pk, _) = ml_dsa_65::KG::keygen_from_seed(&seed);
+            self.round = KeygenRound::Done(Group {
+                index: params.index,
+                threshold: 1,
+                share: seed,
+                public_key: pk.into_bytes().to_vec(),
+                decryption_key,
+                encryption_keys: vec![encryption_key],
+            });
+            return Ok(pack(Vec::new(), ProtocolType::MlDsa));
+        }
+        if !self.authenticated {
+            return Err("key generation requires authenticated messages".into());
+        }
+
+        let msgs = inflate(encryption_key, params.parties as usize - 1);
+        self.decryption_key = decryption_key;
+        self.round = KeygenRound::R1(params);
+        Ok(pack(msgs, ProtocolType::MlDsa))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let (round, msgs) = match &self.round {
+            KeygenRound::R0 => return Err("protocol not initialized".into()),
+            KeygenRound::R1(params) => {
+                let own = p256::SecretKey::from_slice(&self.decryption_key)
+                    .map_err(|_| "invalid decryption key")?
+                    .public_key()
+                    .to_encoded_point(true)
+                    .as_bytes()
+                    .to_vec();
+                let mut keys = Vec::new();
+                for (sender, msg) in unpack_from(data, &params.others())? {
+                    p256::PublicKey::from_sec1_bytes(&msg)
+                        .map_err(|_| format!("invalid key from party {}", sender))?;
+                    keys.push(msg.to_vec());
+                }
+                keys.insert(params.index as usize - 1, own);
+
+                let mut contribution = [0; SEED_LEN];
+                ProtocolRng.fill_bytes(&mut contribution);
+                let shares = split(&contribution, params.threshold, params.parties);
+                let msgs: Vec<Bytes> = params
+                    .others()
+                    .iter()
+                    .map(|i| {
+                        let i = *i as usize - 1;
+                        Ok(hpke::encrypt(&shares[i], &keys[i])?.into())
+                    })
+                    .collect::<Result<_>>()?;
+                self.encryption_keys = keys;
+                let own = shares[params.index as usize - 1];
+                (KeygenRound::R2(params.clone(), own), msgs)
+            }
+            KeygenRound::R2(params, share) => {
+                let mut share = *share;
+                for (sender, msg) in unpack_from(data, &params.others())? {
+                    let received = decrypt_seed(&msg, &self.decryption_key)
+                        .map_err(|_| format!("invalid share from party {}", sender))?;
+                    for (byte, other) in share.iter_mut().zip(&received) {
+                        *byte ^= other;
+                    }
+                }
+                // only the first party learns the shares of the seed
+                let msgs: Vec<Bytes> = params
+                    .others()
+                    .iter()
+                    .map(|i| {
+                        if *i == 1 {
+                            Ok(hpke::encrypt(&share, &self.encryption_keys[0])?.into())
+                        } else {
+                            Ok(Bytes::new())
+                        }
+                    })
+                    .collect::<Result<_>>()?;
+                (KeygenRound::R3(params.clone(), share), msgs)
+            }
+            KeygenRound::R3(params, share) => {
+                let msgs = unpack_from(data, &params.others())?;
+                let peers = params.parties as usize - 1;
+                let (public_key, msgs) = if params.index == 1 {
+                    let mut shares = vec![(params.index, *share)];
+                    for (sender, msg) in msgs {
+                        let received = decrypt_seed(&msg, &self.decryption_key)
+                            .map_err(|_| format!("invalid seed share from party {}", sender))?;
+                        shares.push((sender as u8, received));
+                    }
+                    // the shares beyond the threshold have to agree with the others
+                    let (base, rest) = shares.split_at(params.threshold as usize);
+                    for (x, share) in rest {
+                        if !bool::from(interpolate(base, *x).ct_eq(share)) {
+                            return Err(format!("inconsistent seed share from party {}", x).into());
+                        }
+                    }
+                    let (pk, _) = ml_dsa_65::KG::keygen_from_seed(&interpolate(base, 0));
+                    let pk = pk.into_bytes().to_vec();
+                    (Some(pk.clone()), inflate(pk, peers))
+                } else {
+                    (None, inflate(Bytes::new(), peers))
+                };
+                (KeygenRound::R4(params.clone(), *share, public_key), msgs)
+            }
+            KeygenRound::R4(params, share, pk) => {
+                let public_key = match pk {
+                    Some(pk) => pk.clone(),
+                    None => {
+                        let (_, msg) = unpack_from(data, &params.others())?
+                            .into_iter()
+                            .find(|(sender, _)| *sender == 1)
+                            .ok_or("missing public key")?;
+                        public_key(&msg).map_err(|_| "invalid public key from party 1")?;
+                        msg.to_vec()
+                    }
+                };
+                let msgs = inflate(public_key.clone(), params.parties as usize - 1);
+                let group = Group {
+                    index: params.index,
+                    threshold: params.threshold,
+                    share: *share,
+                    public_key,
+                    decryption_key: std::mem::take(&mut self.decryption_key),
+                    encryption_keys: std::mem::take(&mut self.encryption_keys),
+                };
+                (KeygenRound::Done(group), msgs)
+            }
+            KeygenRound::Done(_) => return Err("protocol already finished".into()),
+        };
+        self.round = round;
+        Ok(pack(msgs, ProtocolType::MlDsa))
+    }
+
+    fn expected(&self) -> Option<usize> {
+        match &self.round {
+            KeygenRound::R0 | KeygenRound::Done(_) => None,
+            KeygenRound::R1(params)
+            | KeygenRound::R2(params, ..)
+            | KeygenRound::R3(params, ..)
+            | KeygenRound::R4(params, ..) => Some(params.parties as usize - 1),
+        }
+    }
+}
+
+#[typetag::serde(name = "mldsa_keygen")]
+impl Protocol for MlDsaKeygenContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            KeygenRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match self.round {
+            KeygenRound::Done(group) => Ok(pack_group(
+                ProtocolType::MlDsa,
+                group.public_key.clone(),
+                serde_json::to_vec(&group)?,
+            )),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.expected()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.authenticated = true;
+    }
+
+    fn debug_state(&self) -> DebugState {
+        DebugState::new("mldsa_keygen", self.round.name(), self.expected())
+    }
+}
+
+impl KeygenProtocol for MlDsaKeygenContext {
+    fn new() -> Self {
+        Self {
+            round: KeygenRound::R0,
+            decryption_key: Vec::new(),
+            encryption_keys: Vec::new(),
+            authenticated: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignRound {
+    R0,
+    R1,
+    /// Signature produced by the first party, if it is this one
+    R2(Option<Vec<u8>>),
+    Done(Vec<u8>),
+}
+
+impl SignRound {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::R0 => "R0",
+            Self::R1 => "R1",
+            Self::R2(..) => "R2",
+            Self::Done(..) => "Done",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MlDsaSignContext {
+    group: Group,
+    indices: Vec<u8>,
+    data: Vec<u8>,
+    round: SignRound,
+}
+
+impl MlDsaSignContext {
+    fn leader(&self) -> u8 {
+        self.indices.iter().copied().min().unwrap_or_default()
+    }
+
+    /// Indices of the other signers, in the order of their messages
+    fn others(&self) -> Vec<u32> {
+        self.indices
+            .iter()
+            .filter(|i| **i != self.group.index)
+            .map(|i| *i as u32)
+            .collect()
+    }
+
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::MlDsa.check(msg.protocol_type)?;
+        let indices = msg
+            .indices
+            .iter()
+            .map(|i| u8::try_from(*i).map_err(|_| "invalid party index".into()))
+            .collect::<Result<Vec<u8>>>()?;
+        if indices.iter().any(|i| *i == 0) {
+            return Err("invalid party index".into());
+        }
+        if !indices.contains(&self.group.index) || msg.index != self.group.index as u32 {
+            return Err("participant index not included".into());
+        }
+        if indices.len() < self.group.threshold as usize {
+            return Err("not enough signers".into());
+        }
+        if indices.len() > 1 && self.group.encryption_keys.is_empty() {
+            return Err("group created without encryption keys".into());
+        }
+        self.indices = indices;
+        self.data = msg.data;
+
+        // only the first signer learns the shares of the seed
+        let leader = self.leader() as u32;
+        let msgs: Vec<Bytes> = self
+            .others()
+            .iter()
+            .map(|i| {
+                if *i == leader {
+                    let key = self
+                        .group
+                        .encryption_keys
+                        .get(leader as usize - 1)
+                        .ok_or("invalid party index")?;
+                    Ok(hpke::encrypt(&self.group.share, key)?.into())
+                } else {
+                    Ok(Bytes::new())
+                }
+            })
+            .collect::<Result<_>>()?;
+        self.round = SignRound::R1;
+
+        if self.indices.len() == 1 {
+            return run_locally(2, ProtocolType::MlDsa, |data| self.update(data));
+        }
+        Ok(pack(msgs, ProtocolType::MlDsa))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msgs = unpack_from(data, &self.others())?;
+        let peers = self.indices.len() - 1;
+        match &self.round {
+            SignRound::R0 => Err("protocol not initialized".into()),
+            SignRound::R1 if self.leader() == self.group.index => {
+                let mut shares = vec![(self.group.index, self.group.share)];
+                for (sender, msg) in msgs {
+                    let share = decrypt_seed(&msg, &self.group.decryption_key)
+                        .map_err(|_| format!("invalid seed share from party {}", sender))?;
+                    shares.push((sender as u8, share));
+                }
+                let (pk, sk) = ml_dsa_65::KG::keygen_from_seed(&interpolate(&shares, 0));
+                if pk.into_bytes()[..] != self.group.public_key[..] {
+                    return Err("seed shares do not match the group key".into());
+                }
+                let signature = sk.try_sign(&self.data, b"")?.to_vec();
+                self.round = SignRound::R2(Some(signature.clone()));
+                Ok(pack(inflate(signature, peers), ProtocolType::MlDsa))
+            }
+            SignRound::R1 => {
+                self.round = SignRound::R2(None);
+                Ok(pack(inflate(Bytes::new(), peers), ProtocolType::MlDsa))
+            }
+            SignRound::R2(signature) => {
+                let signature = match signature {
+                    Some(signature) => signature.clone(),
+                    None => {
+                        let leader = self.leader() as u32;
+                        let (_, msg) = msgs
+                            .into_iter()
+                            .find(|(sender, _)| *sender == leader)
+                            .ok_or("missing signature")?;
+                        verify(&self.group.public_key, &self.data, &msg)
+                            .map_err(|_| format!("invalid signature from party {}", leader))?;
+                        msg.to_vec()
+                    }
+                };
+                self.round = SignRound::Done(signature);
+                Ok(pack(Vec::new(), ProtocolType::MlDsa))
+            }
+            SignRound::Done(_) => Err("protocol already finished".into()),
+        }
+    }
+
+    fn expected(&self) -> Option<usize> {
+        match self.round {
+            SignRound::R0 | SignRound::Done(_) => None,
+            _ => Some(self.indices.len() - 1),
+        }
+    }
+}
+
+#[typetag::serde(name = "mldsa_sign")]
+impl Protocol for MlDsaSignContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            SignRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match self.round {
+            SignRound::Done(signature) => Ok(pack_signature(
+                ProtocolType::MlDsa,
+                signature,
+                self.indices.into_iter().map(u32::from).collect(),
+            )),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.expected()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        DebugState::new("mldsa_sign", self.round.name(), self.expected())
+    }
+}
+
+impl ThresholdProtocol for MlDsaSignContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            group: parse_group_context(group)?,
+            indices: Vec::new(),
+            data: Vec::new(),
+            round: SignRound::R0,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::MlDsa,
+    name: "mldsa",
+    curve: "ml-dsa-65",
+    scheme: "ml-dsa",
+    index_base: 1,
+    keygen_rounds: 5,
+    threshold_rounds: Some(3),
+    max_parties: MAX_GROUP_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<MlDsaKeygenContext>,
+    threshold: registry::threshold::<MlDsaSignContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::tests::{
+        advance_all, run_rounds, KeygenProtocolTest, ThresholdProtocolTest,
+    };
+
+    impl KeygenProtocolTest for MlDsaKeygenContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::MlDsa;
+        const ROUNDS: usize = 5;
+        const INDEX_OFFSET: u32 = 1;
+        const AUTHENTICATED: bool = true;
+    }
+
+    impl ThresholdProtocolTest for MlDsaSignContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::MlDsa;
+        const ROUNDS: usize = 3;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    #[test]
+    fn sharing() {
+        let mut secret = [0; SEED_LEN];
+        ProtocolRng.fill_bytes(&mut secret);
+        let shares: Vec<(u8, Seed)> = (1..).zip(split(&secret, 3, 5)).collect();
+        assert_eq!(interpolate(&shares[..3], 0), secret);
+        assert_eq!(interpolate(&shares[2..], 0), secret);
+        assert_eq!(interpolate(&shares[..3], 5), shares[4].1);
+        assert_ne!(interpolate(&shares[..2], 0), secret);
+    }
+
+    #[test]
+    fn sign() {
+        let (pks, ctxs) = <MlDsaKeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        assert!(pks.iter().all(|key| *key == pk));
+
+        for indices in [vec![0, 1], vec![1, 2], vec![0, 1, 2]] {
+            let results = <MlDsaSignContext as ThresholdProtocolTest>::run(
+                ctxs.clone(),
+                indices,
+                b"hello".to_vec(),
+            );
+            for result in results {
+                let result = SignatureResult::decode(&result[..]).unwrap();
+                assert!(verify(&pk, b"hello", &result.signature).is_ok());
+                assert!(verify(&pk, b"other", &result.signature).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn encrypted_shares() {
+        let init = |index| {
+            ProtocolGroupInit {
+                protocol_type: ProtocolType::MlDsa as i32,
+                index,
+                parties: 2,
+                threshold: 2,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        let mut ctx = MlDsaKeygenContext::new();
+        let error = ctx.advance(&init(1)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "key generation requires authenticated messages"
+        );
+
+        let mut ctxs: Vec<MlDsaKeygenContext> = (0..2)
+            .map(|_| {
+                let mut ctx = MlDsaKeygenContext::new();
+                ctx.set_authenticated();
+                ctx
+            })
+            .collect();
+        let keys = advance_all(&mut ctxs, vec![init(1), init(2)]);
+        let shares = run_rounds(&mut ctxs, keys, 1);
+        // the shares pass the server only encrypted to the recipient
+        for (sender, msgs) in shares.iter().enumerate() {
+            let recipient = &ctxs[1 - sender];
+            assert_eq!(msgs.len(), 1);
+            assert!(msgs[0].len() > SEED_LEN);
+            assert!(decrypt_seed(&msgs[0], &recipient.decryption_key).is_ok());
+            assert!(decrypt_seed(&msgs[0], &ctxs[sender].decryption_key).is_err());
+        }
+    }
+
+    #[test]
+    fn single_party() {
+        let ctx = <MlDsaKeygenContext as KeygenProtocolTest>::run_single();
+        let pk = GroupResult::decode(&ctx[..]).unwrap().public_key;
+        let result = <MlDsaSignContext as ThresholdProtocolTest>::run_single(ctx, b"hi".to_vec());
+        let result = SignatureResult::decode(&result[..]).unwrap();
+        assert!(verify(&pk, b"hi", &result.signature).is_ok());
+    }
+}
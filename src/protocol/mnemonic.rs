@@ -0,0 +1,131 @@
+//! Wordlist-based encoding of binary data for human-friendly backups
+//!
+//! The encoding generalizes BIP-39 to data of any length that is a multiple
+//! of 4 bytes: the data is followed by one checksum bit per 32 bits of data
+//! taken from its SHA-256 digest, and every 11 bits select a word from the
+//! BIP-39 English wordlist. For 16 to 32 bytes of data, the result is exactly
+//! the BIP-39 mnemonic of the data. Longer data takes the checksum bits beyond
+//! the first 256 from the digests of the data followed by a block counter.
+
+use crate::protocol::Result;
+use bip39::Language;
+use sha2::{Digest, Sha256};
+
+fn checksum(data: &[u8], bits: usize) -> Vec<u8> {
+    let mut checksum = Sha256::digest(data).to_vec();
+    let mut block: u32 = 1;
+    while checksum.len() * 8 < bits {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.update(block.to_be_bytes());
+        checksum.extend(hasher.finalize());
+        block += 1;
+    }
+    checksum
+}
+
+fn bit(bytes: &[u8], i: usize) -> bool {
+    bytes[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+/// Encode `data`, whose length must be a nonzero multiple of 4 bytes,
+/// into a space-separated sequence of words
+pub fn encode(data: &[u8]) -> Result<String> {
+    if data.is_empty() || data.len() % 4 != 0 {
+        return Err("data length must be a nonzero multiple of 4 bytes".into());
+    }
+    let data_bits = data.len() * 8;
+    let checksum_bits = data.len() / 4;
+    let checksum = checksum(data, checksum_bits);
+
+    let wordlist = Language::English.word_list();
+    let words: Vec<&str> = (0..(data_bits + checksum_bits) / 11)
+        .map(|word| {
+            let index = (0..11).fold(0, |acc, j| {
+                let i = word * 11 + j;
+                let set = if i < data_bits {
+                    bit(data, i)
+                } else {
+                    bit(&checksum, i - data_bits)
+                };
+                acc << 1 | set as usize
+            });
+            wordlist[index]
+        })
+        .collect();
+    Ok(words.join(" "))
+}
+
+/// Decode a sequence of words produced by `encode`, checking its checksum
+pub fn decode(mnemonic: &str) -> Result<Vec<u8>> {
+    let indices = mnemonic
+        .split_whitespace()
+        .map(|word| {
+            Language::English
+                .find_word(&word.to_lowercase())
+                .ok_or_else(|| format!("unknown word {}", word).into())
+        })
+        .collect::<Result<Vec<u16>>>()?;
+    if indices.is_empty() || indices.len() % 3 != 0 {
+        return Err("invalid number of words".into());
+    }
+
+    let checksum_bits = indices.len() / 3;
+    let data_bits = checksum_bits * 32;
+    let mut bytes = vec![0u8; (data_bits + checksum_bits + 7) / 8];
+    for (word, index) in indices.iter().enumerate() {
+        for j in 0..11 {
+            if index & (0x400 >> j) != 0 {
+                let i = word * 11 + j;
+                bytes[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+    }
+
+    let data = bytes[..data_bits / 8].to_vec();
+    let expected = checksum(&data, checksum_bits);
+    if (0..checksum_bits).any(|i| bit(&bytes, data_bits + i) != bit(&expected, i)) {
+        return Err("invalid mnemonic checksum".into());
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bip39::Mnemonic;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn roundtrip(data in prop::collection::vec(any::<u8>(), 1..64usize)) {
+            let data: Vec<u8> = data.iter().cycle().take(data.len() * 4).cloned().collect();
+            prop_assert_eq!(decode(&encode(&data).unwrap()).unwrap(), data);
+        }
+
+        #[test]
+        fn bip39_compatible(data in prop::collection::vec(any::<u8>(), 4..=8usize)) {
+            let data: Vec<u8> = data.iter().cycle().take(data.len() * 4).cloned().collect();
+            let mnemonic = Mnemonic::from_entropy(&data).unwrap().to_string();
+            prop_assert_eq!(encode(&data).unwrap(), mnemonic);
+        }
+    }
+
+    #[test]
+    fn fixture() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon \
+                        abandon abandon abandon abandon abandon about";
+        assert_eq!(decode(mnemonic).unwrap(), vec![0u8; 16]);
+        assert_eq!(
+            encode(&[0u8; 16]).unwrap(),
+            mnemonic.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(encode(&[0u8; 15]).is_err());
+        assert!(decode("abandon abandon abandon").is_err());
+        assert!(decode("abandon abandon notaword").is_err());
+    }
+}
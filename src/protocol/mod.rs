@@ -1,17 +1,166 @@
+#[cfg(feature = "frost")]
+pub mod adaptor;
+#[cfg(feature = "frost")]
+pub mod age;
+pub mod backup;
+#[cfg(feature = "frost")]
+pub mod blind;
+#[cfg(feature = "gg18")]
+pub mod cms;
+pub mod coordinator;
+pub mod describe;
+pub mod eip712;
+#[cfg(feature = "elgamal")]
 pub mod elgamal;
+#[cfg(feature = "enclave")]
+pub mod enclave;
+#[cfg(feature = "frost")]
+pub mod enroll;
+pub mod fingerprint;
+#[cfg(feature = "frost")]
 pub mod frost;
+#[cfg(feature = "gg18")]
 pub mod gg18;
+#[cfg(feature = "frost")]
+pub mod hpke;
+#[cfg(feature = "pq")]
+pub mod hybrid;
+#[cfg(feature = "gg18")]
+pub mod migration;
+#[cfg(feature = "mldsa")]
+pub mod mldsa;
+pub mod mnemonic;
+#[cfg(feature = "musig2")]
+pub mod musig2;
+#[cfg(feature = "frost")]
+pub mod nested;
+#[cfg(feature = "gg18")]
+pub mod pdf;
+#[cfg(feature = "elgamal")]
+pub mod ppss;
+pub mod psbt;
+#[cfg(feature = "frost")]
+pub mod remove;
+#[cfg(feature = "frost")]
+pub mod roast;
+#[cfg(feature = "gg18")]
+pub mod ssh;
+pub mod storage;
+#[cfg(feature = "elgamal")]
+pub mod stream;
+#[cfg(feature = "gg18")]
+pub mod webauthn;
+pub mod weighted;
+
+mod checkpoint;
+mod codec;
+mod debug;
+#[cfg(feature = "frost")]
+mod ecdh;
+mod inbox;
+mod metadata;
+#[cfg(any(feature = "frost", feature = "musig2"))]
+mod nonces;
+mod pairing;
+mod policy;
+#[cfg(feature = "elgamal")]
+mod proofs;
+mod registry;
+mod replay;
+mod rng;
+mod signed;
+mod telemetry;
+mod transcript;
+mod version;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-use crate::proto::{ProtocolMessage, ProtocolType};
-use prost::Message;
+use crate::proto::{ProtocolGroupInit, ProtocolType};
+pub use checkpoint::{checkpoint, restore, try_deserialize};
+use codec::*;
+pub use codec::{
+    check_context_size, group_context, set_compression, set_size_limits, signers_commitment,
+    validate_envelope, SizeLimitError,
+};
+pub use debug::{state_diagram, DebugState};
+#[cfg(any(feature = "frost", feature = "musig2"))]
+pub use nonces::{export_consumed, import_consumed};
+pub use policy::{set_policy, Policy, SigningRequest};
+pub use registry::{
+    capabilities, protocol_info, protocols, supported_protocols, ProtocolInfo, MAX_PARTIES,
+};
+#[cfg(feature = "deterministic")]
+pub use rng::with_seed;
+#[cfg(feature = "frost")]
+pub(crate) use rng::HedgedRng;
+pub(crate) use rng::ProtocolRng;
 use serde::{Deserialize, Serialize};
+pub use signed::verify_signers;
+use std::convert::TryFrom;
+#[cfg(feature = "tracing")]
+pub use telemetry::{set_telemetry_hook, TelemetryEvent, TelemetryHook};
+pub use transcript::verify_transcript;
 
+/// Context of a single party in a protocol session
+///
+/// Contexts are `Send` because the bindings keep them behind a `Mutex`
+/// in objects shared between threads of the host, e.g. by uniffi, and the
+/// test harness advances the simulated parties on a thread pool.
 #[typetag::serde]
-pub trait Protocol {
+pub trait Protocol: Send {
     fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>>;
     fn finish(self: Box<Self>) -> Result<Vec<u8>>;
+    /// Number of messages from the other parties the next call of `advance`
+    /// expects, or `None` if it expects an initialization message, the
+    /// protocol has finished, or the number is not known
+    fn expected_messages(&self) -> Option<usize> {
+        None
+    }
+    /// Learn that the messages of the other parties are authenticated by
+    /// the keys of their devices, see `authenticated`; the contexts which
+    /// encrypt to keys the other parties send refuse to do so otherwise
+    fn set_authenticated(&mut self) {}
+    /// Indices of the other parties of the session in ascending order,
+    /// once the context is initialized, if the context knows them;
+    /// see `pairing`
+    fn others(&self) -> Option<Vec<u32>> {
+        None
+    }
+    /// Transcript of the session if it is being recorded, see `with_transcript`
+    fn transcript(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Output of the last call of `advance`, to be sent again if it was lost,
+    /// if the context keeps it; see `resumable`
+    fn last_output(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Group result with the session of the context counted in its usage,
+    /// to be stored in place of the group the context was created from
+    /// before the session is started, if the group has metadata;
+    /// see `metadata`
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Where the session stands, for diagnostics; free of secret material
+    fn debug_state(&self) -> DebugState {
+        DebugState::new(
+            std::any::type_name::<Self>(),
+            "unknown",
+            self.expected_messages(),
+        )
+    }
+    /// Buffer the message of the `sender`-th of the other parties, in the
+    /// order of the packed input, given in an envelope of its own as the
+    /// server relayed it, see `progressive`
+    fn feed(&mut self, _sender: usize, _msg: &[u8]) -> Result<()> {
+        Err("context does not accept single messages".into())
+    }
+    /// Whether the messages of all the other parties have been fed,
+    /// so that `advance` may be called with an empty input
+    fn ready(&self) -> bool {
+        false
+    }
 }
 
 pub trait KeygenProtocol: Protocol {
@@ -21,139 +170,433 @@ pub trait KeygenProtocol: Protocol {
 }
 
 pub trait ThresholdProtocol: Protocol {
-    fn new(group: &[u8]) -> Self
+    /// Create a context from the group context produced by the key
+    /// generation, failing if it is malformed
+    fn new(group: &[u8]) -> Result<Self>
     where
         Self: Sized;
+    /// Apply the choices of the party, see `ContextOptions`
+    fn configure(&mut self, _options: &ContextOptions) {}
+}
+
+/// Choices of the party for its threshold contexts, which it makes when
+/// it creates them rather than the initialization message from the server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextOptions {
+    /// Take part in blind FROST sessions, in which the policy cannot see
+    /// the message being signed, see the `blind` module
+    pub blind: bool,
+    /// Hash the secret share and the session into the nonces of FROST
+    /// signing sessions, see `HedgedRng`
+    pub hedged: bool,
+    /// Accept the ElGamal ciphertexts of older versions, which carry no
+    /// label binding them to associated data, in the sessions without
+    /// associated data, see `elgamal`
+    pub legacy_ciphertexts: bool,
+}
+
+/// Protocols identify parties by either zero- or one-based indices
+pub fn index_base(protocol_type: ProtocolType) -> u32 {
+    protocol_info(protocol_type).map_or(0, |info| info.index_base)
 }
 
-fn deserialize_vec<'de, T: Deserialize<'de>>(vec: &'de [Vec<u8>]) -> serde_json::Result<Vec<T>> {
-    vec.iter()
-        .map(|item| serde_json::from_slice::<T>(item))
+/// Indices of the parties of a session counted from one, whatever
+/// the base of the protocol, as they are reported outside the library
+pub(crate) fn one_based(protocol_type: ProtocolType, indices: &[u32]) -> Vec<u32> {
+    let base = index_base(protocol_type);
+    indices
+        .iter()
+        .map(|index| index.saturating_add(1).saturating_sub(base))
         .collect()
 }
 
-fn inflate<T: Clone>(value: T, n: usize) -> Vec<T> {
-    std::iter::repeat(value).take(n).collect()
+/// Create a fresh key generation context for the given protocol,
+/// failing if the protocol was not compiled in; the context returns
+/// its last output again when the same input is delivered repeatedly,
+/// speaks the protocol version given in its initialization message,
+/// and carries the metadata of the group into its result
+pub fn keygen_context(protocol_type: ProtocolType) -> Result<Box<dyn Protocol>> {
+    let ctx = (protocol_info(protocol_type)?.keygen)();
+    let ctx = Box::new(metadata::MetadataContext::new(ctx));
+    Ok(resumable(negotiated(paired(ctx, true))))
+}
+
+/// Create a threshold protocol context from a group context produced
+/// by the key generation of the given protocol, failing if the protocol
+/// was not compiled in or the metadata of the group does not allow it;
+/// the context counts its session in the usage of the group, see
+/// `Protocol::counted_group`, and the context of a hybrid group also
+/// signs by its ML-DSA key on request, see the `hybrid` module;
+/// see `keygen_context` for repeated input
+pub fn threshold_context(protocol_type: ProtocolType, group: &[u8]) -> Result<Box<dyn Protocol>> {
+    threshold_context_with(protocol_type, group, &ContextOptions::default())
+}
+
+/// Create a threshold protocol context as `threshold_context` does,
+/// with the given choices of the party
+pub fn threshold_context_with(
+    protocol_type: ProtocolType,
+    group: &[u8],
+    options: &ContextOptions,
+) -> Result<Box<dyn Protocol>> {
+    let info = protocol_info(protocol_type)?;
+    let counted = metadata::count_use(protocol_type, group)?;
+    let ctx = (info.threshold)(group, options)?;
+    #[cfg(feature = "pq")]
+    let ctx = hybrid::wrap(ctx, group);
+    let ctx: Box<dyn Protocol> = match counted {
+        Some(group) => Box::new(metadata::UsageContext::new(ctx, group)),
+        None => ctx,
+    };
+    Ok(resumable(negotiated(paired(ctx, false))))
+}
+
+fn negotiated(ctx: Box<dyn Protocol>) -> Box<dyn Protocol> {
+    Box::new(version::VersionContext::new(ctx))
+}
+
+fn paired(ctx: Box<dyn Protocol>, keygen: bool) -> Box<dyn Protocol> {
+    Box::new(pairing::PairingContext::new(ctx, keygen))
+}
+
+/// Make a context safe against the repeated delivery of the same input
+pub fn resumable(ctx: Box<dyn Protocol>) -> Box<dyn Protocol> {
+    Box::new(replay::ReplayContext::new(ctx))
 }
 
-/// Serialize value and repeat the result n times,
-/// as the current server always expects one message for each party
-fn serialize_bcast<T: Serialize>(value: &T, n: usize) -> serde_json::Result<Vec<Vec<u8>>> {
-    let ser = serde_json::to_vec(value)?;
-    Ok(inflate(ser, n))
+/// Record the transcript of the session, which is available
+/// through `Protocol::transcript` before the context is finished
+pub fn with_transcript(ctx: Box<dyn Protocol>) -> Box<dyn Protocol> {
+    Box::new(transcript::TranscriptContext::new(ctx))
 }
 
-/// Serialize vector of unicast messages
-fn serialize_uni<T: Serialize>(vec: Vec<T>) -> serde_json::Result<Vec<Vec<u8>>> {
-    vec.iter().map(|item| serde_json::to_vec(item)).collect()
+/// Sign the outgoing messages by the DER encoded `key` of the device and
+/// verify the incoming ones against the certificates of the devices of
+/// the parties, given as an encoded `GroupDevices`
+pub fn authenticated(
+    ctx: Box<dyn Protocol>,
+    key: &[u8],
+    devices: &[u8],
+) -> Result<Box<dyn Protocol>> {
+    Ok(Box::new(signed::SignedContext::new(ctx, key, devices)?))
 }
 
-/// Decode a protobuf message from the server
-fn unpack(data: &[u8]) -> std::result::Result<Vec<Vec<u8>>, prost::DecodeError> {
-    let msgs = ProtocolMessage::decode(data)?.message;
-    Ok(msgs)
+/// Accept the messages of a round one at a time through `Protocol::feed`;
+/// the context must not be wrapped any further
+pub fn progressive(ctx: Box<dyn Protocol>) -> Box<dyn Protocol> {
+    Box::new(inbox::InboxContext::new(ctx))
 }
 
-/// Encode msgs as a protobuf message for the server
-fn pack(msgs: Vec<Vec<u8>>, protocol_type: ProtocolType) -> Vec<u8> {
-    ProtocolMessage {
-        protocol_type: protocol_type.into(),
-        message: msgs,
+/// Check the parameters of a group to be created against the limits of its
+/// protocol, as the underlying libraries panic on invalid ones;
+/// `first_index` is the index of the first party, which differs between
+/// the protocols
+fn check_group_params(msg: &ProtocolGroupInit, first_index: u32) -> Result<()> {
+    let info = protocol_info(ProtocolType::try_from(msg.protocol_type)?)?;
+    if msg.parties == 0
+        || msg.parties > info.max_parties
+        || msg.threshold < info.min_threshold.unwrap_or(msg.parties)
+        || msg.threshold == 0
+        || msg.threshold > msg.parties
+        || msg.index < first_index
+        || msg.index - first_index >= msg.parties
+    {
+        return Err("invalid group parameters".into());
     }
-    .encode_to_vec()
+    Ok(())
+}
+
+/// Run the remaining rounds of a single-party session locally,
+/// as there are no peers to exchange messages with
+fn run_locally(
+    rounds: usize,
+    protocol_type: ProtocolType,
+    mut update: impl FnMut(&[u8]) -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let empty = pack(Vec::new(), protocol_type);
+    let mut out = empty.clone();
+    for _ in 0..rounds {
+        out = update(&empty)?;
+    }
+    Ok(out)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use prost::bytes::Bytes;
+    use prost::{bytes::Bytes, Message};
 
     use crate::{
-        proto::{ProtocolGroupInit, ProtocolInit},
+        proto::{ProtocolGroupInit, ProtocolInit, ProtocolMessage},
         protocol::{KeygenProtocol, ThresholdProtocol},
     };
 
+    pub(super) fn advance_unpacked<P: Protocol>(ctx: &mut P, data: &[u8]) -> Vec<Vec<u8>> {
+        ProtocolMessage::decode::<Bytes>(ctx.advance(data).unwrap().into())
+            .unwrap()
+            .message
+            .iter()
+            .map(|msg| msg.to_vec())
+            .collect()
+    }
+
+    /// Advance all the parties with their respective inputs, in parallel
+    /// unless threads are unavailable or the randomness is to be seeded
+    pub(super) fn advance_all<P: Protocol>(
+        ctxs: &mut [P],
+        inputs: Vec<Vec<u8>>,
+    ) -> Vec<Vec<Vec<u8>>> {
+        #[cfg(not(any(target_arch = "wasm32", feature = "deterministic")))]
+        {
+            use rayon::prelude::*;
+            ctxs.par_iter_mut()
+                .zip(inputs)
+                .map(|(ctx, input)| advance_unpacked(ctx, &input))
+                .collect()
+        }
+        #[cfg(any(target_arch = "wasm32", feature = "deterministic"))]
+        ctxs.iter_mut()
+            .zip(inputs)
+            .map(|(ctx, input)| advance_unpacked(ctx, &input))
+            .collect()
+    }
+
+    /// Relay the messages between the parties for the given number of rounds,
+    /// returning the messages produced in the last one
+    pub(super) fn run_rounds<P: Protocol>(
+        ctxs: &mut [P],
+        mut messages: Vec<Vec<Vec<u8>>>,
+        rounds: usize,
+    ) -> Vec<Vec<Vec<u8>>> {
+        for _ in 0..rounds {
+            for ctx in ctxs.iter() {
+                assert_eq!(ctx.expected_messages(), Some(ctxs.len() - 1));
+            }
+            let inputs = (0..ctxs.len())
+                .map(|idx| {
+                    let relay = messages
+                        .iter()
+                        .enumerate()
+                        .map(|(sender, msg)| {
+                            if sender < idx {
+                                Some(Bytes::from(msg[idx - 1].clone()))
+                            } else if sender > idx {
+                                Some(Bytes::from(msg[idx].clone()))
+                            } else {
+                                None
+                            }
+                        })
+                        .filter(Option::is_some)
+                        .map(Option::unwrap)
+                        .collect();
+
+                    (ProtocolMessage {
+                        protocol_type: ProtocolType::Frost as i32,
+                        message: relay,
+                        ..Default::default()
+                    })
+                    .encode_to_vec()
+                })
+                .collect();
+            messages = advance_all(ctxs, inputs);
+        }
+        messages
+    }
+
+    pub(super) fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Check a signature verification function against test vectors
+    /// in the Wycheproof format, whose "acceptable" cases may go either way
+    #[cfg(feature = "gg18")]
+    pub(super) fn run_wycheproof(vectors: &str, verify: fn(&[u8], &[u8], &[u8]) -> Result<()>) {
+        let vectors: serde_json::Value = serde_json::from_str(vectors).unwrap();
+        for group in vectors["testGroups"].as_array().unwrap() {
+            let key = match group.get("publicKey") {
+                Some(key) => key,
+                None => &group["key"],
+            };
+            let pk = unhex(key["uncompressed"].as_str().unwrap());
+
+            for test in group["tests"].as_array().unwrap() {
+                let msg = unhex(test["msg"].as_str().unwrap());
+                let sig = unhex(test["sig"].as_str().unwrap());
+                let result = verify(&pk, &msg, &sig);
+                match test["result"].as_str().unwrap() {
+                    "valid" => assert!(result.is_ok(), "test {} failed", test["tcId"]),
+                    "invalid" => assert!(result.is_err(), "test {} passed", test["tcId"]),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn context_roundtrip() {
+        for info in protocols() {
+            let ctx = (info.keygen)();
+            let ser = serde_json::to_vec(&ctx).unwrap();
+            let de: Box<dyn Protocol> = serde_json::from_slice(&ser).unwrap();
+            assert_eq!(serde_json::to_vec(&de).unwrap(), ser);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "gg18", feature = "elgamal", feature = "frost"))]
+    fn context_fixtures() {
+        for fixture in [
+            r#"{"gg18_keygen":{"round":"R0"}}"#,
+            r#"{"elgamal_keygen":{"round":"R0"}}"#,
+            r#"{"frost_keygen":{"round":"R0"}}"#,
+        ] {
+            let ctx: Box<dyn Protocol> = serde_json::from_str(fixture).unwrap();
+            assert_eq!(serde_json::to_string(&ctx).unwrap(), fixture);
+        }
+    }
+
+    /// The contexts as the clients store them, with all the wrappers,
+    /// have to be readable by both the native library and its wasm32 build
+    #[test]
+    #[cfg(all(feature = "gg18", feature = "elgamal", feature = "frost"))]
+    fn wrapped_context_fixtures() {
+        for (protocol_type, inner) in [
+            (ProtocolType::Gg18, r#"{"gg18_keygen":{"round":"R0"}}"#),
+            (
+                ProtocolType::Elgamal,
+                r#"{"elgamal_keygen":{"round":"R0"}}"#,
+            ),
+            (ProtocolType::Frost, r#"{"frost_keygen":{"round":"R0"}}"#),
+        ] {
+            let fixture = format!(
+                concat!(
+                    r#"{{"replay":{{"inner":{{"version":{{"inner":{{"pairing":{{"#,
+                    r#""inner":{{"metadata":{{"inner":{},"metadata":null,"initialized":false}}}},"#,
+                    r#""keygen":true,"others":null}}}},"version":null}}}},"#,
+                    r#""last":null,"consumed":0}}}}"#
+                ),
+                inner
+            );
+            let ctx = keygen_context(protocol_type).unwrap();
+            assert_eq!(serde_json::to_string(&ctx).unwrap(), fixture);
+            let ctx: Box<dyn Protocol> = serde_json::from_str(&fixture).unwrap();
+            assert_eq!(serde_json::to_string(&ctx).unwrap(), fixture);
+        }
+    }
+
+    fn fixture_path(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{}.json", name))
+    }
+
+    /// Load the group contexts of all the parties stored by `store_fixture`,
+    /// so that the tests can check that contexts created by older versions
+    /// remain usable; the fixtures are committed, so a missing one fails
+    pub(super) fn load_fixture(name: &str) -> Vec<Vec<u8>> {
+        let data = std::fs::read(fixture_path(name)).unwrap_or_else(|_| {
+            panic!(
+                "fixture {} missing, generate it by \
+                 `cargo test --features deterministic generate_fixture -- --ignored`",
+                name
+            )
+        });
+        serde_json::from_slice(&data).unwrap()
+    }
+
+    /// Run a 2-of-3 keygen with a fixed seed and store the group contexts
+    #[cfg(feature = "deterministic")]
+    pub(super) fn store_fixture<P: KeygenProtocolTest>(name: &str) {
+        let (_, groups) = with_seed([0; 32], || P::run(2, 3));
+        let path = fixture_path(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, serde_json::to_vec(&groups).unwrap()).unwrap();
+    }
+
+    /// Run a key generation of the groups of `protocol_type`, which may
+    /// differ from the protocol of the context if it creates the groups
+    /// of several ones, returning the public keys and the group results
+    pub(super) fn run_keygen<P: KeygenProtocolTest>(
+        protocol_type: ProtocolType,
+        rounds: usize,
+        threshold: u32,
+        parties: u32,
+    ) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        assert!(threshold <= parties);
+
+        // initialize
+        let mut ctxs: Vec<P> = (0..parties).map(|_| P::new()).collect();
+        if P::AUTHENTICATED {
+            ctxs.iter_mut().for_each(|ctx| ctx.set_authenticated());
+        }
+        let inits = (0..parties)
+            .map(|idx| {
+                (ProtocolGroupInit {
+                    protocol_type: protocol_type as i32,
+                    index: idx as u32 + P::INDEX_OFFSET,
+                    parties,
+                    threshold,
+                    weights: Vec::new(),
+                    encrypt: false,
+                    metadata: None,
+                    version: 0,
+                })
+                .encode_to_vec()
+            })
+            .collect();
+        let messages = advance_all(&mut ctxs, inits);
+
+        // protocol rounds
+        let messages = run_rounds(&mut ctxs, messages, rounds - 1);
+
+        let pks: Vec<_> = messages.iter().map(|x| x[0].clone()).collect();
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
+
+        let results = ctxs
+            .into_iter()
+            .map(|ctx| Box::new(ctx).finish().unwrap())
+            .collect();
+
+        (pks, results)
+    }
+
     pub(super) trait KeygenProtocolTest: KeygenProtocol + Sized {
         // Cannot be added in Protocol (yet) due to typetag Trait limitations
         const PROTOCOL_TYPE: ProtocolType;
         const ROUNDS: usize;
         const INDEX_OFFSET: u32 = 0;
+        /// Whether the contexts take part only if their messages are
+        /// authenticated, see `Protocol::set_authenticated`
+        const AUTHENTICATED: bool = false;
 
         fn run(threshold: u32, parties: u32) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
-            assert!(threshold <= parties);
+            run_keygen::<Self>(Self::PROTOCOL_TYPE, Self::ROUNDS, threshold, parties)
+        }
 
-            // initialize
-            let mut ctxs: Vec<Self> = (0..parties).map(|_| Self::new()).collect();
-            let mut messages: Vec<_> = ctxs
-                .iter_mut()
-                .enumerate()
-                .map(|(idx, ctx)| {
-                    ProtocolMessage::decode::<Bytes>(
-                        ctx.advance(
-                            &(ProtocolGroupInit {
-                                protocol_type: Self::PROTOCOL_TYPE as i32,
-                                index: idx as u32 + Self::INDEX_OFFSET,
-                                parties,
-                                threshold,
-                            })
-                            .encode_to_vec(),
-                        )
-                        .unwrap()
-                        .into(),
-                    )
-                    .unwrap()
-                    .message
+        fn run_single() -> Vec<u8> {
+            let mut ctx = Self::new();
+            let out = advance_unpacked(
+                &mut ctx,
+                &(ProtocolGroupInit {
+                    protocol_type: Self::PROTOCOL_TYPE as i32,
+                    index: Self::INDEX_OFFSET,
+                    parties: 1,
+                    threshold: 1,
+                    weights: Vec::new(),
+                    encrypt: false,
+                    metadata: None,
+                    version: 0,
                 })
-                .collect();
-
-            // protocol rounds
-            for _ in 0..(Self::ROUNDS - 1) {
-                messages = ctxs
-                    .iter_mut()
-                    .enumerate()
-                    .map(|(idx, ctx)| {
-                        let relay = messages
-                            .iter()
-                            .enumerate()
-                            .map(|(sender, msg)| {
-                                if sender < idx {
-                                    Some(msg[idx - 1].clone())
-                                } else if sender > idx {
-                                    Some(msg[idx].clone())
-                                } else {
-                                    None
-                                }
-                            })
-                            .filter(Option::is_some)
-                            .map(Option::unwrap)
-                            .collect();
-
-                        ProtocolMessage::decode::<Bytes>(
-                            ctx.advance(
-                                &(ProtocolMessage {
-                                    protocol_type: ProtocolType::Frost as i32,
-                                    message: relay,
-                                })
-                                .encode_to_vec(),
-                            )
-                            .unwrap()
-                            .into(),
-                        )
-                        .unwrap()
-                        .message
-                    })
-                    .collect();
-            }
-
-            let pks: Vec<_> = messages.iter().map(|x| x[0].clone()).collect();
+                .encode_to_vec(),
+            );
+            assert!(out.is_empty());
 
-            let results = ctxs
-                .into_iter()
-                .map(|ctx| Box::new(ctx).finish().unwrap())
-                .collect();
-
-            (pks, results)
+            Box::new(ctx).finish().unwrap()
         }
     }
 
@@ -169,75 +612,51 @@ mod tests {
                 .iter()
                 .enumerate()
                 .filter(|(idx, _)| indices.contains(&(*idx as u16)))
-                .map(|(_, ctx)| Self::new(&ctx))
+                .map(|(_, ctx)| Self::new(&ctx).unwrap())
                 .collect();
-            let mut messages: Vec<_> = indices
+            let inits = indices
                 .iter()
-                .zip(ctxs.iter_mut())
-                .map(|(idx, ctx)| {
-                    ProtocolMessage::decode::<Bytes>(
-                        ctx.advance(
-                            &(ProtocolInit {
-                                protocol_type: Self::PROTOCOL_TYPE as i32,
-                                indices: indices
-                                    .iter()
-                                    .map(|x| *x as u32 + Self::INDEX_OFFSET)
-                                    .collect(),
-                                index: *idx as u32 + Self::INDEX_OFFSET,
-                                data: data.clone(),
-                            })
-                            .encode_to_vec(),
-                        )
-                        .unwrap()
-                        .into(),
-                    )
-                    .unwrap()
-                    .message
+                .map(|idx| {
+                    (ProtocolInit {
+                        protocol_type: Self::PROTOCOL_TYPE as i32,
+                        indices: indices
+                            .iter()
+                            .map(|x| *x as u32 + Self::INDEX_OFFSET)
+                            .collect(),
+                        index: *idx as u32 + Self::INDEX_OFFSET,
+                        data: data.clone(),
+                        ..Default::default()
+                    })
+                    .encode_to_vec()
                 })
                 .collect();
+            let messages = advance_all(&mut ctxs, inits);
 
             // protocol rounds
-            for _ in 0..(Self::ROUNDS - 1) {
-                messages = ctxs
-                    .iter_mut()
-                    .enumerate()
-                    .map(|(idx, ctx)| {
-                        let relay = messages
-                            .iter()
-                            .enumerate()
-                            .map(|(sender, msg)| {
-                                if sender < idx {
-                                    Some(msg[idx - 1].clone())
-                                } else if sender > idx {
-                                    Some(msg[idx].clone())
-                                } else {
-                                    None
-                                }
-                            })
-                            .filter(Option::is_some)
-                            .map(Option::unwrap)
-                            .collect();
-
-                        ProtocolMessage::decode::<Bytes>(
-                            ctx.advance(
-                                &(ProtocolMessage {
-                                    protocol_type: ProtocolType::Frost as i32,
-                                    message: relay,
-                                })
-                                .encode_to_vec(),
-                            )
-                            .unwrap()
-                            .into(),
-                        )
-                        .unwrap()
-                        .message
-                    })
-                    .collect();
-            }
+            run_rounds(&mut ctxs, messages, Self::ROUNDS - 1);
+            assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
 
             ctxs.into_iter()
                 .map(|ctx| Box::new(ctx).finish().unwrap())
                 .collect()
         }
+
+        fn run_single(ctx: Vec<u8>, data: Vec<u8>) -> Vec<u8> {
+            let mut ctx = Self::new(&ctx).unwrap();
+            let out = advance_unpacked(
+                &mut ctx,
+                &(ProtocolInit {
+                    protocol_type: Self::PROTOCOL_TYPE as i32,
+                    indices: vec![Self::INDEX_OFFSET],
+                    index: Self::INDEX_OFFSET,
+                    data,
+                    ..Default::default()
+                })
+                .encode_to_vec(),
+            );
+            assert!(out.is_empty());
+
+            Box::new(ctx).finish().unwrap()
+        }
     }
 }
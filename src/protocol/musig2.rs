@@ -0,0 +1,670 @@
+//! MuSig2 multisignatures of all the parties of a group
+//!
+//! Implements the key aggregation, nonce aggregation and signing of
+//! BIP-327 without tweaks, producing BIP-340 Schnorr signatures under the
+//! x-only aggregate key. The key generation merely exchanges the public
+//! keys of the parties, as every party has to sign, and the signing takes
+//! two rounds: an exchange of public nonces and of partial signatures.
+//!
+//! A session initialized with an adaptor point `T` produces a
+//! pre-signature instead, the compressed final nonce `R` followed by the
+//! sum of the partial signatures. It is completed into a valid signature
+//! by `adapt` only with the discrete logarithm `t` of `T`, which anyone
+//! holding both can in turn recover by `extract_adaptor`, as needed for
+//! atomic swaps.
+
+use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::protocol::telemetry::telemetry;
+use crate::protocol::*;
+
+use k256::elliptic_curve::{
+    group::Group as _, ops::Reduce, point::AffineCoordinates, sec1::ToEncodedPoint, Field,
+    PrimeField,
+};
+use k256::{FieldBytes, ProjectivePoint, PublicKey, Scalar, U256};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const PUBNONCE_LEN: usize = 66;
+const PRESIGNATURE_LEN: usize = 65;
+
+fn tagged_hash(tag: &str, parts: &[&[u8]]) -> FieldBytes {
+    let tag = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    hasher.update(tag);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+fn hash_to_scalar(tag: &str, parts: &[&[u8]]) -> Scalar {
+    <Scalar as Reduce<U256>>::reduce_bytes(&tagged_hash(tag, parts))
+}
+
+fn to_point(bytes: &[u8]) -> Result<ProjectivePoint> {
+    let key = PublicKey::from_sec1_bytes(bytes).map_err(|_| "invalid point")?;
+    Ok(key.to_projective())
+}
+
+fn to_scalar(bytes: &[u8]) -> Result<Scalar> {
+    if bytes.len() != 32 {
+        return Err("invalid scalar".into());
+    }
+    Option::from(Scalar::from_repr(*FieldBytes::from_slice(bytes)))
+        .ok_or_else(|| "invalid scalar".into())
+}
+
+fn cbytes(point: &ProjectivePoint) -> Vec<u8> {
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+/// Compressed encoding which maps the point at infinity to zeros
+fn cbytes_ext(point: &ProjectivePoint) -> Vec<u8> {
+    if point.is_identity().into() {
+        vec![0; 33]
+    } else {
+        cbytes(point)
+    }
+}
+
+fn xbytes(point: &ProjectivePoint) -> FieldBytes {
+    point.to_affine().x()
+}
+
+fn has_even_y(point: &ProjectivePoint) -> bool {
+    !bool::from(point.to_affine().y_is_odd())
+}
+
+/// Aggregate key of the compressed `pubkeys` together with the
+/// coefficients of the individual keys
+fn key_agg(pubkeys: &[Vec<u8>]) -> Result<(ProjectivePoint, Vec<Scalar>)> {
+    let list: Vec<&[u8]> = pubkeys.iter().map(Vec::as_slice).collect();
+    let hash = tagged_hash("KeyAgg list", &list);
+    let second = pubkeys.iter().find(|pk| *pk != &pubkeys[0]);
+
+    let mut aggregate = ProjectivePoint::IDENTITY;
+    let mut coefficients = Vec::new();
+    for pk in pubkeys {
+        let coefficient = if Some(pk) == second {
+            Scalar::ONE
+        } else {
+            hash_to_scalar("KeyAgg coefficient", &[&hash, pk])
+        };
+        aggregate += to_point(pk)? * coefficient;
+        coefficients.push(coefficient);
+    }
+    if aggregate.is_identity().into() {
+        return Err("aggregate key is the identity".into());
+    }
+    Ok((aggregate, coefficients))
+}
+
+/// Values shared by the signers of a session: the nonce coefficient,
+/// the final nonce offset by the `adaptor` point and the challenge
+fn session(
+    pubnonces: &[Vec<u8>],
+    key: &ProjectivePoint,
+    msg: &[u8],
+    adaptor: &ProjectivePoint,
+) -> Result<(Scalar, ProjectivePoint, Scalar)> {
+    let mut aggregate = [ProjectivePoint::IDENTITY; 2];
+    for pubnonce in pubnonces {
+        if pubnonce.len() != PUBNONCE_LEN {
+            return Err("invalid public nonce".into());
+        }
+        aggregate[0] += to_point(&pubnonce[..33])?;
+        aggregate[1] += to_point(&pubnonce[33..])?;
+    }
+    let aggnonce = [cbytes_ext(&aggregate[0]), cbytes_ext(&aggregate[1])].concat();
+
+    let b = hash_to_scalar("MuSig/noncecoef", &[&aggnonce, &xbytes(key), msg]);
+    let mut nonce = aggregate[0] + aggregate[1] * b + adaptor;
+    if nonce.is_identity().into() {
+        nonce = ProjectivePoint::GENERATOR;
+    }
+    let e = hash_to_scalar("BIP0340/challenge", &[&xbytes(&nonce), &xbytes(key), msg]);
+    Ok((b, nonce, e))
+}
+
+/// Verify a BIP-340 signature under the x-only public key `pk`
+pub fn verify(pk: &[u8], msg: &[u8], signature: &[u8]) -> Result<()> {
+    if pk.len() != 32 || signature.len() != 64 {
+        return Err("malformed signature".into());
+    }
+    let key = to_point(&[&[2], pk].concat())?;
+    let s = to_scalar(&signature[32..])?;
+    let e = hash_to_scalar("BIP0340/challenge", &[&signature[..32], pk, msg]);
+
+    let nonce = ProjectivePoint::GENERATOR * s - key * e;
+    if nonce.is_identity().into() || !has_even_y(&nonce) || xbytes(&nonce)[..] != signature[..32] {
+        return Err("invalid signature".into());
+    }
+    Ok(())
+}
+
+/// Nonce, challenge and partial signature sum of a pre-signature
+fn split_presignature(
+    pk: &[u8],
+    msg: &[u8],
+    presignature: &[u8],
+) -> Result<(ProjectivePoint, Scalar, Scalar)> {
+    if pk.len() != 32 || presignature.len() != PRESIGNATURE_LEN {
+        return Err("malformed pre-signature".into());
+    }
+    let nonce = to_point(&presignature[..33])?;
+    let e = hash_to_scalar("BIP0340/challenge", &[&xbytes(&nonce), pk, msg]);
+    Ok((nonce, e, to_scalar(&presignature[33..])?))
+}
+
+/// Verify that the pre-signature under the x-only public key `pk`
+/// completes into a signature with the secret of the `adaptor` point
+pub fn verify_adaptor(pk: &[u8], msg: &[u8], presignature: &[u8], adaptor: &[u8]) -> Result<()> {
+    let (nonce, e, s) = split_presignature(pk, msg, presignature)?;
+    let key = to_point(&[&[2], pk].concat())?;
+    let mut expected = nonce - to_point(adaptor)?;
+    if !has_even_y(&nonce) {
+        expected = -expected;
+    }
+    if ProjectivePoint::GENERATOR * s != expected + key * e {
+        return Err("invalid pre-signature".into());
+    }
+    Ok(())
+}
+
+/// Complete the pre-signature with the adaptor `secret`
+pub fn adapt(presignature: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    if presignature.len() != PRESIGNATURE_LEN {
+        return Err("malformed pre-signature".into());
+    }
+    let nonce = to_point(&presignature[..33])?;
+    let mut t = to_scalar(secret)?;
+    if !has_even_y(&nonce) {
+        t = -t;
+    }
+    let s = to_scalar(&presignature[33..])? + t;
+    Ok([&xbytes(&nonce)[..], &s.to_bytes()].concat())
+}
+
+/// Recover the adaptor secret from the pre-signature and its completion
+pub fn extract_adaptor(presignature: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    if presignature.len() != PRESIGNATURE_LEN || signature.len() != 64 {
+        return Err("malformed signature".into());
+    }
+    let nonce = to_point(&presignature[..33])?;
+    if xbytes(&nonce)[..] != signature[..32] {
+        return Err("signature does not complete the pre-signature".into());
+    }
+    let mut t = to_scalar(&signature[32..])? - to_scalar(&presignature[33..])?;
+    if !has_even_y(&nonce) {
+        t = -t;
+    }
+    Ok(t.to_bytes().to_vec())
+}
+
+/// Keys of a group, ordered by the party indices starting at one
+#[derive(Clone, Serialize, Deserialize)]
+struct Group {
+    index: u16,
+    secret: Vec<u8>,
+    pubkeys: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct KeygenContext {
+    round: KeygenRound,
+}
+
+#[derive(Serialize, Deserialize)]
+enum KeygenRound {
+    R0,
+    /// Own keys and the number of the other parties
+    R1(Group, usize),
+    Done(Group),
+}
+
+impl KeygenContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolGroupInit::decode(data)?;
+        ProtocolType::Musig2.check(msg.protocol_type)?;
+        check_group_params(&msg, 1)?;
+
+        let secret = Scalar::random(&mut ProtocolRng);
+        let pubkey = cbytes(&(ProjectivePoint::GENERATOR * secret));
+        let msgs = inflate(pubkey.clone(), msg.parties as usize - 1);
+        self.round = KeygenRound::R1(
+            Group {
+                index: msg.index as u16,
+                secret: secret.to_bytes().to_vec(),
+                pubkeys: vec![pubkey],
+            },
+            msg.parties as usize - 1,
+        );
+
+        if msg.parties == 1 {
+            return run_locally(1, ProtocolType::Musig2, |data| self.update(data));
+        }
+        Ok(pack(msgs, ProtocolType::Musig2))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let (group, peers) = match &self.round {
+            KeygenRound::R0 => return Err("protocol not initialized".into()),
+            KeygenRound::R1(group, peers) => (group, *peers),
+            KeygenRound::Done(_) => return Err("protocol already finished".into()),
+        };
+
+        let msgs = unpack(data)?;
+        check_message_count(&msgs, peers)?;
+        let mut pubkeys = Vec::new();
+        for msg in msgs {
+            to_point(&msg)?;
+            pubkeys.push(msg.to_vec());
+        }
+        pubkeys.insert(group.index as usize - 1, group.pubkeys[0].clone());
+        let (key, _) = key_agg(&pubkeys)?;
+
+        self.round = KeygenRound::Done(Group {
+            pubkeys,
+            ..group.clone()
+        });
+        Ok(pack(
+            inflate(xbytes(&key).to_vec(), peers),
+            ProtocolType::Musig2,
+        ))
+    }
+}
+
+#[typetag::serde(name = "musig2_keygen")]
+impl Protocol for KeygenContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            KeygenRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match self.round {
+            KeygenRound::Done(group) => {
+                let (key, _) = key_agg(&group.pubkeys)?;
+                Ok(pack_group(
+                    ProtocolType::Musig2,
+                    xbytes(&key).to_vec(),
+                    serde_json::to_vec(&group)?,
+                ))
+            }
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match self.round {
+            KeygenRound::R1(_, peers) => Some(peers),
+            _ => None,
+        }
+    }
+}
+
+impl KeygenProtocol for KeygenContext {
+    fn new() -> Self {
+        Self {
+            round: KeygenRound::R0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SignContext {
+    group: Group,
+    message: Option<Vec<u8>>,
+    /// Adaptor point of a pre-signature, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adaptor: Option<Vec<u8>>,
+    round: SignRound,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignRound {
+    R0,
+    /// Secret nonces and the public nonce committing to them
+    R1([Vec<u8>; 2], Vec<u8>),
+    /// Public nonces of all the signers and the own partial signature
+    R2(Vec<Vec<u8>>, Vec<u8>),
+    Done(Vec<u8>),
+}
+
+impl SignContext {
+    fn index(&self) -> usize {
+        self.group.index as usize - 1
+    }
+
+    fn adaptor(&self) -> Result<ProjectivePoint> {
+        match &self.adaptor {
+            Some(adaptor) => to_point(adaptor),
+            None => Ok(ProjectivePoint::IDENTITY),
+        }
+    }
+
+    /// Collect the messages of the other signers together with the own one
+    fn collect(&self, data: &[u8], own: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let msgs = unpack(data)?;
+        check_message_count(&msgs, self.group.pubkeys.len() - 1)?;
+        let mut all: Vec<Vec<u8>> = msgs.iter().map(|msg| msg.to_vec()).collect();
+        all.insert(self.index(), own.to_vec());
+        Ok(all)
+    }
+
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::Musig2.check(msg.protocol_type)?;
+        let parties = self.group.pubkeys.len() as u32;
+        if msg.indices != (1..=parties).collect::<Vec<_>>() {
+            return Err("all parties have to sign".into());
+        }
+        if msg.index != self.group.index as u32 {
+            return Err("participant index not included".into());
+        }
+        policy::check(ProtocolType::Musig2, &msg)?;
+        if !msg.adaptor.is_empty() {
+            to_point(&msg.adaptor)?;
+            self.adaptor = Some(msg.adaptor);
+        }
+
+        let nonces = [
+            Scalar::random(&mut ProtocolRng),
+            Scalar::random(&mut ProtocolRng),
+        ];
+        let pubnonce = [
+            cbytes(&(ProjectivePoint::GENERATOR * nonces[0])),
+            cbytes(&(ProjectivePoint::GENERATOR * nonces[1])),
+        ]
+        .concat();
+        let msgs = inflate(pubnonce.clone(), parties as usize - 1);
+        self.message = Some(msg.data);
+        self.round = SignRound::R1(
+            [nonces[0].to_bytes().to_vec(), nonces[1].to_bytes().to_vec()],
+            pubnonce,
+        );
+
+        if parties == 1 {
+            return run_locally(2, ProtocolType::Musig2, |data| self.update(data));
+        }
+        Ok(pack(msgs, ProtocolType::Musig2))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = self.message.as_ref().ok_or("protocol not initialized")?;
+        let (key, coefficients) = key_agg(&self.group.pubkeys)?;
+        let peers = self.group.pubkeys.len() - 1;
+
+        match &self.round {
+            SignRound::R0 => Err("protocol not initialized".into()),
+            SignRound::R1(nonces, pubnonce) => {
+                let pubnonces = self.collect(data, pubnonce)?;
+                let (b, nonce, e) = session(&pubnonces, &key, msg, &self.adaptor()?)?;
+
+                let mut k = [to_scalar(&nonces[0])?, to_scalar(&nonces[1])?];
+                if !has_even_y(&nonce) {
+                    k = [-k[0], -k[1]];
+                }
+                let mut secret = to_scalar(&self.group.secret)?;
+                if !has_even_y(&key) {
+                    secret = -secret;
+                }
+                let signed = [
+                    b.to_bytes().to_vec(),
+                    cbytes_ext(&nonce),
+                    e.to_bytes().to_vec(),
+                ];
+                nonces::consume(nonces::session_id(pubnonce)?, &signed)?;
+                let partial = k[0] + b * k[1] + e * coefficients[self.index()] * secret;
+
+                let partial = partial.to_bytes().to_vec();
+                let msgs = inflate(partial.clone(), peers);
+                self.round = SignRound::R2(pubnonces, partial);
+                Ok(pack(msgs, ProtocolType::Musig2))
+            }
+            SignRound::R2(pubnonces, partial) => {
+                let partials = self.collect(data, partial)?;
+                let (b, nonce, e) = session(pubnonces, &key, msg, &self.adaptor()?)?;
+
+                let mut s = Scalar::ZERO;
+                for (i, partial) in partials.iter().enumerate() {
+                    let partial = to_scalar(partial)?;
+                    let mut expected =
+                        to_point(&pubnonces[i][..33])? + to_point(&pubnonces[i][33..])? * b;
+                    if !has_even_y(&nonce) {
+                        expected = -expected;
+                    }
+                    let mut pubkey = to_point(&self.group.pubkeys[i])?;
+                    if !has_even_y(&key) {
+                        pubkey = -pubkey;
+                    }
+                    expected += pubkey * (e * coefficients[i]);
+                    if ProjectivePoint::GENERATOR * partial != expected {
+                        telemetry!(warn, party = i + 1, "invalid partial signature");
+                        return Err(format!("invalid partial signature of party {}", i + 1).into());
+                    }
+                    s += partial;
+                }
+
+                let nonce = match self.adaptor {
+                    Some(_) => cbytes(&nonce),
+                    None => xbytes(&nonce).to_vec(),
+                };
+                let signature = [nonce, s.to_bytes().to_vec()].concat();
+                self.round = SignRound::Done(signature);
+                Ok(pack(Vec::new(), ProtocolType::Musig2))
+            }
+            SignRound::Done(_) => Err("protocol already finished".into()),
+        }
+    }
+}
+
+#[typetag::serde(name = "musig2_sign")]
+impl Protocol for SignContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            SignRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        match self.round {
+            SignRound::Done(signature) => Ok(pack_signature(
+                ProtocolType::Musig2,
+                signature,
+                (1..=self.group.pubkeys.len() as u32).collect(),
+            )),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match self.round {
+            SignRound::R1(..) | SignRound::R2(..) => Some(self.group.pubkeys.len() - 1),
+            _ => None,
+        }
+    }
+}
+
+impl ThresholdProtocol for SignContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            group: parse_group_context(group)?,
+            message: None,
+            adaptor: None,
+            round: SignRound::R0,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Musig2,
+    name: "musig2",
+    curve: "secp256k1",
+    scheme: "bip340",
+    index_base: 1,
+    keygen_rounds: 2,
+    threshold_rounds: Some(3),
+    max_parties: MAX_PARTIES,
+    min_threshold: None,
+    prehashed: false,
+    all_sign: true,
+    resharing: false,
+    keygen: registry::keygen::<KeygenContext>,
+    threshold: registry::threshold::<SignContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::tests::{
+        advance_all, run_rounds, KeygenProtocolTest, ThresholdProtocolTest,
+    };
+    use k256::schnorr;
+    use rand::rngs::OsRng;
+
+    impl KeygenProtocolTest for KeygenContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::Musig2;
+        const ROUNDS: usize = 2;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    impl ThresholdProtocolTest for SignContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::Musig2;
+        const ROUNDS: usize = 3;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    #[test]
+    fn sign() {
+        for parties in 2..5 {
+            let (pks, ctxs) = <KeygenContext as KeygenProtocolTest>::run(parties, parties);
+            let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+            assert!(pks.iter().all(|key| *key == pk));
+
+            let msg = b"hello";
+            let results = <SignContext as ThresholdProtocolTest>::run(
+                ctxs,
+                (0..parties as u16).collect(),
+                msg.to_vec(),
+            );
+            for result in &results {
+                assert_eq!(&results[0], result);
+            }
+            let signature = SignatureResult::decode(&results[0][..]).unwrap().signature;
+            assert!(verify(&pk, msg, &signature).is_ok());
+            assert!(verify(&pk, b"other", &signature).is_err());
+
+            let key = schnorr::VerifyingKey::from_bytes(&pk).unwrap();
+            let signature = schnorr::Signature::try_from(&signature[..]).unwrap();
+            assert!(key.verify_raw(msg, &signature).is_ok());
+        }
+    }
+
+    #[test]
+    fn single_party() {
+        let ctx = <KeygenContext as KeygenProtocolTest>::run_single();
+        let pk = GroupResult::decode(&ctx[..]).unwrap().public_key;
+        let result = <SignContext as ThresholdProtocolTest>::run_single(ctx, b"hello".to_vec());
+        let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+        assert!(verify(&pk, b"hello", &signature).is_ok());
+    }
+
+    #[test]
+    fn bip340() {
+        let key = schnorr::SigningKey::random(&mut OsRng);
+        let pk = key.verifying_key().to_bytes();
+        let signature = key.sign_raw(b"hello", &[0; 32]).unwrap().to_bytes();
+        assert!(verify(&pk, b"hello", &signature).is_ok());
+
+        let mut tampered = signature;
+        tampered[63] ^= 1;
+        assert!(verify(&pk, b"hello", &tampered).is_err());
+    }
+
+    #[test]
+    fn adaptor() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(3, 3);
+        let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        let secret = Scalar::random(&mut OsRng);
+        let point = cbytes(&(ProjectivePoint::GENERATOR * secret));
+
+        let mut ctxs: Vec<SignContext> = ctxs
+            .iter()
+            .map(|ctx| SignContext::new(ctx).unwrap())
+            .collect();
+        let inits = (1..=3)
+            .map(|index| {
+                ProtocolInit {
+                    protocol_type: ProtocolType::Musig2 as i32,
+                    indices: vec![1, 2, 3],
+                    index,
+                    data: b"hello".to_vec(),
+                    adaptor: point.clone(),
+                    ..Default::default()
+                }
+                .encode_to_vec()
+            })
+            .collect();
+        let messages = advance_all(&mut ctxs, inits);
+        run_rounds(&mut ctxs, messages, 2);
+        let result = Box::new(ctxs.remove(0)).finish().unwrap();
+        let presignature = SignatureResult::decode(&result[..]).unwrap().signature;
+
+        assert!(verify_adaptor(&pk, b"hello", &presignature, &point).is_ok());
+        assert!(verify_adaptor(&pk, b"other", &presignature, &point).is_err());
+        assert!(verify(&pk, b"hello", &presignature[1..]).is_err());
+
+        let signature = adapt(&presignature, &secret.to_bytes()).unwrap();
+        assert!(verify(&pk, b"hello", &signature).is_ok());
+        assert_eq!(
+            extract_adaptor(&presignature, &signature).unwrap(),
+            secret.to_bytes().to_vec()
+        );
+
+        let wrong = adapt(&presignature, &Scalar::ONE.to_bytes()).unwrap();
+        assert!(verify(&pk, b"hello", &wrong).is_err());
+    }
+
+    #[test]
+    fn expected_messages() {
+        let mut ctxs: Vec<KeygenContext> = (0..3).map(|_| KeygenContext::new()).collect();
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
+        let inits = (1..=3)
+            .map(|index| {
+                ProtocolGroupInit {
+                    protocol_type: ProtocolType::Musig2 as i32,
+                    index,
+                    parties: 3,
+                    threshold: 3,
+                    ..Default::default()
+                }
+                .encode_to_vec()
+            })
+            .collect();
+        let messages = advance_all(&mut ctxs, inits);
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages() == Some(2)));
+        run_rounds(&mut ctxs, messages, 1);
+        assert!(ctxs.iter().all(|ctx| ctx.expected_messages().is_none()));
+    }
+
+    #[test]
+    fn threshold() {
+        let mut ctx = KeygenContext::new();
+        let init = ProtocolGroupInit {
+            protocol_type: ProtocolType::Musig2 as i32,
+            index: 1,
+            parties: 3,
+            threshold: 2,
+            ..Default::default()
+        };
+        assert!(ctx.advance(&init.encode_to_vec()).is_err());
+    }
+}
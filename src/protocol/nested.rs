@@ -0,0 +1,376 @@
+//! Nested FROST groups, in which a party may itself be a subgroup
+//!
+//! The holder of an outer share splits it among the members of a subgroup,
+//! who then sign on its behalf. A member `j` of the subgroup of the outer
+//! party `p` takes part in signing sessions under the index `p << 8 | j`.
+//!
+//! Signing runs as a single flat FROST session. Each participant rescales
+//! its share (and everyone rescales the verifying shares) so that the
+//! Lagrange coefficients computed by FROST over the flat signer set
+//! yield the nested combination of the outer and the subgroup shares.
+//!
+//! The holder of the outer share deals the shares of its subgroup, so its
+//! members have to trust it: it knows all of their shares, it may keep
+//! signing with the outer share on its own, and the members cannot check
+//! the shares they receive against anything but the verifying shares it
+//! dealt along. The other parties can check by `attach` that the verifying
+//! shares of all the members lie on a single polynomial of the announced
+//! degree whose constant term is the verifying share of the outer party.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+use crate::protocol::blind::secp256k1::{
+    from_point, from_scalar, lagrange, lagrange_at, to_point, to_scalar,
+};
+use crate::protocol::frost::SignContext as FrostSignContext;
+use crate::protocol::*;
+
+use frost::keys::{KeyPackage, PublicKeyPackage, VerifyingShare};
+use frost::Identifier;
+use frost_secp256k1 as frost;
+use k256::elliptic_curve::Field;
+use k256::{ProjectivePoint, Scalar};
+use prost::Message;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct NestedGroup {
+    index: u16,
+    key: KeyPackage,
+    pubkey: PublicKeyPackage,
+    /// Verifying shares of the members of each known subgroup
+    subgroups: BTreeMap<u16, BTreeMap<u16, VerifyingShare>>,
+}
+
+fn outer_index(index: u16) -> u16 {
+    if index > 0xff {
+        index >> 8
+    } else {
+        index
+    }
+}
+
+impl NestedGroup {
+    fn from_slice(group: &[u8]) -> Result<Self> {
+        let group = group_context(group)?;
+        if let Ok(group) = serde_json::from_slice(&group) {
+            return Ok(group);
+        }
+
+        let (key, pubkey): (KeyPackage, PublicKeyPackage) = serde_json::from_slice(&group)?;
+        let index = (1..=0xff)
+            .find(|i| Identifier::try_from(*i).ok().as_ref() == Some(key.identifier()))
+            .ok_or("unsupported party index")?;
+        Ok(Self {
+            index,
+            key,
+            pubkey,
+            subgroups: BTreeMap::new(),
+        })
+    }
+
+    fn verifying_share(&self, index: u16) -> Result<&VerifyingShare> {
+        let share = if index > 0xff {
+            self.subgroups
+                .get(&outer_index(index))
+                .and_then(|members| members.get(&(index & 0xff)))
+        } else {
+            self.pubkey
+                .signer_pubkeys()
+                .get(&Identifier::try_from(index)?)
+        };
+        share.ok_or_else(|| format!("unknown party {}", index).into())
+    }
+
+    /// Rescale the shares so that a flat FROST session over `indices`
+    /// produces the nested combination
+    fn effective(&self, indices: &[u16]) -> Result<(KeyPackage, PublicKeyPackage)> {
+        let mut outer: Vec<u16> = indices.iter().map(|i| outer_index(*i)).collect();
+        outer.sort_unstable();
+        outer.dedup();
+        if indices
+            .iter()
+            .any(|i| *i <= 0xff && indices.iter().any(|m| *m > 0xff && outer_index(*m) == *i))
+        {
+            return Err("party participates both directly and through its subgroup".into());
+        }
+
+        let mut pubkeys = HashMap::new();
+        let mut own = None;
+        for index in indices {
+            let mut coefficient = lagrange(outer_index(*index), &outer)?;
+            if *index > 0xff {
+                let members: Vec<u16> = indices
+                    .iter()
+                    .filter(|m| **m > 0xff && outer_index(**m) == outer_index(*index))
+                    .map(|m| m & 0xff)
+                    .collect();
+                coefficient *= lagrange(index & 0xff, &members)?;
+            }
+            let flat = Option::<Scalar>::from(lagrange(*index, indices)?.invert())
+                .ok_or("invalid index")?;
+            coefficient *= flat;
+
+            let share = from_point(to_point(self.verifying_share(*index)?)? * coefficient)?;
+            pubkeys.insert(Identifier::try_from(*index)?, share);
+            if *index == self.index {
+                own = Some(coefficient);
+            }
+        }
+
+        let coefficient = own.ok_or("participant index not included")?;
+        let identifier = Identifier::try_from(self.index)?;
+        let secret = from_scalar(to_scalar(self.key.secret_share())? * coefficient)?;
+        let key = KeyPackage::new(
+            identifier,
+            secret,
+            pubkeys[&identifier],
+            *self.pubkey.group_public(),
+        );
+        Ok((
+            key,
+            PublicKeyPackage::new(pubkeys, *self.pubkey.group_public()),
+        ))
+    }
+}
+
+/// Split the share of a FROST group context among a `threshold`-of-`parties`
+/// subgroup, returning the contexts of the subgroup members and the public
+/// information to be attached to the contexts of the other parties
+pub fn split(group: &[u8], threshold: u16, parties: u16) -> Result<(Vec<Vec<u8>>, Vec<u8>)> {
+    let group = NestedGroup::from_slice(group)?;
+    if group.index > 0xff {
+        return Err("subgroup members cannot split their share".into());
+    }
+    if threshold == 0 || threshold > parties || parties > 0xff {
+        return Err("invalid subgroup parameters".into());
+    }
+
+    let coefficients: Vec<Scalar> = std::iter::once(to_scalar(group.key.secret_share())?)
+        .chain((1..threshold).map(|_| Scalar::random(&mut OsRng)))
+        .collect();
+    let shares: Vec<(u16, Scalar)> = (1..=parties)
+        .map(|j| {
+            let x = Scalar::from(j as u64);
+            let share = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |acc, c| acc * x + c);
+            (j, share)
+        })
+        .collect();
+    let members = shares
+        .iter()
+        .map(|(j, share)| Ok((*j, from_point(ProjectivePoint::GENERATOR * share)?)))
+        .collect::<Result<BTreeMap<u16, VerifyingShare>>>()?;
+
+    let mut subgroups = group.subgroups.clone();
+    subgroups.insert(group.index, members.clone());
+
+    let ctxs = shares
+        .iter()
+        .map(|(j, share)| {
+            let index = group.index << 8 | j;
+            let key = KeyPackage::new(
+                Identifier::try_from(index)?,
+                from_scalar(*share)?,
+                members[j],
+                *group.pubkey.group_public(),
+            );
+            Ok(serde_json::to_vec(&NestedGroup {
+                index,
+                key,
+                pubkey: group.pubkey.clone(),
+                subgroups: subgroups.clone(),
+            })?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let public = serde_json::to_vec(&(group.index, threshold, members))?;
+    Ok((ctxs, public))
+}
+
+/// Interpolate the verifying shares of the `quorum` at `point`
+fn interpolate(
+    members: &BTreeMap<u16, VerifyingShare>,
+    quorum: &[u16],
+    point: u16,
+) -> Result<VerifyingShare> {
+    let xs: Vec<Scalar> = quorum.iter().map(|j| Scalar::from(*j as u64)).collect();
+    let at = Scalar::from(point as u64);
+    let mut combined = ProjectivePoint::IDENTITY;
+    for (j, x) in quorum.iter().zip(&xs) {
+        combined += to_point(&members[j])? * lagrange_at(at, *x, &xs)?;
+    }
+    from_point(combined)
+}
+
+/// Attach the public information about a subgroup produced by `split`
+/// to a group context, checking it is consistent with the outer share:
+/// the verifying shares of all the members must be the evaluations of
+/// a polynomial of degree `threshold - 1` whose constant term is the
+/// verifying share of the outer party
+pub fn attach(group: &[u8], public: &[u8]) -> Result<Vec<u8>> {
+    let mut group = NestedGroup::from_slice(group)?;
+    let (index, threshold, members): (u16, u16, BTreeMap<u16, VerifyingShare>) =
+        serde_json::from_slice(public)?;
+
+    if members.keys().any(|j| *j == 0 || *j > 0xff) {
+        return Err("invalid subgroup member index".into());
+    }
+    let quorum: Vec<u16> = members.keys().take(threshold as usize).cloned().collect();
+    if threshold == 0 || quorum.len() != threshold as usize {
+        return Err("not enough subgroup members".into());
+    }
+    if interpolate(&members, &quorum, 0)? != *group.verifying_share(index)? {
+        return Err("subgroup does not match the outer share".into());
+    }
+    for (j, share) in members.iter().skip(threshold as usize) {
+        if interpolate(&members, &quorum, *j)? != *share {
+            return Err(format!("inconsistent share of subgroup member {}", j).into());
+        }
+    }
+
+    group.subgroups.insert(index, members);
+    Ok(serde_json::to_vec(&group)?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SignContext {
+    group: NestedGroup,
+    inner: Option<FrostSignContext>,
+}
+
+impl SignContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::Frost.check(msg.protocol_type)?;
+
+        let indices: Vec<u16> = msg.indices.iter().map(|i| *i as u16).collect();
+        let effective = self.group.effective(&indices)?;
+
+        let mut inner = FrostSignContext::new(&serde_json::to_vec(&effective)?)?;
+        let out = inner.advance(data)?;
+        self.inner = Some(inner);
+        Ok(out)
+    }
+}
+
+#[typetag::serde(name = "nested_sign")]
+impl Protocol for SignContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.advance(data),
+            None => self.init(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        Box::new(self.inner.ok_or("protocol not finished")?).finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.as_ref()?.expected_messages()
+    }
+}
+
+impl ThresholdProtocol for SignContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            group: NestedGroup::from_slice(group)?,
+            inner: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::frost::{verify, KeygenContext};
+    use crate::protocol::tests::{advance_unpacked, run_rounds, KeygenProtocolTest};
+
+    fn sign(ctxs: Vec<&[u8]>, indices: Vec<u16>, msg: &[u8]) -> Vec<Vec<u8>> {
+        let mut ctxs: Vec<SignContext> = ctxs
+            .into_iter()
+            .map(|ctx| SignContext::new(ctx).unwrap())
+            .collect();
+        let messages = ctxs
+            .iter_mut()
+            .zip(&indices)
+            .map(|(ctx, index)| {
+                advance_unpacked(
+                    ctx,
+                    &(ProtocolInit {
+                        protocol_type: ProtocolType::Frost as i32,
+                        indices: indices.iter().map(|i| *i as u32).collect(),
+                        index: *index as u32,
+                        data: msg.to_vec(),
+                        ..Default::default()
+                    })
+                    .encode_to_vec(),
+                )
+            })
+            .collect();
+        run_rounds(&mut ctxs, messages, 2);
+
+        ctxs.into_iter()
+            .map(|ctx| Box::new(ctx).finish().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn nested_sign() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let pk = GroupResult::decode(&ctxs[0][..]).unwrap().public_key;
+        let msg = b"hello";
+
+        let (members, public) = split(&ctxs[2], 3, 5).unwrap();
+        let first = attach(&ctxs[0], &public).unwrap();
+
+        let indices = vec![1, 3 << 8 | 1, 3 << 8 | 2, 3 << 8 | 4];
+        let results = sign(
+            vec![
+                &first[..],
+                &members[0][..],
+                &members[1][..],
+                &members[3][..],
+            ],
+            indices,
+            msg,
+        );
+        for result in results {
+            let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+            assert!(verify(&pk, msg, &signature).is_ok());
+        }
+    }
+
+    #[test]
+    fn attach_mismatch() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let (_, public) = split(&ctxs[2], 2, 3).unwrap();
+        let (index, threshold, members): (u16, u16, BTreeMap<u16, VerifyingShare>) =
+            serde_json::from_slice(&public).unwrap();
+        let forged = serde_json::to_vec(&(index - 1, threshold, &members)).unwrap();
+        assert!(attach(&ctxs[0], &forged).is_err());
+
+        // the quorum matches the outer share, the last member does not
+        let mut members = members;
+        let first = members[&1];
+        members.insert(3, first);
+        let forged = serde_json::to_vec(&(index, threshold, &members)).unwrap();
+        let error = attach(&ctxs[0], &forged).unwrap_err();
+        assert_eq!(error.to_string(), "inconsistent share of subgroup member 3");
+
+        // the shares of a quorum interpolating to the identity
+        let point = to_point(&members[&1]).unwrap();
+        let members = BTreeMap::from([
+            (1, from_point(point).unwrap()),
+            (2, from_point(point + point).unwrap()),
+        ]);
+        let forged = serde_json::to_vec(&(index, 2u16, &members)).unwrap();
+        assert!(attach(&ctxs[0], &forged).is_err());
+    }
+}
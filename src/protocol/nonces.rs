@@ -0,0 +1,151 @@
+//! Registry of the signing sessions whose nonces were used
+//!
+//! The nonces of a signing session must never produce two signature
+//! shares, yet a context holding them may be copied, e.g. when a client
+//! restores the same checkpoint twice or runs concurrent sessions from
+//! copies of one state. Each session is identified by the digest of the
+//! commitments to its nonces, and a share is produced only if the session
+//! was not consumed before within this process.
+//!
+//! A client whose upload of a share failed may advance the state it
+//! persisted before once more. The share is then computed for the same
+//! signing package, which reveals nothing new, so the session is consumed
+//! again if it signs what it signed the first time.
+//!
+//! The registry lives in the memory of the process, so a client persisting
+//! its contexts persists the registry alongside them by `export_consumed`
+//! and restores it by `import_consumed` on the next start. It keeps the
+//! latest `MAX_CONSUMED` sessions, evicting the oldest ones; a context
+//! restored from a checkpoint older than that many signatures may thus
+//! sign once more, which the client prevents by discarding such old states.
+
+use crate::protocol::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+/// Number of the latest consumed sessions the registry keeps
+const MAX_CONSUMED: usize = 4096;
+
+struct Registry {
+    /// Digest of what each consumed session signed
+    signed: BTreeMap<[u8; 32], [u8; 32]>,
+    /// Sessions in the order they were consumed, oldest first
+    order: VecDeque<[u8; 32]>,
+}
+
+impl Registry {
+    /// Record the session as consumed to sign `signed`, failing if it
+    /// was consumed to sign something else
+    fn insert(&mut self, session: [u8; 32], signed: [u8; 32]) -> Result<()> {
+        match self.signed.get(&session) {
+            Some(previous) if *previous == signed => return Ok(()),
+            Some(_) => return Err("nonces of the session already used".into()),
+            None => {}
+        }
+        if self.order.len() == MAX_CONSUMED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.signed.remove(&oldest);
+            }
+        }
+        self.signed.insert(session, signed);
+        self.order.push_back(session);
+        Ok(())
+    }
+}
+
+static CONSUMED: Mutex<Registry> = Mutex::new(Registry {
+    signed: BTreeMap::new(),
+    order: VecDeque::new(),
+});
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    CONSUMED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Identifier of the session which committed to its nonces by `commitments`
+pub(crate) fn session_id<T: Serialize>(commitments: &T) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"meesign nonces");
+    hasher.update(serde_json::to_vec(commitments)?);
+    Ok(hasher.finalize().into())
+}
+
+/// Mark the nonces of a session as used to sign `signed`, failing if
+/// they were used before to sign anything else
+pub(crate) fn consume<T: Serialize>(session: [u8; 32], signed: &T) -> Result<()> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"meesign signed");
+    hasher.update(serde_json::to_vec(signed)?);
+    registry().insert(session, hasher.finalize().into())
+}
+
+/// Serialize the registry of the consumed sessions, to be persisted
+/// along with the contexts of the client
+pub fn export_consumed() -> Result<Vec<u8>> {
+    let registry = registry();
+    let entries: Vec<([u8; 32], [u8; 32])> = registry
+        .order
+        .iter()
+        .map(|session| (*session, registry.signed[session]))
+        .collect();
+    Ok(serde_json::to_vec(&entries)?)
+}
+
+/// Add the sessions of a registry serialized by `export_consumed` to the
+/// registry of the process, failing if a session of both signed different
+/// data, in which case the nonces of the session have been used twice
+pub fn import_consumed(data: &[u8]) -> Result<()> {
+    let entries: Vec<([u8; 32], [u8; 32])> = serde_json::from_slice(data)?;
+    let mut registry = registry();
+    for (session, signed) in entries {
+        registry.insert(session, signed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_use() {
+        let session = session_id(&"commitments").unwrap();
+        assert_ne!(session, session_id(&"other commitments").unwrap());
+        assert!(consume(session, &"package").is_ok());
+        assert!(consume(session, &"other package").is_err());
+        // a retry signs the same package again
+        assert!(consume(session, &"package").is_ok());
+    }
+
+    #[test]
+    fn persistence() {
+        let session = session_id(&"persisted commitments").unwrap();
+        consume(session, &"package").unwrap();
+        let exported = export_consumed().unwrap();
+
+        // a restarted process knows the session once the registry is imported
+        registry().signed.remove(&session);
+        registry().order.retain(|s| *s != session);
+        assert!(consume(session, &"other package").is_ok());
+        assert!(import_consumed(&exported).is_err());
+        assert!(import_consumed(b"not a registry").is_err());
+    }
+
+    #[test]
+    fn eviction() {
+        let mut registry = Registry {
+            signed: BTreeMap::new(),
+            order: VecDeque::new(),
+        };
+        for i in 0..=MAX_CONSUMED {
+            let mut session = [0; 32];
+            session[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            registry.insert(session, [1; 32]).unwrap();
+        }
+        assert_eq!(registry.signed.len(), MAX_CONSUMED);
+        assert_eq!(registry.order.len(), MAX_CONSUMED);
+        assert!(!registry.signed.contains_key(&[0; 32]));
+    }
+}
@@ -0,0 +1,169 @@
+//! Pairing of the relayed messages with the parties of a session
+//!
+//! The server may name the sender of each message it relays, and the
+//! messages are then put in the order of their senders, see `codec`. Most
+//! contexts take the messages in the order of the other parties of the
+//! session, which they learn from their initialization message, as FROST
+//! pairs them by `unpack_from`. The wrapper learns the other parties the
+//! same way and rejects, for the contexts of every protocol, the messages
+//! named after anyone else, so that a message is never attributed to
+//! a party which does not take part in the session.
+
+use crate::proto::{ProtocolGroupInit, ProtocolInit, ProtocolType};
+use crate::protocol::{index_base, with_senders, DebugState, Protocol, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PairingContext {
+    inner: Box<dyn Protocol>,
+    /// Whether the context is initialized by a `ProtocolGroupInit`
+    /// rather than a `ProtocolInit`
+    keygen: bool,
+    /// Indices of the other parties of the session, once initialized
+    others: Option<Vec<u32>>,
+}
+
+impl PairingContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>, keygen: bool) -> Self {
+        Self {
+            inner,
+            keygen,
+            others: None,
+        }
+    }
+
+    /// Indices of the other parties named by the initialization message
+    fn parse_others(&self, data: &[u8]) -> Result<Vec<u32>> {
+        let (indices, index) = if self.keygen {
+            let msg = ProtocolGroupInit::decode(data)?;
+            let base = index_base(ProtocolType::try_from(msg.protocol_type)?);
+            let end = base.checked_add(msg.parties).ok_or("too many parties")?;
+            ((base..end).collect::<Vec<_>>(), msg.index)
+        } else {
+            let msg = ProtocolInit::decode(data)?;
+            (msg.indices, msg.index)
+        };
+        let mut others: Vec<u32> = indices.into_iter().filter(|i| *i != index).collect();
+        others.sort_unstable();
+        Ok(others)
+    }
+}
+
+#[typetag::serde(name = "pairing")]
+impl Protocol for PairingContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.others.is_none() {
+            // the initialization message comes from the server unpaired
+            let out = self.inner.advance(data)?;
+            self.others = Some(self.parse_others(data)?);
+            return Ok(out);
+        }
+        let inner = &mut self.inner;
+        with_senders(self.others.as_deref(), || inner.advance(data))
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.others.clone()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("pairing")
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolMessage};
+    use crate::protocol::{keygen_context, pack, unpack};
+
+    fn init(index: u32) -> Vec<u8> {
+        ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index,
+            parties: 3,
+            threshold: 2,
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    /// Messages for the party `idx` from the outputs of the others,
+    /// named after the given `senders` in the reverse order
+    fn relay(outputs: &[Vec<u8>], idx: usize, senders: &[u32]) -> Vec<u8> {
+        let mut msgs: Vec<_> = outputs
+            .iter()
+            .enumerate()
+            .filter(|(sender, _)| *sender != idx)
+            .map(|(sender, out)| {
+                unpack(out).unwrap()[if idx < sender { idx } else { idx - 1 }].clone()
+            })
+            .collect();
+        msgs.reverse();
+        let mut msg = ProtocolMessage::decode(&pack(msgs, ProtocolType::Frost)[..]).unwrap();
+        msg.senders = senders.iter().rev().cloned().collect();
+        msg.encode_to_vec()
+    }
+
+    #[test]
+    fn named_senders() {
+        let mut ctxs: Vec<Box<dyn Protocol>> = (0..3)
+            .map(|_| keygen_context(ProtocolType::Frost).unwrap())
+            .collect();
+        let mut outputs: Vec<Vec<u8>> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| ctx.advance(&init(i as u32 + 1)).unwrap())
+            .collect();
+
+        // a party outside of the session
+        assert!(ctxs[0].advance(&relay(&outputs, 0, &[2, 4])).is_err());
+
+        for _ in 0..2 {
+            outputs = ctxs
+                .iter_mut()
+                .enumerate()
+                .map(|(idx, ctx)| {
+                    let senders: Vec<u32> = (1..=3).filter(|i| *i != idx as u32 + 1).collect();
+                    ctx.advance(&relay(&outputs, idx, &senders)).unwrap()
+                })
+                .collect();
+        }
+
+        let pks: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| {
+                GroupResult::decode(&ctx.finish().unwrap()[..])
+                    .unwrap()
+                    .public_key
+            })
+            .collect();
+        assert!(pks.iter().all(|pk| *pk == pks[0]));
+    }
+}
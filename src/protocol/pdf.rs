@@ -0,0 +1,199 @@
+//! PAdES signing of PDF documents by a GG18 group
+//!
+//! The document has to contain a signature dictionary with placeholders
+//! for the signed byte range and the signature contents, as produced by
+//! PDF libraries for external signing:
+//!
+//! ```text
+//! /ByteRange [0 0000000000 0000000000 0000000000] /Contents <0000...0000>
+//! ```
+//!
+//! `prepare` fills in the byte range, the signing session is initialized
+//! with the digest returned by `signature_digest`, and `embed` places the CMS
+//! signature built from the session result into the contents placeholder.
+//! With `embed_timestamped`, the signature carries a timestamp obtained for
+//! the request from `cms::timestamp_request`.
+
+use crate::protocol::{cms, Result};
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+
+struct Placeholder {
+    byte_range: Range<usize>,
+    contents: Range<usize>,
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+/// Locate the placeholders of the last signature dictionary, the byte range
+/// including its brackets and the contents including its angle brackets
+fn placeholder(pdf: &[u8]) -> Result<Placeholder> {
+    let key = rfind(pdf, b"/ByteRange").ok_or("missing signature placeholder")?;
+    let start = key + find(&pdf[key..], b"[").ok_or("malformed byte range")?;
+    let end = start + find(&pdf[start..], b"]").ok_or("malformed byte range")? + 1;
+    let byte_range = start..end;
+
+    // the dictionary may list the contents either before or after the range
+    let contents = [
+        rfind(&pdf[..key], b"/Contents"),
+        find(&pdf[end..], b"/Contents").map(|i| end + i),
+    ]
+    .iter()
+    .flatten()
+    .filter_map(|&i| {
+        let value = i + b"/Contents".len();
+        let open = value + pdf[value..].iter().position(|b| !b.is_ascii_whitespace())?;
+        if pdf[open] != b'<' {
+            return None;
+        }
+        let close = open + find(&pdf[open..], b">")?;
+        Some(open..close + 1)
+    })
+    .min_by_key(|contents| contents.start.max(key) - contents.start.min(key))
+    .ok_or("missing signature placeholder")?;
+
+    if contents.start < byte_range.end && byte_range.start < contents.end {
+        return Err("malformed signature placeholder".into());
+    }
+    Ok(Placeholder {
+        byte_range,
+        contents,
+    })
+}
+
+/// Fill in the byte range of the signature placeholder, which covers
+/// the whole document except for the signature contents
+pub fn prepare(pdf: &[u8]) -> Result<Vec<u8>> {
+    let Placeholder {
+        byte_range,
+        contents,
+    } = placeholder(pdf)?;
+
+    let mut value = format!(
+        "[0 {} {} {}]",
+        contents.start,
+        contents.end,
+        pdf.len() - contents.end
+    )
+    .into_bytes();
+    if value.len() > byte_range.len() {
+        return Err("byte range placeholder is too short".into());
+    }
+    value.resize(byte_range.len(), b' ');
+
+    let mut prepared = pdf.to_vec();
+    prepared[byte_range].copy_from_slice(&value);
+    Ok(prepared)
+}
+
+/// Compute the digest of the signed byte range of a prepared document
+fn content_digest(pdf: &[u8]) -> Result<Vec<u8>> {
+    let Placeholder { contents, .. } = placeholder(pdf)?;
+    let mut hasher = Sha256::new();
+    hasher.update(&pdf[..contents.start]);
+    hasher.update(&pdf[contents.end..]);
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Compute the digest to be signed by the group holding the key
+/// of `certificate` for a document processed by `prepare`
+pub fn signature_digest(pdf: &[u8], certificate: &[u8]) -> Result<Vec<u8>> {
+    Ok(cms::signature_digest(&content_digest(pdf)?, certificate))
+}
+
+fn fill_contents(pdf: &[u8], signed_data: &[u8]) -> Result<Vec<u8>> {
+    let Placeholder { contents, .. } = placeholder(pdf)?;
+    let mut value: Vec<u8> = signed_data
+        .iter()
+        .flat_map(|b| format!("{:02X}", b).into_bytes())
+        .collect();
+    if value.len() + 2 > contents.len() {
+        return Err("contents placeholder is too short".into());
+    }
+    value.resize(contents.len() - 2, b'0');
+
+    let mut signed = pdf.to_vec();
+    signed[contents.start + 1..contents.end - 1].copy_from_slice(&value);
+    Ok(signed)
+}
+
+/// Embed the signature produced by the group into the prepared document
+pub fn embed(pdf: &[u8], certificate: &[u8], signature: &[u8]) -> Result<Vec<u8>> {
+    let signed_data = cms::detached(&content_digest(pdf)?, certificate, signature)?;
+    fill_contents(pdf, &signed_data)
+}
+
+/// Embed the signature like `embed`, together with the timestamp from
+/// the `response` of the timestamp authority
+pub fn embed_timestamped(
+    pdf: &[u8],
+    certificate: &[u8],
+    signature: &[u8],
+    response: &[u8],
+) -> Result<Vec<u8>> {
+    let signed_data = cms::timestamped(&content_digest(pdf)?, certificate, signature, response)?;
+    fill_contents(pdf, &signed_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> Vec<u8> {
+        let mut pdf = b"%PDF-1.7\n1 0 obj\n<< /Type /Sig /Filter /Adobe.PPKLite \
+                        /SubFilter /ETSI.CAdES.detached /ByteRange [0 0000000000 \
+                        0000000000 0000000000] /Contents <"
+            .to_vec();
+        pdf.extend(vec![b'0'; 8192]);
+        pdf.extend(b"> >>\nendobj\n%%EOF\n");
+        pdf
+    }
+
+    #[test]
+    fn byte_range() {
+        let pdf = prepare(&document()).unwrap();
+        assert_eq!(pdf.len(), document().len());
+
+        let Placeholder {
+            byte_range,
+            contents,
+        } = placeholder(&pdf).unwrap();
+        let value = String::from_utf8(pdf[byte_range].to_vec()).unwrap();
+        let expected = format!(
+            "[0 {} {} {}]",
+            contents.start,
+            contents.end,
+            pdf.len() - contents.end
+        );
+        assert_eq!(value.trim_end(), expected);
+
+        let mut other = pdf.clone();
+        other[contents.start + 1] = b'1';
+        assert_eq!(
+            content_digest(&pdf).unwrap(),
+            content_digest(&other).unwrap()
+        );
+        other[0] = b'!';
+        assert_ne!(
+            content_digest(&pdf).unwrap(),
+            content_digest(&other).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_placeholder() {
+        assert!(prepare(b"%PDF-1.7\n%%EOF\n").is_err());
+        assert!(prepare(b"/ByteRange [0 0 0 0] /Contents 5 0 R").is_err());
+        assert!(prepare(b"/ByteRange [] /Contents <00>").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Policy deciding which sessions this party signs in
+//!
+//! Deployments such as smart cards or enclaves may restrict what their
+//! keys sign, e.g. only hashes on an approved allowlist. Every signing
+//! context consults the policy set by `set_policy` when it is initialized,
+//! before it commits to any nonce or produces any share, and refuses the
+//! session unless the policy approves it.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+use crate::protocol::{one_based, Result};
+use std::sync::RwLock;
+
+/// Signing session submitted to the policy
+pub struct SigningRequest<'a> {
+    pub protocol_type: ProtocolType,
+    /// Data to be signed, empty in blind FROST sessions
+    pub message: &'a [u8],
+    /// Indices of the signers counted from one, whatever the index base
+    /// of the protocol
+    pub signers: &'a [u32],
+    /// Whether the signers sign a challenge blinded by the requester,
+    /// which the policy cannot tie to `message`, see the `blind` module
+    pub blind: bool,
+    /// Adaptor point of a pre-signature, empty for a signature
+    pub adaptor: &'a [u8],
+    /// Index of the only party aggregating the signature counted from one,
+    /// or zero if all of them do
+    pub coordinator: u32,
+}
+
+pub type Policy = Box<dyn Fn(&SigningRequest) -> bool + Send + Sync>;
+
+static POLICY: RwLock<Option<Policy>> = RwLock::new(None);
+
+/// Set the policy approving signing sessions, or remove it with `None`
+/// to sign in any session
+pub fn set_policy(policy: Option<Policy>) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Fail unless the policy approves the session initialized by `msg`
+pub(crate) fn check(protocol_type: ProtocolType, msg: &ProtocolInit) -> Result<()> {
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner());
+    let signers = one_based(protocol_type, &msg.indices);
+    let coordinator = match msg.coordinator {
+        0 => 0,
+        index => one_based(protocol_type, &[index])[0],
+    };
+    let approved = policy.as_ref().map_or(true, |policy| {
+        policy(&SigningRequest {
+            protocol_type,
+            message: &msg.data,
+            signers: &signers,
+            blind: msg.blind,
+            adaptor: &msg.adaptor,
+            coordinator,
+        })
+    });
+    if !approved {
+        return Err("signing refused by policy".into());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::ProtocolInit;
+    use crate::protocol::frost::{KeygenContext, SignContext};
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+    use crate::protocol::{Protocol, ThresholdProtocol};
+    use prost::Message;
+
+    #[test]
+    fn allowlist() {
+        let (_, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        set_policy(Some(Box::new(|request| {
+            request.protocol_type != ProtocolType::Frost
+                || request.message != b"denied"
+                    && (request.message != b"flags" || request.coordinator != 2)
+        })));
+
+        let mut ctx = SignContext::new(&ctxs[0]).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Frost as i32,
+            indices: vec![1, 2],
+            index: 1,
+            data: b"denied".to_vec(),
+            ..Default::default()
+        };
+        let error = ctx.advance(&init.encode_to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "signing refused by policy");
+
+        let flags = |coordinator| {
+            ProtocolInit {
+                protocol_type: ProtocolType::Frost as i32,
+                indices: vec![1, 2],
+                index: 1,
+                data: b"flags".to_vec(),
+                coordinator,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        assert!(SignContext::new(&ctxs[0])
+            .unwrap()
+            .advance(&flags(2))
+            .is_err());
+        assert!(SignContext::new(&ctxs[0])
+            .unwrap()
+            .advance(&flags(0))
+            .is_ok());
+        assert!(SignContext::new(&ctxs[0])
+            .unwrap()
+            .advance(&flags(1))
+            .is_ok());
+
+        let results = <SignContext as ThresholdProtocolTest>::run(ctxs, vec![0, 1], b"hi".to_vec());
+        assert_eq!(results.len(), 2);
+        set_policy(None);
+    }
+}
@@ -0,0 +1,350 @@
+//! Threshold password-protected secret sharing (PPSS)
+//!
+//! An ElGamal group doubles as a threshold OPRF: each party raises a blinded
+//! hash of the password to its share of the group secret. The client unblinds
+//! the combined result into a hardened password key, which seals the user
+//! secret. Recovering the secret requires both the password and a quorum.
+//!
+//! Each evaluation comes with a proof that it was raised to the same share
+//! as the public key share of its party, which the other parties check
+//! before combining the evaluations, so that a faulty party is blamed
+//! instead of the client deriving a wrong key.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+use crate::protocol::elgamal::lagrange_coefficient;
+use crate::protocol::proofs::{self, decode_point, random_scalar, Proof};
+use crate::protocol::telemetry::telemetry;
+use crate::protocol::*;
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT, ristretto::RistrettoPoint, scalar::Scalar,
+    traits::Identity,
+};
+use elastic_elgamal::{group::Ristretto, sharing::ActiveParticipant};
+use rand::rngs::OsRng;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit},
+    Aes256Gcm,
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+const EVALUATION_DOMAIN: &[u8] = b"meesign ppss evaluation";
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RecoverContext {
+    ctx: ActiveParticipant<Ristretto>,
+    indices: Vec<u16>,
+    /// Blinded element of the client, which the proofs are checked against
+    #[serde(default)]
+    blinded: Vec<u8>,
+    shares: Vec<(u16, Vec<u8>)>,
+    result: Option<Vec<u8>>,
+}
+
+impl RecoverContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+
+        ProtocolType::Ppss.check(msg.protocol_type)?;
+
+        self.indices = msg.indices.into_iter().map(|i| i as u16).collect();
+        if !self.indices.contains(&(self.ctx.index() as u16)) {
+            return Err("participant index not included".into());
+        }
+        let blinded = decode_point(&msg.data)?;
+
+        let secret = *self.ctx.secret_share().expose_scalar();
+        let share = secret * blinded;
+        let proof = proofs::prove(
+            EVALUATION_DOMAIN,
+            b"",
+            &[vec![RISTRETTO_BASEPOINT_POINT], vec![blinded]],
+            &[secret * RISTRETTO_BASEPOINT_POINT, share],
+            &[secret],
+        );
+        let share = share.compress().to_bytes().to_vec();
+        let ser = serialize_bcast(&(&share, &proof), self.indices.len() - 1)?;
+        self.blinded = msg.data;
+        self.shares.push((self.ctx.index() as u16, share));
+
+        if self.indices.len() == 1 {
+            return run_locally(1, ProtocolType::Ppss, |data| self.update(data));
+        }
+        Ok(pack(ser, ProtocolType::Ppss))
+    }
+
+    /// Check the proof of the evaluation of the party `index`
+    fn verify(&self, index: u16, share: &[u8], proof: &Proof) -> Result<()> {
+        let key = self
+            .ctx
+            .key_set()
+            .participant_key(index as usize)
+            .ok_or("participant index not included")?
+            .as_element();
+        proofs::verify(
+            EVALUATION_DOMAIN,
+            b"",
+            &[
+                vec![RISTRETTO_BASEPOINT_POINT],
+                vec![decode_point(&self.blinded)?],
+            ],
+            &[key, decode_point(share)?],
+            proof,
+        )
+        .map_err(|_| {
+            telemetry!(warn, party = index, "invalid evaluation");
+            format!("invalid evaluation from party {}", index).into()
+        })
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.shares.is_empty() {
+            return Err("protocol not initialized".into());
+        }
+        if self.result.is_some() {
+            return Err("protocol already finished".into());
+        }
+
+        let msgs = unpack(data)?;
+        check_message_count(&msgs, self.indices.len() - 1)?;
+        let evaluations: Vec<(Vec<u8>, Proof)> = deserialize_vec(&msgs)?;
+        let local_index = self
+            .indices
+            .iter()
+            .position(|x| *x as usize == self.ctx.index())
+            .ok_or("participant index not included")?;
+
+        let mut shares = self.shares.clone();
+        for (mut i, (share, proof)) in evaluations.into_iter().enumerate() {
+            if i >= local_index {
+                i += 1;
+            }
+            let index = self.indices[i];
+            self.verify(index, &share, &proof)?;
+            shares.push((index, share));
+        }
+
+        let result = combine(&shares)?;
+        self.shares = shares;
+        self.result = Some(result.clone());
+
+        let ser = inflate(result, self.indices.len() - 1);
+        Ok(pack(ser, ProtocolType::Ppss))
+    }
+}
+
+#[typetag::serde(name = "ppss_recover")]
+impl Protocol for RecoverContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = if self.shares.is_empty() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }?;
+        Ok(data)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.result.ok_or_else(|| "protocol not finished".into())
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.shares.is_empty() || self.result.is_some() {
+            return None;
+        }
+        Some(self.indices.len() - 1)
+    }
+}
+
+impl ThresholdProtocol for RecoverContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            ctx: parse_group_context(group)?,
+            indices: Vec::new(),
+            blinded: Vec::new(),
+            shares: Vec::new(),
+            result: None,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Ppss,
+    name: "ppss",
+    curve: "ristretto255",
+    scheme: "oprf",
+    index_base: 0,
+    keygen_rounds: 4,
+    threshold_rounds: Some(2),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<elgamal::KeygenContext>,
+    threshold: registry::threshold::<RecoverContext>,
+}];
+
+/// Interpolate the evaluations in the exponent at zero
+fn combine(shares: &[(u16, Vec<u8>)]) -> Result<Vec<u8>> {
+    let indices: Vec<u16> = shares.iter().map(|(i, _)| *i).collect();
+
+    let mut result = RistrettoPoint::identity();
+    for (index, share) in shares {
+        result += lagrange_coefficient(*index, &indices)? * decode_point(share)?;
+    }
+    Ok(result.compress().to_bytes().to_vec())
+}
+
+fn hash_password(password: &[u8]) -> RistrettoPoint {
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(
+        &Sha512::new_with_prefix(b"meesign ppss")
+            .chain_update(password)
+            .finalize(),
+    );
+    RistrettoPoint::from_uniform_bytes(&bytes)
+}
+
+/// Blind the password, returning the blinding factor to keep
+/// and the element to be sent to the group as `ProtocolInit` data
+pub fn blind(password: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let r = random_scalar();
+    let blinded = r * hash_password(password);
+    (
+        r.to_bytes().to_vec(),
+        blinded.compress().to_bytes().to_vec(),
+    )
+}
+
+/// Unblind the group's evaluation and derive the password key
+pub fn finalize(password: &[u8], blind: &[u8], evaluated: &[u8]) -> Result<Vec<u8>> {
+    let mut r = [0u8; 32];
+    if blind.len() != r.len() {
+        return Err("invalid blinding factor length".into());
+    }
+    r.copy_from_slice(blind);
+    let r = Scalar::from_canonical_bytes(r).ok_or("invalid blinding factor")?;
+    if r == Scalar::zero() {
+        return Err("invalid blinding factor".into());
+    }
+
+    let unblinded = r.invert() * decode_point(evaluated)?;
+    let key = Sha256::new_with_prefix(b"meesign ppss key")
+        .chain_update(password)
+        .chain_update(unblinded.compress().as_bytes())
+        .finalize();
+    Ok(key.to_vec())
+}
+
+/// Encrypt the secret under a key obtained from `finalize`
+pub fn seal(secret: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| "invalid key length")?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ct = cipher
+        .encrypt(&nonce, secret)
+        .map_err(|_| "encryption failed")?;
+    Ok(serde_json::to_vec(&(&nonce.to_vec(), &ct))?)
+}
+
+/// Decrypt a secret sealed by `seal`, failing on a wrong password
+pub fn open(sealed: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let (nonce, ct): (Vec<u8>, Vec<u8>) = serde_json::from_slice(sealed)?;
+    if nonce.len() != 12 {
+        return Err("invalid nonce length".into());
+    }
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| "invalid key length")?;
+    let secret = cipher
+        .decrypt(nonce.as_slice().into(), ct.as_slice())
+        .map_err(|_| "decryption failed")?;
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::GroupResult;
+    use crate::protocol::elgamal;
+    use crate::protocol::tests::{run_keygen, ThresholdProtocolTest};
+    use rand::seq::IteratorRandom;
+
+    fn keygen(threshold: u32, parties: u32) -> Vec<Vec<u8>> {
+        let (_, ctxs) =
+            run_keygen::<elgamal::KeygenContext>(ProtocolType::Ppss, 4, threshold, parties);
+        for ctx in &ctxs {
+            let group = GroupResult::decode(&ctx[..]).unwrap();
+            assert_eq!(group.protocol_type, ProtocolType::Ppss as i32);
+        }
+        ctxs
+    }
+
+    impl ThresholdProtocolTest for RecoverContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::Ppss;
+        const ROUNDS: usize = 2;
+    }
+
+    fn derive_key(ctxs: Vec<Vec<u8>>, parties: u16, threshold: usize, password: &[u8]) -> Vec<u8> {
+        let (r, blinded) = blind(password);
+
+        let mut indices = (0..parties).choose_multiple(&mut OsRng, threshold);
+        indices.sort();
+        let results = <RecoverContext as ThresholdProtocolTest>::run(ctxs, indices, blinded);
+
+        for result in &results {
+            assert_eq!(&results[0], result);
+        }
+        finalize(password, &r, &results[0]).unwrap()
+    }
+
+    #[test]
+    fn recover() {
+        for threshold in 2..5 {
+            for parties in threshold..5 {
+                let ctxs = keygen(threshold as u32, parties as u32);
+                let secret = b"credentials";
+
+                let key = derive_key(ctxs.clone(), parties as u16, threshold, b"password");
+                let sealed = seal(secret, &key).unwrap();
+
+                let key = derive_key(ctxs.clone(), parties as u16, threshold, b"password");
+                assert_eq!(open(&sealed, &key).unwrap(), secret);
+
+                let key = derive_key(ctxs, parties as u16, threshold, b"wrong");
+                assert!(open(&sealed, &key).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_evaluation() {
+        let ctxs = keygen(2, 2);
+        let outputs: Vec<Vec<u8>> = ctxs
+            .iter()
+            .enumerate()
+            .map(|(i, ctx)| {
+                // the second party evaluates another element than requested
+                let (_, blinded) = blind(if i == 0 { b"password" } else { b"other" });
+                let init = ProtocolInit {
+                    protocol_type: ProtocolType::Ppss as i32,
+                    indices: vec![0, 1],
+                    index: i as u32,
+                    data: blinded,
+                    ..Default::default()
+                };
+                let mut ctx = RecoverContext::new(ctx).unwrap();
+                let out = ctx.advance(&init.encode_to_vec()).unwrap();
+                if i == 0 {
+                    serde_json::to_vec(&ctx).unwrap()
+                } else {
+                    out
+                }
+            })
+            .collect();
+
+        let mut ctx: RecoverContext = serde_json::from_slice(&outputs[0]).unwrap();
+        let error = ctx.advance(&outputs[1]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid evaluation from party 1");
+    }
+}
@@ -0,0 +1,150 @@
+//! Non-interactive proofs of knowledge over Ristretto
+//!
+//! A proof shows the knowledge of secret scalars `x` such that each of the
+//! `images` is the combination of its row of `bases` by `x`, e.g. a proof
+//! of equality of discrete logarithms has the rows `[G]` and `[H]` and the
+//! images `x * G` and `x * H`. The Fiat-Shamir challenge is derived from
+//! a domain separator, a context such as associated data, the statement
+//! and the commitments, so a proof cannot be replayed in another context.
+
+use crate::protocol::{ProtocolRng, Result};
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Proof {
+    challenge: [u8; 32],
+    responses: Vec<[u8; 32]>,
+}
+
+/// Scalar from 64 bytes of randomness, which makes its bias negligible
+pub(crate) fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    ProtocolRng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+fn parse_scalar(bytes: &[u8; 32]) -> Result<Scalar> {
+    Scalar::from_canonical_bytes(*bytes).ok_or_else(|| "invalid proof".into())
+}
+
+pub(crate) fn decode_point(bytes: &[u8]) -> Result<RistrettoPoint> {
+    if bytes.len() != 32 {
+        return Err("invalid point length".into());
+    }
+    CompressedRistretto::from_slice(bytes)
+        .decompress()
+        .ok_or_else(|| "invalid point".into())
+}
+
+fn combine(row: &[RistrettoPoint], scalars: &[Scalar]) -> RistrettoPoint {
+    row.iter()
+        .zip(scalars)
+        .fold(RistrettoPoint::identity(), |acc, (base, x)| acc + x * base)
+}
+
+fn challenge(
+    domain: &[u8],
+    context: &[u8],
+    bases: &[Vec<RistrettoPoint>],
+    images: &[RistrettoPoint],
+    commitments: &[RistrettoPoint],
+) -> Scalar {
+    let mut hasher = Sha512::new();
+    for part in [domain, context] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.update((bases.len() as u64).to_be_bytes());
+    for row in bases {
+        hasher.update((row.len() as u64).to_be_bytes());
+        for base in row {
+            hasher.update(base.compress().as_bytes());
+        }
+    }
+    for point in images.iter().chain(commitments) {
+        hasher.update(point.compress().as_bytes());
+    }
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+/// Prove the knowledge of `secrets` combining each row of `bases`
+/// into the respective one of `images`
+pub(crate) fn prove(
+    domain: &[u8],
+    context: &[u8],
+    bases: &[Vec<RistrettoPoint>],
+    images: &[RistrettoPoint],
+    secrets: &[Scalar],
+) -> Proof {
+    let nonces: Vec<Scalar> = secrets.iter().map(|_| random_scalar()).collect();
+    let commitments: Vec<RistrettoPoint> = bases.iter().map(|row| combine(row, &nonces)).collect();
+    let c = challenge(domain, context, bases, images, &commitments);
+    Proof {
+        challenge: c.to_bytes(),
+        responses: nonces
+            .iter()
+            .zip(secrets)
+            .map(|(k, x)| (k + c * x).to_bytes())
+            .collect(),
+    }
+}
+
+/// Verify a proof made by `prove` for the same statement and context
+pub(crate) fn verify(
+    domain: &[u8],
+    context: &[u8],
+    bases: &[Vec<RistrettoPoint>],
+    images: &[RistrettoPoint],
+    proof: &Proof,
+) -> Result<()> {
+    if bases.len() != images.len() || bases.iter().any(|row| row.len() != proof.responses.len()) {
+        return Err("invalid proof".into());
+    }
+    let c = parse_scalar(&proof.challenge)?;
+    let responses = proof
+        .responses
+        .iter()
+        .map(parse_scalar)
+        .collect::<Result<Vec<_>>>()?;
+    let commitments: Vec<RistrettoPoint> = bases
+        .iter()
+        .zip(images)
+        .map(|(row, image)| combine(row, &responses) - c * image)
+        .collect();
+    if challenge(domain, context, bases, images, &commitments) != c {
+        return Err("invalid proof".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT as G;
+
+    #[test]
+    fn equality_of_logarithms() {
+        let h = random_scalar() * G;
+        let x = random_scalar();
+        let bases = [vec![G], vec![h]];
+        let images = [x * G, x * h];
+
+        let proof = prove(b"test", b"context", &bases, &images, &[x]);
+        assert!(verify(b"test", b"context", &bases, &images, &proof).is_ok());
+        assert!(verify(b"test", b"other", &bases, &images, &proof).is_err());
+        assert!(verify(b"other", b"context", &bases, &images, &proof).is_err());
+
+        let images = [x * G, (x + Scalar::one()) * h];
+        let proof = prove(b"test", b"context", &bases, &images, &[x]);
+        assert!(verify(b"test", b"context", &bases, &images, &proof).is_err());
+    }
+}
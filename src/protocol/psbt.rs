@@ -0,0 +1,341 @@
+//! Taproot key path signing of Bitcoin PSBTs
+//!
+//! A partially signed transaction (BIP-174, version 0) is parsed for its
+//! unsigned transaction and the witness UTXOs of its inputs, from which
+//! `sighash` computes the BIP-341 digest of spending an input by the key
+//! path with `SIGHASH_DEFAULT`. The digest is signed by a MuSig2 group,
+//! whose x-only aggregate key is the output key of the spent output.
+
+use crate::protocol::Result;
+use sha2::{Digest, Sha256};
+use std::convert::{TryFrom, TryInto};
+
+pub struct Input {
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub sequence: u32,
+    /// Value and script of the spent output, from the witness UTXO
+    pub utxo: Option<(u64, Vec<u8>)>,
+}
+
+pub struct Output {
+    pub value: u64,
+    pub script: Vec<u8>,
+}
+
+pub struct Transaction {
+    pub version: u32,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+    pub lock_time: u32,
+}
+
+impl Transaction {
+    /// Fee of the transaction, known if all the inputs have witness UTXOs,
+    /// failing if the outputs spend more than the inputs
+    pub fn fee(&self) -> Result<Option<u64>> {
+        let mut total: u64 = 0;
+        for input in &self.inputs {
+            let value = match &input.utxo {
+                Some((value, _)) => *value,
+                None => return Ok(None),
+            };
+            total = total.checked_add(value).ok_or("input values overflow")?;
+        }
+        let mut spent: u64 = 0;
+        for output in &self.outputs {
+            spent = spent
+                .checked_add(output.value)
+                .ok_or("output values overflow")?;
+        }
+        let fee = total
+            .checked_sub(spent)
+            .ok_or("outputs exceed the inputs")?;
+        Ok(Some(fee))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err("truncated PSBT".into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn compact(&mut self) -> Result<usize> {
+        let value = match self.bytes(1)?[0] {
+            0xfd => u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as u64,
+            0xfe => self.u32()? as u64,
+            0xff => self.u64()?,
+            byte => byte as u64,
+        };
+        usize::try_from(value).map_err(|_| "oversized PSBT field".into())
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.compact()?;
+        self.bytes(len)
+    }
+
+    /// Read the key-value pairs of a map up to its separator
+    fn map(&mut self) -> Result<Vec<(&'a [u8], &'a [u8])>> {
+        let mut pairs = Vec::new();
+        loop {
+            let key = self.var_bytes()?;
+            if key.is_empty() {
+                return Ok(pairs);
+            }
+            pairs.push((key, self.var_bytes()?));
+        }
+    }
+}
+
+fn compact(len: usize) -> Vec<u8> {
+    match len {
+        0..=0xfc => vec![len as u8],
+        0xfd..=0xffff => [&[0xfd][..], &(len as u16).to_le_bytes()].concat(),
+        _ => [&[0xfe][..], &(len as u32).to_le_bytes()].concat(),
+    }
+}
+
+fn parse_transaction(data: &[u8]) -> Result<Transaction> {
+    let mut reader = Reader(data);
+    let version = reader.u32()?;
+    let mut inputs = Vec::new();
+    for _ in 0..reader.compact()? {
+        let txid = reader.bytes(32)?.try_into().unwrap();
+        let vout = reader.u32()?;
+        if !reader.var_bytes()?.is_empty() {
+            return Err("unsigned transaction has a script".into());
+        }
+        inputs.push(Input {
+            txid,
+            vout,
+            sequence: reader.u32()?,
+            utxo: None,
+        });
+    }
+    let mut outputs = Vec::new();
+    for _ in 0..reader.compact()? {
+        outputs.push(Output {
+            value: reader.u64()?,
+            script: reader.var_bytes()?.to_vec(),
+        });
+    }
+    let lock_time = reader.u32()?;
+    if !reader.0.is_empty() {
+        return Err("trailing bytes in transaction".into());
+    }
+    Ok(Transaction {
+        version,
+        inputs,
+        outputs,
+        lock_time,
+    })
+}
+
+/// Parse the unsigned transaction of a PSBT with the witness UTXOs
+pub fn parse(psbt: &[u8]) -> Result<Transaction> {
+    let mut reader = Reader(psbt);
+    if reader.bytes(5)? != b"psbt\xff" {
+        return Err("not a PSBT".into());
+    }
+    let tx = reader
+        .map()?
+        .into_iter()
+        .find(|(key, _)| *key == [0x00])
+        .ok_or("missing unsigned transaction")?
+        .1;
+    let mut tx = parse_transaction(tx)?;
+
+    for input in &mut tx.inputs {
+        for (key, value) in reader.map()? {
+            if key == [0x01] {
+                let mut utxo = Reader(value);
+                input.utxo = Some((utxo.u64()?, utxo.var_bytes()?.to_vec()));
+            }
+        }
+    }
+    for _ in &tx.outputs {
+        reader.map()?;
+    }
+    Ok(tx)
+}
+
+fn sha256(parts: impl Iterator<Item = Vec<u8>>) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    parts.for_each(|part| hasher.update(part));
+    hasher.finalize().to_vec()
+}
+
+/// Compute the BIP-341 digest of spending the input at `index` by the key
+/// path with `SIGHASH_DEFAULT`
+pub fn sighash(tx: &Transaction, index: usize) -> Result<Vec<u8>> {
+    if index >= tx.inputs.len() {
+        return Err("input index out of range".into());
+    }
+    let utxos = tx
+        .inputs
+        .iter()
+        .map(|input| input.utxo.as_ref())
+        .collect::<Option<Vec<_>>>()
+        .ok_or("missing witness UTXO")?;
+
+    let prevouts = sha256(
+        tx.inputs
+            .iter()
+            .map(|input| [&input.txid[..], &input.vout.to_le_bytes()].concat()),
+    );
+    let amounts = sha256(utxos.iter().map(|(value, _)| value.to_le_bytes().to_vec()));
+    let scripts = sha256(
+        utxos
+            .iter()
+            .map(|(_, script)| [compact(script.len()), script.clone()].concat()),
+    );
+    let sequences = sha256(
+        tx.inputs
+            .iter()
+            .map(|input| input.sequence.to_le_bytes().to_vec()),
+    );
+    let outputs = sha256(tx.outputs.iter().map(|output| {
+        [
+            output.value.to_le_bytes().to_vec(),
+            compact(output.script.len()),
+            output.script.clone(),
+        ]
+        .concat()
+    }));
+
+    let tag = Sha256::digest(b"TapSighash");
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    hasher.update(tag);
+    hasher.update([0x00, 0x00]);
+    hasher.update(tx.version.to_le_bytes());
+    hasher.update(tx.lock_time.to_le_bytes());
+    for part in [prevouts, amounts, scripts, sequences, outputs] {
+        hasher.update(part);
+    }
+    hasher.update([0x00]);
+    hasher.update((index as u32).to_le_bytes());
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::protocol::tests::unhex;
+
+    /// PSBT spending one taproot output of 100000 sat to two outputs
+    pub(crate) fn example() -> Vec<u8> {
+        let tx = [
+            &2u32.to_le_bytes()[..],
+            &[1],
+            &[0x11; 32],
+            &1u32.to_le_bytes(),
+            &[0],
+            &0xfffffffdu32.to_le_bytes(),
+            &[2],
+            &60000u64.to_le_bytes(),
+            &[34, 0x51, 0x20],
+            &[0x22; 32],
+            &39000u64.to_le_bytes(),
+            &[22, 0x00, 0x14],
+            &[0x33; 20],
+            &0u32.to_le_bytes(),
+        ]
+        .concat();
+        let utxo = [&100000u64.to_le_bytes()[..], &[34, 0x51, 0x20], &[0x44; 32]].concat();
+        [
+            &b"psbt\xff"[..],
+            &[1, 0x00],
+            &compact(tx.len()),
+            &tx,
+            &[0],
+            &[1, 0x01],
+            &compact(utxo.len()),
+            &utxo,
+            &[0],
+            &[0],
+            &[0],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn parse_example() {
+        let tx = parse(&example()).unwrap();
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].vout, 1);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, 39000);
+        assert_eq!(tx.fee().unwrap(), Some(1000));
+        assert_eq!(sighash(&tx, 0).unwrap().len(), 32);
+        assert!(sighash(&tx, 1).is_err());
+
+        let psbt = example();
+        assert!(parse(&psbt[..psbt.len() - 2]).is_err());
+        assert!(parse(&psbt[1..]).is_err());
+    }
+
+    #[test]
+    fn fee() {
+        let mut tx = parse(&example()).unwrap();
+        tx.outputs[0].value = u64::MAX;
+        assert!(tx.fee().is_err());
+        tx.outputs[0].value = 100000;
+        assert!(tx.fee().is_err());
+        tx.inputs[0].utxo = None;
+        assert_eq!(tx.fee().unwrap(), None);
+    }
+
+    #[test]
+    #[ignore = "needs the upstream vectors, see vectors/fetch-bip341.sh"]
+    fn bip341_upstream() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/vectors/bip341/wallet-test-vectors.json"
+        );
+        let vectors = std::fs::read_to_string(path)
+            .expect("upstream vectors missing, run vectors/fetch-bip341.sh");
+        let vectors: serde_json::Value = serde_json::from_str(&vectors).unwrap();
+
+        let mut checked = 0;
+        for test in vectors["keyPathSpending"].as_array().unwrap() {
+            let given = &test["given"];
+            let mut tx =
+                parse_transaction(&unhex(given["rawUnsignedTx"].as_str().unwrap())).unwrap();
+            let utxos = given["utxosSpent"].as_array().unwrap();
+            for (input, utxo) in tx.inputs.iter_mut().zip(utxos) {
+                input.utxo = Some((
+                    utxo["amountSats"].as_u64().unwrap(),
+                    unhex(utxo["scriptPubKey"].as_str().unwrap()),
+                ));
+            }
+            for spending in test["inputSpending"].as_array().unwrap() {
+                // only SIGHASH_DEFAULT is supported
+                if spending["given"]["hashType"] != 0 {
+                    continue;
+                }
+                let index = spending["given"]["txinIndex"].as_u64().unwrap() as usize;
+                let expected = unhex(spending["intermediary"]["sigHash"].as_str().unwrap());
+                assert_eq!(sighash(&tx, index).unwrap(), expected, "input {}", index);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "no SIGHASH_DEFAULT vectors");
+    }
+}
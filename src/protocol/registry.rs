@@ -0,0 +1,211 @@
+//! Registry of the protocols compiled into the library
+//!
+//! Every protocol module declares the protocols it implements in its
+//! `PROTOCOLS` list, from which all the dispatch sites are served.
+
+use crate::proto::ProtocolType;
+#[cfg(feature = "gg18")]
+use crate::protocol::gg18;
+#[cfg(feature = "mldsa")]
+use crate::protocol::mldsa;
+#[cfg(feature = "musig2")]
+use crate::protocol::musig2;
+#[cfg(feature = "frost")]
+use crate::protocol::{ecdh, enroll, frost, hpke, remove, roast};
+#[cfg(feature = "elgamal")]
+use crate::protocol::{elgamal, ppss};
+use crate::protocol::{ContextOptions, KeygenProtocol, Protocol, Result, ThresholdProtocol};
+use serde_json::{json, Value};
+
+/// Largest number of parties of a group in any of the protocols
+pub const MAX_PARTIES: u32 = u16::MAX as u32;
+
+/// Description of a protocol together with the constructors of its contexts
+pub struct ProtocolInfo {
+    pub protocol_type: ProtocolType,
+    pub name: &'static str,
+    pub curve: &'static str,
+    /// Scheme computed by the threshold sessions, e.g. `ecdsa` or `schnorr`
+    pub scheme: &'static str,
+    /// Index of the first party, which differs between the protocols
+    pub index_base: u32,
+    pub keygen_rounds: usize,
+    /// Rounds of a threshold session, `None` if they depend on how many
+    /// signers fail to respond, as in ROAST
+    pub threshold_rounds: Option<usize>,
+    /// Largest number of parties of a group, at most `MAX_PARTIES`
+    pub max_parties: u32,
+    /// Smallest threshold of a group, `None` if the threshold has to equal
+    /// the number of parties
+    pub min_threshold: Option<u32>,
+    /// Whether the signing sessions take a digest rather than the message
+    pub prehashed: bool,
+    /// Whether every party of the group takes part in each session
+    pub all_sign: bool,
+    /// Whether the shares of a group can be redistributed
+    pub resharing: bool,
+    pub(crate) keygen: fn() -> Box<dyn Protocol>,
+    pub(crate) threshold: fn(&[u8], &ContextOptions) -> Result<Box<dyn Protocol>>,
+}
+
+pub(crate) fn keygen<P: KeygenProtocol + 'static>() -> Box<dyn Protocol> {
+    Box::new(P::new())
+}
+
+pub(crate) fn threshold<P: ThresholdProtocol + 'static>(
+    group: &[u8],
+    options: &ContextOptions,
+) -> Result<Box<dyn Protocol>> {
+    let mut ctx = P::new(group)?;
+    ctx.configure(options);
+    Ok(Box::new(ctx))
+}
+
+static REGISTRY: &[&[ProtocolInfo]] = &[
+    #[cfg(feature = "gg18")]
+    gg18::PROTOCOLS,
+    #[cfg(feature = "elgamal")]
+    elgamal::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    frost::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    ecdh::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    hpke::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    roast::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    enroll::PROTOCOLS,
+    #[cfg(feature = "frost")]
+    remove::PROTOCOLS,
+    #[cfg(feature = "elgamal")]
+    ppss::PROTOCOLS,
+    #[cfg(feature = "musig2")]
+    musig2::PROTOCOLS,
+    #[cfg(feature = "mldsa")]
+    mldsa::PROTOCOLS,
+];
+
+/// List the protocols compiled into the library
+pub fn protocols() -> impl Iterator<Item = &'static ProtocolInfo> {
+    REGISTRY.iter().flat_map(|protocols| protocols.iter())
+}
+
+/// Look up a protocol, failing if it was not compiled in
+pub fn protocol_info(protocol_type: ProtocolType) -> Result<&'static ProtocolInfo> {
+    protocols()
+        .find(|info| info.protocol_type == protocol_type)
+        .ok_or_else(|| "protocol not supported".into())
+}
+
+fn describe(info: &ProtocolInfo) -> Value {
+    json!({
+        "id": info.protocol_type as i32,
+        "name": info.name,
+        "curve": info.curve,
+        "scheme": info.scheme,
+        "index_base": info.index_base,
+        "keygen_rounds": info.keygen_rounds,
+        "threshold_rounds": info.threshold_rounds,
+        "min_threshold": info.min_threshold,
+        "max_threshold": info.max_parties,
+        "max_parties": info.max_parties,
+        "prehashed": info.prehashed,
+        "all_sign": info.all_sign,
+        "resharing": info.resharing,
+    })
+}
+
+/// Describe the capabilities of a protocol in JSON, so that the server can
+/// validate group configurations without knowledge of the protocols
+pub fn capabilities(protocol_type: ProtocolType) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&describe(protocol_info(
+        protocol_type,
+    )?))?)
+}
+
+/// Describe the capabilities of all the protocols compiled in as a JSON
+/// array, so that clients need not hard-code the supported protocols
+pub fn supported_protocols() -> Result<Vec<u8>> {
+    let protocols: Vec<Value> = protocols().map(describe).collect();
+    Ok(serde_json::to_vec(&protocols)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique() {
+        for (i, info) in protocols().enumerate() {
+            assert!(protocols()
+                .skip(i + 1)
+                .all(|other| other.protocol_type != info.protocol_type && other.name != info.name));
+        }
+    }
+
+    #[test]
+    fn capabilities_json() {
+        for info in protocols() {
+            let json: serde_json::Value =
+                serde_json::from_slice(&capabilities(info.protocol_type).unwrap()).unwrap();
+            assert_eq!(json["name"], info.name);
+            assert_eq!(json["threshold_rounds"], json!(info.threshold_rounds));
+            assert_eq!(json["max_parties"], info.max_parties);
+        }
+    }
+
+    #[test]
+    fn malformed_group() {
+        for info in protocols() {
+            let options = ContextOptions::default();
+            assert!((info.threshold)(b"not a group", &options).is_err());
+            assert!((info.threshold)(&[], &options).is_err());
+        }
+    }
+
+    #[test]
+    fn limits() {
+        for info in protocols() {
+            assert!(info.max_parties <= MAX_PARTIES);
+            assert_eq!(info.min_threshold.is_none(), info.all_sign);
+        }
+        #[cfg(feature = "musig2")]
+        assert_eq!(
+            protocol_info(ProtocolType::Musig2).unwrap().min_threshold,
+            None
+        );
+        #[cfg(feature = "frost")]
+        assert_eq!(
+            protocol_info(ProtocolType::Roast).unwrap().threshold_rounds,
+            None
+        );
+        #[cfg(feature = "mldsa")]
+        assert_eq!(protocol_info(ProtocolType::MlDsa).unwrap().max_parties, 255);
+    }
+
+    #[test]
+    fn protocol_types() {
+        use std::convert::TryFrom;
+
+        for info in protocols() {
+            let value = info.protocol_type as i32;
+            assert_eq!(ProtocolType::try_from(value).unwrap(), info.protocol_type);
+            assert!(info.protocol_type.check(value).is_ok());
+            assert!(info.protocol_type.check(value + 1).is_err());
+        }
+        let error = ProtocolType::try_from(-1).unwrap_err();
+        assert_eq!(error.to_string(), "unknown protocol type");
+    }
+
+    #[test]
+    fn supported() {
+        let json: Vec<serde_json::Value> =
+            serde_json::from_slice(&supported_protocols().unwrap()).unwrap();
+        assert_eq!(json.len(), protocols().count());
+        for (json, info) in json.iter().zip(protocols()) {
+            assert_eq!(json["id"], info.protocol_type as i32);
+            assert_eq!(json["name"], info.name);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Removal of members from FROST groups
+//!
+//! The remaining members refresh their shares while keeping the group key,
+//! so the shares of the removed members no longer combine with theirs.
+//! Each remaining member deals a random polynomial of degree `t - 1` with
+//! a zero constant term, commits to its coefficients and sends its value
+//! at each other member encrypted to the verifying share of that member,
+//! see `enroll::seal`, who verifies the value against the commitments
+//! and adds it to its share. The threshold `t` is derived from the group
+//! context, see `enroll::group_threshold`. The members then compare
+//! digests of all the commitments, as a dealer sending different
+//! commitments to different members would leave them with inconsistent
+//! verifying shares.
+//!
+//! A session is initialized with all the remaining members as the indices
+//! and with the removed indices as the data, each a big-endian `u16`. The
+//! removed indices are recorded in the `removed` field of the resulting
+//! `GroupResult`.
+
+use crate::proto::{GroupResult, ProtocolInit, ProtocolType};
+use crate::protocol::blind::secp256k1::{from_point, from_scalar, to_point, to_scalar};
+use crate::protocol::enroll::{group_threshold, open, seal};
+use crate::protocol::frost::KeygenContext as FrostKeygenContext;
+use crate::protocol::*;
+
+use frost::keys::{KeyPackage, PublicKeyPackage};
+use frost::Identifier;
+use frost_secp256k1 as frost;
+use k256::elliptic_curve::{sec1::ToEncodedPoint, Field, PrimeField};
+use k256::{ProjectivePoint, Scalar};
+use prost::{bytes::Bytes, Message};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use subtle::ConstantTimeEq;
+
+fn parse_scalar(bytes: &[u8]) -> Result<Scalar> {
+    if bytes.len() != 32 {
+        return Err("invalid share".into());
+    }
+    Option::from(Scalar::from_repr(*k256::FieldBytes::from_slice(bytes)))
+        .ok_or_else(|| "invalid share".into())
+}
+
+fn cbytes(point: ProjectivePoint) -> Vec<u8> {
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+fn parse_point(bytes: &[u8]) -> Result<ProjectivePoint> {
+    let key = k256::PublicKey::from_sec1_bytes(bytes).map_err(|_| "invalid point")?;
+    Ok(key.to_projective())
+}
+
+/// Value at `x` of the polynomial with the committed coefficients,
+/// starting from the linear one
+fn evaluate(commitments: &[ProjectivePoint], x: u16) -> ProjectivePoint {
+    let x = Scalar::from(x as u64);
+    commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |acc, c| (acc + c) * x)
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RemoveContext {
+    key: KeyPackage,
+    pubkey: PublicKeyPackage,
+    /// Parties removed from the group in earlier sessions
+    removed: Vec<u32>,
+    round: RemoveRound,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    index: u16,
+    others: Vec<u16>,
+    removed: Vec<u16>,
+    /// Value of the own polynomial at the own index
+    own: Vec<u8>,
+    /// Commitments to the coefficients of the own polynomial
+    commitments: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum RemoveRound {
+    R0,
+    R1(Session),
+    /// Removed indices, digest of the commitments and the refreshed keys
+    R2(Vec<u16>, Vec<u8>, KeyPackage, PublicKeyPackage),
+    Done(KeyPackage, PublicKeyPackage),
+}
+
+impl RemoveContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::FrostRemove.check(msg.protocol_type)?;
+        if msg.data.is_empty() || msg.data.len() % 2 != 0 {
+            return Err("invalid removal data".into());
+        }
+        let removed: Vec<u16> = msg
+            .data
+            .chunks(2)
+            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+            .collect();
+        let threshold = group_threshold(&self.pubkey)?;
+
+        let index = u16::try_from(msg.index)?;
+        if Identifier::try_from(index)? != *self.key.identifier() {
+            return Err("participant index does not match the group".into());
+        }
+        let indices = msg
+            .indices
+            .iter()
+            .map(|i| Ok(u16::try_from(*i)?))
+            .collect::<Result<Vec<u16>>>()?;
+        if !indices.contains(&index) {
+            return Err("participant index not included".into());
+        }
+        // the shares of a group of threshold one are the key itself
+        if threshold < 2 || threshold > indices.len() {
+            return Err("invalid group parameters".into());
+        }
+
+        let mut members: Vec<u16> = indices.iter().chain(&removed).cloned().collect();
+        members.sort_unstable();
+        members.dedup();
+        if members.len() != indices.len() + removed.len()
+            || members.len() != self.pubkey.signer_pubkeys().len()
+        {
+            return Err("indices do not cover the group".into());
+        }
+        for member in &members {
+            if !self
+                .pubkey
+                .signer_pubkeys()
+                .contains_key(&Identifier::try_from(*member)?)
+            {
+                return Err(format!("unknown party {}", member).into());
+            }
+        }
+
+        let coefficients: Vec<Scalar> = (1..threshold)
+            .map(|_| Scalar::random(&mut ProtocolRng))
+            .collect();
+        let value = |x: u16| {
+            let x = Scalar::from(x as u64);
+            coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |acc, c| (acc + c) * x)
+        };
+        let commitments: Vec<Vec<u8>> = coefficients
+            .iter()
+            .map(|c| cbytes(ProjectivePoint::GENERATOR * c))
+            .collect();
+
+        let others: Vec<u16> = indices.into_iter().filter(|i| *i != index).collect();
+        let msgs = others
+            .iter()
+            .map(|j| {
+                let share = self
+                    .pubkey
+                    .signer_pubkeys()
+                    .get(&Identifier::try_from(*j)?)
+                    .ok_or("unknown party")?;
+                let value = seal(&value(*j).to_bytes(), to_point(share)?)?;
+                Ok(serialize_msg(&(commitments.clone(), value))?.into())
+            })
+            .collect::<Result<Vec<Bytes>>>()?;
+
+        self.round = RemoveRound::R1(Session {
+            index,
+            others,
+            removed,
+            own: value(index).to_bytes().to_vec(),
+            commitments,
+        });
+        Ok(pack(msgs, ProtocolType::FrostRemove))
+    }
+
+    /// Verify the values dealt by the others and refresh the keys
+    fn refresh(&self, session: &Session, data: &[u8]) -> Result<(Vec<Bytes>, RemoveRound)> {
+        let data = unpack(data)?;
+        check_message_count(&data, session.others.len())?;
+
+        let mut dealers = vec![(session.index, session.commitments.clone())];
+        let share = to_scalar(self.key.secret_share())?;
+        let mut secret = share + parse_scalar(&session.own)?;
+        for (dealer, msg) in session.others.iter().zip(&data) {
+            let (commitments, value): (Vec<Vec<u8>>, Vec<u8>) = deserialize_msg(msg)?;
+            let blame = || format!("invalid share from party {}", dealer);
+            if commitments.len() != session.commitments.len() {
+                return Err(blame().into());
+            }
+            let points = commitments
+                .iter()
+                .map(|c| parse_point(c))
+                .collect::<Result<Vec<_>>>()?;
+            let value = parse_scalar(&open(&value, &share).map_err(|_| blame())?)?;
+            if !bool::from(
+                (ProjectivePoint::GENERATOR * value).ct_eq(&evaluate(&points, session.index)),
+            ) {
+                return Err(blame().into());
+            }
+            secret += value;
+            dealers.push((*dealer, commitments));
+        }
+        dealers.sort_unstable();
+
+        let mut hasher = Sha256::new();
+        let mut pubkeys = HashMap::new();
+        let members: Vec<u16> = dealers.iter().map(|(index, _)| *index).collect();
+        for (dealer, _) in &dealers {
+            pubkeys.insert(Identifier::try_from(*dealer)?, ProjectivePoint::IDENTITY);
+        }
+        for (dealer, commitments) in &dealers {
+            hasher.update(dealer.to_be_bytes());
+            let points = commitments
+                .iter()
+                .map(|c| {
+                    hasher.update(c);
+                    parse_point(c)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for member in &members {
+                *pubkeys.get_mut(&Identifier::try_from(*member)?).unwrap() +=
+                    evaluate(&points, *member);
+            }
+        }
+
+        let pubkeys = pubkeys
+            .into_iter()
+            .map(|(identifier, delta)| {
+                let share = self
+                    .pubkey
+                    .signer_pubkeys()
+                    .get(&identifier)
+                    .ok_or("unknown party")?;
+                let share = to_point(share)? + delta;
+                Ok((identifier, from_point(share)?))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        let identifier = *self.key.identifier();
+        let verifying = to_point(&pubkeys[&identifier])?;
+        if !bool::from((ProjectivePoint::GENERATOR * secret).ct_eq(&verifying)) {
+            return Err("refreshed share does not match the group".into());
+        }
+        let key = KeyPackage::new(
+            identifier,
+            from_scalar(secret)?,
+            pubkeys[&identifier],
+            *self.pubkey.group_public(),
+        );
+        let pubkey = PublicKeyPackage::new(pubkeys, *self.pubkey.group_public());
+
+        let digest = hasher.finalize().to_vec();
+        let msgs = serialize_bcast(&digest, session.others.len())?;
+        let round = RemoveRound::R2(session.removed.clone(), digest, key, pubkey);
+        Ok((msgs, round))
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let (round, msgs) = match &self.round {
+            RemoveRound::R0 => return Err("protocol not initialized".into()),
+            RemoveRound::R1(session) => {
+                let (msgs, round) = self.refresh(session, data)?;
+                (round, msgs)
+            }
+            RemoveRound::R2(removed, digest, key, pubkey) => {
+                let data = unpack(data)?;
+                check_message_count(&data, pubkey.signer_pubkeys().len() - 1)?;
+                for msg in &data {
+                    let other: Vec<u8> = deserialize_msg(msg)?;
+                    if other != *digest {
+                        return Err("inconsistent commitments".into());
+                    }
+                }
+                self.removed
+                    .extend(removed.iter().map(|index| *index as u32));
+                self.removed.sort_unstable();
+                (RemoveRound::Done(key.clone(), pubkey.clone()), Vec::new())
+            }
+            RemoveRound::Done(_, _) => return Err("protocol already finished".into()),
+        };
+        self.round = round;
+        Ok(pack(msgs, ProtocolType::FrostRemove))
+    }
+}
+
+#[typetag::serde(name = "frost_remove")]
+impl Protocol for RemoveContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.round {
+            RemoveRound::R0 => self.init(data),
+            _ => self.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let this = *self;
+        match this.round {
+            RemoveRound::Done(key, pubkey) => Ok(GroupResult {
+                protocol_type: ProtocolType::Frost.into(),
+                public_key: pubkey.group_public().serialize().as_ref().to_vec(),
+                context: serde_json::to_vec(&(key, pubkey))?,
+                removed: this.removed,
+                format: GROUP_FORMAT,
+                ..Default::default()
+            }
+            .encode_to_vec()),
+            _ => Err("protocol not finished".into()),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        match &self.round {
+            RemoveRound::R0 | RemoveRound::Done(_, _) => None,
+            RemoveRound::R1(session) => Some(session.others.len()),
+            RemoveRound::R2(_, _, _, pubkey) => Some(pubkey.signer_pubkeys().len() - 1),
+        }
+    }
+}
+
+impl ThresholdProtocol for RemoveContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        let (key, pubkey) = parse_group_context(group)?;
+        Ok(Self {
+            key,
+            pubkey,
+            removed: GroupResult::decode(group)
+                .map(|result| result.removed)
+                .unwrap_or_default(),
+            round: RemoveRound::R0,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::FrostRemove,
+    name: "frost_remove",
+    curve: "secp256k1",
+    scheme: "refresh",
+    index_base: 1,
+    keygen_rounds: 3,
+    threshold_rounds: Some(3),
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: true,
+    keygen: registry::keygen::<FrostKeygenContext>,
+    threshold: registry::threshold::<RemoveContext>,
+}];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::SignatureResult;
+    use crate::protocol::frost::{verify, SignContext};
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+
+    impl ThresholdProtocolTest for RemoveContext {
+        const PROTOCOL_TYPE: ProtocolType = ProtocolType::FrostRemove;
+        const ROUNDS: usize = 3;
+        const INDEX_OFFSET: u32 = 1;
+    }
+
+    fn data(removed: &[u16]) -> Vec<u8> {
+        removed.iter().cloned().flat_map(u16::to_be_bytes).collect()
+    }
+
+    /// Public key reconstructed from the shares of the parties with `indices`
+    fn combine(groups: [&[u8]; 2], indices: [u16; 2]) -> Vec<u8> {
+        let mut secret = Scalar::ZERO;
+        for (group, index) in groups.iter().zip(indices) {
+            let (key, _): (KeyPackage, PublicKeyPackage) = parse_group_context(group).unwrap();
+            let other =
+                Scalar::from(indices.iter().find(|i| **i != index).cloned().unwrap() as u64);
+            let coefficient = other * (other - Scalar::from(index as u64)).invert().unwrap();
+            secret += to_scalar(key.secret_share()).unwrap() * coefficient;
+        }
+        cbytes(ProjectivePoint::GENERATOR * secret)
+    }
+
+    #[test]
+    fn remove_member() {
+        let (_, groups) = <FrostKeygenContext as KeygenProtocolTest>::run(2, 4);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+
+        let refreshed = <RemoveContext as ThresholdProtocolTest>::run(
+            groups.clone(),
+            vec![0, 1, 2],
+            data(&[4]),
+        );
+        for group in &refreshed {
+            let result = GroupResult::decode(&group[..]).unwrap();
+            assert_eq!(result.public_key, pk);
+            assert_eq!(result.removed, vec![4]);
+        }
+
+        let results = <SignContext as ThresholdProtocolTest>::run(
+            refreshed.clone(),
+            vec![0, 2],
+            b"hi".to_vec(),
+        );
+        for result in results {
+            let signature = SignatureResult::decode(&result[..]).unwrap().signature;
+            assert!(verify(&pk, b"hi", &signature).is_ok());
+        }
+
+        assert_eq!(combine([&refreshed[0][..], &refreshed[1]], [1, 2]), pk);
+        assert_eq!(combine([&groups[0][..], &groups[3]], [1, 4]), pk);
+        assert_ne!(combine([&refreshed[0][..], &groups[3]], [1, 4]), pk);
+    }
+
+    #[test]
+    fn invalid_removal() {
+        let (_, groups) = <FrostKeygenContext as KeygenProtocolTest>::run(2, 3);
+        let init = |data| {
+            ProtocolInit {
+                protocol_type: ProtocolType::FrostRemove as i32,
+                indices: vec![1, 2],
+                index: 1,
+                data,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        };
+        for invalid in [data(&[]), data(&[4]), data(&[3, 3])] {
+            assert!(RemoveContext::new(&groups[0])
+                .unwrap()
+                .advance(&init(invalid))
+                .is_err());
+        }
+        assert!(RemoveContext::new(&groups[0])
+            .unwrap()
+            .advance(&init(data(&[3])))
+            .is_ok());
+
+        // the two remaining members of a 3-of-3 group cannot sign
+        let (_, groups) = <FrostKeygenContext as KeygenProtocolTest>::run(3, 3);
+        assert!(RemoveContext::new(&groups[0])
+            .unwrap()
+            .advance(&init(data(&[3])))
+            .is_err());
+    }
+}
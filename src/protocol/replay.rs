@@ -0,0 +1,160 @@
+//! Protection against the repeated delivery of the same input
+//!
+//! A client may persist the advanced context and crash before passing
+//! the output on, in which case it retries the delivery of the same input.
+//! The wrapped context then returns the output it produced the last time
+//! instead of advancing twice.
+
+use crate::proto::ProtocolMessage;
+use crate::protocol::telemetry::telemetry;
+#[cfg(feature = "tracing")]
+use crate::protocol::telemetry::Redacted;
+use crate::protocol::{DebugState, Protocol, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReplayContext {
+    inner: Box<dyn Protocol>,
+    /// Digest of the last input and the output it produced
+    last: Option<([u8; 32], Vec<u8>)>,
+    /// Number of messages of the other parties consumed so far
+    #[serde(default)]
+    consumed: usize,
+}
+
+impl ReplayContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>) -> Self {
+        Self {
+            inner,
+            last: None,
+            consumed: 0,
+        }
+    }
+}
+
+#[typetag::serde(name = "replay")]
+impl Protocol for ReplayContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        if let Some((last, out)) = &self.last {
+            if *last == digest {
+                return Ok(out.clone());
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        let state = self.inner.debug_state();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "advance",
+            context = %state.context,
+            round = %state.round,
+            consumed = self.consumed,
+        )
+        .entered();
+        telemetry!(
+            debug,
+            context = %state.context,
+            round = %state.round,
+            input = ?Redacted(data),
+            "advancing"
+        );
+
+        let out = self.inner.advance(data);
+        #[cfg(feature = "tracing")]
+        match &out {
+            Ok(_) => {
+                let round = self.inner.debug_state().round;
+                telemetry!(debug, context = %state.context, %round, "advanced");
+            }
+            Err(error) => {
+                telemetry!(
+                    warn,
+                    context = %state.context,
+                    round = %state.round,
+                    %error,
+                    "advance failed"
+                );
+            }
+        }
+        let out = out?;
+
+        // the first input initializes the context, the others carry
+        // the messages of the other parties
+        if self.last.is_some() {
+            self.consumed += ProtocolMessage::decode(data).map_or(0, |msg| msg.message.len());
+        }
+        self.last = Some((digest, out.clone()));
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.last.as_ref().map(|(_, out)| out.clone())
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        DebugState {
+            consumed: self.consumed,
+            ..self.inner.debug_state().wrapped("replay")
+        }
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{ProtocolGroupInit, ProtocolType};
+    use crate::protocol::keygen_context;
+    use prost::Message;
+
+    #[test]
+    fn repeated_input() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        let init = (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        })
+        .encode_to_vec();
+
+        assert_eq!(ctx.last_output(), None);
+        let out = ctx.advance(&init).unwrap();
+        assert_eq!(ctx.last_output(), Some(out.clone()));
+        let ser = serde_json::to_vec(&ctx).unwrap();
+        let mut ctx: Box<dyn Protocol> = serde_json::from_slice(&ser).unwrap();
+        assert_eq!(ctx.advance(&init).unwrap(), out);
+        assert_eq!(serde_json::to_vec(&ctx).unwrap(), ser);
+        assert_eq!(ctx.expected_messages(), Some(2));
+    }
+}
@@ -0,0 +1,152 @@
+//! Source of randomness for the protocol contexts
+//!
+//! With the `deterministic` feature, the randomness drawn within `with_seed`
+//! is derived from the seed instead, so that test fixtures such as group
+//! contexts are reproducible. GG18 draws its randomness within mpecdsa and
+//! stays random regardless.
+//!
+//! The FROST signing contexts of a party which chose `ContextOptions.hedged`
+//! hash the fresh randomness with the secret share and the session into their
+//! nonces by `HedgedRng`, so that a weak generator of a mobile device cannot
+//! leak the share; the choice is not left to the server.
+
+#[cfg(feature = "deterministic")]
+use rand::SeedableRng;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+#[cfg(feature = "deterministic")]
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "frost")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "deterministic")]
+use std::cell::RefCell;
+
+#[cfg(feature = "deterministic")]
+thread_local! {
+    static SEEDED: RefCell<Option<ChaCha20Rng>> = RefCell::new(None);
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ProtocolRng;
+
+impl RngCore for ProtocolRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        #[cfg(feature = "deterministic")]
+        {
+            let seeded = SEEDED.with(|rng| match rng.borrow_mut().as_mut() {
+                Some(rng) => {
+                    rng.fill_bytes(dest);
+                    true
+                }
+                None => false,
+            });
+            if seeded {
+                return;
+            }
+        }
+        OsRng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ProtocolRng {}
+
+/// Randomness derived from fresh randomness, a secret and the session,
+/// which stays unpredictable unless both of the first two are known
+#[cfg(feature = "frost")]
+pub(crate) struct HedgedRng {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+#[cfg(feature = "frost")]
+impl HedgedRng {
+    pub(crate) fn new(secret: &[u8], session: &[&[u8]]) -> Self {
+        let mut fresh = [0u8; 32];
+        ProtocolRng.fill_bytes(&mut fresh);
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"meesign hedged nonces");
+        hasher.update(fresh);
+        for part in std::iter::once(secret).chain(session.iter().copied()) {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        Self {
+            seed: hasher.finalize().into(),
+            counter: 0,
+        }
+    }
+}
+
+#[cfg(feature = "frost")]
+impl RngCore for HedgedRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(32) {
+            let block = Sha256::new()
+                .chain_update(self.seed)
+                .chain_update(self.counter.to_be_bytes())
+                .finalize();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            self.counter += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "frost")]
+impl CryptoRng for HedgedRng {}
+
+/// Run `f` with the randomness of the contexts advanced by the current
+/// thread derived from `seed`; never use in production
+#[cfg(feature = "deterministic")]
+pub fn with_seed<T>(seed: [u8; 32], f: impl FnOnce() -> T) -> T {
+    let previous = SEEDED.with(|rng| rng.replace(Some(ChaCha20Rng::from_seed(seed))));
+    let result = f();
+    SEEDED.with(|rng| rng.replace(previous));
+    result
+}
+
+#[cfg(all(test, feature = "deterministic"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded() {
+        let draw = || ProtocolRng.next_u64();
+        assert_eq!(with_seed([1; 32], draw), with_seed([1; 32], draw));
+        assert_ne!(with_seed([1; 32], draw), with_seed([2; 32], draw));
+        assert_ne!(draw(), draw());
+    }
+}
@@ -0,0 +1,446 @@
+//! Robust FROST signing by ROAST
+//!
+//! A FROST session fails as soon as one of its signers does not respond
+//! or sends an invalid share. In ROAST (Ruffing et al., CCS 2022) the
+//! coordinator, typically the server, runs concurrent FROST sessions over
+//! the signers which responded so far, so that signing succeeds as long as
+//! `threshold` of the signers are honest and responsive.
+//!
+//! Each signer runs a `RobustSignContext`, which sends a commitment to the
+//! coordinator and answers every signing request with its share and a
+//! fresh commitment. The `Coordinator` starts a new session whenever
+//! `threshold` signers have a fresh commitment, and excludes the signers
+//! whose shares fail to aggregate. The messages exchanged between a signer
+//! and the coordinator are packed one at a time.
+
+use crate::proto::{ProtocolInit, ProtocolType};
+use crate::protocol::frost::KeygenContext;
+use crate::protocol::*;
+
+use frost::keys::{KeyPackage, PublicKeyPackage};
+use frost::round1::{SigningCommitments, SigningNonces};
+use frost::round2::SignatureShare;
+use frost::{Identifier, Signature, SigningPackage};
+use frost_secp256k1 as frost;
+use prost::{bytes::Bytes, Message};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+
+/// Message of the coordinator to a signer
+#[derive(Serialize, Deserialize)]
+enum Request {
+    /// Sign in the given session, including the latest commitment of the signer
+    Sign(u32, SigningPackage),
+    /// Signature produced by the given signers
+    Done(Signature, Vec<u16>),
+}
+
+/// Message of a signer to the coordinator
+#[derive(Serialize, Deserialize)]
+struct Response {
+    /// Share in the requested session, absent from the first response
+    share: Option<(u32, SignatureShare)>,
+    commitments: SigningCommitments,
+}
+
+fn pack_one<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let msg = Bytes::from(serialize_msg(value)?);
+    Ok(pack(vec![msg], ProtocolType::Roast))
+}
+
+fn unpack_one<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    let msgs = unpack(data)?;
+    check_message_count(&msgs, 1)?;
+    deserialize_msg(&msgs[0])
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RobustSignContext {
+    key: KeyPackage,
+    pubkey: PublicKeyPackage,
+    message: Option<Vec<u8>>,
+    /// Nonces of the latest commitment, replaced once used for a share
+    nonces: Option<(SigningNonces, SigningCommitments)>,
+    result: Option<(Signature, Vec<u16>)>,
+}
+
+impl RobustSignContext {
+    fn commit(&mut self) -> SigningCommitments {
+        let (nonces, commitments) =
+            frost::round1::commit(self.key.secret_share(), &mut ProtocolRng);
+        self.nonces = Some((nonces, commitments));
+        commitments
+    }
+
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        ProtocolType::Roast.check(msg.protocol_type)?;
+        policy::check(ProtocolType::Roast, &msg)?;
+
+        self.message = Some(msg.data);
+        let commitments = self.commit();
+        pack_one(&Response {
+            share: None,
+            commitments,
+        })
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.result.is_some() {
+            return Err("protocol already finished".into());
+        }
+        let message = self.message.as_ref().unwrap();
+
+        match unpack_one(data)? {
+            Request::Sign(session, package) => {
+                if package.message() != message {
+                    return Err("unexpected message".into());
+                }
+                let (nonces, commitments) = self.nonces.as_ref().ok_or("no fresh commitment")?;
+                if package.signing_commitments().get(self.key.identifier()) != Some(commitments) {
+                    return Err("stale commitment".into());
+                }
+                nonces::consume(nonces::session_id(commitments)?, &package)?;
+                let share = frost::round2::sign(&package, nonces, &self.key)?;
+
+                let commitments = self.commit();
+                pack_one(&Response {
+                    share: Some((session, share)),
+                    commitments,
+                })
+            }
+            Request::Done(signature, signers) => {
+                self.pubkey.group_public().verify(message, &signature)?;
+                self.result = Some((signature, signers));
+                Ok(pack(Vec::new(), ProtocolType::Roast))
+            }
+        }
+    }
+}
+
+#[typetag::serde(name = "roast_sign")]
+impl Protocol for RobustSignContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        if self.message.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let (signature, signers) = self.result.ok_or("protocol not finished")?;
+        Ok(pack_signature(
+            ProtocolType::Roast,
+            signature.serialize().to_vec(),
+            signers.into_iter().map(u32::from).collect(),
+        ))
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        if self.message.is_none() || self.result.is_some() {
+            return None;
+        }
+        Some(1)
+    }
+}
+
+impl ThresholdProtocol for RobustSignContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        let (key, pubkey): (KeyPackage, PublicKeyPackage) = parse_group_context(group)?;
+        Ok(Self {
+            key,
+            pubkey,
+            message: None,
+            nonces: None,
+            result: None,
+        })
+    }
+}
+
+pub(crate) const PROTOCOLS: &[ProtocolInfo] = &[ProtocolInfo {
+    protocol_type: ProtocolType::Roast,
+    name: "roast",
+    curve: "secp256k1",
+    scheme: "schnorr",
+    index_base: 1,
+    keygen_rounds: 3,
+    threshold_rounds: None,
+    max_parties: MAX_PARTIES,
+    min_threshold: Some(1),
+    prehashed: false,
+    all_sign: false,
+    resharing: false,
+    keygen: registry::keygen::<KeygenContext>,
+    threshold: registry::threshold::<RobustSignContext>,
+}];
+
+/// Export the public key package the coordinator needs to verify
+/// the signature shares, given the group of any of its members
+pub fn public_package(group: &[u8]) -> Result<Vec<u8>> {
+    let (_, pubkey): (KeyPackage, PublicKeyPackage) = parse_group_context(group)?;
+    Ok(serde_json::to_vec(&pubkey)?)
+}
+
+struct Session {
+    signers: Vec<u16>,
+    package: SigningPackage,
+    shares: HashMap<Identifier, SignatureShare>,
+}
+
+/// Coordinator of a robust signing, run by the server
+pub struct Coordinator {
+    pubkey: PublicKeyPackage,
+    threshold: usize,
+    message: Vec<u8>,
+    signers: Vec<u16>,
+    /// Signers with a fresh commitment which take part in no session
+    ready: Vec<(u16, SigningCommitments)>,
+    /// Session whose share is awaited from each signer
+    pending: HashMap<u16, u32>,
+    excluded: BTreeSet<u16>,
+    sessions: Vec<Session>,
+    result: Option<(Signature, Vec<u16>)>,
+}
+
+impl Coordinator {
+    /// Coordinate the signing of `message` by `threshold` of the `signers`,
+    /// given the public key package exported by `public_package`
+    pub fn new(
+        pubkey: &[u8],
+        signers: Vec<u16>,
+        threshold: usize,
+        message: Vec<u8>,
+    ) -> Result<Self> {
+        if threshold == 0 || threshold > signers.len() {
+            return Err("invalid threshold".into());
+        }
+        Ok(Self {
+            pubkey: serde_json::from_slice(pubkey)?,
+            threshold,
+            message,
+            signers,
+            ready: Vec::new(),
+            pending: HashMap::new(),
+            excluded: BTreeSet::new(),
+            sessions: Vec::new(),
+            result: None,
+        })
+    }
+
+    /// Process the output of the signer `index`, returning the packed
+    /// requests to be delivered to the signers
+    pub fn receive(&mut self, index: u16, data: &[u8]) -> Result<Vec<(u16, Vec<u8>)>> {
+        if !self.signers.contains(&index) {
+            return Err(format!("unknown signer {}", index).into());
+        }
+        if self.result.is_some() || self.excluded.contains(&index) {
+            return Ok(Vec::new());
+        }
+
+        let response: Response = match unpack_one(data) {
+            Ok(response) => response,
+            Err(_) => return self.exclude(index),
+        };
+        let expected = self.pending.remove(&index);
+        let unsolicited = self.ready.iter().any(|(i, _)| *i == index);
+        if unsolicited || response.share.as_ref().map(|(session, _)| *session) != expected {
+            return self.exclude(index);
+        }
+
+        if let Some((session, share)) = response.share {
+            let session = &mut self.sessions[session as usize];
+            session.shares.insert(Identifier::try_from(index)?, share);
+            if session.shares.len() == self.threshold {
+                match frost::aggregate(&session.package, &session.shares, &self.pubkey) {
+                    Ok(signature) => {
+                        self.result = Some((signature, session.signers.clone()));
+                        return self.done();
+                    }
+                    Err(frost::Error::InvalidSignatureShare { culprit, .. }) => {
+                        let culprit = session
+                            .signers
+                            .iter()
+                            .copied()
+                            .find(|i| Identifier::try_from(*i).ok() == Some(culprit))
+                            .ok_or("unknown culprit")?;
+                        self.exclude(culprit)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        if self.excluded.contains(&index) {
+            return Ok(Vec::new());
+        }
+        self.ready.push((index, response.commitments));
+        self.start()
+    }
+
+    /// Exclude a misbehaving signer from all future sessions
+    fn exclude(&mut self, index: u16) -> Result<Vec<(u16, Vec<u8>)>> {
+        self.excluded.insert(index);
+        self.ready.retain(|(i, _)| *i != index);
+        self.pending.remove(&index);
+        if self.signers.len() - self.excluded.len() < self.threshold {
+            return Err("too many misbehaving signers".into());
+        }
+        Ok(Vec::new())
+    }
+
+    /// Start a session once enough signers have a fresh commitment
+    fn start(&mut self) -> Result<Vec<(u16, Vec<u8>)>> {
+        if self.ready.len() < self.threshold {
+            return Ok(Vec::new());
+        }
+        let ready: Vec<_> = self.ready.drain(..self.threshold).collect();
+        let commitments = ready
+            .iter()
+            .map(|(i, commitments)| Ok((Identifier::try_from(*i)?, *commitments)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let session = self.sessions.len() as u32;
+        let package = SigningPackage::new(commitments, &self.message);
+        let request = pack_one(&Request::Sign(session, package.clone()))?;
+        let signers: Vec<u16> = ready.into_iter().map(|(i, _)| i).collect();
+        for signer in &signers {
+            self.pending.insert(*signer, session);
+        }
+        self.sessions.push(Session {
+            signers: signers.clone(),
+            package,
+            shares: HashMap::new(),
+        });
+        Ok(signers
+            .into_iter()
+            .map(|signer| (signer, request.clone()))
+            .collect())
+    }
+
+    /// Announce the signature to all the signers which were not excluded
+    fn done(&self) -> Result<Vec<(u16, Vec<u8>)>> {
+        let (signature, signers) = self.result.as_ref().unwrap();
+        let request = pack_one(&Request::Done(*signature, signers.clone()))?;
+        Ok(self
+            .signers
+            .iter()
+            .filter(|i| !self.excluded.contains(i))
+            .map(|i| (*i, request.clone()))
+            .collect())
+    }
+
+    /// Signers excluded for misbehaving
+    pub fn excluded(&self) -> Vec<u16> {
+        self.excluded.iter().copied().collect()
+    }
+
+    /// Signing result for the server, once a session succeeded
+    pub fn result(&self) -> Option<Vec<u8>> {
+        self.result.as_ref().map(|(signature, signers)| {
+            pack_signature(
+                ProtocolType::Roast,
+                signature.serialize().to_vec(),
+                signers.iter().copied().map(u32::from).collect(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, SignatureResult};
+    use crate::protocol::frost::verify;
+    use crate::protocol::tests::KeygenProtocolTest;
+    use std::collections::VecDeque;
+
+    /// Replace the share in the response of a signer by an invalid one
+    fn tamper(output: &[u8]) -> Vec<u8> {
+        let mut response: Response = unpack_one(output).unwrap();
+        if let Some((_, share)) = &mut response.share {
+            *share = SignatureShare::deserialize([1; 32]).unwrap();
+        }
+        pack_one(&response).unwrap()
+    }
+
+    #[test]
+    fn robust() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 4);
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        let msg = b"hello".to_vec();
+        let package = public_package(&groups[0]).unwrap();
+        let mut coordinator = Coordinator::new(&package, vec![1, 2, 3, 4], 2, msg.clone()).unwrap();
+        let mut signers: Vec<RobustSignContext> = groups
+            .iter()
+            .map(|g| RobustSignContext::new(g).unwrap())
+            .collect();
+
+        // signer 4 never responds and signer 3 sends invalid shares
+        let mut queue = VecDeque::new();
+        for index in [3u16, 1, 2] {
+            let init = ProtocolInit {
+                protocol_type: ProtocolType::Roast as i32,
+                indices: vec![1, 2, 3, 4],
+                index: index as u32,
+                data: msg.clone(),
+                ..Default::default()
+            };
+            let output = signers[index as usize - 1].advance(&init.encode_to_vec());
+            queue.push_back((index, output.unwrap()));
+        }
+        while let Some((index, output)) = queue.pop_front() {
+            for (recipient, request) in coordinator.receive(index, &output).unwrap() {
+                if recipient == 4 {
+                    continue;
+                }
+                let mut output = signers[recipient as usize - 1].advance(&request).unwrap();
+                if recipient == 3 {
+                    output = tamper(&output);
+                }
+                queue.push_back((recipient, output));
+            }
+        }
+
+        assert_eq!(coordinator.excluded(), vec![3]);
+        let result = SignatureResult::decode(&coordinator.result().unwrap()[..]).unwrap();
+        assert_eq!(result.indices, vec![1, 2]);
+        assert!(verify(&pk, &msg, &result.signature).is_ok());
+
+        for signer in signers.into_iter().take(2) {
+            assert_eq!(
+                Box::new(signer).finish().unwrap(),
+                coordinator.result().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn nonce_reuse() {
+        let (_, groups) = <KeygenContext as KeygenProtocolTest>::run(2, 2);
+        let mut ctx = RobustSignContext::new(&groups[0]).unwrap();
+        let init = ProtocolInit {
+            protocol_type: ProtocolType::Roast as i32,
+            indices: vec![1, 2],
+            index: 1,
+            data: b"hello".to_vec(),
+            ..Default::default()
+        };
+        let response: Response = unpack_one(&ctx.advance(&init.encode_to_vec()).unwrap()).unwrap();
+        let other = RobustSignContext::new(&groups[1]).unwrap().commit();
+
+        let commitments = BTreeMap::from([
+            (Identifier::try_from(1).unwrap(), response.commitments),
+            (Identifier::try_from(2).unwrap(), other),
+        ]);
+        let request = pack_one(&Request::Sign(
+            0,
+            SigningPackage::new(commitments, b"hello"),
+        ))
+        .unwrap();
+        assert!(ctx.advance(&request).is_ok());
+        // a second request with the same commitment must not be answered
+        assert!(ctx.advance(&request).is_err());
+    }
+}
@@ -0,0 +1,576 @@
+//! Authentication of the relayed messages by the keys of the devices
+//!
+//! The server relays the round messages, so it could substitute the
+//! message of one party for another's. Each party thus signs every message
+//! it sends by the key of its device, whose certificate the others learned
+//! when the group was created, and the wrapped context only receives the
+//! messages whose signatures verify under the certificate of their sender.
+//! The wrapped context learns so by `Protocol::set_authenticated`, as the
+//! contexts encrypting to the keys other parties send rely on it.
+//!
+//! A signed message is `len || signature || payload`, where the ECDSA
+//! signature over `DOMAIN || protocol type || round || session || sender
+//! || recipient || payload` binds it to the session, the round it was sent
+//! in and both of its parties, so that the server can neither replay it
+//! in another session nor redirect it to another party. Each party draws
+//! a random nonce, which it sends between the signature and the payload
+//! of its first messages and which stands for the session in them; the
+//! later messages are bound to the hash of the nonces of all the parties.
+//! The server must name the senders of the messages unless all the parties
+//! of the group take part in the session.
+//!
+//! The `SignatureResult` of a signing session additionally carries the
+//! signature of its `signers_commitment` by the key of the device, so the
+//! server can prove which parties produced the signature.
+
+use crate::proto::{GroupDevices, ProtocolMessage, ProtocolType, SignatureResult};
+use crate::protocol::telemetry::telemetry;
+use crate::protocol::{
+    pack, signers_commitment, unpack, DebugState, Protocol, ProtocolRng, Result,
+};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::{Signer, Verifier};
+use openssl::x509::X509;
+use prost::{bytes::Bytes, Message};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+const DOMAIN: &[u8] = b"meesign-message";
+const SIGNERS_DOMAIN: &[u8] = b"meesign-signers";
+const SESSION_DOMAIN: &[u8] = b"meesign-session";
+const NONCE_LEN: usize = 32;
+
+/// Data signed for a message from `sender` to `recipient`, where the
+/// `session` is the nonce of the sender in the first round
+fn signed_data(
+    protocol_type: i32,
+    round: u32,
+    session: &[u8],
+    sender: u32,
+    recipient: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    [
+        DOMAIN,
+        &protocol_type.to_be_bytes(),
+        &round.to_be_bytes(),
+        session,
+        &sender.to_be_bytes(),
+        &recipient.to_be_bytes(),
+        payload,
+    ]
+    .concat()
+}
+
+/// Identifier of the session, hashed from the nonces of all its parties
+fn session_id(protocol_type: i32, mut nonces: Vec<(u32, Vec<u8>)>) -> Vec<u8> {
+    nonces.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(SESSION_DOMAIN);
+    hasher.update(protocol_type.to_be_bytes());
+    for (index, nonce) in nonces {
+        hasher.update(index.to_be_bytes());
+        hasher.update(nonce);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Decode the result of a signing session, or `None` for other results
+fn signature_result(result: &[u8]) -> Option<SignatureResult> {
+    let result = SignatureResult::decode(result).ok()?;
+    let protocol_type = ProtocolType::try_from(result.protocol_type).ok()?;
+    let commitment = signers_commitment(protocol_type, &result.indices, &result.signature);
+    (commitment == result.signers_commitment).then(|| result)
+}
+
+/// Sign the commitment to the signers of a signing result by the DER
+/// encoded `key` of the device, passing other results through
+fn attest_signers(result: Vec<u8>, key: &[u8]) -> Result<Vec<u8>> {
+    let mut signature = match signature_result(&result) {
+        Some(signature) => signature,
+        None => return Ok(result),
+    };
+    let key = PKey::private_key_from_der(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(&[SIGNERS_DOMAIN, &signature.signers_commitment].concat())?;
+    signature.signers_attestation = signer.sign_to_vec()?;
+    Ok(signature.encode_to_vec())
+}
+
+/// Verify that the device with the DER encoded `certificate` attested
+/// the signers of the encoded `SignatureResult`
+pub fn verify_signers(result: &[u8], certificate: &[u8]) -> Result<()> {
+    let result = signature_result(result).ok_or("invalid signers commitment")?;
+    let key = X509::from_der(certificate)?.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+    verifier.update(&[SIGNERS_DOMAIN, &result.signers_commitment].concat())?;
+    if !verifier
+        .verify(&result.signers_attestation)
+        .unwrap_or(false)
+    {
+        return Err("invalid signers attestation".into());
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SignedContext {
+    inner: Box<dyn Protocol>,
+    /// DER encoded private key of the device
+    key: Vec<u8>,
+    /// Index of each party with the DER encoded public key of its device,
+    /// sorted by the index
+    peers: Vec<(u32, Vec<u8>)>,
+    /// Number of the outputs produced so far
+    round: u32,
+    /// Random nonce of the party, sent with its first messages
+    #[serde(default)]
+    nonce: Vec<u8>,
+    /// Identifier of the session, once the first messages are received
+    #[serde(default)]
+    session: Option<Vec<u8>>,
+}
+
+impl SignedContext {
+    pub(crate) fn new(mut inner: Box<dyn Protocol>, key: &[u8], devices: &[u8]) -> Result<Self> {
+        PKey::private_key_from_der(key)?;
+        let devices = GroupDevices::decode(devices)?;
+        if devices.indices.len() != devices.certificates.len() {
+            return Err("unexpected number of certificates".into());
+        }
+        let mut peers = devices
+            .indices
+            .into_iter()
+            .zip(devices.certificates)
+            .map(|(index, cert)| {
+                Ok((
+                    index,
+                    X509::from_der(&cert)?.public_key()?.public_key_to_der()?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        peers.sort_by_key(|(index, _)| *index);
+        if peers.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err("duplicate device".into());
+        }
+        inner.set_authenticated();
+        let mut nonce = vec![0; NONCE_LEN];
+        ProtocolRng.fill_bytes(&mut nonce);
+        Ok(Self {
+            inner,
+            key: key.to_vec(),
+            peers,
+            round: 0,
+            nonce,
+            session: None,
+        })
+    }
+
+    /// What the messages of the round are bound to besides their parties
+    fn session(&self, round: u32) -> Result<&[u8]> {
+        if round == 0 {
+            return Ok(&self.nonce);
+        }
+        Ok(self.session.as_deref().ok_or("session not established")?)
+    }
+
+    /// Indices of the recipients of the `n` messages of an output,
+    /// the other parties of the session in ascending order
+    fn recipients(&self, n: usize) -> Result<Vec<u32>> {
+        let others = match self.inner.others() {
+            Some(others) => others,
+            None => {
+                let own = self.own_index()?;
+                self.peers
+                    .iter()
+                    .map(|(index, _)| *index)
+                    .filter(|index| *index != own)
+                    .collect()
+            }
+        };
+        if n != 0 && n != others.len() {
+            return Err("unexpected number of messages".into());
+        }
+        Ok(others)
+    }
+
+    fn sign(&self, out: &[u8], round: u32) -> Result<Vec<u8>> {
+        let protocol_type = ProtocolMessage::decode(out)?.protocol_type;
+        let key = PKey::private_key_from_der(&self.key)?;
+        let own = self.own_index()?;
+        let session = self.session(round)?;
+        let nonce: &[u8] = if round == 0 { &self.nonce } else { &[] };
+        let msgs = unpack(out)?;
+        let msgs = self
+            .recipients(msgs.len())?
+            .into_iter()
+            .zip(msgs)
+            .map(|(recipient, payload)| {
+                let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+                signer.update(&signed_data(
+                    protocol_type,
+                    round,
+                    session,
+                    own,
+                    recipient,
+                    &payload,
+                ))?;
+                let signature = signer.sign_to_vec()?;
+                Ok([
+                    &[signature.len() as u8][..],
+                    &signature[..],
+                    nonce,
+                    &payload[..],
+                ]
+                .concat()
+                .into())
+            })
+            .collect::<Result<Vec<Bytes>>>()?;
+        let protocol_type = ProtocolType::try_from(protocol_type)?;
+        Ok(pack(msgs, protocol_type))
+    }
+
+    /// Verify the messages of the input, which are then relayed
+    /// to the wrapped context without their signatures, establishing
+    /// the session from the nonces of the first messages
+    fn verify(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolMessage::decode(data)?;
+        let protocol_type = msg.protocol_type;
+        let round = self.round - 1;
+        let own = self.own_index()?;
+        // the messages are ordered by their senders if they are named
+        let msgs = unpack(data)?;
+        let mut senders = msg.senders;
+        senders.sort_unstable();
+        let named = !senders.is_empty();
+        if !named {
+            if msgs.len() + 1 != self.peers.len() {
+                return Err("senders not named".into());
+            }
+            senders = self
+                .peers
+                .iter()
+                .map(|(index, _)| *index)
+                .filter(|index| *index != own)
+                .collect();
+        }
+
+        let mut nonces = vec![(own, self.nonce.clone())];
+        let payloads = senders
+            .iter()
+            .zip(msgs)
+            .map(|(sender, msg)| {
+                let invalid = || {
+                    telemetry!(warn, party = sender, "invalid signature");
+                    format!("invalid signature from party {}", sender)
+                };
+                let (_, key) = self
+                    .peers
+                    .iter()
+                    .find(|(index, _)| index == sender)
+                    .ok_or_else(|| format!("unknown sender {}", sender))?;
+                let len = *msg.first().ok_or_else(invalid)? as usize;
+                let nonce_len = if round == 0 { NONCE_LEN } else { 0 };
+                if msg.len() < len + 1 + nonce_len {
+                    return Err(invalid().into());
+                }
+                let signature = &msg[1..len + 1];
+                let nonce = &msg[len + 1..len + 1 + nonce_len];
+                let payload = msg.slice(len + 1 + nonce_len..);
+                let session = if round == 0 {
+                    nonce
+                } else {
+                    self.session(round)?
+                };
+
+                let key = PKey::public_key_from_der(key)?;
+                let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+                verifier.update(&signed_data(
+                    protocol_type,
+                    round,
+                    session,
+                    *sender,
+                    own,
+                    &payload,
+                ))?;
+                if !verifier.verify(signature).unwrap_or(false) {
+                    return Err(invalid().into());
+                }
+                nonces.push((*sender, nonce.to_vec()));
+                Ok(payload)
+            })
+            .collect::<Result<Vec<Bytes>>>()?;
+        if round == 0 {
+            self.session = Some(session_id(protocol_type, nonces));
+        }
+
+        Ok(ProtocolMessage {
+            protocol_type,
+            message: payloads,
+            senders: if named { senders } else { Vec::new() },
+            ..Default::default()
+        }
+        .encode_to_vec())
+    }
+
+    /// Index of the party whose device key this is
+    fn own_index(&self) -> Result<u32> {
+        let key = PKey::private_key_from_der(&self.key)?;
+        let public = key.public_key_to_der()?;
+        self.peers
+            .iter()
+            .find(|(_, peer)| *peer == public)
+            .map(|(index, _)| *index)
+            .ok_or_else(|| "device not in the group".into())
+    }
+}
+
+#[typetag::serde(name = "signed")]
+impl Protocol for SignedContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        // the first input initializes the session and comes from the server
+        let out = if self.round == 0 {
+            self.inner.advance(data)?
+        } else {
+            self.inner.advance(&self.verify(data)?)?
+        };
+        let out = self.sign(&out, self.round)?;
+        self.round += 1;
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let key = self.key;
+        attest_signers(self.inner.finish()?, &key)
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    /// The last output of the wrapped context, signed again
+    fn last_output(&self) -> Option<Vec<u8>> {
+        let out = self.inner.last_output()?;
+        self.sign(&out, self.round.checked_sub(1)?).ok()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("signed")
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolGroupInit};
+    use crate::protocol::{keygen_context, pack_signature};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+
+    /// Generate the keys of the devices with self-signed certificates
+    fn devices(n: u32) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let (keys, certificates) = (0..n)
+            .map(|_| {
+                let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+                let mut builder = X509::builder().unwrap();
+                builder.set_pubkey(&key).unwrap();
+                builder.sign(&key, MessageDigest::sha256()).unwrap();
+                let certificate = builder.build().to_der().unwrap();
+                (key.private_key_to_der().unwrap(), certificate)
+            })
+            .unzip();
+        let devices = GroupDevices {
+            indices: (1..=n).collect(),
+            certificates,
+        };
+        (keys, devices.encode_to_vec())
+    }
+
+    fn contexts() -> Vec<SignedContext> {
+        let (keys, devices) = devices(3);
+        sessions(&keys, &devices)
+    }
+
+    /// Contexts of a new session of the devices
+    fn sessions(keys: &[Vec<u8>], devices: &[u8]) -> Vec<SignedContext> {
+        keys.iter()
+            .map(|key| {
+                let inner = keygen_context(ProtocolType::Frost).unwrap();
+                SignedContext::new(inner, key, devices).unwrap()
+            })
+            .collect()
+    }
+
+    fn init(index: u32) -> Vec<u8> {
+        (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        })
+        .encode_to_vec()
+    }
+
+    /// Relay the messages to the `idx`-th party, naming the senders
+    /// in the reverse order if `named`
+    fn relay(outputs: &[Vec<Bytes>], idx: usize, named: bool) -> Vec<u8> {
+        let mut senders: Vec<u32> = (0..3).filter(|i| *i != idx as u32).collect();
+        if named {
+            senders.reverse();
+        }
+        ProtocolMessage {
+            protocol_type: ProtocolType::Frost as i32,
+            message: senders
+                .iter()
+                .map(|sender| {
+                    let sender = *sender as usize;
+                    outputs[sender][if idx < sender { idx } else { idx - 1 }].clone()
+                })
+                .collect(),
+            senders: if named {
+                senders.iter().map(|sender| sender + 1).collect()
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn unpacked(out: Vec<u8>) -> Vec<Bytes> {
+        ProtocolMessage::decode(&out[..]).unwrap().message
+    }
+
+    #[test]
+    fn keygen() {
+        let mut ctxs = contexts();
+        let mut outputs: Vec<Vec<Bytes>> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| unpacked(ctx.advance(&init(i as u32 + 1)).unwrap()))
+            .collect();
+
+        for named in [false, true] {
+            outputs = ctxs
+                .iter_mut()
+                .enumerate()
+                .map(|(idx, ctx)| unpacked(ctx.advance(&relay(&outputs, idx, named)).unwrap()))
+                .collect();
+        }
+
+        let pks: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| {
+                let result = Box::new(ctx).finish().unwrap();
+                GroupResult::decode(&result[..]).unwrap().public_key
+            })
+            .collect();
+        assert!(pks.iter().all(|pk| *pk == pks[0]));
+    }
+
+    #[test]
+    fn signers_attestation() {
+        let (keys, devices) = devices(2);
+        let certificates = GroupDevices::decode(&devices[..]).unwrap().certificates;
+        let result = pack_signature(ProtocolType::Frost, vec![1; 64], vec![1, 2]);
+
+        let attested = attest_signers(result.clone(), &keys[0]).unwrap();
+        assert!(verify_signers(&attested, &certificates[0]).is_ok());
+        assert!(verify_signers(&attested, &certificates[1]).is_err());
+        assert!(verify_signers(&result, &certificates[0]).is_err());
+
+        let mut forged = SignatureResult::decode(&attested[..]).unwrap();
+        forged.indices = vec![2, 1];
+        forged.signers_commitment =
+            signers_commitment(ProtocolType::Frost, &forged.indices, &forged.signature);
+        assert!(verify_signers(&forged.encode_to_vec(), &certificates[0]).is_err());
+
+        // other results pass through unchanged
+        let group = init(1);
+        assert_eq!(attest_signers(group.clone(), &keys[0]).unwrap(), group);
+    }
+
+    #[test]
+    fn forged_message() {
+        let mut ctxs = contexts();
+        let outputs: Vec<Vec<Bytes>> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| unpacked(ctx.advance(&init(i as u32 + 1)).unwrap()))
+            .collect();
+
+        // the server passes the package of party 2 off as that of party 3
+        let mut forged = outputs.clone();
+        forged[2][0] = outputs[1][0].clone();
+        let error = ctxs[0].advance(&relay(&forged, 0, false)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature from party 3");
+
+        // or replays the packages of the first round in the second one
+        for (idx, ctx) in ctxs.iter_mut().enumerate() {
+            ctx.advance(&relay(&outputs, idx, false)).unwrap();
+        }
+        let error = ctxs[0].advance(&relay(&outputs, 0, true)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature from party 2");
+    }
+
+    #[test]
+    fn bound_messages() {
+        let (keys, devices) = devices(3);
+        let mut ctxs = sessions(&keys, &devices);
+        let outputs: Vec<Vec<Bytes>> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| unpacked(ctx.advance(&init(i as u32 + 1)).unwrap()))
+            .collect();
+
+        // the server redirects the message of party 3 for party 2 to party 1
+        let mut redirected = outputs.clone();
+        redirected[2][0] = outputs[2][1].clone();
+        let error = ctxs[0].advance(&relay(&redirected, 0, false)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature from party 3");
+
+        // or replays the messages of another session of the same devices
+        let mut others = sessions(&keys, &devices);
+        let other_outputs: Vec<Vec<Bytes>> = others
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| unpacked(ctx.advance(&init(i as u32 + 1)).unwrap()))
+            .collect();
+        for (idx, ctx) in others.iter_mut().enumerate() {
+            ctx.advance(&relay(&other_outputs, idx, false)).unwrap();
+        }
+        let replayed: Vec<Vec<Bytes>> = others
+            .iter()
+            .map(|ctx| unpacked(ctx.last_output().unwrap()))
+            .collect();
+        for (idx, ctx) in ctxs.iter_mut().enumerate() {
+            ctx.advance(&relay(&outputs, idx, false)).unwrap();
+        }
+        let error = ctxs[0].advance(&relay(&replayed, 0, true)).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature from party 2");
+    }
+}
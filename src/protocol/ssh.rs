@@ -0,0 +1,300 @@
+//! OpenSSH encodings of the GG18 group key and signatures
+//!
+//! GG18 groups hold a P-256 key, which OpenSSH supports as the
+//! `ecdsa-sha2-nistp256` key type, so a group may act as an SSH certificate
+//! authority or sign data in the `sshsig` format of `ssh-keygen -Y sign`.
+//! The signing session is initialized with the digest returned by
+//! `signature_digest` (or `Certificate::signature_digest`), and the signature
+//! from its result is then encoded by `armor_signature` (or
+//! `Certificate::to_openssh`).
+
+use crate::protocol::Result;
+use openssl::base64;
+use p256::ecdsa::VerifyingKey;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+use std::convert::TryInto;
+
+const KEY_TYPE: &str = "ecdsa-sha2-nistp256";
+const CURVE: &str = "nistp256";
+const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+
+fn put_string(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(data);
+}
+
+fn put_mpint(out: &mut Vec<u8>, data: &[u8]) {
+    let start = data.iter().position(|&b| b != 0).unwrap_or(data.len());
+    let mut value = Vec::new();
+    if data.get(start).map_or(false, |b| b & 0x80 != 0) {
+        value.push(0);
+    }
+    value.extend(&data[start..]);
+    put_string(out, &value);
+}
+
+fn read_string<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    if data.len() < 4 {
+        return Err("malformed SSH key".into());
+    }
+    let (len, rest) = data.split_at(4);
+    let len = u32::from_be_bytes(len.try_into()?) as usize;
+    if rest.len() < len {
+        return Err("malformed SSH key".into());
+    }
+    let (value, rest) = rest.split_at(len);
+    *data = rest;
+    Ok(value)
+}
+
+/// Encode a SEC1 P-256 public key into an SSH public key blob
+fn key_blob(pk: &[u8]) -> Result<Vec<u8>> {
+    let pk = VerifyingKey::from_sec1_bytes(pk).map_err(|_| "invalid public key")?;
+    let mut blob = Vec::new();
+    put_string(&mut blob, KEY_TYPE.as_bytes());
+    put_string(&mut blob, CURVE.as_bytes());
+    put_string(&mut blob, pk.to_encoded_point(false).as_bytes());
+    Ok(blob)
+}
+
+/// Encode a 64-byte `r || s` signature into an SSH signature blob
+fn signature_blob(signature: &[u8]) -> Result<Vec<u8>> {
+    if signature.len() != 64 {
+        return Err("malformed signature".into());
+    }
+    let mut inner = Vec::new();
+    put_mpint(&mut inner, &signature[..32]);
+    put_mpint(&mut inner, &signature[32..]);
+
+    let mut blob = Vec::new();
+    put_string(&mut blob, KEY_TYPE.as_bytes());
+    put_string(&mut blob, &inner);
+    Ok(blob)
+}
+
+/// Format the group public key as a line of an `authorized_keys` file
+pub fn public_key(pk: &[u8], comment: &str) -> Result<String> {
+    let line = format!(
+        "{} {} {}",
+        KEY_TYPE,
+        base64::encode_block(&key_blob(pk)?),
+        comment
+    );
+    Ok(line.trim_end().to_string())
+}
+
+/// Parse a line of an `authorized_keys` file into the public key blob
+fn parse_public_key(line: &str) -> Result<Vec<u8>> {
+    let mut parts = line.split_whitespace();
+    let key_type = parts.next().ok_or("malformed SSH key")?;
+    let blob = base64::decode_block(parts.next().ok_or("malformed SSH key")?)?;
+    if read_string(&mut &blob[..])? != key_type.as_bytes() {
+        return Err("malformed SSH key".into());
+    }
+    Ok(blob)
+}
+
+fn signed_data(message: &[u8], namespace: &str) -> Vec<u8> {
+    let mut data = b"SSHSIG".to_vec();
+    put_string(&mut data, namespace.as_bytes());
+    put_string(&mut data, b"");
+    put_string(&mut data, b"sha512");
+    put_string(&mut data, &Sha512::digest(message));
+    data
+}
+
+/// Compute the digest to be signed for an `sshsig` signature of `message`
+pub fn signature_digest(message: &[u8], namespace: &str) -> Vec<u8> {
+    Sha256::digest(signed_data(message, namespace)).to_vec()
+}
+
+/// Encode a signature of the digest from `signature_digest` in the armored
+/// `sshsig` format accepted by `ssh-keygen -Y verify`
+pub fn armor_signature(pk: &[u8], namespace: &str, signature: &[u8]) -> Result<String> {
+    let mut blob = b"SSHSIG".to_vec();
+    blob.extend(1u32.to_be_bytes());
+    put_string(&mut blob, &key_blob(pk)?);
+    put_string(&mut blob, namespace.as_bytes());
+    put_string(&mut blob, b"");
+    put_string(&mut blob, b"sha512");
+    put_string(&mut blob, &signature_blob(signature)?);
+
+    let encoded = base64::encode_block(&blob);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(70)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+    Ok(format!(
+        "-----BEGIN SSH SIGNATURE-----\n{}\n-----END SSH SIGNATURE-----\n",
+        lines.join("\n")
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertType {
+    User = 1,
+    Host = 2,
+}
+
+/// OpenSSH certificate of a key issued by the group
+pub struct Certificate {
+    pub nonce: [u8; 32],
+    /// Public key blob of the certified key
+    pub key: Vec<u8>,
+    pub serial: u64,
+    pub cert_type: CertType,
+    pub key_id: String,
+    /// Principals the certificate is valid for, all if empty
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    /// Critical options with their values, such as `force-command`
+    pub critical_options: Vec<(String, String)>,
+    /// Flag extensions, such as `permit-pty`
+    pub extensions: Vec<String>,
+}
+
+impl Certificate {
+    /// Create a certificate of the key from a line of an `authorized_keys`
+    /// file, valid forever for all principals and without any extensions
+    pub fn new(key: &str, cert_type: CertType, key_id: &str) -> Result<Self> {
+        let mut nonce = [0u8; 32];
+        OsRng.fill_bytes(&mut nonce);
+        Ok(Self {
+            nonce,
+            key: parse_public_key(key)?,
+            serial: 0,
+            cert_type,
+            key_id: key_id.to_string(),
+            principals: Vec::new(),
+            valid_after: 0,
+            valid_before: u64::MAX,
+            critical_options: Vec::new(),
+            extensions: Vec::new(),
+        })
+    }
+
+    fn body(&self, ca: &[u8]) -> Result<Vec<u8>> {
+        let mut key = &self.key[..];
+        let key_type = read_string(&mut key)?;
+
+        let mut body = Vec::new();
+        put_string(&mut body, &[key_type, CERT_SUFFIX.as_bytes()].concat());
+        put_string(&mut body, &self.nonce);
+        body.extend(key);
+        body.extend(self.serial.to_be_bytes());
+        body.extend((self.cert_type as u32).to_be_bytes());
+        put_string(&mut body, self.key_id.as_bytes());
+
+        let mut principals = Vec::new();
+        for principal in &self.principals {
+            put_string(&mut principals, principal.as_bytes());
+        }
+        put_string(&mut body, &principals);
+        body.extend(self.valid_after.to_be_bytes());
+        body.extend(self.valid_before.to_be_bytes());
+
+        let mut options: Vec<_> = self.critical_options.iter().collect();
+        options.sort();
+        let mut critical_options = Vec::new();
+        for (name, value) in options {
+            let mut data = Vec::new();
+            put_string(&mut data, value.as_bytes());
+            put_string(&mut critical_options, name.as_bytes());
+            put_string(&mut critical_options, &data);
+        }
+        put_string(&mut body, &critical_options);
+
+        let mut names: Vec<_> = self.extensions.iter().collect();
+        names.sort();
+        let mut extensions = Vec::new();
+        for name in names {
+            put_string(&mut extensions, name.as_bytes());
+            put_string(&mut extensions, b"");
+        }
+        put_string(&mut body, &extensions);
+
+        put_string(&mut body, b"");
+        put_string(&mut body, &key_blob(ca)?);
+        Ok(body)
+    }
+
+    /// Compute the digest to be signed by the group with public key `ca`
+    pub fn signature_digest(&self, ca: &[u8]) -> Result<Vec<u8>> {
+        Ok(Sha256::digest(self.body(ca)?).to_vec())
+    }
+
+    /// Format the certificate signed by the group as an OpenSSH
+    /// `*-cert.pub` line
+    pub fn to_openssh(&self, ca: &[u8], signature: &[u8]) -> Result<String> {
+        let mut blob = self.body(ca)?;
+        put_string(&mut blob, &signature_blob(signature)?);
+        let key_type = read_string(&mut &blob[..])?;
+        Ok(format!(
+            "{} {}",
+            std::str::from_utf8(key_type)?,
+            base64::encode_block(&blob)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+    #[test]
+    fn mpint() {
+        let mut out = Vec::new();
+        put_mpint(&mut out, &[0, 0, 0x7f, 1]);
+        put_mpint(&mut out, &[0, 0x80]);
+        put_mpint(&mut out, &[0, 0]);
+        assert_eq!(out, [0, 0, 0, 2, 0x7f, 1, 0, 0, 0, 2, 0, 0x80, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sshsig() {
+        let key = SigningKey::random(&mut OsRng);
+        let pk = key.verifying_key().to_sec1_bytes();
+        let line = public_key(&pk, "group").unwrap();
+        assert!(line.starts_with("ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYA"));
+        assert_eq!(parse_public_key(&line).unwrap(), key_blob(&pk).unwrap());
+
+        // signing the data is equivalent to the group signing its digest
+        let data = signed_data(b"message", "file");
+        let signature: Signature = key.sign(&data);
+        assert!(crate::protocol::gg18::verify(&pk, &data, &signature.to_bytes()).is_ok());
+
+        let armored = armor_signature(&pk, "file", &signature.to_bytes()).unwrap();
+        assert!(armored.starts_with("-----BEGIN SSH SIGNATURE-----\nU1NIU0lHAAAAAQ"));
+        assert!(armored.lines().all(|line| line.len() <= 70));
+        assert!(armor_signature(&pk, "file", &[0; 63]).is_err());
+    }
+
+    #[test]
+    fn certificate() {
+        let ca = SigningKey::random(&mut OsRng);
+        let ca_pk = ca.verifying_key().to_sec1_bytes();
+        let user = public_key(
+            &SigningKey::random(&mut OsRng)
+                .verifying_key()
+                .to_sec1_bytes(),
+            "",
+        )
+        .unwrap();
+
+        let mut cert = Certificate::new(&user, CertType::User, "alice").unwrap();
+        cert.principals.push("alice".to_string());
+        cert.extensions = vec![
+            "permit-pty".to_string(),
+            "permit-agent-forwarding".to_string(),
+        ];
+
+        let signature: Signature = ca.sign(&cert.body(&ca_pk).unwrap());
+        let line = cert.to_openssh(&ca_pk, &signature.to_bytes()).unwrap();
+        assert!(line.starts_with("ecdsa-sha2-nistp256-cert-v01@openssh.com "));
+        assert!(Certificate::new("ssh-ed25519 AAAA", CertType::User, "").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Group results encrypted at rest under a storage key of the client
+//!
+//! A stored group consists of a random nonce followed by the group result
+//! encrypted by AES-256-GCM under the 32-byte storage key, e.g. one kept in
+//! the keystore of the device. When the client rotates its storage key,
+//! `rewrap` decrypts and encrypts the group again within the library,
+//! so the group never reaches the calling language in plaintext, and
+//! `threshold_context` creates the context of a session from the stored
+//! group directly for the same reason.
+//!
+//! The encryption is bound to the identifier of the group given by the
+//! client, e.g. its public key, so that the stored blobs of two groups
+//! cannot be swapped in the storage unnoticed. Groups stored without an
+//! identifier are bound to the empty one.
+
+use crate::proto::ProtocolType;
+use crate::protocol::{Protocol, Result};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use rand::{rngs::OsRng, RngCore};
+
+const DOMAIN: &[u8] = b"meesign-storage";
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+fn check_key(key: &[u8]) -> Result<()> {
+    if key.len() != KEY_SIZE {
+        return Err("invalid storage key".into());
+    }
+    Ok(())
+}
+
+fn aad(group_id: &[u8]) -> Vec<u8> {
+    [DOMAIN, group_id].concat()
+}
+
+/// Encrypt a group result identified by `group_id` under the storage
+/// key `key`
+pub fn encrypt_group(group: &[u8], key: &[u8], group_id: &[u8]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let mut tag = [0u8; TAG_SIZE];
+    let ct = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &aad(group_id),
+        group,
+        &mut tag,
+    )
+    .map_err(|_| "encryption failed")?;
+    Ok([&nonce[..], &ct, &tag].concat())
+}
+
+/// Decrypt a group result stored by `encrypt_group` under `key`
+/// and `group_id`
+pub fn decrypt_group(stored: &[u8], key: &[u8], group_id: &[u8]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    if stored.len() < NONCE_SIZE + TAG_SIZE {
+        return Err("truncated stored group".into());
+    }
+    let (nonce, rest) = stored.split_at(NONCE_SIZE);
+    let (ct, tag) = rest.split_at(rest.len() - TAG_SIZE);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &aad(group_id),
+        ct,
+        tag,
+    )
+    .map_err(|_| "group not stored under this key and identifier".into())
+}
+
+/// Encrypt a group stored under `old_key` under `new_key` instead
+pub fn rewrap(stored: &[u8], old_key: &[u8], new_key: &[u8], group_id: &[u8]) -> Result<Vec<u8>> {
+    check_key(new_key)?;
+    encrypt_group(
+        &decrypt_group(stored, old_key, group_id)?,
+        new_key,
+        group_id,
+    )
+}
+
+/// Create a threshold protocol context, see `protocol::threshold_context`,
+/// from a group stored under `key` and `group_id`
+pub fn threshold_context(
+    protocol_type: ProtocolType,
+    stored: &[u8],
+    key: &[u8],
+    group_id: &[u8],
+) -> Result<Box<dyn Protocol>> {
+    let group = decrypt_group(stored, key, group_id)?;
+    crate::protocol::threshold_context(protocol_type, &group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation() {
+        let group = b"group result".to_vec();
+        let stored = encrypt_group(&group, &[1; KEY_SIZE], b"id").unwrap();
+        assert_eq!(
+            decrypt_group(&stored, &[1; KEY_SIZE], b"id").unwrap(),
+            group
+        );
+
+        let rewrapped = rewrap(&stored, &[1; KEY_SIZE], &[2; KEY_SIZE], b"id").unwrap();
+        assert_ne!(rewrapped, stored);
+        assert_eq!(
+            decrypt_group(&rewrapped, &[2; KEY_SIZE], b"id").unwrap(),
+            group
+        );
+        assert!(decrypt_group(&rewrapped, &[1; KEY_SIZE], b"id").is_err());
+
+        assert!(rewrap(&stored, &[2; KEY_SIZE], &[3; KEY_SIZE], b"id").is_err());
+        assert!(rewrap(&stored, &[1; KEY_SIZE], &[3; 16], b"id").is_err());
+        let truncated = &stored[..NONCE_SIZE + TAG_SIZE - 1];
+        assert!(decrypt_group(truncated, &[1; KEY_SIZE], b"id").is_err());
+    }
+
+    #[test]
+    fn group_id() {
+        let stored = encrypt_group(b"group result", &[1; KEY_SIZE], b"id").unwrap();
+        assert!(decrypt_group(&stored, &[1; KEY_SIZE], b"other").is_err());
+        assert!(decrypt_group(&stored, &[1; KEY_SIZE], b"").is_err());
+        assert!(rewrap(&stored, &[1; KEY_SIZE], &[2; KEY_SIZE], b"other").is_err());
+        assert!(threshold_context(ProtocolType::Frost, &stored, &[1; KEY_SIZE], b"other").is_err());
+    }
+}
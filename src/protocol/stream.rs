@@ -0,0 +1,124 @@
+//! Chunked encryption of large data to an ElGamal group
+//!
+//! Only a fresh content key is encrypted to the group, by `encrypt_stream`,
+//! and the data is encrypted chunk by chunk under it, so neither side has
+//! to hold the whole data in memory. The group decrypts the header returned
+//! by `encrypt_stream` with the regular ElGamal `DecryptContext`, whose
+//! plaintext is the content key passed to `decrypt_chunk`.
+//!
+//! The chunks follow the STREAM construction over AES-128-GCM: the nonce
+//! of each chunk consists of its index and a flag marking the last chunk,
+//! so reordered, dropped or truncated chunks fail to decrypt. Every chunk
+//! but the last has to be exactly `CHUNK_SIZE` bytes long, which makes each
+//! encrypted chunk `TAG_SIZE` bytes longer.
+
+use crate::protocol::{elgamal, Result};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes128Gcm, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+pub const TAG_SIZE: usize = 16;
+
+fn nonce(index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[7..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn cipher(key: &[u8]) -> Result<Aes128Gcm> {
+    if key.len() != 16 {
+        return Err("invalid content key".into());
+    }
+    Ok(Aes128Gcm::new(Key::<Aes128Gcm>::from_slice(key)))
+}
+
+/// Generate a content key and encrypt it to the group public key `pk`,
+/// returning the key together with the header to be stored with the data
+pub fn encrypt_stream(pk: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut key = vec![0u8; 16];
+    OsRng.fill_bytes(&mut key);
+    let header = elgamal::encrypt(&key, pk)?;
+    Ok((key, header))
+}
+
+/// Encrypt the chunk at `index`, where `last` marks the final chunk
+pub fn encrypt_chunk(key: &[u8], index: u32, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+    if chunk.len() > CHUNK_SIZE || (!last && chunk.len() != CHUNK_SIZE) {
+        return Err("invalid chunk size".into());
+    }
+    cipher(key)?
+        .encrypt(Nonce::from_slice(&nonce(index, last)), chunk)
+        .map_err(|_| "encryption failed".into())
+}
+
+/// Decrypt the chunk at `index` with the content key decrypted by the group
+pub fn decrypt_chunk(key: &[u8], index: u32, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+    if chunk.len() > CHUNK_SIZE + TAG_SIZE || (!last && chunk.len() != CHUNK_SIZE + TAG_SIZE) {
+        return Err("invalid chunk size".into());
+    }
+    cipher(key)?
+        .decrypt(Nonce::from_slice(&nonce(index, last)), chunk)
+        .map_err(|_| "decryption failed".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::DecryptResult;
+    use crate::protocol::elgamal::{DecryptContext, KeygenContext};
+    use crate::protocol::tests::{KeygenProtocolTest, ThresholdProtocolTest};
+    use prost::Message;
+
+    fn encrypt_all(key: &[u8], data: &[u8]) -> Vec<Vec<u8>> {
+        let chunks: Vec<&[u8]> = data.chunks(CHUNK_SIZE).collect();
+        let count = chunks.len().max(1);
+        (0..count)
+            .map(|i| {
+                let chunk = chunks.get(i).copied().unwrap_or_default();
+                encrypt_chunk(key, i as u32, chunk, i + 1 == count).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let (pks, ctxs) = <KeygenContext as KeygenProtocolTest>::run(2, 3);
+        let (key, header) = encrypt_stream(&pks[0]).unwrap();
+
+        let results = <DecryptContext as ThresholdProtocolTest>::run(ctxs, vec![0, 2], header);
+        let decrypted = DecryptResult::decode(&results[0][..]).unwrap().plaintexts;
+        assert_eq!(decrypted, vec![key.clone()]);
+
+        let data: Vec<u8> = (0..2 * CHUNK_SIZE + 100).map(|i| i as u8).collect();
+        let encrypted = encrypt_all(&key, &data);
+        assert_eq!(encrypted.len(), 3);
+
+        let count = encrypted.len();
+        let mut plaintext = Vec::new();
+        for (i, chunk) in encrypted.iter().enumerate() {
+            plaintext
+                .extend(decrypt_chunk(&decrypted[0], i as u32, chunk, i + 1 == count).unwrap());
+        }
+        assert_eq!(plaintext, data);
+    }
+
+    #[test]
+    fn tampering() {
+        let key = vec![7u8; 16];
+        let encrypted = encrypt_all(&key, &vec![1u8; CHUNK_SIZE + 1]);
+
+        // swapped, truncated or extended streams are rejected
+        assert!(decrypt_chunk(&key, 1, &encrypted[0], false).is_err());
+        assert!(decrypt_chunk(&key, 0, &encrypted[0], true).is_err());
+        assert!(decrypt_chunk(&key, 1, &encrypted[1], false).is_err());
+        assert!(decrypt_chunk(&key, 1, &encrypted[1], true).is_ok());
+
+        assert_eq!(encrypt_all(&key, b"").len(), 1);
+        assert!(encrypt_chunk(&key, 0, b"short", false).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Telemetry of the sessions through the `tracing` crate
+//!
+//! With the `tracing` feature, the contexts emit events when a round starts,
+//! finishes or fails, and when they blame a party, so that integrators can
+//! tell which round of which session failed and how long the rounds take.
+//! The events never carry secret material: data derived from secrets is
+//! recorded only as `Redacted`, which shows nothing but its length, and the
+//! hook drops any field named after a secret in case one slips through.
+//! The library never reads the clock, which is not available on wasm32;
+//! the hook set by `set_telemetry_hook` timestamps the events itself.
+
+/// Emit a `tracing` event if the feature is enabled, or nothing otherwise
+macro_rules! telemetry {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!(target: "meesign_crypto", $($arg)+);
+    };
+}
+pub(crate) use telemetry;
+
+#[cfg(feature = "tracing")]
+pub(crate) use hook::Redacted;
+#[cfg(feature = "tracing")]
+pub use hook::{set_telemetry_hook, TelemetryEvent, TelemetryHook};
+
+#[cfg(feature = "tracing")]
+mod hook {
+    use crate::protocol::Result;
+    use serde::Serialize;
+    use std::fmt;
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    /// Substrings of the names of the fields the hook never passes on
+    const SECRET_FIELDS: &[&str] = &["secret", "share", "nonce", "key", "seed"];
+
+    /// Data derived from secret material, recorded only by its length
+    pub(crate) struct Redacted<'a>(pub(crate) &'a [u8]);
+
+    impl fmt::Debug for Redacted<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "[redacted; {} bytes]", self.0.len())
+        }
+    }
+
+    /// Event emitted by the library, see `set_telemetry_hook`
+    #[derive(Debug, Clone, Serialize)]
+    pub struct TelemetryEvent {
+        pub level: String,
+        pub message: String,
+        /// Named values of the event, e.g. the round or the blamed party
+        pub fields: Vec<(String, String)>,
+    }
+
+    pub type TelemetryHook = Box<dyn Fn(&TelemetryEvent) + Send + Sync>;
+
+    #[derive(Default)]
+    struct Fields {
+        message: String,
+        fields: Vec<(String, String)>,
+    }
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let name = field.name();
+            if name == "message" {
+                self.message = format!("{:?}", value);
+            } else if SECRET_FIELDS.iter().any(|secret| name.contains(secret)) {
+                self.fields.push((name.into(), "[redacted]".into()));
+            } else {
+                self.fields.push((name.into(), format!("{:?}", value)));
+            }
+        }
+    }
+
+    pub(super) struct HookLayer(pub(super) TelemetryHook);
+
+    impl<S: Subscriber> Layer<S> for HookLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let metadata = event.metadata();
+            if metadata.target() != "meesign_crypto" {
+                return;
+            }
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            (self.0)(&TelemetryEvent {
+                level: metadata.level().to_string(),
+                message: fields.message,
+                fields: fields.fields,
+            });
+        }
+    }
+
+    /// Pass the events of the library to `hook`, which may be set only once
+    /// as it becomes the global `tracing` subscriber; integrators with
+    /// a subscriber of their own should consume the events through it
+    pub fn set_telemetry_hook(hook: TelemetryHook) -> Result<()> {
+        let subscriber = tracing_subscriber::registry().with(HookLayer(hook));
+        tracing::subscriber::set_global_default(subscriber)
+            .map_err(|_| "telemetry hook already set".into())
+    }
+}
+
+#[cfg(all(test, feature = "tracing", feature = "frost"))]
+mod tests {
+    use super::hook::*;
+    use crate::proto::{ProtocolGroupInit, ProtocolType};
+    use crate::protocol::keygen_context;
+    use prost::Message;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn round_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let hook: TelemetryHook = Box::new(move |event| sink.lock().unwrap().push(event.clone()));
+        let subscriber = tracing_subscriber::registry().with(HookLayer(hook));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+            let init = (ProtocolGroupInit {
+                protocol_type: ProtocolType::Frost as i32,
+                index: 1,
+                parties: 3,
+                threshold: 2,
+                weights: Vec::new(),
+                encrypt: false,
+                metadata: None,
+                version: 0,
+            })
+            .encode_to_vec();
+            ctx.advance(&init).unwrap();
+            assert!(ctx.advance(b"garbage").is_err());
+        });
+
+        let events = events.lock().unwrap();
+        let messages: Vec<_> = events.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "advancing",
+                "protocol version negotiated",
+                "advanced",
+                "advancing",
+                "advance failed"
+            ]
+        );
+        assert!(events[2].fields.contains(&("round".into(), "R1".into())));
+        let input = format!("[redacted; {} bytes]", b"garbage".len());
+        assert!(events[3].fields.contains(&("input".into(), input)));
+    }
+
+    #[test]
+    fn redacted() {
+        assert_eq!(format!("{:?}", Redacted(&[1, 2, 3])), "[redacted; 3 bytes]");
+    }
+}
@@ -0,0 +1,205 @@
+//! Transcripts of protocol sessions kept as evidence of the participation
+//!
+//! Every round records the digests of the input delivered from the server
+//! and of the output sent back, together with a digest chaining it to the
+//! previous rounds:
+//! `digest = SHA-256(previous digest || SHA-256(input) || SHA-256(output))`,
+//! starting from 32 zero bytes. The digest of the last round thus commits
+//! to the whole session.
+//!
+//! Only the input of the first round, which initializes the session with
+//! the indices of the parties and the data, is kept in full. The messages
+//! exchanged later may carry secret shares, so the transcript holds just
+//! their digests; the parties can still open them to an auditor later.
+
+use crate::proto::{Transcript, TranscriptRound};
+use crate::protocol::{DebugState, Protocol, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+fn chain(previous: &[u8], input_digest: &[u8], output_digest: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(input_digest);
+    hasher.update(output_digest);
+    hasher.finalize().to_vec()
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TranscriptContext {
+    inner: Box<dyn Protocol>,
+    /// Input of the first round, which initializes the session
+    init: Vec<u8>,
+    /// Digests of the input and output and the chained digest of each round
+    digests: Vec<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+}
+
+impl TranscriptContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>) -> Self {
+        Self {
+            inner,
+            init: Vec::new(),
+            digests: Vec::new(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let rounds = self
+            .digests
+            .iter()
+            .enumerate()
+            .map(
+                |(round, (input_digest, output_digest, digest))| TranscriptRound {
+                    input: if round == 0 {
+                        self.init.clone()
+                    } else {
+                        Vec::new()
+                    },
+                    output: Vec::new(),
+                    digest: digest.clone(),
+                    input_digest: input_digest.clone(),
+                    output_digest: output_digest.clone(),
+                },
+            )
+            .collect();
+        Transcript { rounds }.encode_to_vec()
+    }
+}
+
+#[typetag::serde(name = "transcript")]
+impl Protocol for TranscriptContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let out = self.inner.advance(data)?;
+        let input_digest = Sha256::digest(data).to_vec();
+        let output_digest = Sha256::digest(&out).to_vec();
+        // a repeated delivery replayed by the inner context is recorded once
+        if let Some((input, output, _)) = self.digests.last() {
+            if input == &input_digest && output == &output_digest {
+                return Ok(out);
+            }
+        }
+
+        if self.digests.is_empty() {
+            self.init = data.to_vec();
+        }
+        let previous = self
+            .digests
+            .last()
+            .map_or(vec![0; 32], |round| round.2.clone());
+        let digest = chain(&previous, &input_digest, &output_digest);
+        self.digests.push((input_digest, output_digest, digest));
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        Some(self.encode())
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("transcript")
+    }
+}
+
+/// Digest of a payload of a transcript round, checked against the stated
+/// one unless the payload is withheld; transcripts recorded before the
+/// digests were stated carry only the payloads
+fn payload_digest(payload: &[u8], stated: Vec<u8>) -> Result<Vec<u8>> {
+    let digest = Sha256::digest(payload).to_vec();
+    if stated.is_empty() {
+        return Ok(digest);
+    }
+    if !payload.is_empty() && digest != stated {
+        return Err("invalid transcript digest".into());
+    }
+    Ok(stated)
+}
+
+/// Check the digests of a transcript, returning the digest of the last
+/// round which commits to the whole session
+pub fn verify_transcript(transcript: &[u8]) -> Result<Vec<u8>> {
+    let transcript = Transcript::decode(transcript)?;
+    let mut previous = vec![0; 32];
+    for round in transcript.rounds {
+        let input_digest = payload_digest(&round.input, round.input_digest)?;
+        let output_digest = payload_digest(&round.output, round.output_digest)?;
+        if chain(&previous, &input_digest, &output_digest) != round.digest {
+            return Err("invalid transcript digest".into());
+        }
+        previous = round.digest;
+    }
+    Ok(previous)
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{ProtocolGroupInit, ProtocolType};
+    use crate::protocol::{keygen_context, with_transcript};
+
+    #[test]
+    fn record() {
+        let mut ctx = with_transcript(keygen_context(ProtocolType::Frost).unwrap());
+        let init = (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index: 1,
+            parties: 3,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version: 0,
+        })
+        .encode_to_vec();
+        let out = ctx.advance(&init).unwrap();
+        ctx.advance(&init).unwrap();
+
+        let transcript = ctx.transcript().unwrap();
+        let digest = verify_transcript(&transcript).unwrap();
+        let expected = chain(&[0; 32], &Sha256::digest(&init), &Sha256::digest(&out));
+        assert_eq!(digest, expected);
+
+        let mut decoded = Transcript::decode(&transcript[..]).unwrap();
+        assert_eq!(decoded.rounds.len(), 1);
+        assert_eq!(decoded.rounds[0].input, init);
+        assert!(decoded.rounds[0].output.is_empty());
+
+        // the payloads open the digests, and the legacy transcripts
+        // which carry only the payloads remain valid
+        let mut legacy = decoded.clone();
+        legacy.rounds[0].output = out;
+        legacy.rounds[0].input_digest.clear();
+        legacy.rounds[0].output_digest.clear();
+        assert_eq!(
+            verify_transcript(&legacy.encode_to_vec()).unwrap(),
+            expected
+        );
+
+        decoded.rounds[0].output.push(0);
+        assert!(verify_transcript(&decoded.encode_to_vec()).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Negotiation of the protocol version of a session
+//!
+//! The server picks the version of a session from the versions announced
+//! in the envelopes of the parties and passes it in the initialization
+//! message, see `InitVersion`. The wrapped context then encodes all of its
+//! messages for that version, so that clients not yet upgraded can take
+//! part in the sessions of upgraded ones.
+
+use crate::proto::InitVersion;
+use crate::protocol::telemetry::telemetry;
+use crate::protocol::{with_version, DebugState, Protocol, Result, PROTOCOL_VERSION};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct VersionContext {
+    inner: Box<dyn Protocol>,
+    /// Version of the session, known once the context is initialized
+    version: Option<u32>,
+}
+
+impl VersionContext {
+    pub(crate) fn new(inner: Box<dyn Protocol>) -> Self {
+        Self {
+            inner,
+            version: None,
+        }
+    }
+}
+
+#[typetag::serde(name = "version")]
+impl Protocol for VersionContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let version = match self.version {
+            Some(version) => version,
+            // a malformed message is left for the wrapped context to reject
+            None => match InitVersion::decode(data).map_or(0, |msg| msg.version) {
+                0 => PROTOCOL_VERSION,
+                version if version > PROTOCOL_VERSION => {
+                    return Err("unsupported protocol version".into())
+                }
+                version => version,
+            },
+        };
+        let out = with_version(version, || self.inner.advance(data))?;
+        if self.version.is_none() {
+            telemetry!(debug, version, "protocol version negotiated");
+        }
+        self.version = Some(version);
+        Ok(out)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        self.inner.finish()
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.inner.expected_messages()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.inner.set_authenticated()
+    }
+
+    fn others(&self) -> Option<Vec<u32>> {
+        self.inner.others()
+    }
+
+    fn transcript(&self) -> Option<Vec<u8>> {
+        self.inner.transcript()
+    }
+
+    fn last_output(&self) -> Option<Vec<u8>> {
+        self.inner.last_output()
+    }
+
+    fn counted_group(&self) -> Option<Vec<u8>> {
+        self.inner.counted_group()
+    }
+
+    fn debug_state(&self) -> DebugState {
+        self.inner.debug_state().wrapped("version")
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::{GroupResult, ProtocolGroupInit, ProtocolMessage, ProtocolType};
+    use crate::protocol::{keygen_context, VERSION_JSON};
+
+    fn init(index: u32, version: u32) -> Vec<u8> {
+        (ProtocolGroupInit {
+            protocol_type: ProtocolType::Frost as i32,
+            index,
+            parties: 2,
+            threshold: 2,
+            weights: Vec::new(),
+            encrypt: false,
+            metadata: None,
+            version,
+        })
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn first_version() {
+        let mut ctxs: Vec<Box<dyn Protocol>> = (0..2)
+            .map(|_| keygen_context(ProtocolType::Frost).unwrap())
+            .collect();
+        let mut outputs: Vec<ProtocolMessage> = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, ctx)| {
+                let out = ctx.advance(&init(i as u32 + 1, VERSION_JSON)).unwrap();
+                ProtocolMessage::decode(&out[..]).unwrap()
+            })
+            .collect();
+
+        for _ in 0..2 {
+            for output in &outputs {
+                assert_eq!(output.version, VERSION_JSON);
+                assert!(output.message.iter().all(|msg| msg.first() == Some(&b'{')));
+            }
+            outputs = ctxs
+                .iter_mut()
+                .enumerate()
+                .map(|(i, ctx)| {
+                    let input = ProtocolMessage {
+                        protocol_type: ProtocolType::Frost as i32,
+                        message: outputs[1 - i].message.clone(),
+                        ..Default::default()
+                    };
+                    let out = ctx.advance(&input.encode_to_vec()).unwrap();
+                    ProtocolMessage::decode(&out[..]).unwrap()
+                })
+                .collect();
+        }
+
+        let pks: Vec<Vec<u8>> = ctxs
+            .into_iter()
+            .map(|ctx| {
+                GroupResult::decode(&ctx.finish().unwrap()[..])
+                    .unwrap()
+                    .public_key
+            })
+            .collect();
+        assert_eq!(pks[0], pks[1]);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut ctx = keygen_context(ProtocolType::Frost).unwrap();
+        assert!(ctx.advance(&init(1, PROTOCOL_VERSION + 1)).is_err());
+        assert!(ctx.advance(&init(1, PROTOCOL_VERSION)).is_ok());
+    }
+}
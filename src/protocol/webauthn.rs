@@ -0,0 +1,139 @@
+//! WebAuthn assertions signed by a GG18 group
+//!
+//! GG18 groups hold a P-256 key, which WebAuthn supports as the ES256
+//! algorithm, so a group may back a roaming authenticator. The group key is
+//! registered with the relying party as the credential key encoded by
+//! `cose_key`. An assertion is signed by a session initialized with the
+//! digest returned by `signature_digest` for the authenticator data, e.g.
+//! from `authenticator_data`, and the client data, and its signature is
+//! then put together with them by `assertion` in the JSON form of a
+//! `PublicKeyCredential` relying parties accept.
+
+use crate::protocol::Result;
+use openssl::base64;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// User present, as the members of the group approve every assertion
+const USER_PRESENT: u8 = 0x01;
+/// User verified, only if the members of the group verified themselves
+const USER_VERIFIED: u8 = 0x04;
+
+fn base64url(data: &[u8]) -> String {
+    base64::encode_block(data)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Encode the compressed or uncompressed SEC1 group key
+/// as a COSE_Key of the ES256 algorithm
+pub fn cose_key(pk: &[u8]) -> Result<Vec<u8>> {
+    let key = VerifyingKey::from_sec1_bytes(pk).map_err(|_| "invalid public key")?;
+    let point = key.to_encoded_point(false);
+    // {1: 2 (EC2), 3: -7 (ES256), -1: 1 (P-256), -2: x, -3: y}
+    let mut cose = vec![0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20];
+    cose.extend(point.x().ok_or("invalid public key")?);
+    cose.extend([0x22, 0x58, 0x20]);
+    cose.extend(point.y().ok_or("invalid public key")?);
+    Ok(cose)
+}
+
+/// Authenticator data of an assertion for the relying party `rp_id`
+/// without any extensions; `user_verified` is to be set only if the
+/// approving members were verified, e.g. by a PIN or biometrics
+pub fn authenticator_data(rp_id: &str, sign_count: u32, user_verified: bool) -> Vec<u8> {
+    let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+    data.push(if user_verified {
+        USER_PRESENT | USER_VERIFIED
+    } else {
+        USER_PRESENT
+    });
+    data.extend(sign_count.to_be_bytes());
+    data
+}
+
+/// Compute the digest to be signed for an assertion
+pub fn signature_digest(authenticator_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(authenticator_data)
+        .chain_update(Sha256::digest(client_data_json))
+        .finalize()
+        .to_vec()
+}
+
+/// Encode an assertion as the JSON form of a `PublicKeyCredential`,
+/// given the raw or DER-encoded signature of the digest from
+/// `signature_digest`, which is DER-encoded as WebAuthn requires
+pub fn assertion(
+    credential_id: &[u8],
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> Result<String> {
+    let signature = Signature::from_slice(signature)
+        .or_else(|_| Signature::from_der(signature))
+        .map_err(|_| "malformed signature")?;
+    let credential = json!({
+        "id": base64url(credential_id),
+        "rawId": base64url(credential_id),
+        "type": "public-key",
+        "response": {
+            "authenticatorData": base64url(authenticator_data),
+            "clientDataJSON": base64url(client_data_json),
+            "signature": base64url(signature.to_der().as_bytes()),
+        },
+        "clientExtensionResults": {},
+    });
+    Ok(credential.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, signature::Verifier, SigningKey};
+    use rand::rngs::OsRng;
+
+    fn unbase64url(text: &str) -> Vec<u8> {
+        let mut text = text.replace('-', "+").replace('_', "/");
+        while text.len() % 4 != 0 {
+            text.push('=');
+        }
+        base64::decode_block(&text).unwrap()
+    }
+
+    #[test]
+    fn assertion_verifies() {
+        let key = SigningKey::random(&mut OsRng);
+        let pk = key.verifying_key().to_encoded_point(true);
+        let cose = cose_key(pk.as_bytes()).unwrap();
+        assert_eq!(cose.len(), 77);
+
+        let auth_data = authenticator_data("example.org", 7, false);
+        assert_eq!(auth_data.len(), 37);
+        assert_eq!(auth_data[32], USER_PRESENT);
+        let verified = authenticator_data("example.org", 7, true);
+        assert_eq!(verified[32], USER_PRESENT | USER_VERIFIED);
+        let client_data = br#"{"type":"webauthn.get","challenge":"AAAA"}"#;
+        let signed = [&auth_data[..], &Sha256::digest(client_data)[..]].concat();
+        assert_eq!(
+            Sha256::digest(&signed).to_vec(),
+            signature_digest(&auth_data, client_data)
+        );
+
+        // the group signs the digest as ECDSA with SHA-256 over `signed`
+        let signature: Signature = key.sign(&signed);
+        let credential = assertion(b"id", &auth_data, client_data, &signature.to_bytes()).unwrap();
+        let credential: serde_json::Value = serde_json::from_str(&credential).unwrap();
+        let response = &credential["response"];
+        let der = unbase64url(response["signature"].as_str().unwrap());
+        let signature = Signature::from_der(&der).unwrap();
+        assert!(key.verifying_key().verify(&signed, &signature).is_ok());
+        assert_eq!(
+            unbase64url(response["authenticatorData"].as_str().unwrap()),
+            auth_data
+        );
+        assert!(assertion(b"id", &auth_data, client_data, b"short").is_err());
+    }
+}
@@ -0,0 +1,497 @@
+//! Weighted groups, in which a party may hold several shares
+//!
+//! A party of weight w runs w virtual parties of the underlying protocol.
+//! Messages between two parties are bundled into a matrix indexed by the
+//! virtual parties of the sender and of the recipient, while messages
+//! between the virtual parties of a single party never leave the device.
+
+use crate::proto::{GroupResult, ProtocolGroupInit, ProtocolInit, ProtocolType, SignatureResult};
+use crate::protocol::*;
+use prost::bytes::Bytes;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+#[derive(Serialize, Deserialize)]
+struct Group {
+    protocol_type: i32,
+    index: u32,
+    threshold: u32,
+    weights: Vec<u32>,
+    shares: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    protocol_type: i32,
+    /// Number of virtual parties of each participating party
+    weights: Vec<u32>,
+    /// Position of the local party among the participating ones
+    local: usize,
+    contexts: Vec<Box<dyn Protocol>>,
+    /// Messages between the local virtual parties, `internal[k][m]` from `k` to `m`
+    internal: Vec<Vec<Vec<u8>>>,
+}
+
+impl Session {
+    /// Position of the virtual party among all participating virtual parties
+    fn position(&self, party: usize, share: usize) -> usize {
+        self.weights[..party].iter().sum::<u32>() as usize + share
+    }
+
+    /// Bundle the packed outputs of the local virtual parties per recipient
+    fn route(&mut self, outputs: Vec<Vec<u8>>) -> Result<Vec<u8>> {
+        let total: u32 = self.weights.iter().sum();
+        let outputs = outputs
+            .iter()
+            .map(|out| unpack(out))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if outputs.iter().any(|msgs| msgs.len() + 1 != total as usize) {
+            return Err("unexpected number of messages".into());
+        }
+
+        let mut internal = Vec::new();
+        let mut bundles = Vec::new();
+        for party in 0..self.weights.len() {
+            let mut bundle = Vec::new();
+            for (k, msgs) in outputs.iter().enumerate() {
+                let sender = self.position(self.local, k);
+                let row: Vec<Vec<u8>> = (0..self.weights[party] as usize)
+                    .map(|m| {
+                        let recipient = self.position(party, m);
+                        if recipient < sender {
+                            msgs[recipient].to_vec()
+                        } else if recipient > sender {
+                            msgs[recipient - 1].to_vec()
+                        } else {
+                            Vec::new()
+                        }
+                    })
+                    .collect();
+                bundle.push(row);
+            }
+
+            if party == self.local {
+                internal = bundle;
+            } else {
+                bundles.push(serialize_msg(&bundle)?.into());
+            }
+        }
+        self.internal = internal;
+
+        let protocol_type = ProtocolType::try_from(self.protocol_type)?;
+        Ok(pack(bundles, protocol_type))
+    }
+
+    /// Unbundle the messages from other parties into inputs of the local virtual parties
+    fn deliver(&self, msgs: Vec<Bytes>) -> Result<Vec<Vec<u8>>> {
+        if msgs.len() + 1 != self.weights.len() {
+            return Err("wrong number of messages".into());
+        }
+        let mut bundles: Vec<Vec<Vec<Vec<u8>>>> = deserialize_vec(&msgs)?;
+        bundles.insert(self.local, self.internal.clone());
+
+        for (party, bundle) in bundles.iter().enumerate() {
+            if bundle.len() != self.weights[party] as usize
+                || bundle.iter().any(|row| row.len() != self.contexts.len())
+            {
+                return Err(format!("malformed message bundle from party {}", party).into());
+            }
+        }
+
+        let protocol_type = ProtocolType::try_from(self.protocol_type)?;
+        let inputs = (0..self.contexts.len())
+            .map(|k| {
+                let mut input = Vec::new();
+                for (party, bundle) in bundles.iter().enumerate() {
+                    for (share, row) in bundle.iter().enumerate() {
+                        if party != self.local || share != k {
+                            input.push(row[k].clone().into());
+                        }
+                    }
+                }
+                pack(input, protocol_type)
+            })
+            .collect();
+        Ok(inputs)
+    }
+
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let inputs = self.deliver(unpack(data)?)?;
+        let outputs = self
+            .contexts
+            .iter_mut()
+            .zip(inputs)
+            .map(|(ctx, input)| ctx.advance(&input))
+            .collect::<Result<Vec<_>>>()?;
+        self.route(outputs)
+    }
+
+    /// The other parties send their bundles once the local virtual
+    /// parties expect messages
+    fn expected_messages(&self) -> Option<usize> {
+        self.contexts.first()?.expected_messages()?;
+        Some(self.weights.len() - 1)
+    }
+
+    fn finish(self) -> Result<Vec<Vec<u8>>> {
+        self.contexts.into_iter().map(|ctx| ctx.finish()).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct KeygenContext {
+    index: u32,
+    threshold: u32,
+    session: Option<Session>,
+}
+
+impl KeygenContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolGroupInit::decode(data)?;
+        let protocol_type = ProtocolType::try_from(msg.protocol_type)?;
+
+        let weights = if msg.weights.is_empty() {
+            vec![1; msg.parties as usize]
+        } else {
+            msg.weights.clone()
+        };
+        if weights.len() != msg.parties as usize {
+            return Err("wrong number of weights".into());
+        }
+        if weights.len() < 2 {
+            return Err("weighted group needs at least two parties".into());
+        }
+        if weights.contains(&0) {
+            return Err("party weights must be positive".into());
+        }
+
+        let base = index_base(protocol_type);
+        let local = msg
+            .index
+            .checked_sub(base)
+            .map(|i| i as usize)
+            .filter(|i| *i < weights.len())
+            .ok_or("invalid party index")?;
+        let total: u32 = weights.iter().sum();
+        let offset: u32 = weights[..local].iter().sum();
+
+        let mut contexts = Vec::new();
+        let mut outputs = Vec::new();
+        for k in 0..weights[local] {
+            let mut ctx = (protocol_info(protocol_type)?.keygen)();
+            outputs.push(
+                ctx.advance(
+                    &(ProtocolGroupInit {
+                        protocol_type: msg.protocol_type,
+                        index: base + offset + k,
+                        parties: total,
+                        threshold: msg.threshold,
+                        weights: Vec::new(),
+                        encrypt: msg.encrypt,
+                        metadata: msg.metadata.clone(),
+                        version: msg.version,
+                    })
+                    .encode_to_vec(),
+                )?,
+            );
+            contexts.push(ctx);
+        }
+
+        let mut session = Session {
+            protocol_type: msg.protocol_type,
+            weights,
+            local,
+            contexts,
+            internal: Vec::new(),
+        };
+        let out = session.route(outputs)?;
+
+        self.index = msg.index;
+        self.threshold = msg.threshold;
+        self.session = Some(session);
+        Ok(out)
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.session
+            .as_mut()
+            .ok_or("protocol not initialized")?
+            .advance(data)
+    }
+}
+
+#[typetag::serde(name = "weighted_keygen")]
+impl Protocol for KeygenContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = if self.session.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }?;
+        Ok(data)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let session = self.session.ok_or("protocol not finished")?;
+        let protocol_type = session.protocol_type;
+        let weights = session.weights.clone();
+        let shares = session.finish()?;
+        let public_key = GroupResult::decode(&shares[0][..])?.public_key;
+
+        let group = serde_json::to_vec(&Group {
+            protocol_type,
+            index: self.index,
+            threshold: self.threshold,
+            weights,
+            shares,
+        })?;
+        Ok(pack_group(
+            ProtocolType::try_from(protocol_type)?,
+            public_key,
+            group,
+        ))
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.session.as_ref()?.expected_messages()
+    }
+}
+
+impl KeygenProtocol for KeygenContext {
+    fn new() -> Self {
+        Self {
+            index: 0,
+            threshold: 0,
+            session: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ThresholdContext {
+    group: Group,
+    /// Indices of the participating parties
+    #[serde(default)]
+    indices: Vec<u32>,
+    session: Option<Session>,
+}
+
+impl ThresholdContext {
+    fn init(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let msg = ProtocolInit::decode(data)?;
+        let protocol_type = ProtocolType::try_from(msg.protocol_type)?;
+        let base = index_base(protocol_type);
+
+        if !msg.indices.windows(2).all(|w| w[0] < w[1]) {
+            return Err("indices must be sorted".into());
+        }
+        let parties = msg
+            .indices
+            .iter()
+            .map(|i| {
+                i.checked_sub(base)
+                    .map(|i| i as usize)
+                    .filter(|i| *i < self.group.weights.len())
+                    .ok_or("invalid party index")
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if parties.len() < 2 {
+            return Err("weighted session needs at least two parties".into());
+        }
+        let local = msg
+            .indices
+            .iter()
+            .position(|i| *i == self.group.index)
+            .ok_or("participant index not included")?;
+
+        let weights: Vec<u32> = parties.iter().map(|i| self.group.weights[*i]).collect();
+        if weights.iter().sum::<u32>() < self.group.threshold {
+            return Err("not enough weight to reach the threshold".into());
+        }
+
+        let offset = |party: usize| -> u32 { self.group.weights[..party].iter().sum() };
+        let indices: Vec<u32> = parties
+            .iter()
+            .flat_map(|i| (0..self.group.weights[*i]).map(move |k| (*i, k)))
+            .map(|(i, k)| base + offset(i) + k)
+            .collect();
+
+        let mut contexts = Vec::new();
+        let mut outputs = Vec::new();
+        for (k, share) in self.group.shares.iter().enumerate() {
+            let mut ctx =
+                (protocol_info(protocol_type)?.threshold)(share, &ContextOptions::default())?;
+            outputs.push(
+                ctx.advance(
+                    &(ProtocolInit {
+                        protocol_type: msg.protocol_type,
+                        indices: indices.clone(),
+                        index: base + offset(parties[local]) + k as u32,
+                        data: msg.data.clone(),
+                        ..Default::default()
+                    })
+                    .encode_to_vec(),
+                )?,
+            );
+            contexts.push(ctx);
+        }
+
+        let mut session = Session {
+            protocol_type: msg.protocol_type,
+            weights,
+            local,
+            contexts,
+            internal: Vec::new(),
+        };
+        let out = session.route(outputs)?;
+        self.indices = msg.indices;
+        self.session = Some(session);
+        Ok(out)
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.session
+            .as_mut()
+            .ok_or("protocol not initialized")?
+            .advance(data)
+    }
+}
+
+#[typetag::serde(name = "weighted_threshold")]
+impl Protocol for ThresholdContext {
+    fn advance(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = if self.session.is_none() {
+            self.init(data)
+        } else {
+            self.update(data)
+        }?;
+        Ok(data)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let session = self.session.ok_or("protocol not finished")?;
+        let protocol_type = ProtocolType::try_from(session.protocol_type)?;
+        let result = session.finish()?.remove(0);
+
+        // signatures name the virtual parties, replace them by the actual ones
+        match protocol_type {
+            ProtocolType::Gg18 | ProtocolType::Frost | ProtocolType::FrostP256 => {
+                let signature = SignatureResult::decode(&result[..])?.signature;
+                Ok(pack_signature(protocol_type, signature, self.indices))
+            }
+            _ => Ok(result),
+        }
+    }
+
+    fn expected_messages(&self) -> Option<usize> {
+        self.session.as_ref()?.expected_messages()
+    }
+}
+
+impl ThresholdProtocol for ThresholdContext {
+    fn new(group: &[u8]) -> Result<Self> {
+        Ok(Self {
+            group: parse_group_context(group)?,
+            indices: Vec::new(),
+            session: None,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "frost"))]
+mod tests {
+    use super::*;
+    use crate::proto::SignatureResult;
+    use crate::protocol::frost::verify;
+    use crate::protocol::tests::{advance_unpacked, run_rounds};
+
+    fn keygen(weights: Vec<u32>, threshold: u32) -> Vec<Vec<u8>> {
+        let parties = weights.len() as u32;
+        let mut ctxs: Vec<KeygenContext> = (0..parties).map(|_| KeygenContext::new()).collect();
+        let messages = ctxs
+            .iter_mut()
+            .enumerate()
+            .map(|(idx, ctx)| {
+                advance_unpacked(
+                    ctx,
+                    &(ProtocolGroupInit {
+                        protocol_type: ProtocolType::Frost as i32,
+                        index: idx as u32 + 1,
+                        parties,
+                        threshold,
+                        weights: weights.clone(),
+                        encrypt: false,
+                        metadata: None,
+                        version: 0,
+                    })
+                    .encode_to_vec(),
+                )
+            })
+            .collect();
+        run_rounds(&mut ctxs, messages, 2);
+
+        ctxs.into_iter()
+            .map(|ctx| Box::new(ctx).finish().unwrap())
+            .collect()
+    }
+
+    fn init(
+        ctx: &mut ThresholdContext,
+        indices: &[u32],
+        index: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        ctx.advance(
+            &(ProtocolInit {
+                protocol_type: ProtocolType::Frost as i32,
+                indices: indices.to_vec(),
+                index,
+                data: data.to_vec(),
+                ..Default::default()
+            })
+            .encode_to_vec(),
+        )
+    }
+
+    #[test]
+    fn sign() {
+        let groups = keygen(vec![2, 1, 1], 3);
+        let msg = b"hello";
+
+        let indices = vec![1, 2];
+        let mut ctxs: Vec<ThresholdContext> = indices
+            .iter()
+            .map(|i| ThresholdContext::new(&groups[*i as usize - 1]).unwrap())
+            .collect();
+        let messages = ctxs
+            .iter_mut()
+            .zip(&indices)
+            .map(|(ctx, index)| {
+                let out = unpack(&init(ctx, &indices, *index, msg).unwrap()).unwrap();
+                out.iter().map(|msg| msg.to_vec()).collect()
+            })
+            .collect();
+        run_rounds(&mut ctxs, messages, 2);
+
+        let pk = GroupResult::decode(&groups[0][..]).unwrap().public_key;
+        for ctx in ctxs {
+            let result = Box::new(ctx).finish().unwrap();
+            let result = SignatureResult::decode(&result[..]).unwrap();
+            assert!(verify(&pk, msg, &result.signature).is_ok());
+            assert_eq!(result.indices, indices);
+            assert_eq!(
+                result.signers_commitment,
+                signers_commitment(ProtocolType::Frost, &indices, &result.signature)
+            );
+        }
+    }
+
+    #[test]
+    fn insufficient_weight() {
+        let groups = keygen(vec![2, 1, 1], 3);
+
+        let mut ctx = ThresholdContext::new(&groups[1]).unwrap();
+        assert!(init(&mut ctx, &[2, 3], 2, b"hello").is_err());
+    }
+}
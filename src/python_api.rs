@@ -0,0 +1,333 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::convert::TryFrom;
+
+use crate::proto::ProtocolType;
+use crate::protocol::{self, Protocol};
+
+fn to_py_err(error: Box<dyn std::error::Error>) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+fn protocol_type(value: i32) -> PyResult<ProtocolType> {
+    ProtocolType::try_from(value).map_err(to_py_err)
+}
+
+/// Protocol state machine; finishing it consumes the underlying context
+#[pyclass(name = "Protocol")]
+struct PyProtocol {
+    ctx: Option<Box<dyn Protocol>>,
+    /// Transcript of the session kept once it has been finished
+    transcript: Option<Vec<u8>>,
+}
+
+impl PyProtocol {
+    fn ctx(&mut self) -> PyResult<&mut Box<dyn Protocol>> {
+        self.ctx
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("protocol already finished"))
+    }
+}
+
+#[pymethods]
+impl PyProtocol {
+    #[staticmethod]
+    fn keygen(protocol: i32) -> PyResult<Self> {
+        let ctx = protocol::keygen_context(protocol_type(protocol)?).map_err(to_py_err)?;
+        Ok(Self {
+            ctx: Some(ctx),
+            transcript: None,
+        })
+    }
+
+    #[staticmethod]
+    fn init(protocol: i32, group: &[u8]) -> PyResult<Self> {
+        let ctx =
+            protocol::threshold_context(protocol_type(protocol)?, group).map_err(to_py_err)?;
+        Ok(Self {
+            ctx: Some(ctx),
+            transcript: None,
+        })
+    }
+
+    /// Initialize a threshold context from a group stored under `key`
+    /// and `group_id`, see `group_encrypt`
+    #[staticmethod]
+    fn init_stored(protocol: i32, stored: &[u8], key: &[u8], group_id: &[u8]) -> PyResult<Self> {
+        let ctx =
+            protocol::storage::threshold_context(protocol_type(protocol)?, stored, key, group_id)
+                .map_err(to_py_err)?;
+        Ok(Self {
+            ctx: Some(ctx),
+            transcript: None,
+        })
+    }
+
+    #[staticmethod]
+    fn deserialize(ctx: &[u8]) -> PyResult<Self> {
+        let ctx = protocol::try_deserialize(ctx).map_err(to_py_err)?;
+        Ok(Self {
+            ctx: Some(ctx),
+            transcript: None,
+        })
+    }
+
+    /// Restore a context from a snapshot authenticated under `key`
+    #[staticmethod]
+    fn restore(snapshot: &[u8], key: &[u8]) -> PyResult<Self> {
+        let ctx = protocol::restore(snapshot, key).map_err(to_py_err)?;
+        Ok(Self {
+            ctx: Some(ctx),
+            transcript: None,
+        })
+    }
+
+    fn advance<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<&'py PyBytes> {
+        let out = self.ctx()?.advance(data).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn expected_messages(&mut self) -> PyResult<Option<usize>> {
+        Ok(self.ctx()?.expected_messages())
+    }
+
+    /// Start recording the transcript of the session
+    fn record_transcript(&mut self) -> PyResult<()> {
+        let ctx = self
+            .ctx
+            .take()
+            .ok_or_else(|| PyValueError::new_err("protocol already finished"))?;
+        self.ctx = Some(protocol::with_transcript(ctx));
+        Ok(())
+    }
+
+    /// Transcript of the session, also available once it has been finished
+    fn transcript<'py>(&mut self, py: Python<'py>) -> PyResult<Option<&'py PyBytes>> {
+        let transcript = match &self.ctx {
+            Some(ctx) => ctx.transcript(),
+            None => self.transcript.clone(),
+        };
+        Ok(transcript.map(|t| PyBytes::new(py, &t)))
+    }
+
+    /// Output of the last round, to be sent again if it was lost
+    fn last_output<'py>(&mut self, py: Python<'py>) -> PyResult<Option<&'py PyBytes>> {
+        Ok(self.ctx()?.last_output().map(|out| PyBytes::new(py, &out)))
+    }
+
+    /// Group with the session counted in its usage, to be stored
+    /// in place of the group before the session starts
+    fn counted_group<'py>(&mut self, py: Python<'py>) -> PyResult<Option<&'py PyBytes>> {
+        Ok(self
+            .ctx()?
+            .counted_group()
+            .map(|group| PyBytes::new(py, &group)))
+    }
+
+    /// State of the session as JSON, for diagnostics
+    fn debug_state(&mut self) -> PyResult<String> {
+        serde_json::to_string(&self.ctx()?.debug_state()).map_err(|e| to_py_err(e.into()))
+    }
+
+    /// Accept the messages of a round one at a time
+    fn make_progressive(&mut self) -> PyResult<()> {
+        let ctx = self
+            .ctx
+            .take()
+            .ok_or_else(|| PyValueError::new_err("protocol already finished"))?;
+        self.ctx = Some(protocol::progressive(ctx));
+        Ok(())
+    }
+
+    /// Sign the messages by the key of the device and verify those
+    /// of the others against the certificates of their devices
+    fn authenticate(&mut self, key: &[u8], devices: &[u8]) -> PyResult<()> {
+        let ctx = self
+            .ctx
+            .take()
+            .ok_or_else(|| PyValueError::new_err("protocol already finished"))?;
+        self.ctx = Some(protocol::authenticated(ctx, key, devices).map_err(to_py_err)?);
+        Ok(())
+    }
+
+    fn feed(&mut self, sender: usize, msg: &[u8]) -> PyResult<()> {
+        self.ctx()?.feed(sender, msg).map_err(to_py_err)
+    }
+
+    fn ready(&mut self) -> PyResult<bool> {
+        Ok(self.ctx()?.ready())
+    }
+
+    fn serialize<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let ctx = serde_json::to_vec(self.ctx()?).map_err(|e| to_py_err(e.into()))?;
+        Ok(PyBytes::new(py, &ctx))
+    }
+
+    /// Serialize the context into a snapshot authenticated under `key`
+    fn checkpoint<'py>(&mut self, py: Python<'py>, key: &[u8]) -> PyResult<&'py PyBytes> {
+        let snapshot = protocol::checkpoint(&**self.ctx()?, key).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &snapshot))
+    }
+
+    fn finish<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let ctx = self
+            .ctx
+            .take()
+            .ok_or_else(|| PyValueError::new_err("protocol already finished"))?;
+        self.transcript = ctx.transcript();
+        let out = ctx.finish().map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out))
+    }
+}
+
+#[cfg(feature = "elgamal")]
+#[pyfunction]
+fn encrypt<'py>(py: Python<'py>, msg: &[u8], pk: &[u8]) -> PyResult<&'py PyBytes> {
+    let ct = protocol::elgamal::encrypt(msg, pk).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &ct))
+}
+
+#[cfg(feature = "elgamal")]
+#[pyfunction]
+fn encrypt_with_aad<'py>(
+    py: Python<'py>,
+    msg: &[u8],
+    pk: &[u8],
+    aad: &[u8],
+) -> PyResult<&'py PyBytes> {
+    let ct = protocol::elgamal::encrypt_with_aad(msg, pk, aad).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &ct))
+}
+
+#[cfg(feature = "elgamal")]
+#[pyfunction]
+fn rerandomize<'py>(py: Python<'py>, ct: &[u8], pk: &[u8]) -> PyResult<&'py PyBytes> {
+    let ct = protocol::elgamal::rerandomize(ct, pk).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &ct))
+}
+
+#[cfg(feature = "frost")]
+#[pyfunction]
+fn hpke_encrypt<'py>(py: Python<'py>, msg: &[u8], pk: &[u8]) -> PyResult<&'py PyBytes> {
+    let ct = protocol::hpke::encrypt(msg, pk).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &ct))
+}
+
+#[cfg(feature = "frost")]
+#[pyfunction]
+fn hpke_encryption_key<'py>(py: Python<'py>, group: &[u8]) -> PyResult<&'py PyBytes> {
+    let key = protocol::hpke::encryption_key(group).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &key))
+}
+
+#[cfg(feature = "elgamal")]
+#[pyfunction]
+fn verify_decryption_share(key_set: &[u8], data: &[u8], index: u16, msg: &[u8]) -> PyResult<()> {
+    protocol::elgamal::verify_decryption_share(key_set, data, index, msg).map_err(to_py_err)
+}
+
+#[cfg(feature = "elgamal")]
+#[pyfunction]
+fn public_key_set<'py>(py: Python<'py>, group: &[u8]) -> PyResult<&'py PyBytes> {
+    let key_set = protocol::elgamal::public_key_set(group).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &key_set))
+}
+
+/// Short fingerprint of a group result for the comparison between devices
+#[pyfunction]
+fn fingerprint(group: &[u8]) -> PyResult<String> {
+    protocol::fingerprint::fingerprint(group).map_err(to_py_err)
+}
+
+/// Describe the capabilities of a protocol as a JSON object
+#[pyfunction]
+fn capabilities(protocol: i32) -> PyResult<String> {
+    let capabilities = protocol::capabilities(protocol_type(protocol)?).map_err(to_py_err)?;
+    Ok(String::from_utf8(capabilities).unwrap())
+}
+
+/// Describe the capabilities of all the supported protocols as a JSON array
+#[pyfunction]
+fn supported_protocols() -> PyResult<String> {
+    let protocols = protocol::supported_protocols().map_err(to_py_err)?;
+    Ok(String::from_utf8(protocols).unwrap())
+}
+
+/// Encrypt a group result at rest under a storage key, bound to `group_id`
+#[pyfunction]
+fn group_encrypt<'py>(
+    py: Python<'py>,
+    group: &[u8],
+    key: &[u8],
+    group_id: &[u8],
+) -> PyResult<&'py PyBytes> {
+    let stored = protocol::storage::encrypt_group(group, key, group_id).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &stored))
+}
+
+/// Re-encrypt a stored group result under a new storage key
+#[pyfunction]
+fn group_rewrap<'py>(
+    py: Python<'py>,
+    stored: &[u8],
+    old_key: &[u8],
+    new_key: &[u8],
+    group_id: &[u8],
+) -> PyResult<&'py PyBytes> {
+    let stored =
+        protocol::storage::rewrap(stored, old_key, new_key, group_id).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &stored))
+}
+
+/// Check the envelope of a packed message before relaying it
+#[pyfunction]
+fn validate_envelope(data: &[u8]) -> PyResult<()> {
+    protocol::validate_envelope(data).map_err(to_py_err)
+}
+
+/// Verify a signature produced by the given signing protocol
+#[pyfunction]
+fn verify(protocol: i32, pk: &[u8], msg: &[u8], signature: &[u8]) -> PyResult<()> {
+    protocol::coordinator::verify(protocol_type(protocol)?, pk, msg, signature).map_err(to_py_err)
+}
+
+#[pymodule]
+fn meesign_crypto(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("GG18", ProtocolType::Gg18 as i32)?;
+    m.add("ELGAMAL", ProtocolType::Elgamal as i32)?;
+    m.add("FROST", ProtocolType::Frost as i32)?;
+    m.add("PPSS", ProtocolType::Ppss as i32)?;
+    m.add("ELGAMAL_REENCRYPT", ProtocolType::ElgamalReencrypt as i32)?;
+    m.add("FROST_P256", ProtocolType::FrostP256 as i32)?;
+    m.add("ECDH", ProtocolType::Ecdh as i32)?;
+    m.add("HPKE", ProtocolType::Hpke as i32)?;
+    m.add("ROAST", ProtocolType::Roast as i32)?;
+    m.add("MUSIG2", ProtocolType::Musig2 as i32)?;
+    m.add("FROST_ENROLL", ProtocolType::FrostEnroll as i32)?;
+    m.add("FROST_REMOVE", ProtocolType::FrostRemove as i32)?;
+    m.add("ML_DSA", ProtocolType::MlDsa as i32)?;
+
+    m.add_class::<PyProtocol>()?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(capabilities, m)?)?;
+    m.add_function(wrap_pyfunction!(supported_protocols, m)?)?;
+    m.add_function(wrap_pyfunction!(fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_envelope, m)?)?;
+    m.add_function(wrap_pyfunction!(group_encrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(group_rewrap, m)?)?;
+    #[cfg(feature = "elgamal")]
+    {
+        m.add_function(wrap_pyfunction!(encrypt, m)?)?;
+        m.add_function(wrap_pyfunction!(encrypt_with_aad, m)?)?;
+        m.add_function(wrap_pyfunction!(rerandomize, m)?)?;
+        m.add_function(wrap_pyfunction!(verify_decryption_share, m)?)?;
+        m.add_function(wrap_pyfunction!(public_key_set, m)?)?;
+    }
+    #[cfg(feature = "frost")]
+    {
+        m.add_function(wrap_pyfunction!(hpke_encrypt, m)?)?;
+        m.add_function(wrap_pyfunction!(hpke_encryption_key, m)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::proto::ProtocolType;
+use crate::protocol::{self, Protocol};
+
+#[derive(uniffi::Enum)]
+pub enum ProtocolKind {
+    Gg18,
+    Elgamal,
+    Frost,
+    Ppss,
+    ElgamalReencrypt,
+    FrostP256,
+    Ecdh,
+    Hpke,
+    Roast,
+    Musig2,
+    FrostEnroll,
+    FrostRemove,
+    MlDsa,
+}
+
+impl From<ProtocolKind> for ProtocolType {
+    fn from(kind: ProtocolKind) -> Self {
+        match kind {
+            ProtocolKind::Gg18 => ProtocolType::Gg18,
+            ProtocolKind::Elgamal => ProtocolType::Elgamal,
+            ProtocolKind::Frost => ProtocolType::Frost,
+            ProtocolKind::Ppss => ProtocolType::Ppss,
+            ProtocolKind::ElgamalReencrypt => ProtocolType::ElgamalReencrypt,
+            ProtocolKind::FrostP256 => ProtocolType::FrostP256,
+            ProtocolKind::Ecdh => ProtocolType::Ecdh,
+            ProtocolKind::Hpke => ProtocolType::Hpke,
+            ProtocolKind::Roast => ProtocolType::Roast,
+            ProtocolKind::Musig2 => ProtocolType::Musig2,
+            ProtocolKind::FrostEnroll => ProtocolType::FrostEnroll,
+            ProtocolKind::FrostRemove => ProtocolType::FrostRemove,
+            ProtocolKind::MlDsa => ProtocolType::MlDsa,
+        }
+    }
+}
+
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum ProtocolError {
+    Failed(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for ProtocolError {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        ProtocolError::Failed(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(error: serde_json::Error) -> Self {
+        ProtocolError::Failed(error.to_string())
+    }
+}
+
+/// Protocol state machine; finishing it consumes the underlying context
+#[derive(uniffi::Object)]
+pub struct ProtocolSession {
+    ctx: Mutex<Option<Box<dyn Protocol>>>,
+    /// Transcript of the session kept once it has been finished
+    transcript: Mutex<Option<Vec<u8>>>,
+}
+
+impl ProtocolSession {
+    fn wrap(ctx: Box<dyn Protocol>) -> Arc<Self> {
+        Arc::new(Self {
+            ctx: Mutex::new(Some(ctx)),
+            transcript: Mutex::new(None),
+        })
+    }
+
+    fn with<T>(
+        &self,
+        f: impl FnOnce(&mut Box<dyn Protocol>) -> Result<T, ProtocolError>,
+    ) -> Result<T, ProtocolError> {
+        let mut ctx = self.ctx.lock().unwrap();
+        let ctx = ctx
+            .as_mut()
+            .ok_or_else(|| ProtocolError::Failed("protocol already finished".into()))?;
+        f(ctx)
+    }
+}
+
+#[uniffi::export]
+impl ProtocolSession {
+    #[uniffi::constructor]
+    pub fn keygen(kind: ProtocolKind) -> Result<Arc<Self>, ProtocolError> {
+        Ok(Self::wrap(protocol::keygen_context(kind.into())?))
+    }
+
+    #[uniffi::constructor]
+    pub fn init(kind: ProtocolKind, group: Vec<u8>) -> Result<Arc<Self>, ProtocolError> {
+        Ok(Self::wrap(protocol::threshold_context(
+            kind.into(),
+            &group,
+        )?))
+    }
+
+    /// Initialize a threshold context from a group stored under `key`
+    /// and `group_id`, see `group_encrypt`
+    #[uniffi::constructor]
+    pub fn init_stored(
+        kind: ProtocolKind,
+        stored: Vec<u8>,
+        key: Vec<u8>,
+        group_id: Vec<u8>,
+    ) -> Result<Arc<Self>, ProtocolError> {
+        Ok(Self::wrap(protocol::storage::threshold_context(
+            kind.into(),
+            &stored,
+            &key,
+            &group_id,
+        )?))
+    }
+
+    #[uniffi::constructor]
+    pub fn deserialize(ctx: Vec<u8>) -> Result<Arc<Self>, ProtocolError> {
+        Ok(Self::wrap(protocol::try_deserialize(&ctx)?))
+    }
+
+    #[uniffi::constructor]
+    pub fn restore(snapshot: Vec<u8>, key: Vec<u8>) -> Result<Arc<Self>, ProtocolError> {
+        Ok(Self::wrap(protocol::restore(&snapshot, &key)?))
+    }
+
+    pub fn advance(&self, data: Vec<u8>) -> Result<Vec<u8>, ProtocolError> {
+        self.with(|ctx| Ok(ctx.advance(&data)?))
+    }
+
+    pub fn expected_messages(&self) -> Result<Option<u32>, ProtocolError> {
+        self.with(|ctx| Ok(ctx.expected_messages().map(|count| count as u32)))
+    }
+
+    /// Start recording the transcript of the session
+    pub fn record_transcript(&self) -> Result<(), ProtocolError> {
+        let mut ctx = self.ctx.lock().unwrap();
+        let inner = ctx
+            .take()
+            .ok_or_else(|| ProtocolError::Failed("protocol already finished".into()))?;
+        *ctx = Some(protocol::with_transcript(inner));
+        Ok(())
+    }
+
+    /// Transcript of the session, also available once it has been finished
+    pub fn transcript(&self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        match &*self.ctx.lock().unwrap() {
+            Some(ctx) => Ok(ctx.transcript()),
+            None => Ok(self.transcript.lock().unwrap().clone()),
+        }
+    }
+
+    /// Output of the last round, to be sent again if it was lost
+    pub fn last_output(&self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        self.with(|ctx| Ok(ctx.last_output()))
+    }
+
+    /// Group with the session counted in its usage, to be stored
+    /// in place of the group before the session starts
+    pub fn counted_group(&self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        self.with(|ctx| Ok(ctx.counted_group()))
+    }
+
+    /// State of the session as JSON, for diagnostics
+    pub fn debug_state(&self) -> Result<String, ProtocolError> {
+        self.with(|ctx| Ok(serde_json::to_string(&ctx.debug_state())?))
+    }
+
+    /// Accept the messages of a round one at a time
+    pub fn make_progressive(&self) -> Result<(), ProtocolError> {
+        let mut ctx = self.ctx.lock().unwrap();
+        let inner = ctx
+            .take()
+            .ok_or_else(|| ProtocolError::Failed("protocol already finished".into()))?;
+        *ctx = Some(protocol::progressive(inner));
+        Ok(())
+    }
+
+    /// Sign the messages by the key of the device and verify those
+    /// of the others against the certificates of their devices
+    pub fn authenticate(&self, key: Vec<u8>, devices: Vec<u8>) -> Result<(), ProtocolError> {
+        let mut ctx = self.ctx.lock().unwrap();
+        let inner = ctx
+            .take()
+            .ok_or_else(|| ProtocolError::Failed("protocol already finished".into()))?;
+        *ctx = Some(protocol::authenticated(inner, &key, &devices)?);
+        Ok(())
+    }
+
+    pub fn feed(&self, sender: u32, msg: Vec<u8>) -> Result<(), ProtocolError> {
+        self.with(|ctx| Ok(ctx.feed(sender as usize, &msg)?))
+    }
+
+    pub fn ready(&self) -> Result<bool, ProtocolError> {
+        self.with(|ctx| Ok(ctx.ready()))
+    }
+
+    pub fn checkpoint(&self, key: Vec<u8>) -> Result<Vec<u8>, ProtocolError> {
+        self.with(|ctx| Ok(protocol::checkpoint(&**ctx, &key)?))
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, ProtocolError> {
+        self.with(|ctx| Ok(serde_json::to_vec(ctx)?))
+    }
+
+    pub fn finish(&self) -> Result<Vec<u8>, ProtocolError> {
+        let ctx = self
+            .ctx
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| ProtocolError::Failed("protocol already finished".into()))?;
+        *self.transcript.lock().unwrap() = ctx.transcript();
+        Ok(ctx.finish()?)
+    }
+}
+
+/// Encrypt a group result at rest under a storage key, bound to `group_id`
+#[uniffi::export]
+pub fn group_encrypt(
+    group: Vec<u8>,
+    key: Vec<u8>,
+    group_id: Vec<u8>,
+) -> Result<Vec<u8>, ProtocolError> {
+    Ok(protocol::storage::encrypt_group(&group, &key, &group_id)?)
+}
+
+/// Re-encrypt a stored group result under a new storage key
+#[uniffi::export]
+pub fn group_rewrap(
+    stored: Vec<u8>,
+    old_key: Vec<u8>,
+    new_key: Vec<u8>,
+    group_id: Vec<u8>,
+) -> Result<Vec<u8>, ProtocolError> {
+    Ok(protocol::storage::rewrap(
+        &stored, &old_key, &new_key, &group_id,
+    )?)
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Fetch the upstream BIP-341 test vectors run by the ignored `bip341` tests,
+# e.g. `cargo test bip341 -- --ignored`
+set -eu
+
+REV="${BIPS_REV:-master}"
+BASE="https://raw.githubusercontent.com/bitcoin/bips/$REV/bip-0341"
+DIR="$(dirname "$0")/bip341"
+
+mkdir -p "$DIR"
+curl -fsSL "$BASE/wallet-test-vectors.json" -o "$DIR/wallet-test-vectors.json"
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Fetch the upstream Wycheproof test vectors run by the ignored `wycheproof`
+# tests, e.g. `cargo test wycheproof -- --ignored`
+set -eu
+
+REV="${WYCHEPROOF_REV:-main}"
+BASE="https://raw.githubusercontent.com/C2SP/wycheproof/$REV/testvectors_v1"
+DIR="$(dirname "$0")/wycheproof"
+
+mkdir -p "$DIR"
+for name in ecdsa_secp256r1_sha256_p1363_test; do
+    curl -fsSL "$BASE/$name.json" -o "$DIR/$name.json"
+done